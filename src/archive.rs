@@ -0,0 +1,156 @@
+use crate::http;
+use anyhow::{bail, Context, Result};
+use std::borrow::Cow;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+// Tarballs, which `bundle.tar.gz::member.yaml` picks a file out of
+const ARCHIVES: [&str; 7] = [".tar", ".tar.gz", ".tgz", ".tar.zst", ".tzst", ".tar.xz", ".txz"];
+
+/// Compression told from a file's extension, undone when reading it and applied when writing it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gz" | "tgz" => Some(Compression::Gzip),
+            "zst" | "tzst" => Some(Compression::Zstd),
+            "xz" | "txz" => Some(Compression::Xz),
+            _ => None,
+        }
+    }
+
+    pub fn decompress(self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        match self {
+            Compression::Gzip => flate2::read::MultiGzDecoder::new(bytes).read_to_end(&mut decompressed)?,
+            Compression::Zstd => zstd::stream::read::Decoder::new(bytes)?.read_to_end(&mut decompressed)?,
+            Compression::Xz => xz2::read::XzDecoder::new(bytes).read_to_end(&mut decompressed)?,
+        };
+        Ok(decompressed)
+    }
+
+    pub fn compress(self, bytes: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            Compression::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish()?
+            }
+            Compression::Zstd => zstd::stream::encode_all(bytes, 0)?,
+            Compression::Xz => {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(bytes)?;
+                encoder.finish()?
+            }
+        })
+    }
+}
+
+/// An `archive.tar.gz::member.yaml` path split into the archive and the member's path inside it
+pub fn split_member(path: &Path) -> Option<(PathBuf, &str)> {
+    let (archive, member) = path.to_str()?.rsplit_once("::")?;
+    let archive = PathBuf::from(archive);
+    let name = http::name_of(&archive).to_string_lossy().into_owned();
+    ARCHIVES.iter().any(|ext| name.ends_with(ext)).then_some((archive, member))
+}
+
+/// `path` without an extension of compression, like data.json for data.json.gz
+pub fn uncompressed(path: &Path) -> PathBuf {
+    match Compression::from_path(path) {
+        Some(_) => path.with_extension(""),
+        None => path.to_path_buf(),
+    }
+}
+
+/// The name that tells the format of what `read` returns: the member's for a file in an
+/// archive, and otherwise the file's or URL's, without any extension of compression
+pub fn name_of(path: &Path) -> PathBuf {
+    match split_member(path) {
+        Some((_, member)) => uncompressed(Path::new(member)),
+        None => uncompressed(&http::name_of(path)),
+    }
+}
+
+/// The content of a file or URL, decompressed if its extension is one of compression, or of a
+/// file in a tar archive
+pub fn read(path: &Path) -> Result<Vec<u8>> {
+    let Some((archive, member)) = split_member(path) else {
+        let bytes = http::read(path)?;
+        return decompress(&http::name_of(path), bytes);
+    };
+    let tarball = read(&archive)?;
+    let wanted = Path::new(member.trim_start_matches("./"));
+    let mut files = Vec::new();
+    let mut contents = tar::Archive::new(tarball.as_slice());
+    for entry in contents.entries().with_context(|| format!("Failed to read {}", archive.display()))? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let entry_path = entry.path()?.into_owned();
+        let name = entry_path.strip_prefix("./").unwrap_or(&entry_path);
+        if name == wanted {
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes)?;
+            return decompress(wanted, bytes);
+        }
+        files.push(name.display().to_string());
+    }
+    bail!("{} has no file '{member}', only {}", archive.display(), files.join(", "))
+}
+
+fn decompress(name: &Path, bytes: Vec<u8>) -> Result<Vec<u8>> {
+    match Compression::from_path(name) {
+        Some(compression) => compression.decompress(&bytes).with_context(|| format!("Failed to decompress {}", name.display())),
+        None => Ok(bytes),
+    }
+}
+
+/// Write `bytes` to `path`, compressed if its extension is one of compression
+pub fn write(path: &Path, bytes: &[u8]) -> Result<()> {
+    let bytes = match Compression::from_path(path) {
+        Some(compression) => Cow::Owned(compression.compress(bytes)?),
+        None => Cow::Borrowed(bytes),
+    };
+    std::fs::write(path, bytes).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["data.json.gz", "data.json.zst", "data.json.xz"] {
+            let path = dir.path().join(name);
+            write(&path, br#"{"a": 1}"#).unwrap();
+            assert_ne!(std::fs::read(&path).unwrap(), br#"{"a": 1}"#);
+            assert_eq!(read(&path).unwrap(), br#"{"a": 1}"#);
+            assert_eq!(name_of(&path), dir.path().join("data.json"));
+        }
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(7);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "./config/app.yaml", &b"port: 1"[..]).unwrap();
+        let tarball = Compression::Gzip.compress(&builder.into_inner().unwrap()).unwrap();
+        let bundle = dir.path().join("bundle.tar.gz");
+        std::fs::write(&bundle, tarball).unwrap();
+
+        let member = PathBuf::from(format!("{}::config/app.yaml", bundle.display()));
+        assert_eq!(read(&member).unwrap(), b"port: 1");
+        assert_eq!(name_of(&member), PathBuf::from("config/app.yaml"));
+        let missing = PathBuf::from(format!("{}::app.yaml", bundle.display()));
+        assert!(read(&missing).unwrap_err().to_string().ends_with("has no file 'app.yaml', only config/app.yaml"));
+        assert!(split_member(Path::new("notes.txt::app.yaml")).is_none());
+    }
+}
@@ -0,0 +1,180 @@
+use serde_json::{json, Value};
+use std::fmt::Write;
+
+/// One difference between two documents, located by JSON Pointer tokens
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    Added(Vec<String>, Value),
+    Removed(Vec<String>, Value),
+    Changed(Vec<String>, Value, Value),
+}
+
+impl Change {
+    pub fn path(&self) -> &[String] {
+        match self {
+            Change::Added(path, _) | Change::Removed(path, _) | Change::Changed(path, _, _) => path,
+        }
+    }
+}
+
+/// Compare two documents, recursing into maps by key and lists by index.
+pub fn diff(left: &Value, right: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    walk(&mut Vec::new(), left, right, &mut changes);
+    changes
+}
+
+fn walk(path: &mut Vec<String>, left: &Value, right: &Value, changes: &mut Vec<Change>) {
+    match (left, right) {
+        (Value::Object(l), Value::Object(r)) => {
+            for (key, lv) in l {
+                path.push(key.clone());
+                match r.get(key) {
+                    Some(rv) => walk(path, lv, rv, changes),
+                    None => changes.push(Change::Removed(path.clone(), lv.clone())),
+                }
+                path.pop();
+            }
+            for (key, rv) in r {
+                if !l.contains_key(key) {
+                    path.push(key.clone());
+                    changes.push(Change::Added(path.clone(), rv.clone()));
+                    path.pop();
+                }
+            }
+        }
+        (Value::Array(l), Value::Array(r)) => {
+            for (i, (lv, rv)) in l.iter().zip(r).enumerate() {
+                path.push(i.to_string());
+                walk(path, lv, rv, changes);
+                path.pop();
+            }
+            for (i, rv) in r.iter().enumerate().skip(l.len()) {
+                path.push(i.to_string());
+                changes.push(Change::Added(path.clone(), rv.clone()));
+                path.pop();
+            }
+            // From the end, so each index is still valid when the patch is applied in order
+            for (i, lv) in l.iter().enumerate().skip(r.len()).rev() {
+                path.push(i.to_string());
+                changes.push(Change::Removed(path.clone(), lv.clone()));
+                path.pop();
+            }
+        }
+        (l, r) if l != r => changes.push(Change::Changed(path.clone(), l.clone(), r.clone())),
+        _ => {}
+    }
+}
+
+/// The paths of maps whose keys, those both documents have, come in a different order.
+pub fn reordered(left: &Value, right: &Value) -> Vec<Vec<String>> {
+    let mut paths = Vec::new();
+    walk_order(&mut Vec::new(), left, right, &mut paths);
+    paths
+}
+
+fn walk_order(path: &mut Vec<String>, left: &Value, right: &Value, paths: &mut Vec<Vec<String>>) {
+    match (left, right) {
+        (Value::Object(l), Value::Object(r)) => {
+            let l_keys = l.keys().filter(|key| r.contains_key(*key));
+            let r_keys = r.keys().filter(|key| l.contains_key(*key));
+            if !l_keys.eq(r_keys) {
+                paths.push(path.clone());
+            }
+            for (key, lv) in l {
+                if let Some(rv) = r.get(key) {
+                    path.push(key.clone());
+                    walk_order(path, lv, rv, paths);
+                    path.pop();
+                }
+            }
+        }
+        (Value::Array(l), Value::Array(r)) => {
+            for (i, (lv, rv)) in l.iter().zip(r).enumerate() {
+                path.push(i.to_string());
+                walk_order(path, lv, rv, paths);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn pointer(path: &[String]) -> String {
+    path.iter()
+        .map(|t| format!("/{}", t.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+/// Express the changes as a JSON Patch (RFC 6902) that turns the left document into the right.
+pub fn to_patch(changes: &[Change]) -> Value {
+    Value::Array(
+        changes
+            .iter()
+            .map(|change| match change {
+                Change::Added(path, value) => json!({"op": "add", "path": pointer(path), "value": value}),
+                Change::Removed(path, _) => json!({"op": "remove", "path": pointer(path)}),
+                Change::Changed(path, _, value) => json!({"op": "replace", "path": pointer(path), "value": value}),
+            })
+            .collect(),
+    )
+}
+
+/// Print the changes as an indented tree under their common parents, with ANSI colors if `color`.
+pub fn to_tree(changes: &[Change], color: bool) -> String {
+    let paint = |code: &str, s: String| if color { format!("\x1b[{code}m{s}\x1b[0m") } else { s };
+    let mut out = String::new();
+    let mut current: &[String] = &[];
+    for change in changes {
+        let path = change.path();
+        let (parent, leaf) = match path.split_last() {
+            Some((leaf, parent)) => (parent, leaf.as_str()),
+            None => (&[][..], "(root)"),
+        };
+        let common = current.iter().zip(parent).take_while(|(a, b)| a == b).count();
+        for (depth, key) in parent.iter().enumerate().skip(common) {
+            writeln!(out, "{}{key}:", "  ".repeat(depth)).unwrap();
+        }
+        current = parent;
+
+        let indent = "  ".repeat(parent.len());
+        let line = match change {
+            Change::Added(_, v) => paint("32", format!("+ {leaf}: {v}")),
+            Change::Removed(_, v) => paint("31", format!("- {leaf}: {v}")),
+            Change::Changed(_, l, r) => paint("33", format!("~ {leaf}: {l} -> {r}")),
+        };
+        writeln!(out, "{indent}{line}").unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let left = json!({"services": {"web": {"image": "nginx:1.25", "ports": [80, 443]}, "debug": {}}});
+        let right = json!({"services": {"web": {"image": "nginx:1.27", "ports": [80]}, "cache": {"image": "redis"}}});
+        let changes = diff(&left, &right);
+
+        let mut patched = left.clone();
+        let patch: json_patch::Patch = serde_json::from_value(to_patch(&changes)).unwrap();
+        json_patch::patch(&mut patched, &patch).unwrap();
+        assert_eq!(patched, right);
+
+        assert_eq!(
+            to_tree(&changes, false),
+            "services:\n  web:\n    ~ image: \"nginx:1.25\" -> \"nginx:1.27\"\n    ports:\n      - 1: 443\n  - debug: {}\n  + cache: {\"image\":\"redis\"}\n"
+        );
+        assert!(diff(&left, &left).is_empty());
+    }
+
+    #[test]
+    fn test_reordered() {
+        let left = json!({"a": 1, "b": {"x": 1, "y": 2}, "c": [{"p": 1, "q": 2}]});
+        let right = json!({"a": 1, "b": {"y": 2, "x": 1}, "c": [{"q": 2, "p": 1}], "d": 4});
+        assert_eq!(reordered(&left, &right), vec![vec!["b".to_string()], vec!["c".to_string(), "0".to_string()]]);
+        assert_eq!(reordered(&left, &json!({"b": {}, "a": 1})), vec![Vec::<String>::new()]);
+    }
+}
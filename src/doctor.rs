@@ -0,0 +1,173 @@
+use std::path::Path;
+use std::process::Command;
+
+use crate::quadlet::{quadlet_dir, quadlet_generator, QUADLET_GENERATORS};
+use crate::utils::{is_root, which};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// The outcome of one check, with what to do about it if it didn't pass
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &str, detail: impl Into<String>) -> Self {
+        Check { name: name.to_string(), status: Status::Ok, detail: detail.into(), fix: None }
+    }
+
+    fn problem(name: &str, status: Status, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Check { name: name.to_string(), status, detail: detail.into(), fix: Some(fix.into()) }
+    }
+}
+
+// Quadlets were added in podman 4.4
+const QUADLET_PODMAN: (u32, u32) = (4, 4);
+
+/// Check the tools and system configuration that systemd and quadlet modes depend on.
+pub fn run_checks() -> Vec<Check> {
+    let is_root = is_root();
+    let user = std::env::var("USER").unwrap_or_default();
+    let mut checks = vec![
+        tool("podlet", Status::Fail, "Install podlet from https://github.com/containers/podlet/releases"),
+        tool("podman", Status::Fail, "Install podman with your package manager"),
+        tool("systemctl", Status::Fail, "systemd is required to run the generated units"),
+        tool("systemd-analyze", Status::Warn, "Install systemd-analyze to verify generated units fully, rather than with slate's own checks"),
+        tool("skopeo", Status::Warn, "Install skopeo to look up fully qualified image names"),
+    ];
+
+    if let Some(version) = checks.iter().find(|c| c.name == "podman" && c.status == Status::Ok) {
+        if parse_version(&version.detail).is_some_and(|v| v < QUADLET_PODMAN) {
+            checks.push(Check::problem(
+                "podman version",
+                Status::Fail,
+                format!("{} is older than 4.4, which quadlets need", version.detail),
+                "Upgrade podman to 4.4 or later",
+            ));
+        }
+    }
+
+    checks.push(match quadlet_generator() {
+        Some(generator) if generator.exists() => Check::ok("quadlet generator", generator.display().to_string()),
+        Some(generator) => Check::problem(
+            "quadlet generator",
+            Status::Fail,
+            format!("{} not found", generator.display()),
+            "Point SLATE_QUADLET_GENERATOR at the generator, or unset it to search the usual places",
+        ),
+        None => Check::problem(
+            "quadlet generator",
+            Status::Fail,
+            format!("not found in {}", QUADLET_GENERATORS.join(", ")),
+            "Install podman 4.4 or later, which provides the quadlet generator, or set SLATE_QUADLET_GENERATOR to its path",
+        ),
+    });
+
+    if is_root {
+        checks.push(Check::ok("mode", "rootful, units are installed system-wide"));
+    } else {
+        checks.push(Check::ok("mode", format!("rootless, units are installed for user '{user}'")));
+        checks.push(subids(&user));
+        checks.push(if Path::new("/var/lib/systemd/linger").join(&user).exists() {
+            Check::ok("lingering", "enabled, services keep running after logout")
+        } else {
+            Check::problem(
+                "lingering",
+                Status::Warn,
+                "disabled, services stop when you log out and don't start at boot",
+                format!("loginctl enable-linger {user}"),
+            )
+        });
+    }
+
+    checks.push(match quadlet_dir(is_root) {
+        Ok(dir) if dir.is_dir() => Check::ok("quadlet directory", dir.display().to_string()),
+        Ok(dir) => Check::problem(
+            "quadlet directory",
+            Status::Warn,
+            format!("{} doesn't exist", dir.display()),
+            format!("mkdir -p {}", dir.display()),
+        ),
+        Err(e) => Check::problem("quadlet directory", Status::Fail, e.to_string(), "Set HOME"),
+    });
+
+    checks.push(selinux());
+    checks
+}
+
+fn tool(name: &str, missing: Status, fix: &str) -> Check {
+    if which(name).is_none() {
+        return Check::problem(name, missing, "not found on PATH", fix);
+    }
+    match Command::new(name).arg("--version").output() {
+        Ok(output) if output.status.success() => {
+            let version = String::from_utf8_lossy(&output.stdout);
+            Check::ok(name, version.lines().next().unwrap_or_default().trim())
+        }
+        _ => Check::problem(name, missing, "found, but `--version` failed", fix),
+    }
+}
+
+fn subids(user: &str) -> Check {
+    let configured = ["/etc/subuid", "/etc/subgid"]
+        .iter()
+        .all(|path| std::fs::read_to_string(path).is_ok_and(|contents| has_subids(&contents, user)));
+    if configured {
+        Check::ok("subordinate ids", format!("configured for '{user}'"))
+    } else {
+        Check::problem(
+            "subordinate ids",
+            Status::Fail,
+            format!("'{user}' has no entry in /etc/subuid or /etc/subgid, so rootless containers can't start"),
+            format!("sudo usermod --add-subuids 100000-165535 --add-subgids 100000-165535 {user}"),
+        )
+    }
+}
+
+fn selinux() -> Check {
+    match std::fs::read_to_string("/sys/fs/selinux/enforce").as_deref().map(str::trim) {
+        Ok("1") => Check::problem(
+            "selinux",
+            Status::Warn,
+            "enforcing",
+            "Add :Z (private) or :z (shared) to bind mounts so containers can read them",
+        ),
+        Ok(_) => Check::ok("selinux", "permissive"),
+        Err(_) => Check::ok("selinux", "not enabled"),
+    }
+}
+
+/// The major and minor version in output such as `podman version 4.9.3`
+pub fn parse_version(output: &str) -> Option<(u32, u32)> {
+    let version = output.split_whitespace().find(|word| word.starts_with(|c: char| c.is_ascii_digit()))?;
+    let mut parts = version.split('.').map(|part| part.parse().ok());
+    Some((parts.next()??, parts.next()??))
+}
+
+fn has_subids(contents: &str, user: &str) -> bool {
+    contents.lines().any(|line| line.split(':').next() == Some(user))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("podman version 4.9.3"), Some((4, 9)));
+        assert_eq!(parse_version("skopeo version 1.13.3 commit: abc"), Some((1, 13)));
+        assert_eq!(parse_version("podman version dev"), None);
+
+        assert!(has_subids("root:100000:65536\nalice:165536:65536\n", "alice"));
+        assert!(!has_subids("alicia:165536:65536\n", "alice"));
+    }
+}
@@ -0,0 +1,331 @@
+use std::{collections::HashMap, process::Command};
+
+use regex::Regex;
+use tera::{Error, Result, Tera, Value};
+
+use crate::systemd::escape_unit_name;
+
+/// Add slate's filters and functions to `tera`: `systemd_escape`, `duration`, `oncalendar`,
+/// `quote_exec` and `image_digest`
+pub fn register(tera: &mut Tera) {
+    tera.register_filter("systemd_escape", systemd_escape);
+    tera.register_filter("duration", duration);
+    tera.register_filter("oncalendar", oncalendar);
+    tera.register_filter("quote_exec", quote_exec);
+    tera.register_function("image_digest", image_digest);
+    tera.register_function("get_env", get_env);
+}
+
+fn string<'a>(value: &'a Value, filter: &str) -> Result<&'a str> {
+    value.as_str().ok_or_else(|| Error::msg(format!("{filter} only takes strings, not {value}")))
+}
+
+/// `{{ "my app" | systemd_escape }}` escapes a name for use in a unit name
+fn systemd_escape(value: &Value, _: &HashMap<String, Value>) -> Result<Value> {
+    Ok(Value::String(escape_unit_name(string(value, "systemd_escape")?)))
+}
+
+/// `{{ "1h 30min" | duration }}` is the number of seconds in a time span as systemd writes them
+fn duration(value: &Value, _: &HashMap<String, Value>) -> Result<Value> {
+    if let Some(seconds) = value.as_u64() {
+        return Ok(Value::from(seconds));
+    }
+    let span = string(value, "duration")?;
+    let seconds = parse_span(span).ok_or_else(|| Error::msg(format!("'{span}' isn't a time span like '1h 30min'")))?;
+    Ok(Value::from(seconds.round() as u64))
+}
+
+// Seconds in each unit systemd takes in a time span
+const SPAN_UNITS: &[(&[&str], f64)] = &[
+    (&["us", "usec"], 1e-6),
+    (&["ms", "msec"], 1e-3),
+    (&["", "s", "sec", "second", "seconds"], 1.0),
+    (&["m", "min", "minute", "minutes"], 60.0),
+    (&["h", "hr", "hour", "hours"], 3600.0),
+    (&["d", "day", "days"], 86400.0),
+    (&["w", "week", "weeks"], 604800.0),
+    (&["M", "month", "months"], 2629800.0),
+    (&["y", "year", "years"], 31557600.0),
+];
+
+/// The seconds in a time span like `1h 30min`, `90s` or `2 days`
+pub fn parse_span(span: &str) -> Option<f64> {
+    let part = Regex::new(r"^\s*(\d+(?:\.\d+)?)\s*([a-zA-Z]*)").unwrap();
+    let mut rest = span.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    let mut seconds = 0.0;
+    while !rest.is_empty() {
+        let captures = part.captures(rest)?;
+        let number: f64 = captures[1].parse().ok()?;
+        let unit = &captures[2];
+        let (_, scale) = SPAN_UNITS.iter().find(|(names, _)| names.contains(&unit))?;
+        seconds += number * scale;
+        rest = rest[captures[0].len()..].trim_start();
+    }
+    Some(seconds)
+}
+
+/// `{{ spec | oncalendar }}` passes an `OnCalendar=` expression through, failing the render on
+/// one systemd wouldn't parse
+fn oncalendar(value: &Value, _: &HashMap<String, Value>) -> Result<Value> {
+    let spec = string(value, "oncalendar")?;
+    if !is_calendar(spec) {
+        return Err(Error::msg(format!("'{spec}' isn't an OnCalendar= expression like 'Mon..Fri *-*-* 09:00'")));
+    }
+    Ok(value.clone())
+}
+
+const CALENDAR_SHORTHANDS: &[&str] = &[
+    "minutely", "hourly", "daily", "monthly", "weekly", "yearly", "annually", "quarterly", "semiannually",
+];
+
+/// Whether `spec` reads as `[weekdays] [date] [time] [timezone]`, or is a shorthand like `daily`
+pub fn is_calendar(spec: &str) -> bool {
+    if CALENDAR_SHORTHANDS.contains(&spec.trim()) {
+        return true;
+    }
+    // A value, range or `*`, repeating with `/step`, in a comma separated list
+    let field = r"(?:\*|\d+(?:\.\.\d+)?)(?:/\d+)?";
+    let list = format!("{field}(?:,{field})*");
+    let seconds = r"(?:\*|\d+(?:\.\d+)?(?:\.\.\d+(?:\.\d+)?)?)(?:/\d+(?:\.\d+)?)?";
+    let day = "(?:mon|tue|wed|thu|fri|sat|sun)[a-z]*";
+    let weekdays = Regex::new(&format!(r"(?i)^{day}(?:(?:,|\.\.){day})*$")).unwrap();
+    let date = Regex::new(&format!(r"^(?:(?:{list}-)?{list}[-~]{list}|{list}-{list})$")).unwrap();
+    let time = Regex::new(&format!(r"^{list}:{list}(?::{seconds}(?:,{seconds})*)?$")).unwrap();
+    let timezone = Regex::new(r"^(?:UTC|[A-Za-z_]+(?:/[A-Za-z_+\-0-9]+)*)$").unwrap();
+
+    let mut words: Vec<&str> = spec.split_whitespace().collect();
+    if words.first().is_some_and(|word| weekdays.is_match(word)) {
+        words.remove(0);
+    }
+    if words.len() > 1 && words.last().is_some_and(|word| timezone.is_match(word)) {
+        words.pop();
+    }
+    match words.as_slice() {
+        [] => spec.split_whitespace().next().is_some_and(|word| weekdays.is_match(word)),
+        [only] => date.is_match(only) || time.is_match(only),
+        [day, at] => date.is_match(day) && time.is_match(at),
+        _ => false,
+    }
+}
+
+/// `{{ args | quote_exec }}` quotes a string as one argument of an `ExecStart=`, or each string of
+/// a list, so whitespace, quotes, `%` specifiers and `$` variables are taken literally
+fn quote_exec(value: &Value, _: &HashMap<String, Value>) -> Result<Value> {
+    let quoted = match value {
+        Value::Array(args) => args
+            .iter()
+            .map(|arg| string(arg, "quote_exec").map(exec_arg))
+            .collect::<Result<Vec<String>>>()?
+            .join(" "),
+        value => exec_arg(string(value, "quote_exec")?),
+    };
+    Ok(Value::String(quoted))
+}
+
+fn exec_arg(arg: &str) -> String {
+    let literal = arg.replace('%', "%%").replace('$', "$$");
+    if !literal.is_empty() && !literal.contains(|c: char| c.is_whitespace() || "\"'\\;".contains(c)) {
+        return literal;
+    }
+    format!("\"{}\"", literal.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// `{{ image_digest(name="nginx:1.25") }}` is the digest the registry has for a tag, so an image
+/// can be pinned as `nginx@{{ image_digest(name="nginx:1.25") }}` when the template is rendered
+fn image_digest(args: &HashMap<String, Value>) -> Result<Value> {
+    let name = arg(args, "image_digest", "name")?;
+    let output = Command::new("skopeo")
+        .args(["inspect", "--format", "{{.Digest}}"])
+        .arg(format!("docker://{name}"))
+        .output()
+        .map_err(|e| Error::msg(format!("image_digest needs skopeo: {e}")))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::msg(format!("Couldn't look up the digest of {name}: {}", stderr.trim())));
+    }
+    Ok(Value::String(String::from_utf8_lossy(&output.stdout).trim().to_string()))
+}
+
+/// Tera's own `get_env(name, default)`, only reading the variables `--env-allow` lets through
+fn get_env(args: &HashMap<String, Value>) -> Result<Value> {
+    let name = arg(args, "get_env", "name")?;
+    match (crate::utils::env_var(name), args.get("default")) {
+        (Some(value), _) => Ok(Value::String(value)),
+        (None, Some(default)) => Ok(default.clone()),
+        (None, None) => Err(Error::msg(format!("Environment variable `{name}` not found, or not allowed by --env-allow"))),
+    }
+}
+
+/// Add the functions reading secrets at render time, which templates only get with `--secrets`:
+/// `pass(path)`, `keyring(service, user)` and `file(path)`
+pub fn register_secrets(tera: &mut Tera) {
+    tera.register_function("pass", pass);
+    tera.register_function("keyring", keyring);
+    tera.register_function("file", file);
+}
+
+fn arg<'a>(args: &'a HashMap<String, Value>, function: &str, name: &str) -> Result<&'a str> {
+    args.get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::msg(format!("{function} needs {name}=\"...\"")))
+}
+
+// The first line a secret store prints, which is the secret itself
+fn secret(command: &mut Command, what: &str) -> Result<Value> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let output = command.output().map_err(|e| Error::msg(format!("Reading {what} needs {program}: {e}")))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::msg(format!("Couldn't read {what}: {}", stderr.trim())));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(Value::String(stdout.lines().next().unwrap_or_default().to_string()))
+}
+
+/// `{{ pass(path="web/db") }}` is the password `pass` keeps at a path
+fn pass(args: &HashMap<String, Value>) -> Result<Value> {
+    let path = arg(args, "pass", "path")?;
+    secret(Command::new("pass").args(["show", path]), &format!("{path} from pass"))
+}
+
+/// `{{ keyring(service="app", user="admin") }}` is a password from the OS keyring, through
+/// secret-tool, or the security tool on macOS
+fn keyring(args: &HashMap<String, Value>) -> Result<Value> {
+    let service = arg(args, "keyring", "service")?;
+    let user = arg(args, "keyring", "user")?;
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("security");
+        command.args(["find-generic-password", "-s", service, "-a", user, "-w"]);
+        command
+    } else {
+        let mut command = Command::new("secret-tool");
+        command.args(["lookup", "service", service, "username", user]);
+        command
+    };
+    secret(&mut command, &format!("the password of {user} for {service} from the keyring"))
+}
+
+/// `{{ file(path="/run/secrets/token") }}` is the content of a file, without its final newline
+fn file(args: &HashMap<String, Value>) -> Result<Value> {
+    let path = arg(args, "file", "path")?;
+    let content = std::fs::read_to_string(path).map_err(|e| Error::msg(format!("Couldn't read {path}: {e}")))?;
+    Ok(Value::String(content.strip_suffix('\n').unwrap_or(&content).to_string()))
+}
+
+// Words of Tera's expressions rather than variables
+const KEYWORDS: &[&str] = &["and", "or", "not", "in", "true", "false", "True", "False", "loop"];
+
+/// Fail on a variable an `if` or `elif` condition uses that's neither in `context` nor set by the
+/// template, which Tera would take as false. Names tested with `is defined` are left alone.
+pub fn check_conditions(input: &str, context: &tera::Context) -> Result<()> {
+    let condition = Regex::new(r"\{%-?\s*(?:if|elif)\s+(.*?)\s*-?%\}").unwrap();
+    let strings = Regex::new(r#""[^"]*"|'[^']*'|`[^`]*`"#).unwrap();
+    // The prefix marks attributes, filters and tests, and the suffix calls, keyword arguments and
+    // tests of whether the name is defined
+    let name = Regex::new(r"(\.|\|\s*|\bis\s+(?:not\s+)?)?\b([A-Za-z_]\w*)(\s*\(|\s*=[^=]|\s+is\s+(?:not\s+)?(?:un)?defined\b)?").unwrap();
+    let local = Regex::new(r"\{%-?\s*(?:set|set_global)\s+(\w+)|\{%-?\s*for\s+(\w+)(?:\s*,\s*(\w+))?\s+in\b|\{%-?\s*macro\s+\w+\(([^)]*)\)").unwrap();
+
+    let mut locals: Vec<&str> = Vec::new();
+    for captures in local.captures_iter(input) {
+        locals.extend(captures.iter().skip(1).take(3).flatten().map(|m| m.as_str()));
+        if let Some(args) = captures.get(4) {
+            locals.extend(args.as_str().split(',').filter_map(|arg| arg.split('=').next()).map(str::trim));
+        }
+    }
+    for captures in condition.captures_iter(input) {
+        let expression = strings.replace_all(&captures[1], "\"\"");
+        for word in name.captures_iter(&expression) {
+            let variable = &word[2];
+            if word.get(1).is_some() || word.get(3).is_some() || KEYWORDS.contains(&variable) {
+                continue;
+            }
+            if !context.contains_key(variable) && !locals.contains(&variable) {
+                let line = input[..captures.get(0).unwrap().start()].matches('\n').count() + 1;
+                return Err(Error::msg(format!("line {line}: the condition `{}` uses `{variable}`, which isn't defined", &captures[1])));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Tera's render error, with the line of the undefined name it reports, which it leaves out
+pub fn locate_error(input: &str, error: &Error) -> Error {
+    let mut innermost: &dyn std::error::Error = error;
+    while let Some(source) = innermost.source() {
+        innermost = source;
+    }
+    let message = innermost.to_string();
+    let quoted = Regex::new(r"`([^`]+)`").unwrap();
+    let line = quoted
+        .captures(&message)
+        .and_then(|captures| input.lines().position(|line| line.contains(&captures[1])));
+    match line {
+        Some(line) => Error::msg(format!("line {}: {message}", line + 1)),
+        None => Error::msg(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Rendering;
+
+    fn render(template: &str) -> Result<String> {
+        crate::utils::render_template(template, &tera::Context::new(), Rendering::default())
+    }
+
+    #[test]
+    fn test_filters() {
+        assert_eq!(render("{{ 'my app' | systemd_escape }}").unwrap(), "my\\x20app");
+        assert_eq!(render("{{ '1h 30min' | duration }}").unwrap(), "5400");
+        assert_eq!(render("{{ '2 days' | duration }}").unwrap(), "172800");
+        assert!(render("{{ 'soon' | duration }}").is_err());
+
+        for spec in ["daily", "Mon..Fri *-*-* 09:00", "*-*-* 00,12:00:00", "*:0/15", "Sat 2024-*-01 04:30 UTC", "*-02~03"] {
+            assert!(is_calendar(spec), "{}", spec);
+        }
+        for spec in ["every day", "25:00:00:00", "Mon Tue"] {
+            assert!(!is_calendar(spec), "{}", spec);
+        }
+        assert!(render("{{ 'tomorrow' | oncalendar }}").is_err());
+
+        assert_eq!(
+            render(r#"{{ ["/usr/bin/echo", "hello world", "50%", "$HOME"] | quote_exec }}"#).unwrap(),
+            r#"/usr/bin/echo "hello world" 50%% $$HOME"#
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        let strict = Rendering { strict: true, ..Rendering::default() };
+        let mut context = tera::Context::new();
+        context.insert("domain", "example.com");
+
+        let template = "{% set port = 80 %}\n{% if domain and port > 0 %}ok{% endif %}\n{% if tls is defined %}{% endif %}";
+        assert_eq!(crate::utils::render_template(template, &context, strict).unwrap(), "\nok\n");
+
+        let template = "a: 1\n{% if tls_cert %}b: 2{% endif %}";
+        assert_eq!(crate::utils::render_template(template, &context, Rendering::default()).unwrap(), "a: 1\n");
+        let err = crate::utils::render_template(template, &context, strict).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: the condition `tls_cert` uses `tls_cert`, which isn't defined");
+
+        let err = crate::utils::render_template("a: 1\nb: {{ hostname }}", &context, strict).unwrap_err();
+        assert!(err.to_string().starts_with("line 2: Variable `hostname` not found"), "{}", err);
+    }
+
+    #[test]
+    fn test_secrets() {
+        let dir = tempfile::tempdir().unwrap();
+        let token = dir.path().join("token");
+        std::fs::write(&token, "s3cret\n").unwrap();
+        let template = format!("{{{{ file(path='{}') }}}}", token.display());
+
+        assert!(render(&template).is_err());
+        let rendering = Rendering { secrets: true, ..Rendering::default() };
+        let rendered = crate::utils::render_template(&template, &tera::Context::new(), rendering).unwrap();
+        assert_eq!(rendered, "s3cret");
+    }
+}
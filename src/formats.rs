@@ -1,9 +1,81 @@
+pub mod avro;
+pub mod bencode;
+pub mod csv;
+pub mod dupkeys;
+pub mod edn;
+pub mod encoding;
+pub mod env;
+pub mod form;
+pub mod framing;
+pub mod hcl;
+pub mod json;
+pub mod jsonschema;
+pub mod kdl;
+pub mod markdown;
+pub mod parquet;
+pub mod plist;
+pub mod properties;
+pub mod protobuf;
+pub mod rust;
+pub mod sql;
+pub mod sqlite;
+pub mod toml;
+pub mod typehint;
+pub mod xml;
+pub mod xlsx;
+pub mod yaml;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 use indexmap::IndexMap;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+/// How raw byte strings are shown in text formats, and read back when a schema expects bytes
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum BytesRepr {
+    #[default]
+    Base64,
+    Hex,
+    /// A list of integers
+    Array,
+}
+
+impl BytesRepr {
+    pub fn render(&self, bytes: &[u8]) -> serde_json::Value {
+        match self {
+            BytesRepr::Base64 => serde_json::Value::String(STANDARD.encode(bytes)),
+            BytesRepr::Hex => {
+                let mut s = String::with_capacity(bytes.len() * 2);
+                for b in bytes {
+                    write!(s, "{b:02x}").unwrap();
+                }
+                serde_json::Value::String(s)
+            }
+            BytesRepr::Array => serde_json::Value::Array(bytes.iter().map(|b| (*b).into()).collect()),
+        }
+    }
+
+    /// The inverse of [`render`](Self::render), or None if `value` isn't in this representation.
+    pub fn parse(&self, value: &serde_json::Value) -> Option<Vec<u8>> {
+        match (self, value) {
+            (BytesRepr::Base64, serde_json::Value::String(s)) => STANDARD.decode(s).ok(),
+            (BytesRepr::Hex, serde_json::Value::String(s)) if s.len() % 2 == 0 => (0..s.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+                .collect(),
+            (BytesRepr::Array, serde_json::Value::Array(items)) => {
+                items.iter().map(|b| b.as_u64().and_then(|b| u8::try_from(b).ok())).collect()
+            }
+            _ => None,
+        }
+    }
+}
 
 pub type Section = IndexMap<String, String>;
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(transparent)] // Allows UnitFile to be treated as IndexMap for serde
 pub struct Ini(pub IndexMap<String, Section>);
 
@@ -0,0 +1,65 @@
+use anyhow::{Context, Result};
+use apache_avro::{Reader, Schema, Writer};
+use serde_json::Value;
+use std::convert::TryFrom;
+use std::path::Path;
+
+use super::json::PlainNumbers;
+
+/// Decode an Avro object container file, using its embedded schema, into a list of records.
+pub fn from_bytes(input: &[u8]) -> Result<Value> {
+    let reader = Reader::new(input).context("Input is not an Avro object container file")?;
+    let mut records = Vec::new();
+    for value in reader {
+        records.push(Value::try_from(value?)?);
+    }
+    Ok(Value::Array(records))
+}
+
+/// Write `value` (a list of records, or a single record) as an object container file.
+pub fn to_vec(value: Value, schema: &Path) -> Result<Vec<u8>> {
+    let raw = String::from_utf8(crate::archive::read(schema)?)
+        .with_context(|| format!("Failed to read Avro schema: {}", schema.display()))?;
+    let schema = Schema::parse_str(&raw)
+        .with_context(|| format!("Invalid Avro schema: {}", schema.display()))?;
+
+    let records = match value {
+        Value::Array(records) => records,
+        record => vec![record],
+    };
+
+    let mut writer = Writer::new(&schema, Vec::new());
+    for (i, record) in records.into_iter().enumerate() {
+        // Resolving narrows JSON's numbers to the widths the schema gives them
+        let append = |writer: &mut Writer<_>| -> Result<()> {
+            writer.append(apache_avro::to_value(PlainNumbers(&record))?.resolve(&schema)?)?;
+            Ok(())
+        };
+        append(&mut writer).with_context(|| format!("Record {i} does not match the Avro schema"))?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::Write;
+
+    #[test]
+    fn test_round_trip() {
+        let mut schema = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            schema,
+            r#"{{"type": "record", "name": "User", "fields": [
+                {{"name": "name", "type": "string"}},
+                {{"name": "age", "type": "int"}}
+            ]}}"#
+        )
+        .unwrap();
+
+        let records = json!([{"name": "alice", "age": 30}, {"name": "bob", "age": 25}]);
+        let bytes = to_vec(records.clone(), schema.path()).unwrap();
+        assert_eq!(from_bytes(&bytes).unwrap(), records);
+    }
+}
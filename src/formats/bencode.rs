@@ -0,0 +1,138 @@
+use crate::formats::BytesRepr;
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Value};
+
+/// How deep lists and dictionaries may nest, as each level is a recursive call
+const MAX_DEPTH: usize = 256;
+
+/// Decode a bencoded value (as found in .torrent files).
+///
+/// Byte strings that are valid UTF-8 become strings; anything else, such as the `pieces` hash
+/// list, is rendered according to `bytes`.
+pub fn from_bytes(input: &[u8], bytes: BytesRepr) -> Result<Value> {
+    let mut decoder = Decoder { input, pos: 0, depth: 0, bytes };
+    let value = decoder.value()?;
+    if decoder.pos != input.len() {
+        bail!("Trailing data after bencoded value at byte {}", decoder.pos);
+    }
+    Ok(value)
+}
+
+struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+    depth: usize,
+    bytes: BytesRepr,
+}
+
+impl<'a> Decoder<'a> {
+    fn peek(&self) -> Result<u8> {
+        self.input
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| anyhow!("Unexpected end of bencoded input"))
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek()? {
+            b'i' => {
+                self.pos += 1;
+                let digits = self.until(b'e')?;
+                let n: i64 = std::str::from_utf8(digits)?
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid bencode integer at byte {}", self.pos))?;
+                Ok(Value::Number(n.into()))
+            }
+            b'l' => {
+                self.nest()?;
+                let mut items = Vec::new();
+                while self.peek()? != b'e' {
+                    items.push(self.value()?);
+                }
+                self.unnest();
+                Ok(Value::Array(items))
+            }
+            b'd' => {
+                self.nest()?;
+                let mut map = Map::new();
+                while self.peek()? != b'e' {
+                    let key = String::from_utf8_lossy(self.string()?).into_owned();
+                    let value = self.value()?;
+                    map.insert(key, value);
+                }
+                self.unnest();
+                Ok(Value::Object(map))
+            }
+            b'0'..=b'9' => {
+                let raw = self.string()?;
+                Ok(match std::str::from_utf8(raw) {
+                    Ok(s) => Value::String(s.to_string()),
+                    Err(_) => self.bytes.render(raw),
+                })
+            }
+            other => bail!("Unexpected byte '{}' at {}", other as char, self.pos),
+        }
+    }
+
+    // Step into a list or dictionary, refusing to go deeper than MAX_DEPTH
+    fn nest(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            bail!("Bencoded input nests deeper than {MAX_DEPTH} levels at byte {}", self.pos);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    // Step past the `e` closing a list or dictionary
+    fn unnest(&mut self) {
+        self.depth -= 1;
+        self.pos += 1;
+    }
+
+    fn until(&mut self, end: u8) -> Result<&'a [u8]> {
+        let input = self.input;
+        let start = self.pos;
+        let len = input[start..]
+            .iter()
+            .position(|b| *b == end)
+            .ok_or_else(|| anyhow!("Unterminated bencode value at byte {start}"))?;
+        self.pos = start + len + 1;
+        Ok(&input[start..start + len])
+    }
+
+    fn string(&mut self) -> Result<&'a [u8]> {
+        let len: usize = std::str::from_utf8(self.until(b':')?)?
+            .parse()
+            .map_err(|_| anyhow!("Invalid bencode string length at byte {}", self.pos))?;
+        let input = self.input;
+        let raw = input
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| anyhow!("Bencode string runs past the end of input"))?;
+        self.pos += len;
+        Ok(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_bytes() {
+        let input = b"d8:announce14:http://tracker4:infod6:lengthi1024e4:name5:a.txt6:pieces2:\xff\x00e4:listli-3e1:xee";
+        assert_eq!(
+            from_bytes(input, BytesRepr::Hex).unwrap(),
+            json!({
+                "announce": "http://tracker",
+                "info": {"length": 1024, "name": "a.txt", "pieces": "ff00"},
+                "list": [-3, "x"]
+            })
+        );
+        assert!(from_bytes(b"l1:a", BytesRepr::Hex).is_err());
+
+        let deep = [vec![b'l'; 100_000], vec![b'e'; 100_000]].concat();
+        assert!(from_bytes(&deep, BytesRepr::Hex).unwrap_err().to_string().starts_with("Bencoded input nests deeper than 256 levels"));
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+
+/// Rows of string cells under a header, as shared by the tabular outputs.
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Build a table from a list of maps, or a single map as one row.
+    ///
+    /// Columns come from `columns` if given, otherwise from the keys of every row in the order they
+    /// first appear. With `flatten`, nested maps and lists become dotted column names like
+    /// `db.host` or `ports.0`; without it they are an error.
+    pub fn new(value: &Value, columns: Option<&[String]>, flatten: bool) -> Result<Self> {
+        let items = match value {
+            Value::Array(items) => items.as_slice(),
+            item => std::slice::from_ref(item),
+        };
+        let mut records = Vec::with_capacity(items.len());
+        for (i, item) in items.iter().enumerate() {
+            let Value::Object(map) = item else {
+                bail!("Tabular output requires a list of maps, but item {i} is not a map");
+            };
+            let mut record = Map::new();
+            for (key, val) in map {
+                if flatten {
+                    flatten_into(key, val, &mut record);
+                } else if val.is_object() || val.is_array() {
+                    bail!("Column '{key}' holds a nested value, use --flatten to split it into columns");
+                } else {
+                    record.insert(key.clone(), val.clone());
+                }
+            }
+            records.push(record);
+        }
+
+        let columns = match columns {
+            Some(columns) => columns.to_vec(),
+            None => {
+                let mut columns: Vec<String> = Vec::new();
+                for record in &records {
+                    for key in record.keys() {
+                        if !columns.contains(key) {
+                            columns.push(key.clone());
+                        }
+                    }
+                }
+                columns
+            }
+        };
+        let rows = records
+            .iter()
+            .map(|record| columns.iter().map(|c| cell(record.get(c))).collect())
+            .collect();
+        Ok(Table { columns, rows })
+    }
+}
+
+fn flatten_into(prefix: &str, value: &Value, out: &mut Map<String, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, val) in map {
+                flatten_into(&format!("{prefix}.{key}"), val, out);
+            }
+        }
+        Value::Array(seq) => {
+            for (i, item) in seq.iter().enumerate() {
+                flatten_into(&format!("{prefix}.{i}"), item, out);
+            }
+        }
+        scalar => {
+            out.insert(prefix.to_string(), scalar.clone());
+        }
+    }
+}
+
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+/// Write delimited text, quoting cells only where needed.
+pub fn to_vec(table: &Table, delimiter: u8) -> Result<Vec<u8>> {
+    let mut writer = ::csv::WriterBuilder::new().delimiter(delimiter).from_writer(Vec::new());
+    writer.write_record(&table.columns)?;
+    for row in &table.rows {
+        writer.write_record(row)?;
+    }
+    Ok(writer.into_inner().map_err(|e| e.into_error())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_vec() {
+        let value = json!([
+            {"name": "web", "port": 80, "db": {"host": "x"}},
+            {"name": "a, b", "extra": true, "port": null}
+        ]);
+        assert!(Table::new(&value, None, false).is_err());
+
+        let table = Table::new(&value, None, true).unwrap();
+        assert_eq!(table.columns, vec!["name", "port", "db.host", "extra"]);
+        assert_eq!(
+            String::from_utf8(to_vec(&table, b',').unwrap()).unwrap(),
+            "name,port,db.host,extra\nweb,80,x,\n\"a, b\",,,true\n"
+        );
+
+        let columns = vec!["port".to_string(), "name".to_string()];
+        let table = Table::new(&value, Some(&columns), true).unwrap();
+        assert_eq!(
+            String::from_utf8(to_vec(&table, b'\t').unwrap()).unwrap(),
+            "port\tname\n80\tweb\n\ta, b\n"
+        );
+    }
+}
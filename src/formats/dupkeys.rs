@@ -0,0 +1,196 @@
+use clap::ValueEnum;
+use serde::de::{self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde_json::{Map, Number, Value};
+use std::collections::HashSet;
+use std::fmt;
+
+/// What to do when a map in the input repeats a key
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum DupKeys {
+    /// Reject the input
+    #[default]
+    Error,
+    /// Keep the first value
+    First,
+    /// Keep the last value
+    Last,
+    /// Collect every value into a list
+    Append,
+}
+
+// The key serde_json numbers deserialize as, to keep their exact digits
+const NUMBER_TOKEN: &str = "$serde_json::private::Number";
+
+/// Deserializes any self-describing input into a [`Value`], resolving repeated keys per the policy.
+#[derive(Clone, Copy)]
+pub struct DupSeed(pub DupKeys);
+
+impl<'de> DeserializeSeed<'de> for DupSeed {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        d.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for DupSeed {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Number::from_f64(v).map_or(Value::Null, Value::Number))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        self.deserialize(d)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        self.deserialize(d)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element_seed(self)? {
+            items.push(item);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Value, A::Error> {
+        let mut map = Map::new();
+        // Keys whose values have been collected into a list by Append
+        let mut appended = HashSet::new();
+        while let Some(key) = access.next_key_seed(KeySeed)? {
+            if key == NUMBER_TOKEN && map.is_empty() {
+                let digits: String = access.next_value()?;
+                return digits.parse().map(Value::Number).map_err(de::Error::custom);
+            }
+            let value = access.next_value_seed(self)?;
+            let Some(existing) = map.get_mut(&key) else {
+                map.insert(key, value);
+                continue;
+            };
+            match self.0 {
+                DupKeys::Error => return Err(de::Error::custom(format!("duplicate key '{key}' (see --dup-keys)"))),
+                DupKeys::First => {}
+                DupKeys::Last => *existing = value,
+                DupKeys::Append => {
+                    if appended.insert(key) {
+                        *existing = Value::Array(vec![existing.take(), value]);
+                    } else if let Value::Array(items) = existing {
+                        items.push(value);
+                    }
+                }
+            }
+        }
+        Ok(Value::Object(map))
+    }
+
+    // Tagged values, such as YAML's `!Tag value`, become a single-key map
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Value, A::Error> {
+        let (tag, variant) = data.variant_seed(KeySeed)?;
+        let mut map = Map::new();
+        map.insert(tag, variant.newtype_variant_seed(self)?);
+        Ok(Value::Object(map))
+    }
+}
+
+// Map keys as strings, whatever scalar they are written as
+struct KeySeed;
+
+impl<'de> DeserializeSeed<'de> for KeySeed {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<String, D::Error> {
+        d.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for KeySeed {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a scalar map key")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<String, E> {
+        Ok(v)
+    }
+
+    fn visit_unit<E>(self) -> Result<String, E> {
+        Ok("null".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(input: &str, policy: DupKeys) -> Result<Value, serde_json::Error> {
+        DupSeed(policy).deserialize(&mut serde_json::Deserializer::from_str(input))
+    }
+
+    #[test]
+    fn test_dup_keys() {
+        let input = r#"{"a": 1, "b": {"c": 1.50}, "a": 2, "a": 3}"#;
+        assert_eq!(json(input, DupKeys::First).unwrap().to_string(), r#"{"a":1,"b":{"c":1.50}}"#);
+        assert_eq!(json(input, DupKeys::Last).unwrap().to_string(), r#"{"a":3,"b":{"c":1.50}}"#);
+        assert_eq!(json(input, DupKeys::Append).unwrap().to_string(), r#"{"a":[1,2,3],"b":{"c":1.50}}"#);
+        assert!(json(input, DupKeys::Error).unwrap_err().to_string().starts_with("duplicate key 'a'"));
+
+        let yaml = DupSeed(DupKeys::Last).deserialize(serde_yaml::Deserializer::from_str("1: x\n1: y\n")).unwrap();
+        assert_eq!(yaml.to_string(), r#"{"1":"y"}"#);
+    }
+}
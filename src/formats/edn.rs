@@ -0,0 +1,293 @@
+//! EDN (extensible data notation) input.
+//!
+//! Keywords and symbols become strings (`:port` reads as `"port"`), lists, vectors and sets all
+//! become sequences, characters become one-character strings and non-string map keys are printed
+//! back to EDN-ish text. Tagged literals such as `#inst "..."` are handled per [`TagMode`].
+
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use serde_json::{Map, Number, Value};
+use std::{iter::Peekable, str::Chars};
+
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum TagMode {
+    /// Drop the tag and keep the tagged value
+    #[default]
+    Strip,
+    /// Keep the tag as a single-entry map, e.g. `{"#inst": "2020-01-01"}`
+    Wrap,
+    /// Refuse input containing tagged literals
+    Error,
+}
+
+pub fn from_str(input: &str, tags: TagMode) -> Result<Value> {
+    let mut parser = Parser { chars: input.chars().peekable(), tags };
+    let value = parser.value()?.ok_or_else(|| anyhow!("EDN input is empty"))?;
+    parser.skip_ws()?;
+    if let Some(c) = parser.chars.peek() {
+        bail!("Unexpected '{c}' after the end of the EDN value");
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    tags: TagMode,
+}
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || c == ',' || "()[]{}\";".contains(c)
+}
+
+impl Parser<'_> {
+    fn skip_ws(&mut self) -> Result<()> {
+        loop {
+            match self.chars.peek() {
+                Some(c) if c.is_whitespace() || *c == ',' => {
+                    self.chars.next();
+                }
+                Some(';') => while self.chars.next_if(|c| *c != '\n').is_some() {},
+                Some('#') => {
+                    let mut ahead = self.chars.clone();
+                    ahead.next();
+                    if ahead.peek() != Some(&'_') {
+                        return Ok(());
+                    }
+                    self.chars.next();
+                    self.chars.next();
+                    // #_ discards the next form
+                    self.value()?;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    // None at a closing delimiter or the end of input
+    fn value(&mut self) -> Result<Option<Value>> {
+        self.skip_ws()?;
+        let Some(&c) = self.chars.peek() else {
+            return Ok(None);
+        };
+        let value = match c {
+            ')' | ']' | '}' => return Ok(None),
+            '(' => {
+                self.chars.next();
+                Value::Array(self.seq(')')?)
+            }
+            '[' => {
+                self.chars.next();
+                Value::Array(self.seq(']')?)
+            }
+            '{' => {
+                self.chars.next();
+                self.map()?
+            }
+            '"' => {
+                self.chars.next();
+                Value::String(self.string()?)
+            }
+            '\\' => {
+                self.chars.next();
+                Value::String(self.character()?)
+            }
+            '#' => {
+                self.chars.next();
+                self.dispatch()?
+            }
+            ':' => {
+                self.chars.next();
+                Value::String(self.token())
+            }
+            _ => self.atom()?,
+        };
+        Ok(Some(value))
+    }
+
+    fn seq(&mut self, close: char) -> Result<Vec<Value>> {
+        let mut items = Vec::new();
+        while let Some(item) = self.value()? {
+            items.push(item);
+        }
+        match self.chars.next() {
+            Some(c) if c == close => Ok(items),
+            Some(c) => bail!("Expected '{close}' but found '{c}'"),
+            None => bail!("Unterminated collection, expected '{close}'"),
+        }
+    }
+
+    fn map(&mut self) -> Result<Value> {
+        let items = self.seq('}')?;
+        if items.len() % 2 != 0 {
+            bail!("EDN map has a key without a value");
+        }
+        let mut map = Map::new();
+        let mut items = items.into_iter();
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            let key = match key {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            map.insert(key, value);
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn dispatch(&mut self) -> Result<Value> {
+        if self.chars.next_if_eq(&'{').is_some() {
+            return Ok(Value::Array(self.seq('}')?));
+        }
+        let tag = self.token();
+        if tag.is_empty() {
+            bail!("Unsupported dispatch character after '#'");
+        }
+        let value = self
+            .value()?
+            .ok_or_else(|| anyhow!("Tag #{tag} is missing its value"))?;
+        match self.tags {
+            TagMode::Strip => Ok(value),
+            TagMode::Wrap => {
+                let mut map = Map::new();
+                map.insert(format!("#{tag}"), value);
+                Ok(Value::Object(map))
+            }
+            TagMode::Error => bail!("Tagged literal #{tag} found, see --edn-tags"),
+        }
+    }
+
+    fn token(&mut self) -> String {
+        let mut token = String::new();
+        while let Some(c) = self.chars.next_if(|c| !is_delimiter(*c)) {
+            token.push(c);
+        }
+        token
+    }
+
+    fn atom(&mut self) -> Result<Value> {
+        let token = self.token();
+        if token.is_empty() {
+            bail!("Unexpected character in EDN input");
+        }
+        Ok(match token.as_str() {
+            "nil" => Value::Null,
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            t if t.starts_with(|c: char| c.is_ascii_digit())
+                || (t.len() > 1 && t.starts_with(['+', '-']) && t[1..].starts_with(|c: char| c.is_ascii_digit())) =>
+            {
+                number(t)?
+            }
+            // Symbols
+            t => Value::String(t.to_string()),
+        })
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.chars.next() {
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some('n') => s.push('\n'),
+                    Some('u') => s.push(self.unicode()?),
+                    Some(c) => s.push(c),
+                    None => break,
+                },
+                Some(c) => s.push(c),
+                None => break,
+            }
+        }
+        bail!("Unterminated EDN string")
+    }
+
+    fn character(&mut self) -> Result<String> {
+        let first = self.chars.next().ok_or_else(|| anyhow!("Incomplete character literal"))?;
+        let mut name = first.to_string();
+        name.push_str(&self.token());
+        Ok(match name.as_str() {
+            "newline" => "\n".to_string(),
+            "return" => "\r".to_string(),
+            "space" => " ".to_string(),
+            "tab" => "\t".to_string(),
+            n if n.len() == 5 && n.starts_with('u') => {
+                let code = u32::from_str_radix(&n[1..], 16)?;
+                char::from_u32(code).unwrap_or('\u{fffd}').to_string()
+            }
+            _ => name,
+        })
+    }
+
+    fn unicode(&mut self) -> Result<char> {
+        let hex: String = self.chars.by_ref().take(4).collect();
+        let code = u32::from_str_radix(&hex, 16)?;
+        Ok(char::from_u32(code).unwrap_or('\u{fffd}'))
+    }
+}
+
+fn number(token: &str) -> Result<Value> {
+    let invalid = || anyhow!("Invalid EDN number '{token}'");
+    // Arbitrary precision markers: N for integers, M for decimals
+    let t = token.trim_end_matches(['N', 'M']);
+    if let Ok(i) = t.parse::<i64>() {
+        return Ok(Value::Number(i.into()));
+    }
+    if let Ok(u) = t.parse::<u64>() {
+        return Ok(Value::Number(u.into()));
+    }
+    if let Some((num, den)) = t.split_once('/') {
+        let ratio = num.parse::<f64>().map_err(|_| invalid())? / den.parse::<f64>().map_err(|_| invalid())?;
+        return Number::from_f64(ratio).map(Value::Number).ok_or_else(invalid);
+    }
+    let f = t.parse::<f64>().map_err(|_| invalid())?;
+    Number::from_f64(f).map(Value::Number).ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_str() {
+        let input = r#"
+; service config
+{:name "app"
+ :ports [80 443]
+ :tags #{:web :public}
+ :ratio 1/2
+ :big 12N
+ :enabled true, :parent nil
+ :started #inst "2020-01-01T00:00:00Z"
+ #_ :ignored #_ "this"
+ :initial \a
+ 1 (+ 1 2)}
+"#;
+        assert_eq!(
+            from_str(input, TagMode::Strip).unwrap(),
+            json!({
+                "name": "app",
+                "ports": [80, 443],
+                "tags": ["web", "public"],
+                "ratio": 0.5,
+                "big": 12,
+                "enabled": true,
+                "parent": null,
+                "started": "2020-01-01T00:00:00Z",
+                "initial": "a",
+                "1": ["+", 1, 2]
+            })
+        );
+    }
+
+    #[test]
+    fn test_tag_modes() {
+        let input = r#"[#uuid "f81d4fae-7dec-11d0-a765-00a0c91e6bf6"]"#;
+        assert_eq!(
+            from_str(input, TagMode::Wrap).unwrap(),
+            json!([{"#uuid": "f81d4fae-7dec-11d0-a765-00a0c91e6bf6"}])
+        );
+        assert!(from_str(input, TagMode::Error).is_err());
+    }
+}
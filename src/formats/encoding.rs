@@ -0,0 +1,69 @@
+use anyhow::{anyhow, bail, Result};
+
+/// Text encodings recognized in input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+/// Work out the encoding of text input from its byte order mark, or for UTF-16 without one, from
+/// the zero bytes around a leading ASCII character. Returns the encoding and the length of the BOM.
+pub fn detect(input: &[u8]) -> (Encoding, usize) {
+    match input {
+        [0xef, 0xbb, 0xbf, ..] => (Encoding::Utf8, 3),
+        [0xff, 0xfe, ..] => (Encoding::Utf16Le, 2),
+        [0xfe, 0xff, ..] => (Encoding::Utf16Be, 2),
+        [a, 0, ..] if a.is_ascii() && *a != 0 => (Encoding::Utf16Le, 0),
+        [0, b, ..] if b.is_ascii() && *b != 0 => (Encoding::Utf16Be, 0),
+        _ => (Encoding::Utf8, 0),
+    }
+}
+
+/// Transcode text input to UTF-8 without a BOM, checking that it is valid.
+pub fn to_utf8(input: Vec<u8>) -> Result<Vec<u8>> {
+    let (encoding, bom) = detect(&input);
+    let body = &input[bom..];
+    match encoding {
+        Encoding::Utf8 => {
+            if let Err(e) = std::str::from_utf8(body) {
+                bail!("Input is not valid UTF-8 at byte {}", bom + e.valid_up_to());
+            }
+            let mut input = input;
+            input.drain(..bom);
+            Ok(input)
+        }
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            if !body.len().is_multiple_of(2) {
+                bail!("{encoding:?} input has an odd number of bytes");
+            }
+            let units: Vec<u16> = body
+                .chunks_exact(2)
+                .map(|pair| match encoding {
+                    Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                })
+                .collect();
+            let text = String::from_utf16(&units).map_err(|_| anyhow!("Input is not valid {encoding:?}"))?;
+            Ok(text.into_bytes())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_utf8() {
+        assert_eq!(to_utf8(b"\xef\xbb\xbf{}".to_vec()).unwrap(), b"{}");
+        assert_eq!(to_utf8(b"\xff\xfe{\0}\0".to_vec()).unwrap(), b"{}");
+        assert_eq!(to_utf8(b"\xfe\xff\0{\0}".to_vec()).unwrap(), b"{}");
+        assert_eq!(to_utf8(b"a\0=\0\xe9\0".to_vec()).unwrap(), "a=é".as_bytes());
+        assert_eq!(to_utf8("a: é".as_bytes().to_vec()).unwrap(), "a: é".as_bytes());
+
+        let err = to_utf8(b"a: \xe9".to_vec()).unwrap_err();
+        assert_eq!(err.to_string(), "Input is not valid UTF-8 at byte 3");
+    }
+}
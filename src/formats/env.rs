@@ -0,0 +1,257 @@
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+use std::{iter::Peekable, str::Chars};
+
+/// Flatten a map into `KEY=value` lines, joining nested keys and list indices with `_`.
+///
+/// Values are double-quoted whenever they contain anything beyond a conservative set of
+/// shell-safe characters, which is the quoting both dotenv loaders and systemd's
+/// `EnvironmentFile=` understand.
+pub fn to_string(value: &Value) -> Result<String> {
+    let Value::Object(map) = value else {
+        bail!("Env output requires a map at the top level");
+    };
+
+    let mut out = String::new();
+    for (key, val) in map {
+        flatten(&sanitize_key(key), val, &mut out);
+    }
+    Ok(out)
+}
+
+fn flatten(prefix: &str, value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            for (key, val) in map {
+                flatten(&format!("{prefix}_{}", sanitize_key(key)), val, out);
+            }
+        }
+        Value::Array(seq) => {
+            for (i, item) in seq.iter().enumerate() {
+                flatten(&format!("{prefix}_{i}"), item, out);
+            }
+        }
+        Value::Null => {
+            out.push_str(prefix);
+            out.push_str("=\n");
+        }
+        Value::String(s) => {
+            out.push_str(prefix);
+            out.push('=');
+            out.push_str(&quote(s));
+            out.push('\n');
+        }
+        other => {
+            out.push_str(prefix);
+            out.push('=');
+            out.push_str(&other.to_string());
+            out.push('\n');
+        }
+    }
+}
+
+fn sanitize_key(key: &str) -> String {
+    key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect()
+}
+
+fn quote(s: &str) -> String {
+    if !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./:@%+,".contains(c))
+    {
+        return s.to_string();
+    }
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+    out
+}
+
+/// Parse a dotenv file into a flat map of strings.
+///
+/// Supports `#` comments, an optional `export ` prefix, and single- or double-quoted values that
+/// may span several lines. Double-quoted values understand the usual backslash escapes.
+pub fn from_str(input: &str) -> Result<Value> {
+    let mut map = Map::new();
+    let mut chars = input.chars().peekable();
+    let mut line_no = 1;
+
+    loop {
+        skip_blank(&mut chars, &mut line_no);
+        let Some(&c) = chars.peek() else { break };
+        if c == '#' {
+            skip_line(&mut chars);
+            continue;
+        }
+
+        let mut key = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == '=' || c == '\n' {
+                break;
+            }
+            key.push(c);
+            chars.next();
+        }
+        if chars.next() != Some('=') {
+            bail!("Expected KEY=value on line {line_no}, found '{}'", key.trim());
+        }
+        let key = key.trim();
+        let key = key.strip_prefix("export ").unwrap_or(key).trim();
+        if key.is_empty() {
+            bail!("Missing key on line {line_no}");
+        }
+
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        let value = match chars.peek() {
+            Some('"') => {
+                chars.next();
+                let value = double_quoted(&mut chars, &mut line_no)?;
+                skip_line(&mut chars);
+                value
+            }
+            Some('\'') => {
+                chars.next();
+                let value = single_quoted(&mut chars, &mut line_no)?;
+                skip_line(&mut chars);
+                value
+            }
+            _ => unquoted(&mut chars),
+        };
+        map.insert(key.to_string(), Value::String(value));
+    }
+    Ok(Value::Object(map))
+}
+
+fn skip_blank(chars: &mut Peekable<Chars>, line_no: &mut usize) {
+    while let Some(c) = chars.next_if(|c| c.is_whitespace()) {
+        if c == '\n' {
+            *line_no += 1;
+        }
+    }
+}
+
+// Consumes the rest of the current line, leaving the newline for skip_blank to count
+fn skip_line(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| *c != '\n').is_some() {}
+}
+
+fn unquoted(chars: &mut Peekable<Chars>) -> String {
+    let mut value = String::new();
+    while let Some(c) = chars.next_if(|c| *c != '\n') {
+        // An inline comment needs whitespace before the '#'
+        if c == '#' && (value.is_empty() || value.ends_with([' ', '\t'])) {
+            skip_line(chars);
+            break;
+        }
+        value.push(c);
+    }
+    value.trim_end().to_string()
+}
+
+fn double_quoted(chars: &mut Peekable<Chars>, line_no: &mut usize) -> Result<String> {
+    let start = *line_no;
+    let mut value = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Ok(value),
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('r') => value.push('\r'),
+                // Line continuation
+                Some('\n') => *line_no += 1,
+                Some(c @ ('"' | '\\' | '$' | '`')) => value.push(c),
+                Some(c) => {
+                    value.push('\\');
+                    value.push(c);
+                }
+                None => break,
+            },
+            '\n' => {
+                *line_no += 1;
+                value.push(c);
+            }
+            c => value.push(c),
+        }
+    }
+    bail!("Unterminated double-quoted value starting on line {start}")
+}
+
+fn single_quoted(chars: &mut Peekable<Chars>, line_no: &mut usize) -> Result<String> {
+    let start = *line_no;
+    let mut value = String::new();
+    for c in chars.by_ref() {
+        match c {
+            '\'' => return Ok(value),
+            '\n' => {
+                *line_no += 1;
+                value.push(c);
+            }
+            c => value.push(c),
+        }
+    }
+    bail!("Unterminated single-quoted value starting on line {start}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_string() {
+        let value = json!({
+            "db": {"host": "localhost", "port": 5432},
+            "hosts": ["a", "b"],
+            "greeting": "hello $USER \"x\"",
+            "empty": null,
+            "my-key": true
+        });
+        assert_eq!(
+            to_string(&value).unwrap(),
+            "db_host=localhost\ndb_port=5432\nhosts_0=a\nhosts_1=b\ngreeting=\"hello \\$USER \\\"x\\\"\"\nempty=\nmy_key=true\n"
+        );
+        assert!(to_string(&json!([1])).is_err());
+    }
+
+    #[test]
+    fn test_from_str() {
+        let input = r#"
+# database settings
+export DB_HOST=localhost # the host
+DB_PASS='p#ss word'
+GREETING="hello \$USER\nbye"
+MULTI="a
+b"
+URL=http://x/#anchor
+EMPTY=
+"#;
+        assert_eq!(
+            from_str(input).unwrap(),
+            json!({
+                "DB_HOST": "localhost",
+                "DB_PASS": "p#ss word",
+                "GREETING": "hello $USER\nbye",
+                "MULTI": "a\nb",
+                "URL": "http://x/#anchor",
+                "EMPTY": ""
+            })
+        );
+        assert!(from_str("NOPE\n").is_err());
+        assert!(from_str("A=\"open\n").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = json!({"A": "plain", "B": "with \"quotes\" and $VARS", "C": "two\nlines"});
+        assert_eq!(from_str(&to_string(&value).unwrap()).unwrap(), value);
+    }
+}
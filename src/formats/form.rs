@@ -0,0 +1,74 @@
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+
+/// Parse `application/x-www-form-urlencoded` data into a flat map. Repeated keys become lists.
+pub fn from_bytes(input: &[u8]) -> Result<Value> {
+    let pairs: Vec<(String, String)> = serde_urlencoded::from_bytes(input)?;
+    let mut map = Map::new();
+    for (key, value) in pairs {
+        let value = Value::String(value);
+        match map.get_mut(&key) {
+            Some(Value::Array(seq)) => seq.push(value),
+            Some(existing) => {
+                let first = existing.take();
+                *existing = Value::Array(vec![first, value]);
+            }
+            None => {
+                map.insert(key, value);
+            }
+        }
+    }
+    Ok(Value::Object(map))
+}
+
+pub fn to_string(value: &Value) -> Result<String> {
+    let Value::Object(map) = value else {
+        bail!("Form encoding requires a map at the top level");
+    };
+
+    let mut pairs: Vec<(&str, String)> = Vec::new();
+    for (key, val) in map {
+        match val {
+            Value::Array(seq) if seq.iter().all(is_scalar) => {
+                for item in seq {
+                    pairs.push((key, scalar(item)));
+                }
+            }
+            v if is_scalar(v) => pairs.push((key, scalar(v))),
+            _ => bail!("Form encoding only supports flat maps, but '{key}' is nested"),
+        }
+    }
+    Ok(serde_urlencoded::to_string(pairs)?)
+}
+
+fn is_scalar(value: &Value) -> bool {
+    !matches!(value, Value::Array(_) | Value::Object(_))
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip() {
+        let input = b"name=slate+rs&tag=a&tag=b&q=%26%3D";
+        let value = from_bytes(input).unwrap();
+        assert_eq!(value, json!({"name": "slate rs", "tag": ["a", "b"], "q": "&="}));
+        assert_eq!(to_string(&value).unwrap(), "name=slate+rs&tag=a&tag=b&q=%26%3D");
+    }
+
+    #[test]
+    fn test_nested_error() {
+        let err = to_string(&json!({"a": {"b": 1}})).unwrap_err();
+        assert_eq!(err.to_string(), "Form encoding only supports flat maps, but 'a' is nested");
+    }
+}
@@ -0,0 +1,74 @@
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+
+/// How several records are packed into one input
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum Framing {
+    /// The input holds a single value
+    #[default]
+    None,
+    /// Values are simply concatenated, e.g. CBOR Sequences (RFC 8742), whitespace separated JSON,
+    /// multi-document YAML or back to back BSON documents
+    Sequence,
+    /// Each value is preceded by its length as a 4-byte big-endian integer
+    LengthPrefixed,
+}
+
+/// Split input made of `u32` big-endian length prefixes followed by that many bytes.
+pub fn split_length_prefixed(input: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut frames = Vec::new();
+    let mut rest = input;
+    while !rest.is_empty() {
+        let Some((len, tail)) = rest.split_first_chunk::<4>() else {
+            bail!("Truncated length prefix at byte {}", input.len() - rest.len());
+        };
+        let len = u32::from_be_bytes(*len) as usize;
+        if tail.len() < len {
+            bail!(
+                "Frame at byte {} claims {len} bytes but only {} remain",
+                input.len() - rest.len(),
+                tail.len()
+            );
+        }
+        let (frame, tail) = tail.split_at(len);
+        frames.push(frame);
+        rest = tail;
+    }
+    Ok(frames)
+}
+
+/// Split back to back BSON documents, each of which starts with its own little-endian length.
+pub fn split_bson(input: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut frames = Vec::new();
+    let mut pos = 0;
+    while pos < input.len() {
+        let Some(len) = input.get(pos..pos + 4) else {
+            bail!("Truncated BSON document at byte {pos}");
+        };
+        let len = i32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        let Some(doc) = input.get(pos..pos + len) else {
+            bail!("BSON document at byte {pos} runs past the end of input");
+        };
+        if len < 5 {
+            bail!("Invalid BSON document length {len} at byte {pos}");
+        }
+        frames.push(doc);
+        pos += len;
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_length_prefixed() {
+        let input = [0, 0, 0, 2, b'a', b'b', 0, 0, 0, 0, 0, 0, 0, 1, b'c'];
+        let frames = split_length_prefixed(&input).unwrap();
+        assert_eq!(frames, vec![&b"ab"[..], &b""[..], &b"c"[..]]);
+
+        assert!(split_length_prefixed(&[0, 0, 0, 5, b'a']).is_err());
+        assert!(split_length_prefixed(&[0, 0]).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+//! HCL output.
+//!
+//! Top-level Terraform block types follow Terraform's JSON syntax, so
+//! `{resource: {aws_instance: {web: {...}}}}` becomes `resource "aws_instance" "web" { ... }`.
+//! Elsewhere, lists of maps become repeated nested blocks and maps become object attributes.
+
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+
+// Number of labels taken by top-level block types
+const BLOCK_LABELS: &[(&str, usize)] = &[
+    ("resource", 2),
+    ("data", 2),
+    ("module", 1),
+    ("variable", 1),
+    ("output", 1),
+    ("provider", 1),
+    ("terraform", 0),
+    ("locals", 0),
+];
+
+pub fn to_string(value: &Value) -> Result<String> {
+    let Value::Object(map) = value else {
+        bail!("HCL output requires a map at the top level");
+    };
+    let mut out = String::new();
+    body(map, 0, true, &mut out)?;
+    Ok(out)
+}
+
+fn body(map: &Map<String, Value>, indent: usize, top: bool, out: &mut String) -> Result<()> {
+    for (key, val) in map {
+        let labels = BLOCK_LABELS.iter().find(|(name, _)| *name == key).map(|(_, n)| *n);
+        match (labels, val) {
+            (Some(n), Value::Object(_)) if top => labeled(key, n, val, &mut Vec::new(), indent, out)?,
+            (_, Value::Array(items)) if !items.is_empty() && items.iter().all(Value::is_object) => {
+                for item in items {
+                    block(key, &[], item, indent, out)?;
+                }
+            }
+            _ => {
+                if !is_identifier(key) {
+                    bail!("'{key}' is not a valid HCL attribute name");
+                }
+                out.push_str(&" ".repeat(indent));
+                out.push_str(key);
+                out.push_str(" = ");
+                expr(val, indent, out);
+                out.push('\n');
+            }
+        }
+    }
+    Ok(())
+}
+
+fn labeled<'a>(
+    key: &str,
+    remaining: usize,
+    val: &'a Value,
+    labels: &mut Vec<&'a str>,
+    indent: usize,
+    out: &mut String,
+) -> Result<()> {
+    match val {
+        Value::Object(map) if remaining > 0 => {
+            for (label, child) in map {
+                labels.push(label);
+                labeled(key, remaining - 1, child, labels, indent, out)?;
+                labels.pop();
+            }
+            Ok(())
+        }
+        // Repeated blocks, such as providers with aliases
+        Value::Array(items) => {
+            for item in items {
+                block(key, labels, item, indent, out)?;
+            }
+            Ok(())
+        }
+        _ => block(key, labels, val, indent, out),
+    }
+}
+
+fn block(key: &str, labels: &[&str], val: &Value, indent: usize, out: &mut String) -> Result<()> {
+    let Value::Object(map) = val else {
+        bail!("Block '{key}' must be a map");
+    };
+    out.push_str(&" ".repeat(indent));
+    out.push_str(key);
+    for label in labels {
+        out.push(' ');
+        string(label, out);
+    }
+    out.push_str(" {\n");
+    body(map, indent + 2, false, out)?;
+    out.push_str(&" ".repeat(indent));
+    out.push_str("}\n");
+    Ok(())
+}
+
+fn expr(value: &Value, indent: usize, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => out.push_str(&n.to_string()),
+        Value::String(s) => string(s, out),
+        Value::Array(items) if items.iter().all(|i| !i.is_array() && !i.is_object()) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                expr(item, indent, out);
+            }
+            out.push(']');
+        }
+        Value::Array(items) => {
+            out.push_str("[\n");
+            for item in items {
+                out.push_str(&" ".repeat(indent + 2));
+                expr(item, indent + 2, out);
+                out.push_str(",\n");
+            }
+            out.push_str(&" ".repeat(indent));
+            out.push(']');
+        }
+        Value::Object(map) if map.is_empty() => out.push_str("{}"),
+        Value::Object(map) => {
+            out.push_str("{\n");
+            for (key, val) in map {
+                out.push_str(&" ".repeat(indent + 2));
+                if is_identifier(key) {
+                    out.push_str(key);
+                } else {
+                    string(key, out);
+                }
+                out.push_str(" = ");
+                expr(val, indent + 2, out);
+                out.push('\n');
+            }
+            out.push_str(&" ".repeat(indent));
+            out.push('}');
+        }
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+fn string(s: &str, out: &mut String) {
+    out.push('"');
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            // Keep template sequences literal
+            '$' | '%' if chars.peek() == Some(&'{') => {
+                out.push(c);
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_string() {
+        let value = json!({
+            "resource": {
+                "aws_instance": {
+                    "web": {
+                        "ami": "ami-123",
+                        "count": 2,
+                        "tags": {"Name": "web ${var.env}"},
+                        "ebs_block_device": [{"device_name": "sda"}, {"device_name": "sdb"}]
+                    }
+                }
+            },
+            "variable": {"env": {"default": "dev"}},
+            "ports": [80, 443]
+        });
+        assert_eq!(
+            to_string(&value).unwrap(),
+            r#"resource "aws_instance" "web" {
+  ami = "ami-123"
+  count = 2
+  tags = {
+    Name = "web $${var.env}"
+  }
+  ebs_block_device {
+    device_name = "sda"
+  }
+  ebs_block_device {
+    device_name = "sdb"
+  }
+}
+variable "env" {
+  default = "dev"
+}
+ports = [80, 443]
+"#
+        );
+        assert!(to_string(&json!({"not valid": 1})).is_err());
+    }
+}
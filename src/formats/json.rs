@@ -0,0 +1,475 @@
+use serde::de::DeserializeSeed;
+use serde::ser::{
+    Error as _, Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+    SerializeTupleStruct, SerializeTupleVariant, Serializer,
+};
+use serde_json::{Map, Value};
+use std::fmt::Write;
+
+use super::dupkeys::{DupKeys, DupSeed};
+use super::BytesRepr;
+
+/// Parse JSON input, resolving repeated keys per `dup_keys`.
+pub fn from_slice(input: &[u8], dup_keys: DupKeys) -> Result<Value, serde_json::Error> {
+    // serde_json itself keeps the last value
+    if dup_keys == DupKeys::Last {
+        return serde_json::from_slice(input);
+    }
+    let mut de = serde_json::Deserializer::from_slice(input);
+    let value = DupSeed(dup_keys).deserialize(&mut de)?;
+    de.end()?;
+    Ok(value)
+}
+
+/// Recursively reorder every object in `value` by key.
+pub fn sort_keys(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = std::mem::take(map).into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            for (key, mut val) in entries {
+                sort_keys(&mut val);
+                map.insert(key, val);
+            }
+        }
+        Value::Array(seq) => {
+            for item in seq.iter_mut() {
+                sort_keys(item);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Serialize `value` following the JSON Canonicalization Scheme (RFC 8785):
+/// no insignificant whitespace, object keys ordered by their UTF-16 code units,
+/// and numbers written the way ECMAScript would print them.
+pub fn to_canonical(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        // Integers too, as a double would hold them, so those beyond 2^53 are rounded
+        Value::Number(n) => out.push_str(&es_number(n.as_f64().unwrap_or(0.0))),
+        Value::String(s) => write_string(s, out),
+        Value::Array(seq) => {
+            out.push('[');
+            for (i, item) in seq.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            out.push('{');
+            for (i, (key, val)) in utf16_sorted(map).into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(key, out);
+                out.push(':');
+                write_canonical(val, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn utf16_sorted(map: &Map<String, Value>) -> Vec<(&String, &Value)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.encode_utf16().cmp(b.0.encode_utf16()));
+    entries
+}
+
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// ECMAScript Number::toString: plain notation within [1e-6, 1e21), exponent notation outside it
+fn es_number(f: f64) -> String {
+    if f == 0.0 || !f.is_finite() {
+        return "0".to_string();
+    }
+    let abs = f.abs();
+    if (1e-6..1e21).contains(&abs) {
+        return format!("{f}");
+    }
+    let s = format!("{f:e}");
+    match s.split_once('e') {
+        Some((mantissa, exp)) if !exp.starts_with('-') => format!("{mantissa}e+{exp}"),
+        _ => s,
+    }
+}
+
+// The struct name serde_json numbers serialize as, to keep their exact digits
+const NUMBER_TOKEN: &str = "$serde_json::private::Number";
+
+/// Serializes the wrapped value, turning serde_json numbers back into plain integers and floats.
+///
+/// With exact number preservation, a `serde_json::Number` presents itself to serializers other
+/// than serde_json's as a private single-field struct holding its digits. Formats that can't make
+/// use of those should see this wrapper instead.
+pub struct PlainNumbers<'a, T: ?Sized>(pub &'a T);
+
+impl<T: Serialize + ?Sized> Serialize for PlainNumbers<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Adapted(self.0, Adapt { numbers: true, bytes: None }).serialize(serializer)
+    }
+}
+
+/// Serializes the wrapped value with its byte strings rendered as text, for formats that have no
+/// byte string type of their own.
+pub struct RenderBytes<'a, T: ?Sized>(pub &'a T, pub BytesRepr);
+
+impl<T: Serialize + ?Sized> Serialize for RenderBytes<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Adapted(self.0, Adapt { numbers: false, bytes: Some(self.1) }).serialize(serializer)
+    }
+}
+
+// What the adapting serializer rewrites, carried down to every nested value
+#[derive(Clone, Copy)]
+struct Adapt {
+    numbers: bool,
+    bytes: Option<BytesRepr>,
+}
+
+struct Adapted<'a, T: ?Sized>(&'a T, Adapt);
+
+impl<T: Serialize + ?Sized> Serialize for Adapted<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(Plain(serializer, self.1))
+    }
+}
+
+struct Plain<S>(S, Adapt);
+
+enum PlainStruct<S: Serializer> {
+    Struct(S::SerializeStruct, Adapt),
+    Number(Option<S>, Option<Result<S::Ok, S::Error>>),
+}
+
+macro_rules! forward {
+    ($($method:ident($ty:ty)),* $(,)?) => {
+        $(fn $method(self, v: $ty) -> Result<S::Ok, S::Error> {
+            self.0.$method(v)
+        })*
+    };
+}
+
+impl<S: Serializer> Serializer for Plain<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Plain<S::SerializeSeq>;
+    type SerializeTuple = Plain<S::SerializeTuple>;
+    type SerializeTupleStruct = Plain<S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Plain<S::SerializeTupleVariant>;
+    type SerializeMap = Plain<S::SerializeMap>;
+    type SerializeStruct = PlainStruct<S>;
+    type SerializeStructVariant = Plain<S::SerializeStructVariant>;
+
+    forward!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_unit_struct(&'static str),
+    );
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<S::Ok, S::Error> {
+        match self.1.bytes {
+            Some(repr) => repr.render(v).serialize(Plain(self.0, Adapt { numbers: true, bytes: None })),
+            None => self.0.serialize_bytes(v),
+        }
+    }
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.0.serialize_none()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        self.0.serialize_some(&Adapted(value, self.1))
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.0.serialize_unit()
+    }
+
+    fn serialize_unit_variant(self, name: &'static str, index: u32, variant: &'static str) -> Result<S::Ok, S::Error> {
+        self.0.serialize_unit_variant(name, index, variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, name: &'static str, value: &T) -> Result<S::Ok, S::Error> {
+        self.0.serialize_newtype_struct(name, &Adapted(value, self.1))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        self.0.serialize_newtype_variant(name, index, variant, &Adapted(value, self.1))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        Ok(Plain(self.0.serialize_seq(len)?, self.1))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        Ok(Plain(self.0.serialize_tuple(len)?, self.1))
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeTupleStruct, S::Error> {
+        Ok(Plain(self.0.serialize_tuple_struct(name, len)?, self.1))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        Ok(Plain(self.0.serialize_tuple_variant(name, index, variant, len)?, self.1))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        Ok(Plain(self.0.serialize_map(len)?, self.1))
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct, S::Error> {
+        if self.1.numbers && name == NUMBER_TOKEN {
+            return Ok(PlainStruct::Number(Some(self.0), None));
+        }
+        Ok(PlainStruct::Struct(self.0.serialize_struct(name, len)?, self.1))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        Ok(Plain(self.0.serialize_struct_variant(name, index, variant, len)?, self.1))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+}
+
+impl<S: SerializeSeq> SerializeSeq for Plain<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.0.serialize_element(&Adapted(value, self.1))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: SerializeTuple> SerializeTuple for Plain<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.0.serialize_element(&Adapted(value, self.1))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: SerializeTupleStruct> SerializeTupleStruct for Plain<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.0.serialize_field(&Adapted(value, self.1))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: SerializeTupleVariant> SerializeTupleVariant for Plain<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.0.serialize_field(&Adapted(value, self.1))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: SerializeMap> SerializeMap for Plain<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), S::Error> {
+        self.0.serialize_key(&Adapted(key, self.1))
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.0.serialize_value(&Adapted(value, self.1))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: SerializeStructVariant> SerializeStructVariant for Plain<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), S::Error> {
+        self.0.serialize_field(key, &Adapted(value, self.1))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), S::Error> {
+        self.0.skip_field(key)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: Serializer> SerializeStruct for PlainStruct<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), S::Error> {
+        match self {
+            PlainStruct::Struct(inner, adapt) => inner.serialize_field(key, &Adapted(value, *adapt)),
+            PlainStruct::Number(serializer, result) => {
+                let Some(serializer) = serializer.take() else {
+                    return Err(S::Error::custom("number has more than one field"));
+                };
+                let digits = match serde_json::to_value(value) {
+                    Ok(Value::String(digits)) => digits,
+                    _ => return Err(S::Error::custom("number without digits")),
+                };
+                *result = Some(if let Ok(u) = digits.parse::<u64>() {
+                    serializer.serialize_u64(u)
+                } else if let Ok(i) = digits.parse::<i64>() {
+                    serializer.serialize_i64(i)
+                } else if let Ok(u) = digits.parse::<u128>() {
+                    serializer.serialize_u128(u)
+                } else if let Ok(i) = digits.parse::<i128>() {
+                    serializer.serialize_i128(i)
+                } else if let Ok(f) = digits.parse::<f64>() {
+                    serializer.serialize_f64(f)
+                } else {
+                    serializer.serialize_str(&digits)
+                });
+                Ok(())
+            }
+        }
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), S::Error> {
+        match self {
+            PlainStruct::Struct(inner, _) => inner.skip_field(key),
+            PlainStruct::Number(..) => Ok(()),
+        }
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        match self {
+            PlainStruct::Struct(inner, _) => inner.end(),
+            PlainStruct::Number(_, result) => {
+                result.unwrap_or_else(|| Err(S::Error::custom("number without digits")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_keys() {
+        let mut value: Value = serde_json::from_str(r#"{"b": {"z": 1, "a": 2}, "a": [{"d": 1, "c": 2}]}"#).unwrap();
+        sort_keys(&mut value);
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"a":[{"c":2,"d":1}],"b":{"a":2,"z":1}}"#
+        );
+    }
+
+    #[test]
+    fn test_to_canonical() {
+        let value: Value = serde_json::from_str(
+            r#"{"numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000001, 1e-7, 9007199254740993, -42], "string": "€$\u000f\nA'B\"\\\\\"/", "literals": [null, true, false]}"#,
+        ).unwrap();
+        assert_eq!(
+            to_canonical(&value),
+            r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,0.000001,1e-7,9007199254740992,-42],"string":"€$\u000f\nA'B\"\\\\\"/"}"#
+        );
+    }
+
+    #[test]
+    fn test_exact_numbers() {
+        let input = r#"{"id":18446744073709551616,"price":1.50,"count":3}"#;
+        let value: Value = serde_json::from_str(input).unwrap();
+        assert_eq!(serde_json::to_string(&value).unwrap(), input);
+
+        let yaml = serde_yaml::to_string(&PlainNumbers(&value)).unwrap();
+        assert!(!yaml.contains("serde_json"));
+        assert!(yaml.contains("price: 1.5\ncount: 3\n"));
+        assert!(yaml.starts_with("id: 18446744073709551616\n"));
+    }
+
+    #[test]
+    fn test_render_bytes() {
+        let value = serde_cbor::Value::Array(vec![serde_cbor::Value::Bytes(vec![0xde, 0xad])]);
+        assert_eq!(serde_json::to_string(&RenderBytes(&value, BytesRepr::Hex)).unwrap(), r#"["dead"]"#);
+        assert_eq!(serde_yaml::to_string(&PlainNumbers(&RenderBytes(&value, BytesRepr::Array))).unwrap(), "- - 222\n  - 173\n");
+    }
+}
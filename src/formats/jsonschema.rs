@@ -0,0 +1,166 @@
+//! Infer a JSON Schema (draft 2020-12) from example data.
+//!
+//! Every element of a list contributes to one `items` schema, so a list of records yields the
+//! union of their properties, with `required` listing the keys present in all of them. Strings
+//! that repeat a handful of distinct values are given an `enum`.
+
+use indexmap::IndexMap;
+use serde_json::{json, Map, Value};
+
+// Largest set of distinct strings considered an enum
+const ENUM_MAX: usize = 5;
+
+/// Everything observed at one position of the document, merged across list items
+#[derive(Default)]
+pub(super) struct Shape {
+    pub types: Vec<&'static str>,
+    strings: Vec<String>,
+    string_count: usize,
+    string_overflow: bool,
+    /// How many maps were seen, to tell which properties appear in all of them
+    pub objects: usize,
+    /// Each property with the number of maps it appeared in
+    pub properties: IndexMap<String, (usize, Shape)>,
+    pub items: Option<Box<Shape>>,
+}
+
+impl Shape {
+    pub fn new(value: &Value) -> Self {
+        let mut shape = Shape::default();
+        shape.add(value);
+        shape
+    }
+
+    fn add(&mut self, value: &Value) {
+        let ty = match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(n) if n.is_f64() => "number",
+            Value::Number(_) => "integer",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        };
+        if !self.types.contains(&ty) {
+            self.types.push(ty);
+        }
+        match value {
+            Value::String(s) => {
+                self.string_count += 1;
+                if !self.strings.contains(s) {
+                    if self.strings.len() < ENUM_MAX {
+                        self.strings.push(s.clone());
+                    } else {
+                        self.string_overflow = true;
+                    }
+                }
+            }
+            Value::Array(items) => {
+                let shape = self.items.get_or_insert_with(Default::default);
+                for item in items {
+                    shape.add(item);
+                }
+            }
+            Value::Object(map) => {
+                self.objects += 1;
+                for (key, val) in map {
+                    let (count, shape) = self.properties.entry(key.clone()).or_default();
+                    *count += 1;
+                    shape.add(val);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn schema(&self) -> Map<String, Value> {
+        let mut types = self.types.clone();
+        // Integers are numbers too
+        if types.contains(&"number") {
+            types.retain(|t| *t != "integer");
+        }
+
+        let mut schema = Map::new();
+        match types.as_slice() {
+            [] => {}
+            [ty] => {
+                schema.insert("type".into(), json!(ty));
+            }
+            _ => {
+                schema.insert("type".into(), json!(types));
+            }
+        }
+        if types == ["string"] && !self.string_overflow && self.string_count > self.strings.len() {
+            schema.insert("enum".into(), json!(self.strings));
+        }
+        if let Some(items) = &self.items {
+            schema.insert("items".into(), Value::Object(items.schema()));
+        }
+        if self.objects > 0 {
+            let properties: Map<String, Value> = self
+                .properties
+                .iter()
+                .map(|(key, (_, shape))| (key.clone(), Value::Object(shape.schema())))
+                .collect();
+            let required: Vec<&String> = self
+                .properties
+                .iter()
+                .filter(|(_, (count, _))| *count == self.objects)
+                .map(|(key, _)| key)
+                .collect();
+            schema.insert("properties".into(), Value::Object(properties));
+            if !required.is_empty() {
+                schema.insert("required".into(), json!(required));
+            }
+        }
+        schema
+    }
+}
+
+pub fn infer(value: &Value) -> Value {
+    let shape = Shape::new(value);
+    let mut schema = Map::new();
+    schema.insert("$schema".into(), json!("https://json-schema.org/draft/2020-12/schema"));
+    schema.extend(shape.schema());
+    Value::Object(schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer() {
+        let value = json!({
+            "services": [
+                {"name": "web", "port": 80, "env": "prod", "tags": ["a"]},
+                {"name": "db", "port": 5432.5, "env": "prod"},
+                {"name": "cache", "env": "dev", "owner": null}
+            ]
+        });
+        assert_eq!(
+            infer(&value),
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "properties": {
+                    "services": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "name": {"type": "string"},
+                                "port": {"type": "number"},
+                                "env": {"type": "string", "enum": ["prod", "dev"]},
+                                "tags": {"type": "array", "items": {"type": "string"}},
+                                "owner": {"type": "null"}
+                            },
+                            "required": ["name", "env"]
+                        }
+                    }
+                },
+                "required": ["services"]
+            })
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! KDL documents.
+//!
+//! Nodes map to values as follows:
+//! - `name` with no entries is `null`, `name 1` is a scalar and `name 1 2 3` a list of scalars
+//! - `name { - ...; - ... }`, where every child is named `-`, is a list of the children's values
+//! - any other node is a map built from its properties and children; positional arguments sit
+//!   under the `-` key
+//! - sibling nodes sharing a name collect into a list
+//!
+//! Writing goes the other way, always putting map entries in a children block.
+
+use ::kdl::{KdlDocument, KdlEntry, KdlNode, KdlValue};
+use anyhow::{bail, Result};
+use serde_json::{Map, Number, Value};
+
+pub fn from_str(input: &str) -> Result<Value> {
+    let doc: KdlDocument = input.parse()?;
+    Ok(Value::Object(document_to_map(&doc)))
+}
+
+pub fn to_string(value: &Value) -> Result<String> {
+    let Value::Object(map) = value else {
+        bail!("KDL output requires a map at the top level");
+    };
+    let mut doc = map_to_document(map);
+    doc.fmt();
+    Ok(doc.to_string())
+}
+
+fn document_to_map(doc: &KdlDocument) -> Map<String, Value> {
+    let mut map = Map::new();
+    for node in doc.nodes() {
+        let value = node_to_value(node);
+        push_entry(&mut map, node.name().value(), value);
+    }
+    map
+}
+
+// Repeated keys collect into a list
+fn push_entry(map: &mut Map<String, Value>, key: &str, value: Value) {
+    match map.get_mut(key) {
+        Some(Value::Array(seq)) => seq.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, value]);
+        }
+        None => {
+            map.insert(key.to_string(), value);
+        }
+    }
+}
+
+fn node_to_value(node: &KdlNode) -> Value {
+    let args: Vec<Value> = node
+        .entries()
+        .iter()
+        .filter(|e| e.name().is_none())
+        .map(|e| scalar(e.value()))
+        .collect();
+    let props: Vec<&KdlEntry> = node.entries().iter().filter(|e| e.name().is_some()).collect();
+
+    if let Some(children) = node.children() {
+        if args.is_empty() && props.is_empty() && children.nodes().iter().all(|n| n.name().value() == "-") {
+            return Value::Array(children.nodes().iter().map(node_to_value).collect());
+        }
+    } else if props.is_empty() {
+        return match args.len() {
+            0 => Value::Null,
+            1 => args.into_iter().next().unwrap(),
+            _ => Value::Array(args),
+        };
+    }
+
+    let mut map = Map::new();
+    match args.len() {
+        0 => {}
+        1 => {
+            map.insert("-".to_string(), args.into_iter().next().unwrap());
+        }
+        _ => {
+            map.insert("-".to_string(), Value::Array(args));
+        }
+    }
+    for prop in props {
+        if let Some(name) = prop.name() {
+            map.insert(name.value().to_string(), scalar(prop.value()));
+        }
+    }
+    if let Some(children) = node.children() {
+        for child in children.nodes() {
+            push_entry(&mut map, child.name().value(), node_to_value(child));
+        }
+    }
+    Value::Object(map)
+}
+
+fn scalar(value: &KdlValue) -> Value {
+    match value {
+        KdlValue::RawString(s) | KdlValue::String(s) => Value::String(s.clone()),
+        KdlValue::Base2(i) | KdlValue::Base8(i) | KdlValue::Base10(i) | KdlValue::Base16(i) => {
+            Value::Number((*i).into())
+        }
+        KdlValue::Base10Float(f) => Number::from_f64(*f).map(Value::Number).unwrap_or(Value::Null),
+        KdlValue::Bool(b) => Value::Bool(*b),
+        KdlValue::Null => Value::Null,
+    }
+}
+
+fn map_to_document(map: &Map<String, Value>) -> KdlDocument {
+    let mut doc = KdlDocument::new();
+    for (key, value) in map {
+        doc.nodes_mut().push(value_to_node(key, value));
+    }
+    doc
+}
+
+fn value_to_node(name: &str, value: &Value) -> KdlNode {
+    let mut node = KdlNode::new(name);
+    match value {
+        Value::Array(seq) if seq.len() > 1 && seq.iter().all(is_scalar) => {
+            for item in seq {
+                node.push(KdlEntry::new(to_kdl_value(item)));
+            }
+        }
+        Value::Array(seq) => {
+            let mut children = KdlDocument::new();
+            for item in seq {
+                children.nodes_mut().push(value_to_node("-", item));
+            }
+            node.set_children(children);
+        }
+        Value::Object(map) => {
+            node.set_children(map_to_document(map));
+        }
+        Value::Null => {}
+        scalar => node.push(KdlEntry::new(to_kdl_value(scalar))),
+    }
+    node
+}
+
+fn is_scalar(value: &Value) -> bool {
+    !matches!(value, Value::Array(_) | Value::Object(_))
+}
+
+fn to_kdl_value(value: &Value) -> KdlValue {
+    match value {
+        Value::Null => KdlValue::Null,
+        Value::Bool(b) => KdlValue::Bool(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => KdlValue::Base10(i),
+            None => KdlValue::Base10Float(n.as_f64().unwrap_or(0.0)),
+        },
+        Value::String(s) => KdlValue::String(s.clone()),
+        Value::Array(_) | Value::Object(_) => unreachable!("only scalars become KDL entries"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_str() {
+        let input = r#"
+title "Config"
+ports 80 443
+server "main" host="localhost" {
+    timeout 30
+}
+users {
+    - name="alice"
+    - name="bob"
+}
+include "a.kdl"
+include "b.kdl"
+enabled
+"#;
+        assert_eq!(
+            from_str(input).unwrap(),
+            json!({
+                "title": "Config",
+                "ports": [80, 443],
+                "server": {"-": "main", "host": "localhost", "timeout": 30},
+                "users": [{"name": "alice"}, {"name": "bob"}],
+                "include": ["a.kdl", "b.kdl"],
+                "enabled": null
+            })
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = json!({
+            "name": "app",
+            "ports": [80, 443],
+            "single": [1],
+            "db": {"host": "localhost", "port": 5432, "replicas": [{"host": "r1"}, {"host": "r2"}]},
+            "ratio": 0.5,
+            "debug": false,
+            "nothing": null
+        });
+        assert_eq!(from_str(&to_string(&value).unwrap()).unwrap(), value);
+    }
+}
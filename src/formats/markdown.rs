@@ -0,0 +1,58 @@
+use super::csv::Table;
+
+/// Render a GitHub-flavored Markdown table with columns padded to line up.
+pub fn to_string(table: &Table) -> String {
+    let header: Vec<String> = table.columns.iter().map(|c| escape(c)).collect();
+    let rows: Vec<Vec<String>> = table
+        .rows
+        .iter()
+        .map(|row| row.iter().map(|c| escape(c)).collect())
+        .collect();
+
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count().max(3)).collect();
+    for row in &rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: &[String]| {
+        let cells: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(c, w)| format!("{c}{}", " ".repeat(w - c.chars().count())))
+            .collect();
+        format!("| {} |\n", cells.join(" | "))
+    };
+    let mut out = line(&header);
+    let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    out.push_str(&line(&rule));
+    for row in &rows {
+        out.push_str(&line(row));
+    }
+    out
+}
+
+// Pipes would end the cell and newlines the row
+fn escape(s: &str) -> String {
+    s.replace('|', "\\|").replace("\r\n", "<br>").replace('\n', "<br>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_string() {
+        let value = json!([{"name": "web", "cmd": "a | b"}, {"name": "db\nprimary", "port": 5432}]);
+        let table = Table::new(&value, None, false).unwrap();
+        assert_eq!(
+            to_string(&table),
+            "| name          | cmd    | port |\n\
+             | ------------- | ------ | ---- |\n\
+             | web           | a \\| b |      |\n\
+             | db<br>primary |        | 5432 |\n"
+        );
+    }
+}
@@ -0,0 +1,14 @@
+use ::parquet::file::reader::{FileReader, SerializedFileReader};
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+/// Read every row of a Parquet file into a list of maps keyed by column name.
+pub fn from_bytes(input: Vec<u8>) -> Result<Value> {
+    let reader = SerializedFileReader::new(bytes::Bytes::from(input))
+        .context("Input is not a Parquet file")?;
+    let mut rows = Vec::new();
+    for row in reader.get_row_iter(None)? {
+        rows.push(row?.to_json_value());
+    }
+    Ok(Value::Array(rows))
+}
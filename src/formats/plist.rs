@@ -0,0 +1,43 @@
+use anyhow::Result;
+use serde::Serialize;
+
+/// The first bytes of a binary plist. `.plist` files come in both forms, so this tells them apart.
+pub const MAGIC: &[u8] = b"bplist00";
+
+/// Whether `input` is a binary plist rather than an XML one
+pub fn is_binary(input: &[u8]) -> bool {
+    input.starts_with(MAGIC)
+}
+
+/// Write `obj` as a plist, in the binary form or as XML.
+///
+/// Reading needs no counterpart, as `plist::from_bytes` takes either form.
+pub fn to_vec<T: Serialize>(obj: &T, binary: bool) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    if binary {
+        ::plist::to_writer_binary(&mut buf, obj)?;
+    } else {
+        ::plist::to_writer_xml(&mut buf, obj)?;
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_round_trip() {
+        let value = json!({"Label": "com.example.agent", "RunAtLoad": true, "StartInterval": 300, "ProgramArguments": ["/bin/echo", "hi"]});
+
+        let xml = to_vec(&value, false).unwrap();
+        assert!(!is_binary(&xml));
+        assert!(String::from_utf8_lossy(&xml).contains("<key>Label</key>\n\t<string>com.example.agent</string>"));
+        assert_eq!(::plist::from_bytes::<Value>(&xml).unwrap(), value);
+
+        let binary = to_vec(&value, true).unwrap();
+        assert!(is_binary(&binary));
+        assert_eq!(::plist::from_bytes::<Value>(&binary).unwrap(), value);
+    }
+}
@@ -0,0 +1,246 @@
+//! Java `.properties` files.
+//!
+//! Dotted keys map to nested maps (`server.port=80` becomes `{server: {port: "80"}}`). When a key
+//! is both a value and a prefix of other keys, as in `log4j.appender.out=...` next to
+//! `log4j.appender.out.layout=...`, the value is kept under the empty key `""` of the nested map.
+//! Lists are written with their index as the last key segment.
+
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+use std::fmt::Write;
+
+pub fn from_str(input: &str) -> Result<Value> {
+    let mut root = Map::new();
+    let mut lines = input.lines();
+
+    while let Some(line) = lines.next() {
+        let mut logical = line.trim_start().to_string();
+        if logical.is_empty() || logical.starts_with('#') || logical.starts_with('!') {
+            continue;
+        }
+        while ends_with_continuation(&logical) {
+            logical.pop();
+            match lines.next() {
+                Some(next) => logical.push_str(next.trim_start()),
+                None => break,
+            }
+        }
+
+        let (key, value) = split_entry(&logical);
+        let key = unescape(key)?;
+        let value = unescape(value)?;
+        insert(&mut root, &key, value);
+    }
+    Ok(Value::Object(root))
+}
+
+pub fn to_string(value: &Value) -> Result<String> {
+    let Value::Object(map) = value else {
+        bail!("Properties output requires a map at the top level");
+    };
+    let mut out = String::new();
+    for (key, val) in map {
+        flatten(key, val, &mut out);
+    }
+    Ok(out)
+}
+
+// An odd number of trailing backslashes joins the next line
+fn ends_with_continuation(s: &str) -> bool {
+    s.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+}
+
+fn split_entry(line: &str) -> (&str, &str) {
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '=' | ':' => return (&line[..i], line[i + 1..].trim_start()),
+            c if c.is_whitespace() => {
+                let rest = line[i..].trim_start();
+                let rest = rest
+                    .strip_prefix(['=', ':'])
+                    .map(str::trim_start)
+                    .unwrap_or(rest);
+                return (&line[..i], rest);
+            }
+            _ => {}
+        }
+    }
+    (line, "")
+}
+
+fn unescape(s: &str) -> Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    let mut pending_surrogate: Option<u32> = None;
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('f') => out.push('\u{0c}'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let code = u32::from_str_radix(&hex, 16)
+                    .map_err(|_| anyhow::anyhow!("Invalid unicode escape '\\u{hex}'"))?;
+                match (pending_surrogate.take(), code) {
+                    (None, 0xD800..=0xDBFF) => pending_surrogate = Some(code),
+                    (Some(high), 0xDC00..=0xDFFF) => {
+                        let c = 0x10000 + ((high - 0xD800) << 10) + (code - 0xDC00);
+                        out.extend(char::from_u32(c));
+                    }
+                    (_, code) => out.push(char::from_u32(code).unwrap_or('\u{fffd}')),
+                }
+            }
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    Ok(out)
+}
+
+fn insert(map: &mut Map<String, Value>, key: &str, value: String) {
+    let (head, rest) = match key.split_once('.') {
+        Some((head, rest)) if !head.is_empty() && !rest.is_empty() => (head, Some(rest)),
+        _ => (key, None),
+    };
+
+    let entry = map.entry(head.to_string()).or_insert(Value::Null);
+    match rest {
+        None => match entry {
+            Value::Object(child) => {
+                child.insert(String::new(), Value::String(value));
+            }
+            _ => *entry = Value::String(value),
+        },
+        Some(rest) => {
+            if !entry.is_object() {
+                let mut child = Map::new();
+                if let Value::String(existing) = entry.take() {
+                    child.insert(String::new(), Value::String(existing));
+                }
+                *entry = Value::Object(child);
+            }
+            if let Value::Object(child) = entry {
+                insert(child, rest, value);
+            }
+        }
+    }
+}
+
+fn flatten(prefix: &str, value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            for (key, val) in map {
+                if key.is_empty() {
+                    flatten(prefix, val, out);
+                } else {
+                    flatten(&format!("{prefix}.{key}"), val, out);
+                }
+            }
+        }
+        Value::Array(seq) => {
+            for (i, item) in seq.iter().enumerate() {
+                flatten(&format!("{prefix}.{i}"), item, out);
+            }
+        }
+        Value::Null => {
+            escape(prefix, true, out);
+            out.push_str("=\n");
+        }
+        Value::String(s) => {
+            escape(prefix, true, out);
+            out.push('=');
+            escape(s, false, out);
+            out.push('\n');
+        }
+        other => {
+            escape(prefix, true, out);
+            out.push('=');
+            out.push_str(&other.to_string());
+            out.push('\n');
+        }
+    }
+}
+
+fn escape(s: &str, is_key: bool, out: &mut String) {
+    for (i, c) in s.chars().enumerate() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\u{0c}' => out.push_str("\\f"),
+            ' ' if is_key || i == 0 => out.push_str("\\ "),
+            '=' | ':' if is_key => {
+                out.push('\\');
+                out.push(c);
+            }
+            '#' | '!' if i == 0 => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if (c as u32) < 0x20 || (c as u32) > 0x7e => {
+                let mut buf = [0u16; 2];
+                for unit in c.encode_utf16(&mut buf) {
+                    write!(out, "\\u{unit:04X}").unwrap();
+                }
+            }
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_str() {
+        let input = r#"
+# comment
+! also a comment
+server.port = 8080
+server.host:localhost
+greeting Grüße \
+    Welt
+log4j.appender.out=ConsoleAppender
+log4j.appender.out.layout=PatternLayout
+key\ with\ spaces=value
+"#;
+        assert_eq!(
+            from_str(input).unwrap(),
+            json!({
+                "server": {"port": "8080", "host": "localhost"},
+                "greeting": "Grüße Welt",
+                "log4j": {"appender": {"out": {"": "ConsoleAppender", "layout": "PatternLayout"}}},
+                "key with spaces": "value"
+            })
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = json!({
+            "a": {"": "root", "b": "x=y", "c": " leading"},
+            "emoji": "😀",
+            "key with spaces": "multi\nline"
+        });
+        let s = to_string(&value).unwrap();
+        assert_eq!(
+            s,
+            "a=root\na.b=x=y\na.c=\\ leading\nemoji=\\uD83D\\uDE00\nkey\\ with\\ spaces=multi\\nline\n"
+        );
+        assert_eq!(from_str(&s).unwrap(), value);
+    }
+}
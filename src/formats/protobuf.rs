@@ -0,0 +1,42 @@
+use anyhow::{anyhow, bail, Context, Result};
+use prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor};
+use serde_json::Value;
+use std::path::Path;
+
+/// Load `message` from a serialized `FileDescriptorSet` (as written by `protoc --descriptor_set_out`
+/// or `buf build -o`). The message name may be left out when the set defines exactly one message.
+pub fn load_message(descriptor: &Path, message: Option<&str>) -> Result<MessageDescriptor> {
+    let bytes = crate::archive::read(descriptor)
+        .with_context(|| format!("Failed to read descriptor set: {}", descriptor.display()))?;
+    let pool = DescriptorPool::decode(bytes.as_slice())
+        .with_context(|| format!("Invalid descriptor set: {}", descriptor.display()))?;
+
+    match message {
+        Some(name) => pool
+            .get_message_by_name(name)
+            .ok_or_else(|| anyhow!("Message '{name}' not found in {}", descriptor.display())),
+        None => {
+            let mut messages = pool.all_messages();
+            match (messages.next(), messages.next()) {
+                (Some(only), None) => Ok(only),
+                (None, _) => bail!("{} defines no messages", descriptor.display()),
+                _ => bail!("{} defines several messages, choose one with --message", descriptor.display()),
+            }
+        }
+    }
+}
+
+pub fn decode(desc: MessageDescriptor, input: &[u8]) -> Result<Value> {
+    let name = desc.full_name().to_string();
+    let msg = DynamicMessage::decode(desc, input)
+        .with_context(|| format!("Failed to decode input as {name}"))?;
+    Ok(serde_json::to_value(&msg)?)
+}
+
+pub fn encode(desc: MessageDescriptor, value: Value) -> Result<Vec<u8>> {
+    let name = desc.full_name().to_string();
+    let msg = DynamicMessage::deserialize(desc, value)
+        .with_context(|| format!("Input does not match message {name}"))?;
+    Ok(msg.encode_to_vec())
+}
@@ -0,0 +1,259 @@
+//! Generate serde-annotated Rust types that can deserialize documents shaped like the input.
+//!
+//! Types are inferred as for JSON Schema output: maps become structs named after their key, fields
+//! missing from some records or seen as `null` become `Option`, and positions holding mixed
+//! types fall back to `serde_json::Value`.
+
+use super::jsonschema::Shape;
+use clap::ValueEnum;
+use serde_json::Value;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Derive {
+    Debug,
+    Clone,
+    PartialEq,
+    Default,
+}
+
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum StringStyle {
+    /// `String`
+    #[default]
+    Owned,
+    /// `&'a str`, borrowing from the input
+    Borrowed,
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "dyn", "else", "enum", "extern", "false", "fn",
+    "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static",
+    "struct", "trait", "true", "try", "type", "unsafe", "use", "where", "while", "yield",
+];
+
+pub fn to_string(value: &Value, derives: &[Derive], strings: StringStyle) -> String {
+    let mut derive = vec!["Serialize".to_string(), "Deserialize".to_string()];
+    derive.extend(derives.iter().map(|d| format!("{d:?}")));
+    let mut gen = Generator { strings, derive: derive.join(", "), defs: Vec::new(), names: Vec::new() };
+
+    let shape = Shape::new(value);
+    if shape.types == ["object"] {
+        gen.structure("Root", &shape);
+    } else {
+        let slot = gen.reserve();
+        let ty = gen.type_of("Root", &shape, false);
+        let lifetime = if ty.contains("'a") { "<'a>" } else { "" };
+        gen.defs[slot] = format!("pub type Root{lifetime} = {ty};\n");
+    }
+    format!("use serde::{{Deserialize, Serialize}};\n\n{}", gen.defs.join("\n"))
+}
+
+struct Generator {
+    strings: StringStyle,
+    derive: String,
+    defs: Vec<String>,
+    names: Vec<String>,
+}
+
+impl Generator {
+    // Keeps parents above the types they use
+    fn reserve(&mut self) -> usize {
+        self.defs.push(String::new());
+        self.defs.len() - 1
+    }
+
+    fn type_of(&mut self, hint: &str, shape: &Shape, optional: bool) -> String {
+        let mut types: Vec<&str> = shape.types.iter().copied().filter(|t| *t != "null").collect();
+        if types.contains(&"number") {
+            types.retain(|t| *t != "integer");
+        }
+        let ty = match types.as_slice() {
+            [] => return "Option<serde_json::Value>".to_string(),
+            ["boolean"] => "bool".to_string(),
+            ["integer"] => "i64".to_string(),
+            ["number"] => "f64".to_string(),
+            ["string"] => match self.strings {
+                StringStyle::Owned => "String".to_string(),
+                StringStyle::Borrowed => "&'a str".to_string(),
+            },
+            ["array"] => match &shape.items {
+                Some(items) => format!("Vec<{}>", self.type_of(&singular(hint), items, false)),
+                None => "Vec<serde_json::Value>".to_string(),
+            },
+            ["object"] => self.structure(hint, shape),
+            _ => return "serde_json::Value".to_string(),
+        };
+        if optional || shape.types.contains(&"null") {
+            format!("Option<{ty}>")
+        } else {
+            ty
+        }
+    }
+
+    fn structure(&mut self, hint: &str, shape: &Shape) -> String {
+        let mut name = pascal_case(hint);
+        let base = name.clone();
+        let mut n = 1;
+        while self.names.contains(&name) {
+            n += 1;
+            name = format!("{base}{n}");
+        }
+        self.names.push(name.clone());
+        let slot = self.reserve();
+
+        let mut fields = String::new();
+        let mut field_names: Vec<String> = Vec::new();
+        let mut borrowed = false;
+        for (key, (count, prop)) in &shape.properties {
+            let ty = self.type_of(key, prop, *count < shape.objects);
+            let mut field = snake_case(key);
+            let base = field.clone();
+            let mut n = 1;
+            while field_names.contains(&field) {
+                n += 1;
+                field = format!("{base}_{n}");
+            }
+            field_names.push(field.clone());
+
+            if field.trim_start_matches("r#") != key {
+                fields.push_str(&format!("    #[serde(rename = {key:?})]\n"));
+            }
+            if ty.contains("'a") {
+                borrowed = true;
+                // serde only borrows plain &str on its own
+                if ty != "&'a str" {
+                    fields.push_str("    #[serde(borrow)]\n");
+                }
+            }
+            fields.push_str(&format!("    pub {field}: {ty},\n"));
+        }
+
+        let lifetime = if borrowed { "<'a>" } else { "" };
+        self.defs[slot] = format!("#[derive({})]\npub struct {name}{lifetime} {{\n{fields}}}\n", self.derive);
+        format!("{name}{lifetime}")
+    }
+}
+
+fn words(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut prev_lower = false;
+    for c in s.chars() {
+        if !c.is_ascii_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            prev_lower = false;
+            continue;
+        }
+        // camelCase boundary
+        if c.is_ascii_uppercase() && prev_lower {
+            words.push(std::mem::take(&mut word));
+        }
+        prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn pascal_case(s: &str) -> String {
+    let mut out: String = words(s)
+        .iter()
+        .map(|w| {
+            let mut chars = w.chars();
+            let first = chars.next().map(|c| c.to_ascii_uppercase()).into_iter();
+            first.chain(chars.map(|c| c.to_ascii_lowercase())).collect::<String>()
+        })
+        .collect();
+    if out.is_empty() {
+        out.push_str("Item");
+    } else if out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, 'T');
+    }
+    out
+}
+
+fn snake_case(s: &str) -> String {
+    let mut out = words(s)
+        .iter()
+        .map(|w| w.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join("_");
+    if out.is_empty() {
+        out.push_str("field");
+    } else if out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    match out.as_str() {
+        "self" | "super" | "crate" => out.push('_'),
+        kw if KEYWORDS.contains(&kw) => out.insert_str(0, "r#"),
+        _ => {}
+    }
+    out
+}
+
+// Name for the items of a list: `services` holds `Service`s
+fn singular(name: &str) -> String {
+    match name.strip_suffix('s') {
+        Some(stem) if !stem.is_empty() && !stem.ends_with('s') => stem.to_string(),
+        _ => format!("{name}Item"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_string() {
+        let value = json!({
+            "name": "app",
+            "type": "web",
+            "maxConns": 10,
+            "services": [{"host": "a", "port": 80}, {"host": "b"}]
+        });
+        assert_eq!(
+            to_string(&value, &[Derive::Debug], StringStyle::Owned),
+            r#"use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Root {
+    pub name: String,
+    pub r#type: String,
+    #[serde(rename = "maxConns")]
+    pub max_conns: i64,
+    pub services: Vec<Service>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Service {
+    pub host: String,
+    pub port: Option<i64>,
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_borrowed() {
+        let value = json!([{"tags": ["a"], "id": 1}]);
+        assert_eq!(
+            to_string(&value, &[], StringStyle::Borrowed),
+            r#"use serde::{Deserialize, Serialize};
+
+pub type Root<'a> = Vec<RootItem<'a>>;
+
+#[derive(Serialize, Deserialize)]
+pub struct RootItem<'a> {
+    #[serde(borrow)]
+    pub tags: Vec<&'a str>,
+    pub id: i64,
+}
+"#
+        );
+    }
+}
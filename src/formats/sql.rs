@@ -0,0 +1,105 @@
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use serde_json::Value;
+
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    #[default]
+    Sqlite,
+    Postgres,
+    Mysql,
+}
+
+impl Dialect {
+    fn ident(&self, name: &str) -> String {
+        match self {
+            Dialect::Mysql => format!("`{}`", name.replace('`', "``")),
+            _ => format!("\"{}\"", name.replace('"', "\"\"")),
+        }
+    }
+
+    fn string(&self, s: &str) -> String {
+        let mut out = String::with_capacity(s.len() + 2);
+        out.push('\'');
+        for c in s.chars() {
+            match c {
+                '\'' => out.push_str("''"),
+                // MySQL treats backslashes as escapes inside string literals by default
+                '\\' if *self == Dialect::Mysql => out.push_str("\\\\"),
+                c => out.push(c),
+            }
+        }
+        out.push('\'');
+        out
+    }
+
+    fn literal(&self, value: &Value) -> String {
+        match value {
+            Value::Null => "NULL".to_string(),
+            Value::Bool(b) => match self {
+                // SQLite has no boolean type
+                Dialect::Sqlite => (*b as u8).to_string(),
+                _ => if *b { "TRUE" } else { "FALSE" }.to_string(),
+            },
+            Value::Number(n) => n.to_string(),
+            Value::String(s) => self.string(s),
+            // Nested values are stored as JSON text
+            nested => self.string(&nested.to_string()),
+        }
+    }
+}
+
+/// Render a list of maps (or a single map) as one `INSERT` statement per row.
+///
+/// Each statement names only the columns present in its row, so columns missing from a row keep
+/// their database default.
+pub fn to_string(value: &Value, table: &str, dialect: Dialect) -> Result<String> {
+    let rows = match value {
+        Value::Array(rows) => rows.as_slice(),
+        row => std::slice::from_ref(row),
+    };
+    let table = dialect.ident(table);
+    let mut out = String::new();
+    for (i, row) in rows.iter().enumerate() {
+        let Value::Object(row) = row else {
+            bail!("SQL output requires a list of maps, but item {i} is not a map");
+        };
+        if row.is_empty() {
+            out.push_str(&format!("INSERT INTO {table} DEFAULT VALUES;\n"));
+            continue;
+        }
+        let columns: Vec<String> = row.keys().map(|k| dialect.ident(k)).collect();
+        let values: Vec<String> = row.values().map(|v| dialect.literal(v)).collect();
+        out.push_str(&format!(
+            "INSERT INTO {table} ({}) VALUES ({});\n",
+            columns.join(", "),
+            values.join(", ")
+        ));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_string() {
+        let value = json!([
+            {"name": "O'Brien", "admin": true, "tags": ["a"]},
+            {"name": "back\\slash", "age": null}
+        ]);
+        assert_eq!(
+            to_string(&value, "users", Dialect::Sqlite).unwrap(),
+            "INSERT INTO \"users\" (\"name\", \"admin\", \"tags\") VALUES ('O''Brien', 1, '[\"a\"]');\n\
+             INSERT INTO \"users\" (\"name\", \"age\") VALUES ('back\\slash', NULL);\n"
+        );
+        assert_eq!(
+            to_string(&value, "users", Dialect::Mysql).unwrap(),
+            "INSERT INTO `users` (`name`, `admin`, `tags`) VALUES ('O''Brien', TRUE, '[\"a\"]');\n\
+             INSERT INTO `users` (`name`, `age`) VALUES ('back\\\\slash', NULL);\n"
+        );
+        assert!(to_string(&json!([1]), "t", Dialect::Postgres).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use super::BytesRepr;
+use anyhow::{anyhow, bail, Context, Result};
+use rusqlite::{types::ValueRef, Connection, OpenFlags};
+use serde_json::{Map, Number, Value};
+use std::io::Write;
+
+/// Read the rows of a table, or of an arbitrary query, into a list of maps keyed by column name.
+///
+/// With neither, the database must contain exactly one table. Blobs are rendered per `bytes`.
+pub fn from_bytes(input: &[u8], table: Option<&str>, query: Option<&str>, bytes: BytesRepr) -> Result<Value> {
+    // SQLite only opens files
+    let mut file = tempfile::NamedTempFile::new()?;
+    file.write_all(input)?;
+    let conn = Connection::open_with_flags(file.path(), OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    conn.query_row("PRAGMA schema_version", [], |_| Ok(()))
+        .context("Input is not a SQLite database")?;
+
+    let sql = match (table, query) {
+        (Some(_), Some(_)) => bail!("Use either --table or --query, not both"),
+        (_, Some(query)) => query.to_string(),
+        (Some(table), None) => format!("SELECT * FROM {}", quote_ident(table)),
+        (None, None) => {
+            let mut stmt = conn.prepare(
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+            )?;
+            let tables = stmt
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            match tables.as_slice() {
+                [table] => format!("SELECT * FROM {}", quote_ident(table)),
+                [] => bail!("Database has no tables"),
+                _ => bail!("Database has several tables, choose one with --table: {}", tables.join(", ")),
+            }
+        }
+    };
+
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| anyhow!("Invalid query '{sql}': {e}"))?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let mut rows = stmt.query([])?;
+    let mut out = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut map = Map::new();
+        for (i, column) in columns.iter().enumerate() {
+            let value = match row.get_ref(i)? {
+                ValueRef::Null => Value::Null,
+                ValueRef::Integer(i) => Value::Number(i.into()),
+                ValueRef::Real(f) => Number::from_f64(f).map(Value::Number).unwrap_or(Value::Null),
+                ValueRef::Text(t) => Value::String(String::from_utf8_lossy(t).into_owned()),
+                ValueRef::Blob(b) => bytes.render(b),
+            };
+            map.insert(column.clone(), value);
+        }
+        out.push(Value::Object(map));
+    }
+    Ok(Value::Array(out))
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_bytes() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let conn = Connection::open(file.path()).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE hosts (name TEXT, port INTEGER, weight REAL, key BLOB);
+             INSERT INTO hosts VALUES ('a', 80, 0.5, x'0102'), ('b', NULL, 1.0, NULL);",
+        )
+        .unwrap();
+        drop(conn);
+        let input = std::fs::read(file.path()).unwrap();
+
+        let rows = from_bytes(&input, None, None, BytesRepr::Hex).unwrap();
+        assert_eq!(
+            rows,
+            json!([
+                {"name": "a", "port": 80, "weight": 0.5, "key": "0102"},
+                {"name": "b", "port": null, "weight": 1.0, "key": null}
+            ])
+        );
+        assert_eq!(
+            from_bytes(&input, None, Some("SELECT name FROM hosts WHERE port = 80"), BytesRepr::Hex).unwrap(),
+            json!([{"name": "a"}])
+        );
+        assert!(from_bytes(&input, Some("missing"), None, BytesRepr::Hex).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+use toml_edit::Item;
+
+use super::json::PlainNumbers;
+use crate::diff::Change;
+
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum TableStyle {
+    /// Nested maps become `[table]` headers and lists of maps `[[array.of.tables]]`
+    #[default]
+    Table,
+    /// Every top-level key is written on a single line using inline tables and arrays
+    Inline,
+}
+
+/// Serialize `obj` as a TOML document, reporting the path of any node TOML cannot represent.
+pub fn to_string<T: Serialize + ?Sized>(obj: &T, style: TableStyle) -> Result<String> {
+    let mut value = serde_json::to_value(obj)?;
+    check(&value)?;
+    drop_nulls(&mut value);
+
+    let s = ::toml::to_string(&PlainNumbers(&value)).map_err(|e| anyhow!("Failed to serialize TOML: {e}"))?;
+
+    match style {
+        TableStyle::Table => Ok(s),
+        TableStyle::Inline => inline(&s),
+    }
+}
+
+/// Check that `value` can be written as a TOML document.
+pub fn check(value: &Value) -> Result<()> {
+    match value {
+        Value::Object(map) => {
+            for (key, val) in map {
+                check_node(val, key, false)?;
+            }
+            Ok(())
+        }
+        other => bail!(
+            "TOML documents must be a table at the top level, found {}. Wrap the input under a key first.",
+            kind(other)
+        ),
+    }
+}
+
+fn check_node(value: &Value, path: &str, in_array: bool) -> Result<()> {
+    match value {
+        Value::Null if in_array => bail!("TOML cannot represent null at '{path}'"),
+        Value::Null => {
+            log::warn!("Dropping null at '{path}', TOML has no null value");
+        }
+        Value::Object(map) => {
+            for (key, val) in map {
+                check_node(val, &format!("{path}.{key}"), false)?;
+            }
+        }
+        Value::Array(seq) => {
+            for (i, item) in seq.iter().enumerate() {
+                check_node(item, &format!("{path}[{i}]"), true)?;
+            }
+        }
+        Value::Number(n) if !n.is_i64() && !n.is_f64() => {
+            bail!("TOML integers are 64-bit signed, {n} at '{path}' is out of range")
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// Remove the nulls of maps, which `check` has warned about, as TOML has no way to write them
+fn drop_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, val| !val.is_null());
+            map.values_mut().for_each(drop_nulls);
+        }
+        Value::Array(seq) => seq.iter_mut().for_each(drop_nulls),
+        _ => {}
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "a table",
+    }
+}
+
+fn inline(s: &str) -> Result<String> {
+    let mut doc: toml_edit::DocumentMut = s.parse()?;
+    let table = doc.as_table_mut();
+    let keys: Vec<String> = table.iter().map(|(k, _)| k.to_string()).collect();
+    for key in keys {
+        if let Some(item) = table.get_mut(&key) {
+            let taken = std::mem::take(item);
+            *item = match taken.into_value() {
+                Ok(v) => toml_edit::Item::Value(v),
+                Err(i) => i,
+            };
+        }
+        // A table header's key has no room around it, unlike `key = value`
+        if let Some(mut key) = table.key_mut(&key) {
+            key.leaf_decor_mut().clear();
+        }
+    }
+    Ok(doc.to_string())
+}
+
+/// Apply `changes` to a parsed document, keeping the comments and layout of everything else.
+///
+/// Returns false if some change has no place in the document, such as a `null`.
+pub fn apply_changes(doc: &mut toml_edit::DocumentMut, changes: &[Change]) -> bool {
+    changes.iter().all(|change| apply_change(doc.as_item_mut(), change).is_some())
+}
+
+fn apply_change(root: &mut Item, change: &Change) -> Option<()> {
+    let (last, parents) = change.path().split_last()?;
+    let mut parent = root;
+    for segment in parents {
+        parent = index_mut(parent, segment)?;
+    }
+    match change {
+        Change::Changed(_, _, new) => {
+            let item = index_mut(parent, last)?;
+            // Keep the whitespace and trailing comment around a replaced value
+            match (item.as_value().map(|v| v.decor().clone()), new.is_object()) {
+                (Some(decor), false) => {
+                    let mut value = to_edit_value(new)?;
+                    *value.decor_mut() = decor;
+                    *item = Item::Value(value);
+                }
+                _ => *item = to_edit_item(new)?,
+            }
+        }
+        Change::Added(_, new) => match parent {
+            Item::Table(table) => {
+                table.insert(last.as_str(), to_edit_item(new)?);
+            }
+            Item::Value(toml_edit::Value::InlineTable(table)) => {
+                table.insert(last.as_str(), to_edit_value(new)?);
+            }
+            Item::Value(toml_edit::Value::Array(array)) => array.push(to_edit_value(new)?),
+            Item::ArrayOfTables(array) => array.push(to_edit_item(new)?.into_table().ok()?),
+            _ => return None,
+        },
+        Change::Removed(..) => match parent {
+            Item::Table(table) => {
+                table.remove(last.as_str())?;
+            }
+            Item::Value(toml_edit::Value::InlineTable(table)) => {
+                table.remove(last.as_str())?;
+            }
+            Item::Value(toml_edit::Value::Array(array)) => {
+                let i: usize = last.parse().ok()?;
+                if i >= array.len() {
+                    return None;
+                }
+                array.remove(i);
+            }
+            Item::ArrayOfTables(array) => {
+                let i: usize = last.parse().ok()?;
+                if i >= array.len() {
+                    return None;
+                }
+                array.remove(i);
+            }
+            _ => return None,
+        },
+    }
+    Some(())
+}
+
+fn index_mut<'a>(item: &'a mut Item, segment: &str) -> Option<&'a mut Item> {
+    match item {
+        Item::ArrayOfTables(_) | Item::Value(toml_edit::Value::Array(_)) => item.get_mut(segment.parse::<usize>().ok()?),
+        _ => item.get_mut(segment),
+    }
+}
+
+// Maps become `[table]`s, everything else a value
+fn to_edit_item(value: &Value) -> Option<Item> {
+    Some(match to_edit_value(value)? {
+        toml_edit::Value::InlineTable(table) => Item::Table(table.into_table()),
+        other => Item::Value(other),
+    })
+}
+
+fn to_edit_value(value: &Value) -> Option<toml_edit::Value> {
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(b) => (*b).into(),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into(),
+            None => n.as_f64()?.into(),
+        },
+        Value::String(s) => s.as_str().into(),
+        Value::Array(items) => {
+            let mut array = toml_edit::Array::new();
+            for item in items {
+                array.push(to_edit_value(item)?);
+            }
+            array.into()
+        }
+        Value::Object(map) => {
+            let mut table = toml_edit::InlineTable::new();
+            for (key, val) in map {
+                table.insert(key.as_str(), to_edit_value(val)?);
+            }
+            table.into()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_check_reports_path() {
+        let value = json!({"servers": [{"ports": [80, null]}]});
+        let err = check(&value).unwrap_err();
+        assert_eq!(err.to_string(), "TOML cannot represent null at 'servers[0].ports[1]'");
+
+        assert!(check(&json!([1, 2])).is_err());
+        assert!(check(&json!({"a": null, "b": {"c": 1}})).is_ok());
+
+        let value: Value = serde_json::from_str(r#"{"a": {"id": 18446744073709551615, "x": 1.5}}"#).unwrap();
+        assert_eq!(
+            check(&value).unwrap_err().to_string(),
+            "TOML integers are 64-bit signed, 18446744073709551615 at 'a.id' is out of range"
+        );
+    }
+
+    #[test]
+    fn test_inline_style() {
+        let value = json!({"name": "x", "owner": {"id": 1}, "items": [{"a": 1}, {"a": 2}]});
+        let s = to_string(&value, TableStyle::Inline).unwrap();
+        assert!(s.contains("owner = { id = 1 }"));
+        assert!(s.contains("items = ["));
+        assert!(!s.contains("[["));
+    }
+
+    #[test]
+    fn test_nulls_dropped() {
+        let value = json!({"a": null, "b": 1, "c": {"d": null}});
+        assert_eq!(to_string(&value, TableStyle::Table).unwrap(), "b = 1\n\n[c]\n");
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let input = "# settings\nname = \"app\" # the name\nport = 80\n\n[db]\n# primary\nhost = \"a\"\nold = 1\n";
+        let mut doc: toml_edit::DocumentMut = input.parse().unwrap();
+        let original: Value = ::toml::from_str(input).unwrap();
+        let mut edited = original.clone();
+        edited["name"] = json!("web");
+        edited["db"].as_object_mut().unwrap().remove("old");
+        edited["db"]["user"] = json!("admin");
+
+        assert!(apply_changes(&mut doc, &crate::diff::diff(&original, &edited)));
+        assert_eq!(
+            doc.to_string(),
+            "# settings\nname = \"web\" # the name\nport = 80\n\n[db]\n# primary\nhost = \"a\"\nuser = \"admin\"\n"
+        );
+    }
+}
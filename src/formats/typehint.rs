@@ -0,0 +1,448 @@
+//! Type hints for formats that aren't self-describing, such as bincode and postcard.
+//!
+//! A hint describes the shape of the encoded data:
+//!
+//! ```text
+//! type  := bool | u8 | u16 | u32 | u64 | i8 | i16 | i32 | i64 | f32 | f64
+//!        | char | string | bytes | unit
+//!        | [type]                    a list
+//!        | (type, ...)               a tuple
+//!        | {field: type, ...}        a struct, fields in declaration order
+//!        | option<type> | map<type, type>
+//! ```
+//!
+//! e.g. `{name: string, port: u16, tags: [string], owner: option<string>}`.
+//! The same hint drives decoding and encoding, so data survives a round trip.
+
+use crate::formats::BytesRepr;
+use anyhow::{anyhow, bail, Result};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq, SerializeTuple, Serializer};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeHint {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    Char,
+    String,
+    Bytes,
+    Unit,
+    Seq(Box<TypeHint>),
+    Tuple(Vec<TypeHint>),
+    Struct(Vec<(String, TypeHint)>),
+    Option(Box<TypeHint>),
+    Map(Box<TypeHint>, Box<TypeHint>),
+}
+
+impl std::str::FromStr for TypeHint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = HintParser { s, pos: 0 };
+        let hint = parser.hint()?;
+        parser.skip_ws();
+        if parser.pos != s.len() {
+            bail!("Unexpected '{}' in type hint", &s[parser.pos..]);
+        }
+        Ok(hint)
+    }
+}
+
+struct HintParser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> HintParser<'a> {
+    fn skip_ws(&mut self) {
+        let rest = &self.s[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_ws();
+        if self.s[self.pos..].starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if !self.eat(c) {
+            bail!("Expected '{c}' at position {} of type hint", self.pos);
+        }
+        Ok(())
+    }
+
+    fn ident(&mut self) -> Result<&'a str> {
+        self.skip_ws();
+        let rest: &'a str = &self.s[self.pos..];
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            bail!("Expected a name at position {} of type hint", self.pos);
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn hint(&mut self) -> Result<TypeHint> {
+        if self.eat('[') {
+            let inner = self.hint()?;
+            self.expect(']')?;
+            return Ok(TypeHint::Seq(Box::new(inner)));
+        }
+        if self.eat('(') {
+            let mut items = Vec::new();
+            if !self.eat(')') {
+                loop {
+                    items.push(self.hint()?);
+                    if self.eat(')') {
+                        break;
+                    }
+                    self.expect(',')?;
+                }
+            }
+            return Ok(TypeHint::Tuple(items));
+        }
+        if self.eat('{') {
+            let mut fields = Vec::new();
+            if !self.eat('}') {
+                loop {
+                    let name = self.ident()?.to_string();
+                    self.expect(':')?;
+                    fields.push((name, self.hint()?));
+                    if self.eat('}') {
+                        break;
+                    }
+                    self.expect(',')?;
+                }
+            }
+            return Ok(TypeHint::Struct(fields));
+        }
+
+        let name = self.ident()?;
+        Ok(match name {
+            "bool" => TypeHint::Bool,
+            "u8" => TypeHint::U8,
+            "u16" => TypeHint::U16,
+            "u32" => TypeHint::U32,
+            "u64" | "usize" => TypeHint::U64,
+            "i8" => TypeHint::I8,
+            "i16" => TypeHint::I16,
+            "i32" => TypeHint::I32,
+            "i64" | "isize" => TypeHint::I64,
+            "f32" => TypeHint::F32,
+            "f64" => TypeHint::F64,
+            "char" => TypeHint::Char,
+            "string" | "String" | "str" => TypeHint::String,
+            "bytes" => TypeHint::Bytes,
+            "unit" => TypeHint::Unit,
+            "option" | "Option" => {
+                self.expect('<')?;
+                let inner = self.hint()?;
+                self.expect('>')?;
+                TypeHint::Option(Box::new(inner))
+            }
+            "map" | "Map" => {
+                self.expect('<')?;
+                let key = self.hint()?;
+                self.expect(',')?;
+                let value = self.hint()?;
+                self.expect('>')?;
+                TypeHint::Map(Box::new(key), Box::new(value))
+            }
+            other => bail!("Unknown type '{other}' in type hint"),
+        })
+    }
+}
+
+/// Decodes a value shaped like `hint` into a [`Value`]
+#[derive(Clone, Copy)]
+pub struct HintSeed<'a> {
+    pub hint: &'a TypeHint,
+    pub bytes: BytesRepr,
+}
+
+impl<'a> HintSeed<'a> {
+    fn with(self, hint: &'a TypeHint) -> Self {
+        HintSeed { hint, bytes: self.bytes }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for HintSeed<'_> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        Ok(match self.hint {
+            TypeHint::Bool => bool::deserialize(d)?.into(),
+            TypeHint::U8 => u8::deserialize(d)?.into(),
+            TypeHint::U16 => u16::deserialize(d)?.into(),
+            TypeHint::U32 => u32::deserialize(d)?.into(),
+            TypeHint::U64 => u64::deserialize(d)?.into(),
+            TypeHint::I8 => i8::deserialize(d)?.into(),
+            TypeHint::I16 => i16::deserialize(d)?.into(),
+            TypeHint::I32 => i32::deserialize(d)?.into(),
+            TypeHint::I64 => i64::deserialize(d)?.into(),
+            TypeHint::F32 => f32::deserialize(d)?.into(),
+            TypeHint::F64 => f64::deserialize(d)?.into(),
+            TypeHint::Char => char::deserialize(d)?.to_string().into(),
+            TypeHint::String => String::deserialize(d)?.into(),
+            TypeHint::Bytes => self.bytes.render(&Vec::<u8>::deserialize(d)?),
+            TypeHint::Unit => {
+                <()>::deserialize(d)?;
+                Value::Null
+            }
+            TypeHint::Seq(_) => d.deserialize_seq(HintVisitor(self))?,
+            TypeHint::Tuple(items) => d.deserialize_tuple(items.len(), HintVisitor(self))?,
+            TypeHint::Struct(fields) => d.deserialize_tuple(fields.len(), HintVisitor(self))?,
+            TypeHint::Option(_) => d.deserialize_option(HintVisitor(self))?,
+            TypeHint::Map(..) => d.deserialize_map(HintVisitor(self))?,
+        })
+    }
+}
+
+struct HintVisitor<'a>(HintSeed<'a>);
+
+impl<'de> Visitor<'de> for HintVisitor<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a value matching the type hint {:?}", self.0.hint)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        match self.0.hint {
+            TypeHint::Option(inner) => self.0.with(inner).deserialize(d),
+            _ => Err(de::Error::custom("unexpected option")),
+        }
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let missing = || -> A::Error { de::Error::custom("sequence ended early") };
+        match self.0.hint {
+            TypeHint::Seq(inner) => {
+                let mut items = Vec::new();
+                while let Some(item) = seq.next_element_seed(self.0.with(inner))? {
+                    items.push(item);
+                }
+                Ok(Value::Array(items))
+            }
+            TypeHint::Tuple(hints) => {
+                let mut items = Vec::new();
+                for hint in hints {
+                    items.push(seq.next_element_seed(self.0.with(hint))?.ok_or_else(missing)?);
+                }
+                Ok(Value::Array(items))
+            }
+            TypeHint::Struct(fields) => {
+                let mut map = Map::new();
+                for (name, hint) in fields {
+                    let value = seq.next_element_seed(self.0.with(hint))?.ok_or_else(missing)?;
+                    map.insert(name.clone(), value);
+                }
+                Ok(Value::Object(map))
+            }
+            _ => Err(de::Error::custom("unexpected sequence")),
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Value, A::Error> {
+        let TypeHint::Map(key_hint, value_hint) = self.0.hint else {
+            return Err(de::Error::custom("unexpected map"));
+        };
+        let mut map = Map::new();
+        while let Some(key) = access.next_key_seed(self.0.with(key_hint))? {
+            let value = access.next_value_seed(self.0.with(value_hint))?;
+            let key = match key {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            map.insert(key, value);
+        }
+        Ok(Value::Object(map))
+    }
+}
+
+/// Serializes `value` with the exact layout described by `hint`
+pub struct Hinted<'a> {
+    pub value: &'a Value,
+    pub hint: &'a TypeHint,
+    /// How `bytes` values are written in the input
+    pub bytes: BytesRepr,
+}
+
+impl Hinted<'_> {
+    fn with<'b>(&self, value: &'b Value, hint: &'b TypeHint) -> Hinted<'b> {
+        Hinted { value, hint, bytes: self.bytes }
+    }
+
+    fn int<T: TryFrom<i128>, S: Serializer>(&self) -> Result<T, S::Error> {
+        let n = self
+            .value
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| self.value.as_u64().map(i128::from))
+            .ok_or_else(|| -> S::Error {
+                ser::Error::custom(format!("expected an integer, found {}", self.value))
+            })?;
+        T::try_from(n).map_err(|_| ser::Error::custom(format!("{n} does not fit in {:?}", self.hint)))
+    }
+}
+
+impl Serialize for Hinted<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mismatch = || -> S::Error {
+            ser::Error::custom(format!("{} does not match the type hint {:?}", self.value, self.hint))
+        };
+        let hint = self.hint;
+        match hint {
+            TypeHint::Bool => s.serialize_bool(self.value.as_bool().ok_or_else(mismatch)?),
+            TypeHint::U8 => s.serialize_u8(self.int::<_, S>()?),
+            TypeHint::U16 => s.serialize_u16(self.int::<_, S>()?),
+            TypeHint::U32 => s.serialize_u32(self.int::<_, S>()?),
+            TypeHint::U64 => s.serialize_u64(self.int::<_, S>()?),
+            TypeHint::I8 => s.serialize_i8(self.int::<_, S>()?),
+            TypeHint::I16 => s.serialize_i16(self.int::<_, S>()?),
+            TypeHint::I32 => s.serialize_i32(self.int::<_, S>()?),
+            TypeHint::I64 => s.serialize_i64(self.int::<_, S>()?),
+            TypeHint::F32 => s.serialize_f32(self.value.as_f64().ok_or_else(mismatch)? as f32),
+            TypeHint::F64 => s.serialize_f64(self.value.as_f64().ok_or_else(mismatch)?),
+            TypeHint::Char => {
+                let mut chars = self.value.as_str().ok_or_else(mismatch)?.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => s.serialize_char(c),
+                    _ => Err(mismatch()),
+                }
+            }
+            TypeHint::String => s.serialize_str(self.value.as_str().ok_or_else(mismatch)?),
+            TypeHint::Bytes => s.serialize_bytes(&self.bytes.parse(self.value).ok_or_else(mismatch)?),
+            TypeHint::Unit => s.serialize_unit(),
+            TypeHint::Seq(inner) => {
+                let items = self.value.as_array().ok_or_else(mismatch)?;
+                let mut seq = s.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(&self.with(item, inner))?;
+                }
+                seq.end()
+            }
+            TypeHint::Tuple(hints) => {
+                let items = self.value.as_array().ok_or_else(mismatch)?;
+                if items.len() != hints.len() {
+                    return Err(mismatch());
+                }
+                let mut tuple = s.serialize_tuple(hints.len())?;
+                for (item, hint) in items.iter().zip(hints) {
+                    tuple.serialize_element(&self.with(item, hint))?;
+                }
+                tuple.end()
+            }
+            TypeHint::Struct(fields) => {
+                let map = self.value.as_object().ok_or_else(mismatch)?;
+                let mut tuple = s.serialize_tuple(fields.len())?;
+                for (name, hint) in fields {
+                    let value = map.get(name).unwrap_or(&Value::Null);
+                    tuple.serialize_element(&self.with(value, hint))?;
+                }
+                tuple.end()
+            }
+            TypeHint::Option(inner) => match self.value {
+                Value::Null => s.serialize_none(),
+                value => s.serialize_some(&self.with(value, inner)),
+            },
+            TypeHint::Map(key_hint, value_hint) => {
+                let map = self.value.as_object().ok_or_else(mismatch)?;
+                let mut out = s.serialize_map(Some(map.len()))?;
+                for (key, value) in map {
+                    // Keys arrive as strings, so numeric keys are parsed back
+                    let key = match **key_hint {
+                        TypeHint::String | TypeHint::Char => Value::String(key.clone()),
+                        _ => serde_json::from_str(key).map_err(|_| mismatch())?,
+                    };
+                    out.serialize_entry(
+                        &self.with(&key, key_hint),
+                        &self.with(value, value_hint),
+                    )?;
+                }
+                out.end()
+            }
+        }
+    }
+}
+
+pub fn parse(hint: Option<&str>, format: &str) -> Result<TypeHint> {
+    hint.ok_or_else(|| anyhow!("{format} is not self-describing, describe the data with --type-hint"))?
+        .parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse() {
+        let hint: TypeHint = "{name: string, ports: [u16], owner: option<(u8, bool)>}".parse().unwrap();
+        assert_eq!(
+            hint,
+            TypeHint::Struct(vec![
+                ("name".to_string(), TypeHint::String),
+                ("ports".to_string(), TypeHint::Seq(Box::new(TypeHint::U16))),
+                (
+                    "owner".to_string(),
+                    TypeHint::Option(Box::new(TypeHint::Tuple(vec![TypeHint::U8, TypeHint::Bool])))
+                ),
+            ])
+        );
+        assert!("{name string}".parse::<TypeHint>().is_err());
+        assert!("float".parse::<TypeHint>().is_err());
+    }
+
+    #[test]
+    fn test_postcard_round_trip() {
+        let hint: TypeHint = "{name: string, port: u16, tags: [string], owner: option<string>, env: map<string, i32>}"
+            .parse()
+            .unwrap();
+        let value = json!({"name": "app", "port": 8080, "tags": ["a", "b"], "owner": null, "env": {"A": -1}});
+
+        let encoded = postcard::to_allocvec(&Hinted { value: &value, hint: &hint, bytes: BytesRepr::Array }).unwrap();
+        let mut de = postcard::Deserializer::from_bytes(&encoded);
+        let decoded = HintSeed { hint: &hint, bytes: BytesRepr::Array }.deserialize(&mut de).unwrap();
+        assert_eq!(decoded, value);
+
+        assert!(postcard::to_allocvec(&Hinted { value: &json!({"port": 70000}), hint: &hint, bytes: BytesRepr::Array }).is_err());
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let hint: TypeHint = "{data: bytes}".parse().unwrap();
+        let value = json!({"data": "dead"});
+
+        let encoded = postcard::to_allocvec(&Hinted { value: &value, hint: &hint, bytes: BytesRepr::Hex }).unwrap();
+        assert_eq!(encoded, [2, 0xde, 0xad]);
+        let mut de = postcard::Deserializer::from_bytes(&encoded);
+        assert_eq!(HintSeed { hint: &hint, bytes: BytesRepr::Hex }.deserialize(&mut de).unwrap(), value);
+
+        assert!(postcard::to_allocvec(&Hinted { value: &json!({"data": "xyz"}), hint: &hint, bytes: BytesRepr::Hex }).is_err());
+    }
+}
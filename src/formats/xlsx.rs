@@ -0,0 +1,68 @@
+use ::calamine::{open_workbook_auto_from_rs, Data, Reader};
+use anyhow::{anyhow, Context, Result};
+use serde_json::{Map, Number, Value};
+use std::io::Cursor;
+
+/// Read a worksheet into a list of maps keyed by the header row.
+///
+/// Without `sheet`, the first worksheet is used. Fully empty rows are skipped and empty cells are
+/// left out of their row's map. Also reads `.xls`, `.xlsb` and `.ods` workbooks.
+pub fn from_bytes(input: Vec<u8>, sheet: Option<&str>) -> Result<Value> {
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(input)).context("Input is not a spreadsheet")?;
+    let names = workbook.sheet_names();
+    let name = match sheet {
+        Some(sheet) => names
+            .iter()
+            .find(|n| *n == sheet)
+            .cloned()
+            .ok_or_else(|| anyhow!("No sheet named '{sheet}', the workbook has: {}", names.join(", ")))?,
+        None => names.first().cloned().ok_or_else(|| anyhow!("Workbook has no sheets"))?,
+    };
+    let range = workbook.worksheet_range(&name)?;
+
+    let mut rows = range.rows();
+    let Some(header) = rows.next() else {
+        return Ok(Value::Array(Vec::new()));
+    };
+    let header: Vec<String> = header
+        .iter()
+        .enumerate()
+        .map(|(i, cell)| match cell_value(cell) {
+            Value::Null => format!("column{}", i + 1),
+            Value::String(s) => s,
+            other => other.to_string(),
+        })
+        .collect();
+
+    let mut out = Vec::new();
+    for row in rows {
+        let mut map = Map::new();
+        for (key, cell) in header.iter().zip(row) {
+            let value = cell_value(cell);
+            if !value.is_null() {
+                map.insert(key.clone(), value);
+            }
+        }
+        if !map.is_empty() {
+            out.push(Value::Object(map));
+        }
+    }
+    Ok(Value::Array(out))
+}
+
+fn cell_value(cell: &Data) -> Value {
+    match cell {
+        Data::Empty => Value::Null,
+        Data::Int(i) => Value::Number((*i).into()),
+        // Spreadsheets store every number as a float
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => Value::Number((*f as i64).into()),
+        Data::Float(f) => Number::from_f64(*f).map(Value::Number).unwrap_or(Value::Null),
+        Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s) => Value::String(s.clone()),
+        Data::Bool(b) => Value::Bool(*b),
+        Data::DateTime(dt) => match dt.as_datetime() {
+            Some(dt) => Value::String(dt.format("%Y-%m-%dT%H:%M:%S").to_string()),
+            None => Number::from_f64(dt.as_f64()).map(Value::Number).unwrap_or(Value::Null),
+        },
+        Data::Error(e) => Value::String(format!("#{e:?}")),
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::{bail, Result};
+use quick_xml::events::Event;
+use quick_xml::se::Serializer;
+use quick_xml::{Reader, Writer};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Write `value` as an XML document under a `root` element.
+///
+/// Map keys starting with `attr_prefix` become attributes of their parent element and a `#text`
+/// key becomes its text content, matching the layout tools like xmltodict read. Lists repeat their
+/// element once per item, and a list at the top level is written as `<item>` elements.
+pub fn to_string(value: Value, root: &str, attr_prefix: &str) -> Result<String> {
+    let value = match rename(value, attr_prefix)? {
+        Value::Array(items) => {
+            let mut map = Map::new();
+            map.insert("item".to_string(), Value::Array(items));
+            Value::Object(map)
+        }
+        other => other,
+    };
+
+    let mut compact = String::new();
+    value.serialize(Serializer::with_root(&mut compact, Some(root))?)?;
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&indent(&compact)?);
+    out.push('\n');
+    Ok(out)
+}
+
+// Lay out elements one per line. The serializer's own indentation would also wrap text content
+// in whitespace, changing it, where quick-xml's writer keeps text on its element's line.
+fn indent(xml: &str) -> Result<String> {
+    let mut reader = Reader::from_str(xml);
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+    loop {
+        match reader.read_event()? {
+            Event::Eof => break,
+            event => writer.write_event(event)?,
+        }
+    }
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+// Translate keys to quick-xml's conventions: `@name` for attributes and `$text` for text
+fn rename(value: Value, attr_prefix: &str) -> Result<Value> {
+    Ok(match value {
+        Value::Object(map) => {
+            let mut out = Map::new();
+            for (key, val) in map {
+                let key = match key.strip_prefix(attr_prefix) {
+                    Some(name) if !attr_prefix.is_empty() => {
+                        if val.is_object() || val.is_array() {
+                            bail!("Attribute '{key}' must be a scalar value");
+                        }
+                        format!("@{name}")
+                    }
+                    _ if key == "#text" => "$text".to_string(),
+                    _ => key,
+                };
+                out.insert(key, rename(val, attr_prefix)?);
+            }
+            Value::Object(out)
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| rename(item, attr_prefix))
+                .collect::<Result<_>>()?,
+        ),
+        // Written as their exact digits, which quick-xml can't get from a serde_json number
+        Value::Number(n) => Value::String(n.to_string()),
+        scalar => scalar,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_string() {
+        let value = json!({"-id": "1", "name": "x", "tags": ["a", "b"], "note": {"-lang": "en", "#text": "hi"}});
+        let xml = to_string(value, "config", "-").unwrap();
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<config id=\"1\">"));
+        assert!(xml.contains("<name>x</name>"));
+        assert!(xml.contains("<tags>a</tags>"));
+        assert!(xml.contains("<tags>b</tags>"));
+        assert!(xml.contains("<note lang=\"en\">hi</note>"));
+
+        let xml = to_string(json!({"a": {"b": " padded  text "}}), "root", "-").unwrap();
+        assert!(xml.ends_with("<root>\n  <a>\n    <b> padded  text </b>\n  </a>\n</root>\n"));
+
+        assert!(to_string(json!({"-bad": [1]}), "root", "-").is_err());
+    }
+}
@@ -0,0 +1,682 @@
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use serde::de::DeserializeSeed;
+use serde_yaml::{Mapping, Value};
+use std::{collections::HashMap, fmt::Write};
+use yaml_rust2::{
+    parser::{Event, Parser},
+    scanner::TScalarStyle,
+};
+
+use super::dupkeys::{DupKeys, DupSeed};
+use super::json::PlainNumbers;
+use crate::diff::Change;
+
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum QuoteStyle {
+    /// Quote strings only when they would otherwise be read back as another type
+    #[default]
+    Minimal,
+    /// Prefer 'single quotes' for string values
+    Single,
+    /// Prefer "double quotes" for string values
+    Double,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct YamlStyle {
+    /// Spaces per nesting level
+    pub indent: usize,
+    /// Write multi-line strings as `|` literal blocks instead of escaped flow scalars
+    pub block_scalars: bool,
+    pub quote: QuoteStyle,
+    /// Begin the document with an explicit `---` marker
+    pub document_start: bool,
+}
+
+impl Default for YamlStyle {
+    fn default() -> Self {
+        YamlStyle {
+            indent: 2,
+            block_scalars: false,
+            quote: QuoteStyle::Minimal,
+            document_start: false,
+        }
+    }
+}
+
+/// What happens to `&anchor`/`*alias` pairs and `<<` merge keys in YAML input
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum AliasPolicy {
+    /// Replace aliases with what they refer to and apply merge keys
+    #[default]
+    Expand,
+    /// Keep them as written when converting YAML to YAML, otherwise expand them
+    Preserve,
+    /// Refuse input containing aliases
+    Error,
+}
+
+/// Limits guarding against inputs whose aliases expand to huge documents
+#[derive(Debug, Clone, Copy)]
+pub struct AliasLimits {
+    /// Most nodes that aliases may expand to, across the whole input
+    pub nodes: usize,
+    /// Deepest nesting of collections, counting what aliases expand to
+    pub depth: usize,
+}
+
+struct Frame {
+    anchor: usize,
+    depth: usize,
+    reach: usize,
+    nodes: usize,
+}
+
+/// Walk the parse events of `input`, enforcing `policy` and `limits` before it is expanded.
+pub fn check_aliases(input: &str, policy: AliasPolicy, limits: AliasLimits) -> Result<()> {
+    let mut parser = Parser::new_from_str(input);
+    // Size and depth of each anchored node, so an alias weighs as much as what it expands to
+    let mut anchors: HashMap<usize, (usize, usize)> = HashMap::new();
+    let mut frames = vec![Frame { anchor: 0, depth: 0, reach: 0, nodes: 0 }];
+    let mut expanded = 0;
+
+    loop {
+        let (event, mark) = parser.next_token()?;
+        let top = frames.last_mut().expect("the root frame is never popped");
+        match event {
+            Event::StreamEnd => break,
+            Event::Alias(id) => {
+                if policy == AliasPolicy::Error {
+                    bail!("YAML alias at line {}, column {} (see --yaml-aliases)", mark.line(), mark.col() + 1);
+                }
+                let (nodes, depth) = anchors.get(&id).copied().unwrap_or((1, 0));
+                expanded += nodes;
+                top.nodes += nodes;
+                top.reach = top.reach.max(top.depth + depth);
+                if expanded > limits.nodes {
+                    bail!("YAML aliases expand to more than {} nodes (see --yaml-alias-limit)", limits.nodes);
+                }
+            }
+            Event::Scalar(_, _, anchor, _) => {
+                top.nodes += 1;
+                if anchor > 0 {
+                    anchors.insert(anchor, (1, 0));
+                }
+            }
+            Event::SequenceStart(anchor, _) | Event::MappingStart(anchor, _) => {
+                let depth = top.depth + 1;
+                frames.push(Frame { anchor, depth, reach: depth, nodes: 1 });
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                let frame = frames.pop().expect("collection ends match their starts");
+                if frame.reach > limits.depth {
+                    bail!("YAML input nests deeper than {} levels (see --yaml-max-depth)", limits.depth);
+                }
+                if frame.anchor > 0 {
+                    anchors.insert(frame.anchor, (frame.nodes, frame.reach - frame.depth + 1));
+                }
+                let parent = frames.last_mut().expect("the root frame is never popped");
+                parent.nodes += frame.nodes;
+                parent.reach = parent.reach.max(frame.reach);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Parse YAML input under `policy`, expanding aliases and applying `<<` merge keys. Repeated
+/// keys are resolved per `dup_keys`.
+pub fn from_slice(input: &[u8], policy: AliasPolicy, limits: AliasLimits, dup_keys: DupKeys) -> Result<Value> {
+    check_aliases(std::str::from_utf8(input)?, policy, limits)?;
+    let mut value: Value = match dup_keys {
+        // serde_yaml itself rejects repeated keys
+        DupKeys::Error => serde_yaml::from_slice(input)?,
+        _ => {
+            let value = DupSeed(dup_keys).deserialize(serde_yaml::Deserializer::from_slice(input))?;
+            serde_yaml::to_value(PlainNumbers(&value))?
+        }
+    };
+    value.apply_merge()?;
+    Ok(value)
+}
+
+/// Apply `changes` to YAML text as edits to the lines they touch, so comments and layout survive.
+///
+/// Single-line scalars are replaced in place, and keys are removed from or added to block
+/// mappings. For anything else, such as lists that grow or values reached through aliases, this
+/// returns None.
+pub fn apply_changes(input: &str, changes: &[Change]) -> Option<String> {
+    let layout = layout(input)?;
+    let mut edits = Vec::new();
+    for change in changes {
+        let edit = match change {
+            Change::Changed(path, _, new) => {
+                if new.is_object() || new.is_array() {
+                    return None;
+                }
+                let &(start, end) = layout.scalars.get(path)?;
+                let text = serde_yaml::to_string(&PlainNumbers(new)).ok()?;
+                let text = text.trim_end_matches('\n');
+                // Plain text could end a flow collection early
+                if text.contains('\n') || (!text.starts_with(['\'', '"']) && text.contains([',', '[', ']', '{', '}'])) {
+                    return None;
+                }
+                (start, end, text.to_string())
+            }
+            Change::Removed(path, _) => {
+                let &(start, end) = layout.entries.get(path)?;
+                (start, end, String::new())
+            }
+            Change::Added(path, new) => {
+                let (key, parent) = path.split_last()?;
+                let &(indent, end) = layout.maps.get(parent)?;
+                let mut entry = serde_json::Map::new();
+                entry.insert(key.clone(), new.clone());
+                let mut text = String::new();
+                if end > 0 && !input[..end].ends_with('\n') {
+                    text.push('\n');
+                }
+                for line in serde_yaml::to_string(&PlainNumbers(&entry)).ok()?.lines() {
+                    if !line.is_empty() {
+                        text.push_str(&" ".repeat(indent));
+                    }
+                    text.push_str(line);
+                    text.push('\n');
+                }
+                (end, end, text)
+            }
+        };
+        edits.push(edit);
+    }
+    // From the end, so earlier offsets stay valid, and keys added at one place keep their order
+    let mut edits: Vec<_> = edits.into_iter().enumerate().collect();
+    edits.sort_by_key(|(i, (start, _, _))| std::cmp::Reverse((*start, *i)));
+    let mut out = input.to_string();
+    for (_, (start, end, text)) in edits {
+        out.replace_range(start..end, &text);
+    }
+    Some(out)
+}
+
+enum Container {
+    // The pending key, whether the mapping is in block style, and where each key's line starts
+    Map(Option<String>, bool, Vec<(String, usize)>),
+    Seq(usize),
+}
+
+// Where the parts of a single-document input are, by path
+#[derive(Default)]
+struct Layout {
+    // Byte ranges of single-line scalar values
+    scalars: HashMap<Vec<String>, (usize, usize)>,
+    // The lines of each entry of a block mapping, from its key through its value
+    entries: HashMap<Vec<String>, (usize, usize)>,
+    // The column of each block mapping's keys, and where a key added after its last one goes
+    maps: HashMap<Vec<String>, (usize, usize)>,
+}
+
+fn layout(input: &str) -> Option<Layout> {
+    // Parser positions count characters
+    let offsets: Vec<usize> = input.char_indices().map(|(i, _)| i).chain([input.len()]).collect();
+    let mut parser = Parser::new_from_str(input);
+    let mut layout = Layout::default();
+    let mut stack: Vec<Container> = Vec::new();
+    let mut path: Vec<String> = Vec::new();
+    let mut documents = 0;
+
+    loop {
+        let (event, mark) = parser.next_token().ok()?;
+        let start = *offsets.get(mark.index())?;
+        // The key a node sits under, or None if the node is itself a key
+        let segment = match stack.last_mut() {
+            Some(Container::Map(key @ None, block, keys)) => match &event {
+                Event::Scalar(name, ..) => {
+                    *key = Some(name.clone());
+                    let line = line_start(input, start);
+                    // Keys sharing their line with something else, like `- a: 1`, can't be removed
+                    let own_line = input[line..start].trim().is_empty();
+                    keys.push((name.clone(), if *block && own_line { line } else { usize::MAX }));
+                    continue;
+                }
+                Event::MappingEnd => None,
+                // Collections and aliases as keys can't be addressed by path
+                _ => return None,
+            },
+            Some(Container::Map(Some(key), ..)) => Some(key.clone()),
+            Some(Container::Seq(i)) => Some(i.to_string()),
+            None => None,
+        };
+        match event {
+            Event::StreamEnd => break,
+            Event::DocumentStart => {
+                documents += 1;
+                if documents > 1 {
+                    return None;
+                }
+            }
+            Event::Scalar(value, style, anchor, _) => {
+                let end = match style {
+                    TScalarStyle::Plain => input[start..].starts_with(&value).then(|| start + value.len()),
+                    TScalarStyle::SingleQuoted => quoted_end(input, start, '\''),
+                    TScalarStyle::DoubleQuoted => quoted_end(input, start, '"'),
+                    _ => None,
+                };
+                // Editing an anchored scalar would also change its aliases
+                if let Some(end) = end.filter(|end| anchor == 0 && !input[start..*end].contains('\n')) {
+                    let mut full = path.clone();
+                    full.extend(segment);
+                    layout.scalars.insert(full, (start, end));
+                }
+                advance(&mut stack);
+            }
+            Event::Alias(_) => advance(&mut stack),
+            Event::SequenceStart(..) => {
+                path.extend(segment);
+                stack.push(Container::Seq(0));
+            }
+            Event::MappingStart(..) => {
+                path.extend(segment);
+                stack.push(Container::Map(None, !input[start..].starts_with('{'), Vec::new()));
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                if let Some(Container::Map(_, true, keys)) = stack.pop() {
+                    record_entries(input, &mut layout, &path, &keys, start);
+                }
+                if !stack.is_empty() {
+                    path.pop();
+                }
+                advance(&mut stack);
+            }
+            _ => {}
+        }
+    }
+    Some(layout)
+}
+
+// Record the lines of a block mapping's entries, each running to the next one's key, or to
+// `end` where the mapping ends, less the comments and blank lines before what follows
+fn record_entries(input: &str, layout: &mut Layout, path: &[String], keys: &[(String, usize)], end: usize) {
+    let Some(&(_, first)) = keys.first() else {
+        return;
+    };
+    let end = if end >= input.len() { input.len() } else { line_start(input, end) };
+    if first == usize::MAX {
+        return;
+    }
+    let indent = input[first..].len() - input[first..].trim_start_matches(' ').len();
+    for (i, (key, start)) in keys.iter().enumerate() {
+        let next = keys.get(i + 1).map_or(end, |(_, next)| *next);
+        if *start == usize::MAX || next == usize::MAX {
+            continue;
+        }
+        let mut full = path.to_vec();
+        full.push(key.clone());
+        layout.entries.insert(full, (*start, back_off(input, next, indent)));
+    }
+    layout.maps.insert(path.to_vec(), (indent, back_off(input, end, indent)));
+}
+
+fn line_start(input: &str, offset: usize) -> usize {
+    input[..offset].rfind('\n').map_or(0, |i| i + 1)
+}
+
+// Back up from `end`, at the start of a line, over blank lines and over comments indented no
+// further than `indent`, which belong with what follows
+fn back_off(input: &str, mut end: usize, indent: usize) -> usize {
+    while end > 0 {
+        let start = line_start(input, end - 1);
+        let line = &input[start..end];
+        let content = line.trim_start_matches(' ');
+        let column = line.len() - content.len();
+        if content.trim().is_empty() || (content.starts_with('#') && column <= indent) {
+            end = start;
+        } else {
+            break;
+        }
+    }
+    end
+}
+
+// Move past a value in the enclosing collection
+fn advance(stack: &mut [Container]) {
+    match stack.last_mut() {
+        Some(Container::Map(key, ..)) => *key = None,
+        Some(Container::Seq(i)) => *i += 1,
+        None => {}
+    }
+}
+
+fn quoted_end(input: &str, start: usize, quote: char) -> Option<usize> {
+    let mut chars = input[start..].char_indices();
+    if chars.next()?.1 != quote {
+        return None;
+    }
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if quote == '"' => {
+                chars.next();
+            }
+            // '' is an escaped quote inside single quotes
+            c if c == quote && quote == '\'' && input[start + i + 1..].starts_with('\'') => {
+                chars.next();
+            }
+            c if c == quote => return Some(start + i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Emit `value` as a YAML document laid out according to `style`.
+pub fn to_string(value: &Value, style: &YamlStyle) -> Result<String> {
+    if style.indent == 0 {
+        bail!("YAML indent must be at least 1");
+    }
+    let mut emitter = Emitter { style, out: String::new() };
+    if style.document_start {
+        emitter.out.push_str("---\n");
+    }
+    match value {
+        Value::Mapping(map) if !map.is_empty() => emitter.mapping(map, 0, false),
+        Value::Sequence(seq) if !seq.is_empty() => emitter.sequence(seq, 0, false),
+        other => {
+            emitter.inline_value(other, 0);
+        }
+    }
+    Ok(emitter.out)
+}
+
+struct Emitter<'a> {
+    style: &'a YamlStyle,
+    out: String,
+}
+
+impl Emitter<'_> {
+    fn pad(&mut self, indent: usize) {
+        self.out.push_str(&" ".repeat(indent));
+    }
+
+    fn mapping(&mut self, map: &Mapping, indent: usize, first_inline: bool) {
+        for (i, (key, value)) in map.iter().enumerate() {
+            if i > 0 || !first_inline {
+                self.pad(indent);
+            }
+            let key = self.key(key);
+            self.out.push_str(&key);
+            self.out.push(':');
+            self.child(value, indent);
+        }
+    }
+
+    fn sequence(&mut self, seq: &[Value], indent: usize, first_inline: bool) {
+        for (i, item) in seq.iter().enumerate() {
+            if i > 0 || !first_inline {
+                self.pad(indent);
+            }
+            self.out.push('-');
+            // Collections start on the dash line, so their entries line up two columns in
+            match item {
+                Value::Mapping(map) if !map.is_empty() => {
+                    self.out.push(' ');
+                    self.mapping(map, indent + 2, true);
+                }
+                Value::Sequence(seq) if !seq.is_empty() => {
+                    self.out.push(' ');
+                    self.sequence(seq, indent + 2, true);
+                }
+                other => self.child(other, indent),
+            }
+        }
+    }
+
+    // Writes the remainder of a line ending in `key:` or `-`, placing nested collections below it
+    fn child(&mut self, value: &Value, indent: usize) {
+        let child_indent = indent + self.style.indent;
+        match value {
+            Value::Mapping(map) if !map.is_empty() => {
+                self.out.push('\n');
+                self.mapping(map, child_indent, false);
+            }
+            Value::Sequence(seq) if !seq.is_empty() => {
+                self.out.push('\n');
+                self.sequence(seq, child_indent, false);
+            }
+            Value::Tagged(tagged) => {
+                write!(self.out, " {}", tagged.tag).unwrap();
+                self.child(&tagged.value, indent);
+            }
+            other => {
+                self.out.push(' ');
+                self.inline_value(other, child_indent);
+            }
+        }
+    }
+
+    fn inline_value(&mut self, value: &Value, indent: usize) {
+        match value {
+            Value::String(s) if self.style.block_scalars && is_block_safe(s) => {
+                self.block_scalar(s, indent);
+                return;
+            }
+            Value::String(s) => {
+                let s = self.string(s);
+                self.out.push_str(&s);
+            }
+            other => {
+                let s = flow(other);
+                self.out.push_str(&s);
+            }
+        }
+        self.out.push('\n');
+    }
+
+    fn block_scalar(&mut self, s: &str, indent: usize) {
+        self.out.push('|');
+        let body = s.trim_end_matches('\n');
+        match s.len() - body.len() {
+            0 => self.out.push('-'),
+            1 => {}
+            _ => self.out.push('+'),
+        }
+        self.out.push('\n');
+        for line in body.split('\n') {
+            if !line.is_empty() {
+                self.pad(indent);
+                self.out.push_str(line);
+            }
+            self.out.push('\n');
+        }
+        for _ in 1..s.len() - body.len() {
+            self.out.push('\n');
+        }
+    }
+
+    fn key(&self, key: &Value) -> String {
+        match key {
+            Value::String(s) => plain_or_double(s, false),
+            other => flow(other),
+        }
+    }
+
+    fn string(&self, s: &str) -> String {
+        match self.style.quote {
+            QuoteStyle::Minimal => plain_or_double(s, false),
+            QuoteStyle::Single if !s.chars().any(char::is_control) => {
+                format!("'{}'", s.replace('\'', "''"))
+            }
+            QuoteStyle::Single | QuoteStyle::Double => double_quoted(s),
+        }
+    }
+}
+
+fn flow(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => plain_or_double(s, true),
+        Value::Sequence(seq) => {
+            let items: Vec<String> = seq.iter().map(flow).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Mapping(map) => {
+            let items: Vec<String> = map
+                .iter()
+                .map(|(k, v)| format!("{}: {}", flow(k), flow(v)))
+                .collect();
+            format!("{{{}}}", items.join(", "))
+        }
+        Value::Tagged(tagged) => format!("{} {}", tagged.tag, flow(&tagged.value)),
+    }
+}
+
+// Literal blocks can't start with indentation or blank lines without an explicit indentation indicator
+fn is_block_safe(s: &str) -> bool {
+    s.contains('\n')
+        && !s.starts_with([' ', '\n'])
+        && !s.chars().any(|c| c.is_control() && c != '\n' && c != '\t')
+}
+
+fn plain_or_double(s: &str, in_flow: bool) -> String {
+    if is_plain_safe(s, in_flow) {
+        s.to_string()
+    } else {
+        double_quoted(s)
+    }
+}
+
+fn is_plain_safe(s: &str, in_flow: bool) -> bool {
+    if s.is_empty() || s.trim() != s || s.chars().any(char::is_control) {
+        return false;
+    }
+    if s.starts_with(|c| "-?:,[]{}#&*!|>'\"%@`".contains(c)) {
+        return false;
+    }
+    if s.contains(": ") || s.contains(" #") || s.ends_with(':') {
+        return false;
+    }
+    if in_flow && s.contains(|c| ",[]{}".contains(c)) {
+        return false;
+    }
+    // YAML 1.1 readers still treat these as booleans. The spec lists y and n too, but readers
+    // like PyYAML don't, and quoting them would garble common keys such as coordinates
+    if matches!(
+        s,
+        "yes" | "Yes" | "YES" | "no" | "No" | "NO" | "on" | "On" | "ON" | "off" | "Off" | "OFF"
+    ) {
+        return false;
+    }
+    // Anything that reads back as a bool, number or null needs quotes to stay a string
+    matches!(serde_yaml::from_str::<Value>(s), Ok(Value::String(ref p)) if p == s)
+}
+
+fn double_quoted(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\0' => out.push_str("\\0"),
+            c if c.is_control() => write!(out, "\\u{:04X}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emit(input: &str, style: &YamlStyle) -> String {
+        let value: Value = serde_yaml::from_str(input).unwrap();
+        let s = to_string(&value, style).unwrap();
+        let reparsed: Value = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(value, reparsed, "emitted YAML does not round trip:\n{s}");
+        s
+    }
+
+    #[test]
+    fn test_indent_and_document_start() {
+        let style = YamlStyle { indent: 4, document_start: true, ..Default::default() };
+        let s = emit("a:\n  b: [1, 2]\n  c:\n  - x: 1\n    y: 2\n", &style);
+        assert_eq!(s, "---\na:\n    b:\n        - 1\n        - 2\n    c:\n        - x: 1\n          y: 2\n");
+    }
+
+    #[test]
+    fn test_quoting() {
+        let input = "a: 'true'\nb: plain text\nc: 'x: y'\nd: '123'\n";
+        assert_eq!(
+            emit(input, &YamlStyle::default()),
+            "a: \"true\"\nb: plain text\nc: \"x: y\"\nd: \"123\"\n"
+        );
+        let style = YamlStyle { quote: QuoteStyle::Single, ..Default::default() };
+        assert_eq!(emit(input, &style), "a: 'true'\nb: 'plain text'\nc: 'x: y'\nd: '123'\n");
+        assert_eq!(emit("a: 'yes'\nb: y\n", &YamlStyle::default()), "a: \"yes\"\nb: y\n");
+    }
+
+    #[test]
+    fn test_block_scalars() {
+        let style = YamlStyle { block_scalars: true, ..Default::default() };
+        let s = emit("script: \"echo a\\necho b\\n\"\nlist:\n- \"x\\ny\"\n", &style);
+        assert_eq!(s, "script: |\n  echo a\n  echo b\nlist:\n  - |-\n    x\n    y\n");
+    }
+
+    #[test]
+    fn test_aliases() {
+        let limits = AliasLimits { nodes: 100, depth: 8 };
+        let input = "base: &base\n  image: nginx\n  restart: always\nweb:\n  <<: *base\n  restart: never\n";
+        let value = from_slice(input.as_bytes(), AliasPolicy::Expand, limits, DupKeys::Error).unwrap();
+        assert_eq!(
+            serde_yaml::to_string(&value["web"]).unwrap(),
+            "restart: never\nimage: nginx\n"
+        );
+        assert!(from_slice(input.as_bytes(), AliasPolicy::Error, limits, DupKeys::Error).is_err());
+
+        let bomb = "a: &a [x, x, x, x, x, x, x, x, x, x]\nb: &b [*a, *a, *a, *a, *a, *a, *a, *a, *a, *a]\nc: [*b, *b]\n";
+        assert!(check_aliases(bomb, AliasPolicy::Expand, limits).is_err());
+        let deep = "a: &a [[[[[[x]]]]]]\nb: [[[*a]]]\n";
+        assert!(check_aliases(deep, AliasPolicy::Expand, limits).is_err());
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let input = "# app\nname: app # the name\nports:\n  - 80\n  - '443'\ndb: {host: \"a\"}\n";
+        let original: serde_json::Value = serde_yaml::from_str(input).unwrap();
+        let mut edited = original.clone();
+        edited["name"] = serde_json::json!("web");
+        edited["ports"][1] = serde_json::json!(8443);
+        edited["db"]["host"] = serde_json::json!("it's");
+        let changes = crate::diff::diff(&original, &edited);
+        assert_eq!(
+            apply_changes(input, &changes).unwrap(),
+            "# app\nname: web # the name\nports:\n  - 80\n  - 8443\ndb: {host: it's}\n"
+        );
+
+        edited["ports"].as_array_mut().unwrap().push(serde_json::json!(1));
+        assert!(apply_changes(input, &crate::diff::diff(&original, &edited)).is_none());
+    }
+
+    #[test]
+    fn test_add_and_remove_keys() {
+        let input = "# app\nname: app\nport: 80 # public\n\n# the database\ndb:\n  host: a\n  user: b\n";
+        let original: serde_json::Value = serde_yaml::from_str(input).unwrap();
+        let mut edited = original.clone();
+        edited.as_object_mut().unwrap().shift_remove("port");
+        edited["db"].as_object_mut().unwrap().shift_remove("user");
+        edited["db"]["pool"] = serde_json::json!({"size": 5});
+        edited["debug"] = serde_json::json!(true);
+        let changes = crate::diff::diff(&original, &edited);
+        assert_eq!(
+            apply_changes(input, &changes).unwrap(),
+            "# app\nname: app\n\n# the database\ndb:\n  host: a\n  pool:\n    size: 5\ndebug: true\n"
+        );
+    }
+}
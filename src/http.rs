@@ -0,0 +1,147 @@
+use anyhow::{bail, Context, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+// Headers sent with every request, like `Authorization: Bearer ...`
+static HEADERS: OnceLock<Vec<(String, String)>> = OnceLock::new();
+
+/// Send these `Name: value` headers when fetching URLs
+pub fn set_headers(headers: &[String]) -> Result<()> {
+    let mut parsed = Vec::new();
+    for header in headers {
+        let Some((name, value)) = header.split_once(':') else {
+            bail!("'{header}' should be a header like 'Authorization: Bearer <token>'");
+        };
+        parsed.push((name.trim().to_string(), value.trim().to_string()));
+    }
+    let _ = HEADERS.set(parsed);
+    Ok(())
+}
+
+/// Whether `path` is an http(s) URL rather than a file
+pub fn is_url(path: &Path) -> bool {
+    path.to_str().is_some_and(|p| p.starts_with("http://") || p.starts_with("https://"))
+}
+
+/// `path` without a URL's query and fragment, so its format can be told from its extension
+pub fn name_of(path: &Path) -> PathBuf {
+    match path.to_str() {
+        Some(url) if is_url(path) => PathBuf::from(url.split(['?', '#']).next().unwrap_or(url)),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// The content of a file, or of a URL fetched over http(s)
+pub fn read(path: &Path) -> Result<Vec<u8>> {
+    if is_url(path) {
+        let url = path.to_string_lossy();
+        fetch(&url).with_context(|| format!("Failed to fetch {url}"))
+    } else {
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+    }
+}
+
+// Fetch `url`, keeping a copy under ~/.cache/slate/http that's reused while the server answers
+// that it's unchanged, or can't be reached
+fn fetch(url: &str) -> Result<Vec<u8>> {
+    let cached = cache_path(url);
+    let etag = cached.as_ref().and_then(|path| std::fs::read_to_string(path.with_extension("etag")).ok());
+
+    let mut request = ureq::get(url);
+    for (name, value) in HEADERS.get().into_iter().flatten() {
+        request = request.set(name, value);
+    }
+    if let (Some(etag), Some(path)) = (&etag, &cached) {
+        if path.exists() {
+            request = request.set("If-None-Match", etag);
+        }
+    }
+    let response = match request.call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(status, response)) => {
+            bail!("The server answered {status} {}", response.status_text())
+        }
+        Err(e) => match cached.as_ref().and_then(|path| std::fs::read(path).ok()) {
+            Some(body) => {
+                log::warn!("Using the cached copy of {url}, as it couldn't be fetched: {e}");
+                return Ok(body);
+            }
+            None => return Err(e.into()),
+        },
+    };
+    if response.status() == 304 {
+        if let Some(body) = cached.as_ref().and_then(|path| std::fs::read(path).ok()) {
+            return Ok(body);
+        }
+    }
+
+    let new_etag = response.header("ETag").map(str::to_string);
+    let mut body = Vec::new();
+    response.into_reader().read_to_end(&mut body)?;
+    if let (Some(path), Some(etag)) = (&cached, new_etag) {
+        if let Err(e) = save(path, &body, &etag) {
+            log::debug!("Couldn't cache {url}: {e}");
+        }
+    }
+    Ok(body)
+}
+
+/// Send `body` to `url` with `method`, like POST or PUT, failing unless the server accepts it
+pub fn send(url: &str, method: &str, content_type: &str, body: &[u8]) -> Result<()> {
+    let mut request = ureq::request(method, url).set("Content-Type", content_type);
+    for (name, value) in HEADERS.get().into_iter().flatten() {
+        request = request.set(name, value);
+    }
+    match request.send_bytes(body) {
+        Ok(response) => {
+            log::info!("{method} {url}: {} {}", response.status(), response.status_text());
+            Ok(())
+        }
+        Err(ureq::Error::Status(status, response)) => {
+            let text = response.status_text().to_string();
+            let reply = response.into_string().unwrap_or_default();
+            bail!("{method} {url}: the server answered {status} {text} {}", reply.trim())
+        }
+        Err(e) => Err(anyhow::Error::new(e).context(format!("{method} {url} failed"))),
+    }
+}
+
+fn cache_path(url: &str) -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    Some(dir.join("slate").join("http").join(format!("{:016x}", hasher.finish())))
+}
+
+// Responses may need credentials to fetch, so only their owner can read them
+fn save(path: &Path, body: &[u8], etag: &str) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, body)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    std::fs::write(path.with_extension("etag"), etag)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_urls() {
+        assert!(is_url(Path::new("https://example.com/compose.yaml")));
+        assert!(!is_url(Path::new("compose.yaml")));
+        assert_eq!(name_of(Path::new("https://example.com/api/items.json?page=2#top")), PathBuf::from("https://example.com/api/items.json"));
+        assert_eq!(name_of(Path::new("data.toml")), PathBuf::from("data.toml"));
+        assert!(set_headers(&["no colon".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// What one host of an inventory gets, e.g. in YAML
+///
+/// ```yaml
+/// web1:
+///   vars:
+///     domain: web1.example.com
+///   units: [caddy, app]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Variables for Tera templates
+    #[serde(default)]
+    pub vars: serde_json::Map<String, serde_json::Value>,
+    /// The entries or compose services the host runs, all of them if not given
+    pub units: Option<Vec<String>>,
+}
+
+impl Profile {
+    /// The profile of `host` in an inventory mapping hostnames to profiles
+    pub fn from_inventory(inventory: serde_json::Value, host: &str) -> Result<Profile> {
+        let mut profiles: HashMap<String, Profile> = serde_json::from_value(inventory).context("Invalid inventory")?;
+        match profiles.remove(host) {
+            Some(profile) => Ok(profile),
+            None => {
+                let mut hosts: Vec<String> = profiles.into_keys().collect();
+                hosts.sort();
+                bail!("The inventory has no profile for '{host}', only for {}", hosts.join(", "))
+            }
+        }
+    }
+
+    /// The Tera context of templates rendered for `host`, its variables along with `host` itself
+    pub fn context(&self, host: &str) -> Result<tera::Context> {
+        let mut context = tera::Context::from_value(serde_json::Value::Object(self.vars.clone()))?;
+        context.insert("host", host);
+        Ok(context)
+    }
+
+    /// Whether the host runs the entry or compose service `name`
+    pub fn includes(&self, name: &str) -> bool {
+        self.units.as_ref().is_none_or(|units| units.iter().any(|unit| unit == name))
+    }
+
+    /// Fail on a unit the profile lists that isn't among `names`, which is likely a typo
+    pub fn check_units<'a>(&self, host: &str, names: impl Iterator<Item = &'a String>) -> Result<()> {
+        let names: Vec<&String> = names.collect();
+        for unit in self.units.iter().flatten() {
+            if !names.contains(&unit) {
+                bail!("The profile of '{host}' lists '{unit}', which no input defines");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The name of this machine, which picks its profile when none is given
+pub fn hostname() -> Option<String> {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_profile() {
+        let inventory = json!({
+            "web1": { "vars": { "domain": "web1.example.com" }, "units": ["caddy", "app"] },
+            "db1": { "units": ["postgres"] },
+        });
+        let profile = Profile::from_inventory(inventory.clone(), "web1").unwrap();
+        assert!(profile.includes("caddy") && !profile.includes("postgres"));
+        let context = profile.context("web1").unwrap();
+        assert_eq!(context.get("domain"), Some(&json!("web1.example.com")));
+        assert_eq!(context.get("host"), Some(&json!("web1")));
+
+        let names = ["caddy".to_string(), "postgres".to_string()];
+        assert!(profile.check_units("web1", names.iter()).unwrap_err().to_string().contains("'app'"));
+
+        let err = Profile::from_inventory(inventory, "web2").unwrap_err();
+        assert_eq!(err.to_string(), "The inventory has no profile for 'web2', only for db1, web1");
+    }
+}
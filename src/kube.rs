@@ -0,0 +1,260 @@
+use anyhow::{bail, Result};
+use serde_json::{json, Map, Value as JsonValue};
+use serde_yaml::Value;
+
+use crate::formats::{json::PlainNumbers, Ini, Section};
+use crate::quadlet::ComposeFile;
+
+/// The name of the pod, from the compose project name
+pub fn pod_name(file: &ComposeFile) -> String {
+    let name = file.other.get("name").and_then(|n| n.as_str()).unwrap_or("pod");
+    dns_name(name)
+}
+
+/// Convert a compose file to a Kubernetes Pod running every service as a container, in the
+/// subset of Kubernetes YAML that `podman kube play` understands.
+pub fn to_pod(file: &ComposeFile) -> Result<String> {
+    let mut names: Vec<&String> = file.services.keys().collect();
+    names.sort();
+
+    let mut containers = Vec::new();
+    let mut volumes: Vec<JsonValue> = Vec::new();
+    let mut restart_policy = None;
+    for name in names {
+        let service = &file.services[name];
+        let Some(image) = service.get("image").and_then(|i| i.as_str()) else {
+            bail!("Service '{name}' has no image, build it first as kube output can't build images");
+        };
+
+        let mut container = Map::new();
+        container.insert("name".into(), dns_name(name).into());
+        container.insert("image".into(), image.into());
+        if let Some(entrypoint) = service.get("entrypoint") {
+            container.insert("command".into(), words(entrypoint).into());
+        }
+        if let Some(command) = service.get("command") {
+            container.insert("args".into(), words(command).into());
+        }
+        if let Some(dir) = service.get("working_dir").and_then(|d| d.as_str()) {
+            container.insert("workingDir".into(), dir.into());
+        }
+
+        let env = environment(service.get("environment"));
+        if !env.is_empty() {
+            container.insert("env".into(), env.into());
+        }
+
+        let ports: Vec<JsonValue> =
+            service.get("ports").and_then(|p| p.as_sequence()).into_iter().flatten().filter_map(port).collect();
+        if !ports.is_empty() {
+            container.insert("ports".into(), ports.into());
+        }
+
+        let mut mounts = Vec::new();
+        for volume in service.get("volumes").and_then(|v| v.as_sequence()).into_iter().flatten() {
+            let Some((source, target, read_only)) = mount(volume) else {
+                continue;
+            };
+            let is_path = source.starts_with('/') || source.starts_with('.');
+            let volume_name = if is_path { format!("{}-{}", dns_name(name), mounts.len()) } else { dns_name(&source) };
+            if !volumes.iter().any(|v| v["name"] == volume_name.as_str()) {
+                volumes.push(if is_path {
+                    json!({"name": volume_name, "hostPath": {"path": source}})
+                } else {
+                    json!({"name": volume_name, "persistentVolumeClaim": {"claimName": source}})
+                });
+            }
+            let mut mount = json!({"name": volume_name, "mountPath": target});
+            if read_only {
+                mount["readOnly"] = true.into();
+            }
+            mounts.push(mount);
+        }
+        if !mounts.is_empty() {
+            container.insert("volumeMounts".into(), mounts.into());
+        }
+
+        if restart_policy.is_none() {
+            restart_policy = match service.get("restart").and_then(|r| r.as_str()) {
+                Some("always") | Some("unless-stopped") => Some("Always"),
+                Some("on-failure") => Some("OnFailure"),
+                Some("no") => Some("Never"),
+                _ => None,
+            };
+        }
+        containers.push(JsonValue::Object(container));
+    }
+
+    let mut spec = json!({"containers": containers});
+    if let Some(policy) = restart_policy {
+        spec["restartPolicy"] = policy.into();
+    }
+    if !volumes.is_empty() {
+        spec["volumes"] = volumes.into();
+    }
+    let pod = json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {"name": pod_name(file)},
+        "spec": spec,
+    });
+    Ok(serde_yaml::to_string(&PlainNumbers(&pod))?)
+}
+
+/// A `.kube` unit that plays the YAML file `yaml`, a path relative to the unit
+pub fn kube_unit(yaml: &str, wanted_by: bool) -> Ini {
+    let mut unit = Ini::new();
+    let mut kube = Section::new();
+    kube.insert("Yaml".to_string(), yaml.to_string());
+    unit.insert("Kube".to_string(), kube);
+    if wanted_by {
+        let mut install = Section::new();
+        install.insert("WantedBy".to_string(), "default.target".to_string());
+        unit.insert("Install".to_string(), install);
+    }
+    unit
+}
+
+// Lowercase alphanumerics and dashes, as Kubernetes names must be
+fn dns_name(name: &str) -> String {
+    let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' }).collect();
+    name.trim_matches('-').to_string()
+}
+
+// Commands are a list, or a string split on whitespace without any shell quoting
+fn words(value: &Value) -> Vec<String> {
+    match value {
+        Value::Sequence(items) => items.iter().map(scalar).collect(),
+        other => scalar(other).split_whitespace().map(str::to_string).collect(),
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => serde_yaml::to_string(other).unwrap_or_default().trim_end().to_string(),
+    }
+}
+
+fn environment(value: Option<&Value>) -> Vec<JsonValue> {
+    let pairs: Vec<(String, String)> = match value {
+        Some(Value::Mapping(map)) => map.iter().filter_map(|(k, v)| Some((k.as_str()?.to_string(), scalar(v)))).collect(),
+        Some(Value::Sequence(items)) => items
+            .iter()
+            .filter_map(|item| {
+                let (k, v) = item.as_str()?.split_once('=')?;
+                Some((k.to_string(), v.to_string()))
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    pairs.into_iter().map(|(name, value)| json!({"name": name, "value": value})).collect()
+}
+
+// `[host_ip:][host_port:]container_port[/protocol]`, or the long syntax
+fn port(value: &Value) -> Option<JsonValue> {
+    let (host_ip, host_port, container_port, protocol) = match value {
+        Value::Mapping(map) => (
+            map.get("host_ip").map(scalar),
+            map.get("published").map(scalar),
+            scalar(map.get("target")?),
+            map.get("protocol").map(scalar),
+        ),
+        other => {
+            let spec = scalar(other);
+            let (spec, protocol) = match spec.split_once('/') {
+                Some((spec, protocol)) => (spec, Some(protocol.to_string())),
+                None => (spec.as_str(), None),
+            };
+            let mut parts: Vec<&str> = spec.rsplitn(3, ':').collect();
+            parts.reverse();
+            match parts.as_slice() {
+                [container] => (None, None, container.to_string(), protocol),
+                [host, container] => (None, Some(host.to_string()), container.to_string(), protocol),
+                [ip, host, container] => (Some(ip.to_string()), Some(host.to_string()), container.to_string(), protocol),
+                _ => return None,
+            }
+        }
+    };
+
+    let mut port = json!({"containerPort": container_port.parse::<u16>().ok()?});
+    if let Some(host_port) = host_port.and_then(|p| p.parse::<u16>().ok()) {
+        port["hostPort"] = host_port.into();
+    }
+    if let Some(ip) = host_ip.filter(|ip| !ip.is_empty()) {
+        port["hostIP"] = ip.into();
+    }
+    if let Some(protocol) = protocol {
+        port["protocol"] = protocol.to_uppercase().into();
+    }
+    Some(port)
+}
+
+// `source:target[:mode]`, or the long syntax, skipping anonymous volumes
+fn mount(value: &Value) -> Option<(String, String, bool)> {
+    match value {
+        Value::Mapping(map) => Some((
+            scalar(map.get("source")?),
+            scalar(map.get("target")?),
+            map.get("read_only").and_then(|r| r.as_bool()).unwrap_or(false),
+        )),
+        other => {
+            let spec = scalar(other);
+            let mut parts = spec.splitn(3, ':');
+            let source = parts.next()?.to_string();
+            let target = parts.next()?.to_string();
+            let read_only = parts.next().is_some_and(|mode| mode.split(',').any(|m| m == "ro"));
+            Some((source, target, read_only))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_pod() {
+        let input = r#"
+name: My_App
+services:
+  web:
+    image: docker.io/library/nginx:1.25
+    entrypoint: nginx -g
+    command: ["daemon off;"]
+    restart: unless-stopped
+    environment:
+      MODE: production
+      WORKERS: 4
+    ports:
+      - "127.0.0.1:8080:80"
+      - 53:53/udp
+    volumes:
+      - ./html:/usr/share/nginx/html:ro
+      - cache:/var/cache/nginx
+  db:
+    image: docker.io/library/postgres:16
+    environment:
+      - POSTGRES_PASSWORD=secret
+"#;
+        let file: ComposeFile = serde_yaml::from_str(input).unwrap();
+        let pod: JsonValue = serde_yaml::from_str(&to_pod(&file).unwrap()).unwrap();
+
+        assert_eq!(pod["metadata"]["name"], "my-app");
+        assert_eq!(pod["spec"]["restartPolicy"], "Always");
+        let web = &pod["spec"]["containers"][1];
+        assert_eq!(web["name"], "web");
+        assert_eq!(web["command"], json!(["nginx", "-g"]));
+        assert_eq!(web["args"], json!(["daemon off;"]));
+        assert_eq!(web["env"][1], json!({"name": "WORKERS", "value": "4"}));
+        assert_eq!(web["ports"][0], json!({"containerPort": 80, "hostPort": 8080, "hostIP": "127.0.0.1"}));
+        assert_eq!(web["ports"][1], json!({"containerPort": 53, "hostPort": 53, "protocol": "UDP"}));
+        assert_eq!(web["volumeMounts"][0], json!({"name": "web-0", "mountPath": "/usr/share/nginx/html", "readOnly": true}));
+        assert_eq!(pod["spec"]["volumes"][1], json!({"name": "cache", "persistentVolumeClaim": {"claimName": "cache"}}));
+        assert_eq!(pod["spec"]["containers"][0]["env"][0]["value"], "secret");
+
+        let file: ComposeFile = serde_yaml::from_str("services:\n  app:\n    build: .\n").unwrap();
+        assert!(to_pod(&file).is_err());
+    }
+}
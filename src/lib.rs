@@ -1,4 +1,17 @@
+pub mod archive;
+pub mod config;
+pub mod diff;
+pub mod doctor;
+pub mod filters;
 pub mod formats;
+pub mod http;
+pub mod inventory;
+pub mod kube;
+pub mod prompt;
 pub mod quadlet;
+#[cfg(feature = "tui")]
+pub mod review;
+pub mod sourcemap;
 pub mod systemd;
+pub mod transform;
 pub mod utils;
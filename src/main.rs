@@ -2,41 +2,88 @@ use env_logger::Builder;
 use log::LevelFilter;
 use serde::de::DeserializeOwned;
 use std::{
-    env, io::{stdin, stdout, Read, Write}, path::{Path, PathBuf}, str
+    collections::HashMap, env, io::{stdin, stdout, IsTerminal, Read, Write}, path::{Path, PathBuf}, str
 };
-use tera::Tera;
+
+pub mod diff;
+
+pub mod transform;
+use transform::KeyPath;
 
 pub mod systemd;
-use systemd::{activate_units, process_systemd};
+use systemd::{activate_units, parse_activation, Activation, add_on_failure, attach_portable, build_portable, environment_files, extract_environment, harden_timers, inline_environment, unchanged_units, write_environment_files, machines, process_systemd, run_transient};
 
 pub mod utils;
-use utils::{is_interactive, print_files, write_files};
+pub mod filters;
+
+pub mod sourcemap;
+use sourcemap::Rendered;
+
+pub mod http;
+pub mod archive;
+use utils::{is_interactive, print_files, render_template, write_files, Rendering};
+
+pub mod prompt;
+use prompt::{Preset, PresetPrompter, Prompter, RecordingPrompter, ReplayPrompter, TtyPrompter};
+
+pub mod config;
+use config::Config;
+
+pub mod inventory;
+use inventory::Profile;
+
+#[cfg(feature = "tui")]
+pub mod review;
 
 pub mod formats;
+use formats::dupkeys::DupKeys;
+use formats::edn::TagMode;
+use formats::framing::Framing;
+use formats::rust::{Derive as RustDerive, StringStyle};
+use formats::sql::Dialect;
+use formats::BytesRepr;
+use formats::toml::TableStyle;
+use formats::yaml::{AliasLimits, AliasPolicy, QuoteStyle, YamlStyle};
+
+pub mod doctor;
+use doctor::Status;
 
 pub mod quadlet;
-use quadlet::{process_compose, process_quadlets, activate_quadlets};
+pub mod kube;
+use quadlet::{verify_signatures, check_keys, gate_version, check_resources, add_replicas, extract_replicas, extract_init_containers, check_port_conflicts, prefix_units, set_project_name, add_target, stop_pod, restart_changed, update_quadlets, UnitChange, add_directives, apply_overrides, write_merged, extract_overrides, add_image_units, add_network_settings, extract_network_settings, extract_builds, extract_devices, extract_kernel_settings, extract_mounts, extract_resource_limits, extract_restart_policies, extract_security_options, relabel_bind_mounts, LimitsIn, preset_answers, process_compose, process_quadlets, activate_quadlets};
+use quadlet::keys::{parse_target_version, Version};
 
-use anyhow::{anyhow, Result};
-use clap::{Parser, ValueEnum};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use std::cell::RefCell;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
-use crate::{formats::IniFiles, quadlet::{get_raw_quadlets, ComposeFile}, utils::ask_confirm};
+use crate::{formats::IniFiles, quadlet::{get_raw_quadlets, ComposeFile}};
 use tempfile::Builder as TempFileBuilder;
 
 #[derive(Parser, Debug)]
-#[clap(name = "slate", version = "0.1.0", author = "squirreljetpack")]
+#[clap(name = "slate", version = "0.1.0", author = "squirreljetpack", about, args_conflicts_with_subcommands = true)]
 pub struct Opts {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
     #[clap(flatten)]
     pub file_cmd: FileCmd,
     #[clap(short, long, action = clap::ArgAction::Count)]
     verbose: u8,
+    /// Only let templates' get_env and ${VAR} replacement in compose files read environment variables matching these patterns, like APP_*
+    #[clap(long, global = true, value_name = "PATTERN")]
+    env_allow: Vec<String>,
+    /// A header sent when fetching URLs given as inputs or files, like 'Authorization: Bearer <token>'
+    #[clap(long, global = true)]
+    header: Vec<String>,
 }
 
 #[derive(Parser, Debug)]
 #[clap(name = "slate")]
 pub struct FileCmd {
     // if no input is given, then switch to console mode
-    pub input: Option<PathBuf>,
+    /// Input files. Systemd and quadlet modes take several, or directories of them, merged into one run
+    pub input: Vec<PathBuf>,
     // todo: describe that this specifies a directory path for quadlet and systemd modes
     /// output filepath
     #[clap(short, long)]
@@ -45,33 +92,873 @@ pub struct FileCmd {
     pub from: Option<FromVariant>,
     #[clap(short, long, value_enum)]
     pub to: Option<ToVariant>,
+    /// Read the output back and fail on any way it differs from the input, like numbers that changed type, dropped keys or reordered ones
+    #[clap(long)]
+    pub verify: bool,
 
     // also: #[clap(long, action = clap::ArgAction::Set, default_value_t = false)] for --tera=false
     #[clap(long, action = clap::ArgAction::SetTrue, overrides_with = "no_tera")]
     pub tera: bool,
     #[clap(long = "no-tera", action = clap::ArgAction::SetFalse, hide = true)]
     pub no_tera: bool,
+
+    #[clap(flatten)]
+    pub input_opts: InputOpts,
+    #[clap(flatten)]
+    pub output_opts: OutputOpts,
+    #[clap(flatten)]
+    pub schema_opts: SchemaOpts,
+    #[clap(flatten)]
+    pub transform_opts: TransformOpts,
+    #[clap(flatten)]
+    pub prompt_opts: PromptOpts,
+    #[clap(flatten)]
+    pub quadlet_opts: QuadletOpts,
+    #[clap(flatten)]
+    pub systemd_opts: SystemdOpts,
+    #[clap(flatten)]
+    pub notify_opts: NotifyOpts,
+    #[clap(flatten)]
+    pub inventory_opts: InventoryOpts,
+    #[clap(flatten)]
+    pub template_opts: TemplateOpts,
+    #[clap(flatten)]
+    pub url_output_opts: UrlOutputOpts,
+    /// Configuration file, by default ~/.config/slate/config.toml
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+}
+
+// Sending the converted output to a URL instead of writing it
+#[derive(Args, Debug, Default, Clone)]
+pub struct UrlOutputOpts {
+    /// Send the converted output as the body of an HTTP request to this URL, like a webhook, instead of writing it
+    #[clap(long, conflicts_with = "output")]
+    pub output_url: Option<String>,
+    /// The method of the request to --output-url
+    #[clap(long, default_value = "POST", requires = "output_url")]
+    pub method: String,
+    /// The Content-Type of the request to --output-url, by default the output format's
+    #[clap(long, requires = "output_url")]
+    pub content_type: Option<String>,
+}
+
+// Rendering Tera templates
+#[derive(Args, Debug, Default, Clone)]
+pub struct TemplateOpts {
+    /// Let templates read secrets with pass(path), keyring(service, user) and file(path)
+    #[clap(long)]
+    pub secrets: bool,
+    /// Fail on undefined variables anywhere in templates, even in if conditions, reporting their line
+    #[clap(long)]
+    pub strict_templates: bool,
+    /// Only render the input as a template, writing the text out without reading it as data, for files like scripts or nginx snippets
+    #[clap(long)]
+    pub render_only: bool,
+}
+
+impl TemplateOpts {
+    fn rendering(&self) -> Rendering {
+        Rendering { autoescape: true, secrets: self.secrets, strict: self.strict_templates }
+    }
+}
+
+// Renders inputs that are templates
+struct Renderer {
+    context: tera::Context,
+    rendering: Rendering,
+    verbose: bool,
+}
+
+impl Renderer {
+    // The rendered bytes, along with the template to trace errors reading them back to
+    fn render(&self, bytes: &[u8]) -> Result<(Vec<u8>, Rendered)> {
+        let template = str::from_utf8(bytes)?;
+        let rendered = render_template(template, &self.context, self.rendering)?;
+        if self.verbose {
+            println!("# Tera output");
+            println!("{rendered}\n");
+            println!("---\n");
+        }
+        let source_map = Rendered::new(template, &rendered);
+        Ok((rendered.into_bytes(), source_map))
+    }
+}
+
+// Point an error reading a rendered template at the template line it likely came from
+fn traced<T>(result: Result<T>, rendered: &Option<Rendered>) -> Result<T> {
+    match rendered {
+        Some(rendered) => result.map_err(|e| rendered.explain(e)),
+        None => result,
+    }
+}
+
+// Generating for one host of many
+#[derive(Args, Debug, Default, Clone)]
+pub struct InventoryOpts {
+    /// A file mapping hostnames to profiles of Tera variables (`vars`) and the entries or compose services the host runs (`units`)
+    #[clap(long)]
+    pub inventory: Option<PathBuf>,
+    /// The host to generate for, by default this machine's hostname
+    #[clap(long, requires = "inventory")]
+    pub profile: Option<String>,
+}
+
+impl InventoryOpts {
+    /// The chosen host and its profile, if there's an inventory
+    fn load(&self) -> Result<Option<(String, Profile)>> {
+        let Some(path) = &self.inventory else { return Ok(None) };
+        let host = match &self.profile {
+            Some(host) => host.clone(),
+            None => inventory::hostname().ok_or_else(|| anyhow!("Couldn't tell this machine's hostname, choose a profile with --profile"))?,
+        };
+        let profile = Profile::from_inventory(read_document(path)?, &host)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Some((host, profile)))
+    }
+}
+
+// Alerting on failed units, in systemd and quadlet modes
+#[derive(Args, Debug, Default, Clone)]
+pub struct NotifyOpts {
+    /// Run this command, a Tera template given `unit` and `host`, when any generated service or container fails
+    #[clap(long)]
+    pub notify_failure: Option<String>,
+}
+
+// Options for the units generated in systemd mode
+#[derive(Args, Debug, Default, Clone)]
+pub struct SystemdOpts {
+    /// Start the services and timers as transient units with systemd-run instead of writing them, to try them out
+    #[clap(long, conflicts_with = "output")]
+    pub transient: bool,
+    /// How to activate units: enable-now (the default), enable to only start them at boot, or start to run them once without enabling them. `<type>=<mode>`, like `timer=enable`, sets it for one type of unit
+    #[clap(long, value_name = "[TYPE=]MODE", value_parser = parse_activation)]
+    pub activate: Vec<(Option<String>, Activation)>,
+    /// Verify units with slate's own checks rather than systemd-analyze, which is also what happens when it isn't installed
+    #[clap(long)]
+    pub offline_verify: bool,
+}
+
+// Options for the units generated in quadlet mode
+#[derive(Args, Debug, Default, Clone)]
+pub struct QuadletOpts {
+    /// Emit an .image unit for each image, so systemd pulls it before its containers start
+    #[clap(long)]
+    pub image_units: bool,
+    /// Pull every tag of each image, with --image-units
+    #[clap(long, requires = "image_units")]
+    pub all_tags: bool,
+    /// Architecture to pull images for, with --image-units
+    #[clap(long, requires = "image_units")]
+    pub arch: Option<String>,
+    /// Add SELinux labels to bind mounts without asking, on hosts enforcing SELinux
+    #[clap(long)]
+    pub relabel: bool,
+    /// Name the project, and so the pod and the units of its services, instead of the compose file's `name:` or COMPOSE_PROJECT_NAME
+    #[clap(long)]
+    pub project_name: Option<String>,
+    /// Prefix the file names of the generated units and the names of their containers
+    #[clap(long)]
+    pub name_prefix: Option<String>,
+    /// Also warn about published ports that something on the host is listening on already, using `ss`
+    #[clap(long)]
+    pub check_listening: bool,
+    /// Convert each service to a standalone container publishing its own ports, rather than running them all in a pod
+    #[clap(long)]
+    pub no_pod: bool,
+    /// Verify the signature of every image before writing the units, failing on any that can't be verified
+    #[clap(long)]
+    pub verify_signatures: bool,
+    /// Verify signatures with cosign and this public key, rather than with skopeo
+    #[clap(long, requires = "verify_signatures")]
+    pub cosign_key: Option<PathBuf>,
+    /// The containers signature policy skopeo verifies against, by default the system's
+    #[clap(long, requires = "verify_signatures", conflicts_with = "cosign_key")]
+    pub signature_policy: Option<PathBuf>,
+    /// Add a <pod>.target wanting the pod and its containers, to start and stop them together
+    #[clap(long)]
+    pub target: bool,
+    /// Where the memory, CPU and process limits of services are enforced
+    #[clap(long, value_enum, default_value_t)]
+    pub limits: LimitsIn,
+    /// Generate for this podman version, like 4.9, rather than the installed one, rewriting newer keys as PodmanArgs where possible
+    #[clap(long, value_parser = parse_target_version)]
+    pub target_podman_version: Option<Version>,
+}
+
+// Where the answers to questions asked in systemd and quadlet modes come from
+#[derive(Args, Debug, Default, Clone)]
+pub struct PromptOpts {
+    /// Write every question asked and its answer to this file, to replay with --answers
+    #[clap(long)]
+    pub record_answers: Option<PathBuf>,
+    /// Answer questions from a file written by --record-answers instead of asking
+    #[clap(long)]
+    pub answers: Option<PathBuf>,
+    /// In quadlet mode, choose the directives to add to each unit on one screen instead of being asked about each
+    #[cfg(feature = "tui")]
+    #[clap(long)]
+    pub review: bool,
+}
+
+impl PromptOpts {
+    /// Questions with a preset answer aren't asked, or replayed, but are still recorded
+    fn prompter(&self, presets: HashMap<String, Preset>) -> Result<Box<dyn Prompter>> {
+        let prompter: Box<dyn Prompter> = match &self.answers {
+            Some(path) => Box::new(
+                ReplayPrompter::load(path).with_context(|| format!("Failed to read answers from {}", path.display()))?,
+            ),
+            None => Box::new(TtyPrompter),
+        };
+        let prompter = Box::new(PresetPrompter::new(prompter, presets));
+        Ok(match &self.record_answers {
+            Some(path) => Box::new(RecordingPrompter::new(prompter, path.clone())),
+            None => prompter,
+        })
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Show the structural differences between two data files, which may be in different formats
+    Diff(DiffCmd),
+    /// List the supported formats, their extensions and limitations
+    Formats,
+    /// Check the tools and configuration systemd and quadlet modes need, and how to fix what's missing
+    Doctor,
+    /// Manage the quadlets installed from a compose file
+    Quadlet {
+        #[clap(subcommand)]
+        command: QuadletCommand,
+    },
+    /// Package the units generated in systemd mode
+    Systemd {
+        #[clap(subcommand)]
+        command: SystemdCommand,
+    },
+    /// Render every template matching a glob into a mirrored output directory
+    Render(RenderCmd),
+}
+
+#[derive(Args, Debug)]
+pub struct RenderCmd {
+    /// A glob of templates, like 'templates/**/*.tera', quoted so the shell leaves it alone
+    pub pattern: String,
+    /// The directory to write the rendered files to, in the same layout as below the glob's first wildcard, without their .tera extension
+    #[clap(short, long)]
+    pub output: PathBuf,
+    /// A file of variables for the templates, in any supported format
+    #[clap(long)]
+    pub var_file: Option<PathBuf>,
+    /// Let templates read secrets with pass(path), keyring(service, user) and file(path)
+    #[clap(long)]
+    pub secrets: bool,
+    /// Fail on undefined variables anywhere in templates, even in if conditions, reporting their line
+    #[clap(long)]
+    pub strict_templates: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SystemdCommand {
+    /// Bundle the units of a systemd YAML file and a root directory into a portable service image, and attach it with portablectl
+    Portable(PortableCmd),
+}
+
+#[derive(Args, Debug)]
+pub struct PortableCmd {
+    /// The systemd YAML file, rendered with Tera if it ends in .tera
+    pub input: PathBuf,
+    /// The name of the image, which its units must be prefixed with, by default the input file's name
+    #[clap(long)]
+    pub name: Option<String>,
+    /// A root directory with the binaries the units run, copied into the image
+    #[clap(long)]
+    pub root: Option<PathBuf>,
+    /// The directory to create the image directory in
+    #[clap(short, long, default_value = ".")]
+    pub output: PathBuf,
+    #[clap(flatten)]
+    pub prompt_opts: PromptOpts,
+    #[clap(flatten)]
+    pub template_opts: TemplateOpts,
+    /// Configuration file, by default ~/.config/slate/config.toml
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum QuadletCommand {
+    /// Regenerate the quadlets of a compose file, show how they differ from the installed ones, and restart only the units that changed
+    Update(Box<UpdateCmd>),
+    /// Stop a pod and its containers without removing their units, until they're restarted or the next boot
+    Down {
+        /// The name of the pod, as in <pod>.pod
+        pod: String,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct UpdateCmd {
+    pub compose: PathBuf,
+    /// Show what would change without writing or restarting anything
+    #[clap(long)]
+    pub dry_run: bool,
+    #[clap(flatten)]
+    pub prompt_opts: PromptOpts,
+    #[clap(flatten)]
+    pub quadlet_opts: QuadletOpts,
+    #[clap(flatten)]
+    pub notify_opts: NotifyOpts,
+    /// Configuration file, by default ~/.config/slate/config.toml
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffCmd {
+    pub left: PathBuf,
+    pub right: PathBuf,
+    #[clap(long, value_enum, default_value_t)]
+    pub format: DiffFormat,
+}
+
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum DiffFormat {
+    /// Changes grouped under their parent keys
+    #[default]
+    Tree,
+    /// A JSON Patch turning the left file into the right one
+    Patch,
+}
+
+fn run_diff(cmd: DiffCmd) -> Result<()> {
+    let left = read_document(&cmd.left)?;
+    let right = read_document(&cmd.right)?;
+    let changes = diff::diff(&left, &right);
+    let out = match cmd.format {
+        DiffFormat::Tree => {
+            let color = stdout().is_terminal() && env::var_os("NO_COLOR").is_none();
+            diff::to_tree(&changes, color)
+        }
+        DiffFormat::Patch => serde_json::to_string_pretty(&diff::to_patch(&changes))? + "\n",
+    };
+    stdout().lock().write_all(out.as_bytes())?;
+    Ok(())
+}
+
+#[derive(Default)]
+struct FormatRow {
+    name: String,
+    read: bool,
+    write: bool,
+    extensions: Vec<&'static str>,
+    notes: Vec<&'static str>,
+}
+
+impl FormatRow {
+    fn add(&mut self, extensions: &[&'static str], note: &'static str) {
+        for extension in extensions {
+            if !self.extensions.contains(extension) {
+                self.extensions.push(extension);
+            }
+        }
+        if !note.is_empty() {
+            self.notes.push(note);
+        }
+    }
+}
+
+fn run_doctor() -> Result<()> {
+    let checks = doctor::run_checks();
+    let width = checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for check in &checks {
+        let mark = match check.status {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        };
+        out.push_str(&format!("{mark:4}  {:width$}  {}\n", check.name, check.detail));
+        if let Some(fix) = &check.fix {
+            out.push_str(&format!("{:width$}  fix: {fix}\n", "", width = width + 6));
+        }
+    }
+    stdout().lock().write_all(out.as_bytes())?;
+
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    if failed > 0 {
+        bail!("{failed} check(s) failed");
+    }
+    Ok(())
+}
+
+// Print a row per format name, merging its input and output variants
+fn list_formats() -> Result<()> {
+    fn row<'a>(rows: &'a mut Vec<FormatRow>, variant: &impl ValueEnum) -> &'a mut FormatRow {
+        let name = variant.to_possible_value().expect("no variants are skipped");
+        match rows.iter().position(|r| r.name == name.get_name()) {
+            Some(i) => &mut rows[i],
+            None => {
+                rows.push(FormatRow { name: name.get_name().to_string(), ..Default::default() });
+                rows.last_mut().expect("just pushed")
+            }
+        }
+    }
+
+    let mut rows = Vec::new();
+    for variant in FromVariant::value_variants() {
+        let entry = row(&mut rows, variant);
+        entry.read = true;
+        entry.add(variant.extensions(), variant.limitations());
+    }
+    for variant in ToVariant::value_variants() {
+        let entry = row(&mut rows, variant);
+        entry.write = true;
+        entry.add(variant.extensions(), variant.limitations());
+    }
+
+    let yes = |b: bool| if b { "yes" } else { "-" }.to_string();
+    let mut table = vec![["FORMAT", "READ", "WRITE", "EXTENSIONS", "NOTES"].map(String::from)];
+    for row in rows {
+        let extensions: Vec<String> = row.extensions.iter().map(|e| format!(".{e}")).collect();
+        table.push([row.name, yes(row.read), yes(row.write), extensions.join(" "), row.notes.join("; ")]);
+    }
+    let widths: Vec<usize> = (0..5).map(|c| table.iter().map(|r| r[c].chars().count()).max().unwrap_or(0)).collect();
+    let mut out = String::new();
+    for cells in table {
+        let line: Vec<String> = cells.iter().zip(&widths).map(|(cell, width)| format!("{cell:width$}")).collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    stdout().lock().write_all(out.as_bytes())?;
+    Ok(())
+}
+
+// Edits applied to the input document before it is written
+#[derive(Args, Debug, Default, Clone)]
+pub struct TransformOpts {
+    /// JSON Patch (RFC 6902) document to apply, in any input format
+    #[clap(long)]
+    pub patch: Option<PathBuf>,
+    /// JSON Merge Patch (RFC 7386) document to apply, where null removes a key
+    #[clap(long)]
+    pub merge_patch: Option<PathBuf>,
+    /// Keep only the values under this dotted path, e.g. 'services.*.image' (repeatable)
+    #[clap(long)]
+    pub only: Vec<KeyPath>,
+    /// Remove the values under this dotted path, e.g. 'x-*' (repeatable)
+    #[clap(long)]
+    pub exclude: Vec<KeyPath>,
+    /// Drop null values, e.g. for TOML output
+    #[clap(long)]
+    pub prune_nulls: bool,
+    /// Drop empty strings, lists and maps
+    #[clap(long)]
+    pub prune_empty: bool,
+}
+
+impl TransformOpts {
+    fn is_empty(&self) -> bool {
+        self.patch.is_none()
+            && self.merge_patch.is_none()
+            && self.only.is_empty()
+            && self.exclude.is_empty()
+            && !self.prune_nulls
+            && !self.prune_empty
+    }
+
+    fn apply(&self, value: &mut serde_json::Value) -> Result<()> {
+        if let Some(path) = &self.patch {
+            let patch: json_patch::Patch = serde_json::from_value(read_document(path)?)
+                .with_context(|| format!("Invalid JSON Patch: {}", path.display()))?;
+            json_patch::patch(value, &patch).with_context(|| format!("Failed to apply {}", path.display()))?;
+        }
+        if let Some(path) = &self.merge_patch {
+            json_patch::merge(value, &read_document(path)?);
+        }
+        if !self.only.is_empty() {
+            transform::only(value, &self.only);
+        }
+        transform::exclude(value, &self.exclude);
+        if self.prune_nulls || self.prune_empty {
+            transform::prune(value, self.prune_nulls, self.prune_empty);
+        }
+        Ok(())
+    }
+
+    // Deserialize with the edits applied
+    fn deserialize_into<T>(&self, from: FromVariant, s: &[u8], opts: &InputOpts, schema: &SchemaOpts) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        if self.is_empty() {
+            return from.deserialize_into(s, opts, schema);
+        }
+        let mut value: serde_json::Value = from.deserialize_into(s, opts, schema)?;
+        self.apply(&mut value)?;
+        serde_json::from_value(value).map_err(anyhow::Error::new)
+    }
+}
+
+// Read an auxiliary document, such as a patch, in the format given by its extension
+fn read_document(path: &Path) -> Result<serde_json::Value> {
+    let mut bytes = archive::read(path)?;
+    let from = FromVariant::from(&archive::name_of(path));
+    if from.is_text() {
+        bytes = formats::encoding::to_utf8(bytes)?;
+    }
+    from.deserialize_into(&bytes, &InputOpts::default(), &SchemaOpts::default())
+}
+
+// Rewrite a YAML or TOML document as itself, editing only what the transforms change so its
+// comments survive. None if the conversion can't be done in place.
+fn edit_in_place(
+    from: FromVariant,
+    to: ToVariant,
+    input: &[u8],
+    transform_opts: &TransformOpts,
+    input_opts: &InputOpts,
+    output_opts: &OutputOpts,
+) -> Result<Option<Vec<u8>>> {
+    let same_format = matches!((from, to), (FromVariant::Yaml, ToVariant::Yaml) | (FromVariant::Toml, ToVariant::Toml));
+    if !same_format
+        || input_opts.framing != Framing::None
+        || output_opts.yaml_style().is_some()
+        || output_opts.toml_style != TableStyle::Table
+    {
+        return Ok(None);
+    }
+    if transform_opts.is_empty() {
+        return Ok(Some(input.to_vec()));
+    }
+    let text = str::from_utf8(input)?;
+    let original: serde_json::Value = match from.deserialize_into(input, input_opts, &SchemaOpts::default()) {
+        Ok(value) => value,
+        Err(_) => return Ok(None),
+    };
+    let mut edited = original.clone();
+    transform_opts.apply(&mut edited)?;
+    let changes = diff::diff(&original, &edited);
+
+    if matches!(from, FromVariant::Toml) {
+        let mut doc: toml_edit::DocumentMut = text.parse()?;
+        if formats::toml::apply_changes(&mut doc, &changes) {
+            return Ok(Some(doc.to_string().into_bytes()));
+        }
+        log::warn!("These edits can't be made in place, comments and formatting will be lost");
+        return Ok(None);
+    }
+    // The edited text must still read back as the edited document, as with aliases it may not
+    let out = formats::yaml::apply_changes(text, &changes).filter(|out| {
+        from.deserialize_into::<serde_json::Value>(out.as_bytes(), input_opts, &SchemaOpts::default())
+            .is_ok_and(|value| value == edited)
+    });
+    match out {
+        Some(out) => Ok(Some(out.into_bytes())),
+        None => bail!(
+            "These edits can't be made to the YAML in place, and rewriting it would lose its comments. \
+             Pass a style option such as --yaml-indent 2 to rewrite it anyway"
+        ),
+    }
+}
+
+// Schemas for formats that aren't self-describing, used in either direction
+#[derive(Args, Debug, Default, Clone)]
+pub struct SchemaOpts {
+    /// Protobuf FileDescriptorSet used to decode or encode protobuf
+    #[clap(long)]
+    pub descriptor: Option<PathBuf>,
+    /// Fully qualified protobuf message type, e.g. pkg.Type
+    #[clap(long)]
+    pub message: Option<String>,
+    /// Avro schema (.avsc) for Avro output
+    #[clap(long)]
+    pub avro_schema: Option<PathBuf>,
+    /// Shape of bincode/postcard data, e.g. '{name: string, port: u16, tags: [string]}'
+    #[clap(long)]
+    pub type_hint: Option<String>,
+    /// Table to read from SQLite input, or to insert into with SQL output
+    #[clap(long)]
+    pub table: Option<String>,
+    /// SQL query whose rows are read from SQLite input, instead of --table
+    #[clap(long)]
+    pub query: Option<String>,
+    /// How byte strings are written in text formats, and read back into `bytes` of a --type-hint
+    #[clap(long, value_enum, default_value_t)]
+    pub bytes: BytesRepr,
+}
+
+impl SchemaOpts {
+    fn protobuf_message(&self) -> Result<prost_reflect::MessageDescriptor> {
+        let descriptor = self
+            .descriptor
+            .as_ref()
+            .ok_or_else(|| anyhow!("Protobuf requires --descriptor <FILE>"))?;
+        formats::protobuf::load_message(descriptor, self.message.as_deref())
+    }
+}
+
+#[derive(Args, Debug, Default, Clone)]
+pub struct InputOpts {
+    /// How tagged literals like `#inst "..."` in EDN input are handled
+    #[clap(long, value_enum, default_value_t)]
+    pub edn_tags: TagMode,
+    /// Read a stream of several records, which are converted as a list
+    #[clap(long, value_enum, default_value_t)]
+    pub framing: Framing,
+    /// Worksheet to read from a spreadsheet, defaults to the first
+    #[clap(long)]
+    pub sheet: Option<String>,
+    /// Handling of anchors, aliases and `<<` merge keys in YAML input
+    #[clap(long, value_enum, default_value_t)]
+    pub yaml_aliases: AliasPolicy,
+    /// Most nodes that aliases in YAML input may expand to
+    #[clap(long, default_value_t = 100_000)]
+    pub yaml_alias_limit: usize,
+    /// Deepest nesting allowed in YAML input, counting what aliases expand to
+    #[clap(long, default_value_t = 256)]
+    pub yaml_max_depth: usize,
+    /// What to do when a map in JSON or YAML input repeats a key (TOML never allows it)
+    #[clap(long, value_enum, default_value_t)]
+    pub dup_keys: DupKeys,
+}
+
+impl InputOpts {
+    fn alias_limits(&self) -> AliasLimits {
+        AliasLimits { nodes: self.yaml_alias_limit, depth: self.yaml_max_depth }
+    }
+}
+
+#[derive(Args, Debug, Default, Clone)]
+pub struct OutputOpts {
+    /// Sort object keys in JSON output
+    #[clap(long)]
+    pub sort_keys: bool,
+    /// Emit canonical JSON (RFC 8785), implies --sort-keys
+    #[clap(long)]
+    pub canonical: bool,
+    /// Layout of nested tables in TOML output
+    #[clap(long, value_enum, default_value_t)]
+    pub toml_style: TableStyle,
+    /// Indentation width for YAML output
+    #[clap(long)]
+    pub yaml_indent: Option<usize>,
+    /// Write multi-line strings in YAML output as literal blocks
+    #[clap(long)]
+    pub yaml_block_scalars: bool,
+    /// Quoting policy for string values in YAML output
+    #[clap(long, value_enum)]
+    pub yaml_quote: Option<QuoteStyle>,
+    /// Begin YAML output with an explicit `---` document marker
+    #[clap(long)]
+    pub yaml_document_start: bool,
+    /// Column order of tabular output, defaults to the order keys first appear in
+    #[clap(long, value_delimiter = ',')]
+    pub columns: Option<Vec<String>>,
+    /// Split nested maps and lists of tabular output into dotted columns like `db.host`
+    #[clap(long)]
+    pub flatten: bool,
+    /// Name of the root element of XML output
+    #[clap(long, default_value = "root")]
+    pub xml_root: String,
+    /// Keys with this prefix become attributes in XML output
+    #[clap(long, default_value = "@")]
+    pub xml_attr_prefix: String,
+    /// Extra traits derived by generated Rust types, besides Serialize and Deserialize
+    #[clap(long, value_enum, value_delimiter = ',')]
+    pub rust_derive: Vec<RustDerive>,
+    /// String type of generated Rust types
+    #[clap(long, value_enum, default_value_t)]
+    pub rust_strings: StringStyle,
+    /// Quoting and literal syntax of SQL output
+    #[clap(long, value_enum, default_value_t)]
+    pub sql_dialect: Dialect,
+}
+
+impl OutputOpts {
+    // None keeps serde_yaml's own layout
+    fn yaml_style(&self) -> Option<YamlStyle> {
+        if self.yaml_indent.is_none()
+            && !self.yaml_block_scalars
+            && self.yaml_quote.is_none()
+            && !self.yaml_document_start
+        {
+            return None;
+        }
+        let default = YamlStyle::default();
+        Some(YamlStyle {
+            indent: self.yaml_indent.unwrap_or(default.indent),
+            block_scalars: self.yaml_block_scalars,
+            quote: self.yaml_quote.unwrap_or(default.quote),
+            document_start: self.yaml_document_start,
+        })
+    }
 }
 
 #[derive(ValueEnum, Debug, Clone, Copy)]
 pub enum FromVariant {
     Json,
+    /// JSON5: comments, trailing commas, unquoted keys and single-quoted strings
+    Json5,
     Yaml,
     Cbor,
     Ron,
     Toml,
     Bson,
+    Env,
+    Properties,
+    Plist,
+    Kdl,
+    Edn,
+    /// application/x-www-form-urlencoded
+    Form,
+    /// Binary protobuf, requires --descriptor
+    Protobuf,
+    /// Avro object container file
+    Avro,
+    Parquet,
+    /// Excel or OpenDocument spreadsheet, one map per row keyed by the header row
+    Xlsx,
+    /// SQLite database, one map per row of --table or --query
+    Sqlite,
+    /// Bencode, as used by .torrent files
+    Bencode,
+    Pickle,
+    /// Requires --type-hint
+    Bincode,
+    /// Requires --type-hint
+    Postcard,
+    Flexbuffers,
 }
 
 impl FromVariant {
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            FromVariant::Bson => &["bson", "bs"],
+            FromVariant::Cbor => &["cbor", "cb"],
+            FromVariant::Json => &["json"],
+            FromVariant::Json5 => &["json5", "hjson"],
+            FromVariant::Ron => &["ron"],
+            FromVariant::Toml => &["toml", "service"],
+            FromVariant::Yaml => &["yaml", "yml"],
+            FromVariant::Env => &["env"],
+            FromVariant::Properties => &["properties"],
+            FromVariant::Plist => &["plist"],
+            FromVariant::Kdl => &["kdl"],
+            FromVariant::Edn => &["edn"],
+            FromVariant::Protobuf => &["binpb", "pb"],
+            FromVariant::Avro => &["avro"],
+            FromVariant::Parquet => &["parquet"],
+            FromVariant::Xlsx => &["xlsx", "xlsm", "xls", "xlsb", "ods"],
+            FromVariant::Sqlite => &["sqlite", "sqlite3", "db"],
+            FromVariant::Bencode => &["torrent", "bencode"],
+            FromVariant::Pickle => &["pickle", "pkl"],
+            FromVariant::Bincode => &["bincode", "bc"],
+            FromVariant::Postcard => &["postcard", "pc"],
+            FromVariant::Flexbuffers => &["flexbuffers", "fb"],
+            FromVariant::Form => &[],
+        }
+    }
+
+    // What reading the format needs
+    fn limitations(self) -> &'static str {
+        match self {
+            FromVariant::Protobuf => "requires --descriptor and --message",
+            FromVariant::Bincode | FromVariant::Postcard => "requires --type-hint",
+            FromVariant::Sqlite => "reads --table or --query",
+            FromVariant::Xlsx => "reads the first sheet or --sheet",
+            FromVariant::Edn => "tagged literals per --edn-tags",
+            _ => "",
+        }
+    }
+
+    // Whether the format is text, which may need transcoding to UTF-8 first
+    fn is_text(self) -> bool {
+        matches!(
+            self,
+            FromVariant::Json
+                | FromVariant::Json5
+                | FromVariant::Yaml
+                | FromVariant::Ron
+                | FromVariant::Toml
+                | FromVariant::Env
+                | FromVariant::Properties
+                | FromVariant::Kdl
+                | FromVariant::Edn
+                | FromVariant::Form
+        )
+    }
+
+    // Guess the format of unlabeled input, such as stdin, from its content
+    fn sniff(input: &[u8]) -> Option<Self> {
+        const MAGIC: &[(&[u8], FromVariant)] = &[
+            (b"SQLite format 3\0", FromVariant::Sqlite),
+            (b"PAR1", FromVariant::Parquet),
+            (b"Obj\x01", FromVariant::Avro),
+            (b"PK\x03\x04", FromVariant::Xlsx),
+            (b"\xd0\xcf\x11\xe0", FromVariant::Xlsx),
+            (formats::plist::MAGIC, FromVariant::Plist),
+            // CBOR's self-describe tag
+            (b"\xd9\xd9\xf7", FromVariant::Cbor),
+        ];
+        if let Some((_, variant)) = MAGIC.iter().find(|(magic, _)| input.starts_with(magic)) {
+            return Some(*variant);
+        }
+        // BSON documents start with their own length and end with a zero byte
+        if let Some(len) = input.first_chunk::<4>() {
+            if u32::from_le_bytes(*len) as usize == input.len() && input.last() == Some(&0) {
+                return Some(FromVariant::Bson);
+            }
+        }
+        // Pickle protocol 2 and later start with PROTO
+        if matches!(input, [0x80, 2..=5, ..]) {
+            return Some(FromVariant::Pickle);
+        }
+        // Torrents are bencoded dictionaries, whose `pieces` hashes aren't text
+        if matches!(input, [b'd', b'0'..=b'9' | b'e', ..]) && formats::bencode::from_bytes(input, BytesRepr::Hex).is_ok() {
+            return Some(FromVariant::Bencode);
+        }
+
+        let Ok(text) = formats::encoding::to_utf8(input.to_vec()) else {
+            // A CBOR map or array that isn't text
+            return matches!(input.first(), Some(0x80..=0xbf)).then_some(FromVariant::Cbor);
+        };
+        let text = str::from_utf8(&text).ok()?;
+        let trimmed = text.trim_start();
+        if trimmed.starts_with("<?xml") && trimmed.contains("<plist") {
+            Some(FromVariant::Plist)
+        } else if trimmed.starts_with("---") || trimmed.starts_with("%YAML") {
+            Some(FromVariant::Yaml)
+        } else if serde_json::from_str::<serde::de::IgnoredAny>(text).is_ok() {
+            Some(FromVariant::Json)
+        } else if trimmed.starts_with(['{', '[']) && json5::from_str::<serde_json::Value>(text).is_ok() {
+            Some(FromVariant::Json5)
+        } else if text.parse::<toml_edit::DocumentMut>().is_ok() {
+            Some(FromVariant::Toml)
+        } else if serde_yaml::from_str::<serde::de::IgnoredAny>(text).is_ok() {
+            Some(FromVariant::Yaml)
+        } else {
+            None
+        }
+    }
+
     // Deserialize into a struct
-    pub fn deserialize_into<T>(&self, s: &[u8]) -> Result<T>
+    pub fn deserialize_into<T>(&self, s: &[u8], opts: &InputOpts, schema: &SchemaOpts) -> Result<T>
     where
         T: DeserializeOwned,
     {
         match self {
-            FromVariant::Json => serde_json::from_slice(s).map_err(anyhow::Error::new),
-            FromVariant::Yaml => serde_yaml::from_slice(s).map_err(anyhow::Error::new),
+            FromVariant::Json => {
+                // Through Value, which hands exactly representable numbers to T as plain numbers
+                let v = formats::json::from_slice(s, opts.dup_keys)?;
+                serde_json::from_value(v).map_err(anyhow::Error::new)
+            }
+            FromVariant::Json5 => json5::from_str(str::from_utf8(s)?).map_err(anyhow::Error::new),
+            FromVariant::Yaml => {
+                let v = formats::yaml::from_slice(s, opts.yaml_aliases, opts.alias_limits(), opts.dup_keys)?;
+                serde_yaml::from_value(v).map_err(anyhow::Error::new)
+            }
             FromVariant::Cbor => serde_cbor::from_slice(s).map_err(anyhow::Error::new),
             FromVariant::Ron => ron::de::from_bytes(s).map_err(anyhow::Error::new),
             FromVariant::Toml => {
@@ -79,40 +966,225 @@ impl FromVariant {
                 toml::from_str(s).map_err(anyhow::Error::new)
             }
             FromVariant::Bson => bson::from_slice(s).map_err(anyhow::Error::new),
+            FromVariant::Env => {
+                let v = formats::env::from_str(str::from_utf8(s)?)?;
+                serde_json::from_value(v).map_err(anyhow::Error::new)
+            }
+            FromVariant::Properties => {
+                let v = formats::properties::from_str(str::from_utf8(s)?)?;
+                serde_json::from_value(v).map_err(anyhow::Error::new)
+            }
+            FromVariant::Plist => plist::from_bytes(s).map_err(anyhow::Error::new),
+            FromVariant::Kdl => {
+                let v = formats::kdl::from_str(str::from_utf8(s)?)?;
+                serde_json::from_value(v).map_err(anyhow::Error::new)
+            }
+            FromVariant::Edn => {
+                let v = formats::edn::from_str(str::from_utf8(s)?, opts.edn_tags)?;
+                serde_json::from_value(v).map_err(anyhow::Error::new)
+            }
+            FromVariant::Form => serde_urlencoded::from_bytes(s).map_err(anyhow::Error::new),
+            FromVariant::Protobuf => {
+                let v = formats::protobuf::decode(schema.protobuf_message()?, s)?;
+                serde_json::from_value(v).map_err(anyhow::Error::new)
+            }
+            FromVariant::Avro => {
+                let v = formats::avro::from_bytes(s)?;
+                serde_json::from_value(v).map_err(anyhow::Error::new)
+            }
+            FromVariant::Parquet => {
+                let v = formats::parquet::from_bytes(s.to_vec())?;
+                serde_json::from_value(v).map_err(anyhow::Error::new)
+            }
+            FromVariant::Xlsx => {
+                let v = formats::xlsx::from_bytes(s.to_vec(), opts.sheet.as_deref())?;
+                serde_json::from_value(v).map_err(anyhow::Error::new)
+            }
+            FromVariant::Sqlite => {
+                let v = formats::sqlite::from_bytes(s, schema.table.as_deref(), schema.query.as_deref(), schema.bytes)?;
+                serde_json::from_value(v).map_err(anyhow::Error::new)
+            }
+            FromVariant::Bencode => {
+                let v = formats::bencode::from_bytes(s, schema.bytes)?;
+                serde_json::from_value(v).map_err(anyhow::Error::new)
+            }
+            FromVariant::Pickle => {
+                serde_pickle::from_slice(s, serde_pickle::DeOptions::new()).map_err(anyhow::Error::new)
+            }
+            FromVariant::Flexbuffers => flexbuffers::from_slice(s).map_err(anyhow::Error::new),
+            FromVariant::Bincode | FromVariant::Postcard => {
+                let v = self.decode_hinted(s, schema)?;
+                serde_json::from_value(v).map_err(anyhow::Error::new)
+            }
         }
     }
 
     // Run a callback on deserialized object without intermediate Box
-    fn serialize<T>(&self, input: Vec<u8>, s: T)
+    fn serialize<T>(&self, input: Vec<u8>, opts: &InputOpts, schema: &SchemaOpts, s: T) -> Result<()>
     where
-        T: Fn(&dyn erased_serde::Serialize),
+        T: Fn(&dyn erased_serde::Serialize) -> Result<()>,
     {
+        if opts.framing != Framing::None {
+            let records = self.records(&input, opts, schema)?;
+            return s(&records);
+        }
         match self {
             FromVariant::Json => {
-                let v = serde_json::from_slice::<serde_json::Value>(&input).unwrap();
-                s(&v);
+                let v = formats::json::from_slice(&input, opts.dup_keys)?;
+                s(&v)
+            }
+            FromVariant::Json5 => {
+                let v = json5::from_str::<serde_json::Value>(str::from_utf8(&input)?)?;
+                s(&v)
             }
             FromVariant::Yaml => {
-                let v = serde_yaml::from_slice::<serde_yaml::Value>(&input).unwrap();
-                s(&v);
+                let v = formats::yaml::from_slice(&input, opts.yaml_aliases, opts.alias_limits(), opts.dup_keys)?;
+                s(&v)
             }
             FromVariant::Cbor => {
-                let v = serde_cbor::from_slice::<serde_cbor::Value>(&input).unwrap();
-                s(&v);
+                let v = serde_cbor::from_slice::<serde_cbor::Value>(&input)?;
+                s(&v)
             }
             FromVariant::Ron => {
-                let v = ron::de::from_bytes::<ron::Value>(&input).unwrap();
-                s(&v);
+                let v = ron::de::from_bytes::<ron::Value>(&input)?;
+                s(&v)
             }
             FromVariant::Toml => {
-                let st = str::from_utf8(&input).unwrap();
-                let v = toml::from_str::<toml::Value>(st).unwrap();
-                s(&v);
+                let st = str::from_utf8(&input)?;
+                let v = toml::from_str::<toml::Value>(st)?;
+                s(&v)
             }
             FromVariant::Bson => {
-                let v = bson::from_slice::<bson::Bson>(&input).unwrap();
-                s(&v);
+                let v = bson::from_slice::<bson::Bson>(&input)?;
+                s(&v)
+            }
+            FromVariant::Env => {
+                let v = formats::env::from_str(str::from_utf8(&input)?)?;
+                s(&v)
+            }
+            FromVariant::Properties => {
+                let v = formats::properties::from_str(str::from_utf8(&input)?)?;
+                s(&v)
+            }
+            FromVariant::Plist => {
+                let v = plist::from_bytes::<plist::Value>(&input)?;
+                s(&v)
+            }
+            FromVariant::Kdl => {
+                let v = formats::kdl::from_str(str::from_utf8(&input)?)?;
+                s(&v)
+            }
+            FromVariant::Edn => {
+                let v = formats::edn::from_str(str::from_utf8(&input)?, opts.edn_tags)?;
+                s(&v)
+            }
+            FromVariant::Form => {
+                let v = formats::form::from_bytes(&input)?;
+                s(&v)
+            }
+            FromVariant::Protobuf => {
+                let v = formats::protobuf::decode(schema.protobuf_message()?, &input)?;
+                s(&v)
+            }
+            FromVariant::Avro => {
+                let v = formats::avro::from_bytes(&input)?;
+                s(&v)
+            }
+            FromVariant::Parquet => {
+                let v = formats::parquet::from_bytes(input)?;
+                s(&v)
+            }
+            FromVariant::Xlsx => {
+                let v = formats::xlsx::from_bytes(input, opts.sheet.as_deref())?;
+                s(&v)
+            }
+            FromVariant::Sqlite => {
+                let v = formats::sqlite::from_bytes(&input, schema.table.as_deref(), schema.query.as_deref(), schema.bytes)?;
+                s(&v)
+            }
+            FromVariant::Bencode => {
+                let v = formats::bencode::from_bytes(&input, schema.bytes)?;
+                s(&v)
+            }
+            FromVariant::Pickle => {
+                // serde_yaml::Value keeps non-string dict keys
+                let v = serde_pickle::from_slice::<serde_yaml::Value>(&input, serde_pickle::DeOptions::new())?;
+                s(&v)
+            }
+            FromVariant::Flexbuffers => {
+                let v = flexbuffers::from_slice::<serde_yaml::Value>(&input)?;
+                s(&v)
+            }
+            FromVariant::Bincode | FromVariant::Postcard => {
+                let v = self.decode_hinted(&input, schema)?;
+                s(&v)
+            }
+        }
+    }
+
+    // Decode every record of a framed stream
+    fn records(&self, input: &[u8], opts: &InputOpts, schema: &SchemaOpts) -> Result<Vec<serde_json::Value>> {
+        match (opts.framing, self) {
+            (Framing::Sequence, FromVariant::Cbor) => serde_cbor::Deserializer::from_slice(input)
+                .into_iter::<serde_cbor::Value>()
+                .map(|v| -> Result<_> { Ok(serde_json::to_value(v?)?) })
+                .collect(),
+            (Framing::Sequence, FromVariant::Json) => serde_json::Deserializer::from_slice(input)
+                .into_iter::<serde_json::Value>()
+                .map(|v| -> Result<_> { Ok(v?) })
+                .collect(),
+            (Framing::Sequence, FromVariant::Yaml) => {
+                formats::yaml::check_aliases(str::from_utf8(input)?, opts.yaml_aliases, opts.alias_limits())?;
+                serde_yaml::Deserializer::from_slice(input)
+                    .map(|doc| -> Result<_> {
+                        let mut v = serde_yaml::Value::deserialize(doc)?;
+                        v.apply_merge()?;
+                        Ok(serde_json::to_value(v)?)
+                    })
+                    .collect()
             }
+            (Framing::Sequence, FromVariant::Bson) => formats::framing::split_bson(input)?
+                .into_iter()
+                .map(|doc| -> Result<_> { Ok(serde_json::to_value(bson::from_slice::<bson::Bson>(doc)?)?) })
+                .collect(),
+            (Framing::Sequence, other) => {
+                bail!("{other:?} records can't be told apart when concatenated, use --framing length-prefixed")
+            }
+            (Framing::LengthPrefixed, _) => {
+                let single = InputOpts { framing: Framing::None, ..opts.clone() };
+                let records = RefCell::new(Vec::new());
+                for frame in formats::framing::split_length_prefixed(input)? {
+                    self.serialize(frame.to_vec(), &single, schema, |obj| {
+                        records.borrow_mut().push(serde_json::to_value(obj)?);
+                        Ok(())
+                    })?;
+                }
+                Ok(records.into_inner())
+            }
+            (Framing::None, _) => unreachable!("unframed input is decoded directly"),
+        }
+    }
+
+    fn decode_hinted(&self, input: &[u8], schema: &SchemaOpts) -> Result<serde_json::Value> {
+        use serde::de::DeserializeSeed;
+
+        let format = format!("{self:?}");
+        let hint = formats::typehint::parse(schema.type_hint.as_deref(), &format)?;
+        let seed = formats::typehint::HintSeed { hint: &hint, bytes: schema.bytes };
+        match self {
+            FromVariant::Bincode => {
+                use bincode::Options;
+                // Same configuration as bincode::serialize
+                let options = bincode::DefaultOptions::new()
+                    .with_fixint_encoding()
+                    .allow_trailing_bytes();
+                Ok(options.deserialize_seed(seed, input)?)
+            }
+            FromVariant::Postcard => {
+                let mut de = postcard::Deserializer::from_bytes(input);
+                seed.deserialize(&mut de).map_err(|e| anyhow!("{e}"))
+            }
+            _ => unreachable!("only bincode and postcard need a type hint"),
         }
     }
 }
@@ -121,11 +1193,28 @@ impl From<FromVariant> for ToVariant {
     fn from(variant: FromVariant) -> Self {
         match variant {
             FromVariant::Json => ToVariant::Json,
+            FromVariant::Json5 => ToVariant::PrettyJson,
             FromVariant::Yaml => ToVariant::Yaml,
             FromVariant::Cbor => ToVariant::Cbor,
             FromVariant::Ron => ToVariant::Ron,
             FromVariant::Toml => ToVariant::Toml,
             FromVariant::Bson => ToVariant::Bson,
+            FromVariant::Env => ToVariant::Env,
+            FromVariant::Properties => ToVariant::Properties,
+            FromVariant::Plist => ToVariant::Plist,
+            FromVariant::Kdl => ToVariant::Kdl,
+            FromVariant::Edn => ToVariant::PrettyJson,
+            FromVariant::Form => ToVariant::Form,
+            FromVariant::Protobuf => ToVariant::PrettyJson,
+            FromVariant::Avro => ToVariant::PrettyJson,
+            FromVariant::Parquet => ToVariant::PrettyJson,
+            FromVariant::Xlsx => ToVariant::PrettyJson,
+            FromVariant::Sqlite => ToVariant::PrettyJson,
+            FromVariant::Bencode => ToVariant::PrettyJson,
+            FromVariant::Pickle => ToVariant::Pickle,
+            FromVariant::Bincode => ToVariant::Bincode,
+            FromVariant::Postcard => ToVariant::Postcard,
+            FromVariant::Flexbuffers => ToVariant::Flexbuffers,
         }
     }
 }
@@ -133,18 +1222,18 @@ impl From<FromVariant> for ToVariant {
 // Get Variant from filepath
 impl From<&PathBuf> for FromVariant {
     fn from(path: &PathBuf) -> Self {
+        // dotfiles like .env have no extension of their own
+        if path.file_name().and_then(|n| n.to_str()) == Some(".env") {
+            return FromVariant::Env;
+        }
         let p = path
             .extension()
             .expect("Extension not found, the type of the file could not be inferred.");
-        match p.to_str().unwrap() {
-            "bson" | "bs" => FromVariant::Bson,
-            "cbor" | "cb" => FromVariant::Cbor,
-            "json" => FromVariant::Json,
-            "ron" => FromVariant::Ron,
-            "toml" | "service" => FromVariant::Toml,
-            "yaml" | "yml" => FromVariant::Yaml,
-            _ => panic!("Type of the file could not be inferred"),
-        }
+        let p = p.to_str().unwrap();
+        *FromVariant::value_variants()
+            .iter()
+            .find(|variant| variant.extensions().contains(&p))
+            .expect("Type of the file could not be inferred")
     }
 }
 
@@ -163,114 +1252,726 @@ pub enum ToVariant {
     Toml,
     Bson,
     Ini,
+    Env,
+    Properties,
+    Plist,
+    BinaryPlist,
+    Kdl,
+    /// application/x-www-form-urlencoded
+    Form,
+    /// Binary protobuf, requires --descriptor
+    Protobuf,
+    /// Avro object container file, requires --avro-schema
+    Avro,
+    /// Newline delimited JSON, one line per list item
+    Ndjson,
+    /// INSERT statements, requires --table
+    Sql,
+    Csv,
+    Tsv,
+    /// GitHub-flavored Markdown table
+    Markdown,
+    Xml,
+    /// JSON Schema (draft 2020-12) inferred from the input
+    JsonSchema,
+    /// Rust type definitions inferred from the input
+    Rust,
+    Hcl,
     Systemd,
     Quadlet,
+    /// Kubernetes Pod YAML for podman kube play, with a .kube unit when writing to a directory
+    Kube,
 }
 
 impl ToVariant {
     fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        if path.as_ref().file_name()?.to_str()? == ".env" {
+            return Some(Self::Env);
+        }
         let p = path.as_ref().extension()?.to_str()?;
-        match p {
-            "bincode" | "bc" => Some(Self::Bincode),
-            "bson" | "bs" => Some(Self::Bson),
-            "cbor" | "cb" => Some(Self::Cbor),
-            "yaml" | "yml" => Some(Self::Yaml),
-            "flexbuffers" | "fb" => Some(Self::Flexbuffers),
-            "postcard" | "pc" => Some(Self::Postcard),
-            "pickle" | "pkl" => Some(Self::Pickle),
-            "json" => Some(Self::Json),
-            "hjson" => Some(Self::PrettyJson),
-            "ron" => Some(Self::Ron),
-            "hron" => Some(Self::PrettyRon),
-            "toml" => Some(Self::Toml),
-            "ini" => Some(Self::Ini),
+        ToVariant::value_variants()
+            .iter()
+            .find(|variant| variant.extensions().contains(&p))
+            .copied()
+    }
+
+    // The format output is read back as by --verify
+    fn read_back(self) -> Option<FromVariant> {
+        match self {
+            ToVariant::Json | ToVariant::PrettyJson => Some(FromVariant::Json),
+            ToVariant::Yaml => Some(FromVariant::Yaml),
+            ToVariant::Cbor => Some(FromVariant::Cbor),
+            ToVariant::Ron | ToVariant::PrettyRon => Some(FromVariant::Ron),
+            ToVariant::Toml => Some(FromVariant::Toml),
+            ToVariant::Bson => Some(FromVariant::Bson),
+            ToVariant::Env => Some(FromVariant::Env),
+            ToVariant::Properties => Some(FromVariant::Properties),
+            ToVariant::Plist | ToVariant::BinaryPlist => Some(FromVariant::Plist),
+            ToVariant::Kdl => Some(FromVariant::Kdl),
+            ToVariant::Form => Some(FromVariant::Form),
+            ToVariant::Protobuf => Some(FromVariant::Protobuf),
+            ToVariant::Avro => Some(FromVariant::Avro),
+            ToVariant::Pickle => Some(FromVariant::Pickle),
+            ToVariant::Bincode => Some(FromVariant::Bincode),
+            ToVariant::Postcard => Some(FromVariant::Postcard),
+            ToVariant::Flexbuffers => Some(FromVariant::Flexbuffers),
             _ => None,
         }
     }
 
-    fn to_buf(self, obj: &dyn erased_serde::Serialize) -> Vec<u8> {
+    // The media type sent with the output to --output-url
+    fn content_type(self) -> &'static str {
+        match self {
+            ToVariant::Json | ToVariant::PrettyJson | ToVariant::JsonSchema => "application/json",
+            ToVariant::Ndjson => "application/x-ndjson",
+            ToVariant::Yaml | ToVariant::Kube => "application/yaml",
+            ToVariant::Toml => "application/toml",
+            ToVariant::Xml | ToVariant::Plist => "application/xml",
+            ToVariant::Cbor => "application/cbor",
+            ToVariant::Bson => "application/bson",
+            ToVariant::Form => "application/x-www-form-urlencoded",
+            ToVariant::Protobuf => "application/x-protobuf",
+            ToVariant::Avro => "application/avro",
+            ToVariant::Csv => "text/csv",
+            ToVariant::Tsv => "text/tab-separated-values",
+            ToVariant::Markdown => "text/markdown",
+            ToVariant::Sql => "application/sql",
+            ToVariant::Ron | ToVariant::PrettyRon | ToVariant::Ini | ToVariant::Env | ToVariant::Properties
+            | ToVariant::Kdl | ToVariant::Rust | ToVariant::Hcl | ToVariant::Systemd | ToVariant::Quadlet => "text/plain",
+            ToVariant::Pickle | ToVariant::Bincode | ToVariant::Postcard | ToVariant::Flexbuffers | ToVariant::BinaryPlist => {
+                "application/octet-stream"
+            }
+        }
+    }
+
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            ToVariant::Bincode => &["bincode", "bc"],
+            ToVariant::Bson => &["bson", "bs"],
+            ToVariant::Cbor => &["cbor", "cb"],
+            ToVariant::Yaml => &["yaml", "yml"],
+            ToVariant::Flexbuffers => &["flexbuffers", "fb"],
+            ToVariant::Postcard => &["postcard", "pc"],
+            ToVariant::Pickle => &["pickle", "pkl"],
+            ToVariant::Json => &["json"],
+            ToVariant::PrettyJson => &["hjson"],
+            ToVariant::Ron => &["ron"],
+            ToVariant::PrettyRon => &["hron"],
+            ToVariant::Toml => &["toml"],
+            ToVariant::Ini => &["ini"],
+            ToVariant::Env => &["env"],
+            ToVariant::Properties => &["properties"],
+            ToVariant::Plist => &["plist"],
+            ToVariant::Kdl => &["kdl"],
+            ToVariant::Protobuf => &["binpb", "pb"],
+            ToVariant::Avro => &["avro"],
+            ToVariant::Ndjson => &["ndjson", "jsonl"],
+            ToVariant::Sql => &["sql"],
+            ToVariant::Csv => &["csv"],
+            ToVariant::Tsv => &["tsv"],
+            ToVariant::Markdown => &["md", "markdown"],
+            ToVariant::Xml => &["xml"],
+            ToVariant::Hcl => &["hcl", "tf"],
+            ToVariant::BinaryPlist | ToVariant::Form | ToVariant::JsonSchema | ToVariant::Rust | ToVariant::Systemd | ToVariant::Quadlet | ToVariant::Kube => &[],
+        }
+    }
+
+    // What the format can't hold or needs to be written
+    fn limitations(self) -> &'static str {
         match self {
+            ToVariant::Toml => "no null values, a table at the top level",
+            ToVariant::Ini => "flat sections of string values only",
+            ToVariant::Env => "a map at the top level, nested keys are joined with _",
+            ToVariant::Form => "flat maps of strings and lists",
+            ToVariant::Csv | ToVariant::Tsv | ToVariant::Markdown => "a list of flat maps, or see --flatten",
+            ToVariant::Sql => "a list of maps, requires --table",
+            ToVariant::Protobuf => "requires --descriptor and --message",
+            ToVariant::Avro => "requires --avro-schema",
+            ToVariant::Bincode | ToVariant::Postcard => "not self-describing, use --type-hint to read it back",
+            ToVariant::Xml => "wrapped in --xml-root",
+            ToVariant::JsonSchema => "a schema inferred from the input",
+            ToVariant::Rust => "type definitions inferred from the input",
+            ToVariant::Systemd | ToVariant::Quadlet => "writes unit files, see README",
+            ToVariant::Kube => "a compose file, see README",
+            _ => "",
+        }
+    }
+
+    // Whether the format can hold raw byte strings
+    fn has_bytes(self) -> bool {
+        matches!(
+            self,
+            ToVariant::Pickle
+                | ToVariant::Bincode
+                | ToVariant::Postcard
+                | ToVariant::Flexbuffers
+                | ToVariant::Cbor
+                | ToVariant::Bson
+                | ToVariant::Plist
+                | ToVariant::BinaryPlist
+        )
+    }
+
+    fn to_buf(
+        self,
+        obj: &dyn erased_serde::Serialize,
+        opts: &OutputOpts,
+        schema: &SchemaOpts,
+    ) -> Result<Vec<u8>> {
+        // Formats without a byte string type get them as text
+        let rendered = formats::json::RenderBytes(obj, schema.bytes);
+        let obj: &dyn erased_serde::Serialize = if self.has_bytes() { obj } else { &rendered };
+        // Exact serde_json numbers are only understood by serde_json itself. Writers that take a
+        // `Value` read the digits from it, so they get the number as it was written.
+        let plain = formats::json::PlainNumbers(obj);
+        let obj: &dyn erased_serde::Serialize = match self {
+            ToVariant::Pickle
+            | ToVariant::Bincode
+            | ToVariant::Postcard
+            | ToVariant::Flexbuffers
+            | ToVariant::Yaml
+            | ToVariant::Cbor
+            | ToVariant::Ron
+            | ToVariant::PrettyRon
+            | ToVariant::Bson
+            | ToVariant::Ini
+            | ToVariant::Plist
+            | ToVariant::BinaryPlist => &plain,
+            _ => obj,
+        };
+        let buf = match self {
+            ToVariant::Json | ToVariant::PrettyJson if opts.canonical => {
+                let value = serde_json::to_value(obj)?;
+                formats::json::to_canonical(&value).into_bytes()
+            }
+            ToVariant::Json | ToVariant::PrettyJson if opts.sort_keys => {
+                let mut value = serde_json::to_value(obj)?;
+                formats::json::sort_keys(&mut value);
+                if self == ToVariant::PrettyJson {
+                    serde_json::to_vec_pretty(&value)?
+                } else {
+                    serde_json::to_vec(&value)?
+                }
+            }
             ToVariant::Pickle => {
-                serde_pickle::to_vec(&obj, serde_pickle::SerOptions::new()).unwrap()
-            }
-            ToVariant::Bincode => bincode::serialize(&obj).unwrap(),
-            ToVariant::Postcard => postcard::to_allocvec(&obj).unwrap(),
-            ToVariant::Flexbuffers => flexbuffers::to_vec(obj).unwrap(),
-            ToVariant::Json => serde_json::to_vec(&obj).unwrap(),
-            ToVariant::PrettyJson => serde_json::to_vec_pretty(&obj).unwrap(),
-            ToVariant::Yaml => serde_yaml::to_string(&obj).unwrap().into_bytes(),
-            ToVariant::Cbor => serde_cbor::to_vec(&obj).unwrap(),
-            ToVariant::Ron => ron::to_string(&obj).unwrap().into_bytes(),
+                serde_pickle::to_vec(&obj, serde_pickle::SerOptions::new())?
+            }
+            ToVariant::Bincode | ToVariant::Postcard if schema.type_hint.is_some() => {
+                let hint = formats::typehint::parse(schema.type_hint.as_deref(), "")?;
+                let value = serde_json::to_value(obj)?;
+                let hinted = formats::typehint::Hinted { value: &value, hint: &hint, bytes: schema.bytes };
+                if self == ToVariant::Bincode {
+                    bincode::serialize(&hinted)?
+                } else {
+                    postcard::to_allocvec(&hinted).map_err(|e| anyhow!("{e}"))?
+                }
+            }
+            ToVariant::Bincode => bincode::serialize(&obj)?,
+            ToVariant::Postcard => postcard::to_allocvec(&obj).map_err(|e| anyhow!("{e}"))?,
+            ToVariant::Flexbuffers => flexbuffers::to_vec(obj)?,
+            ToVariant::Json => serde_json::to_vec(&obj)?,
+            ToVariant::Ndjson => {
+                let mut buf = Vec::new();
+                match serde_json::to_value(obj)? {
+                    serde_json::Value::Array(items) => {
+                        for item in items {
+                            serde_json::to_writer(&mut buf, &item)?;
+                            buf.push(b'\n');
+                        }
+                    }
+                    other => {
+                        serde_json::to_writer(&mut buf, &other)?;
+                        buf.push(b'\n');
+                    }
+                }
+                buf
+            }
+            ToVariant::PrettyJson => serde_json::to_vec_pretty(&obj)?,
+            ToVariant::Yaml => match opts.yaml_style() {
+                Some(style) => formats::yaml::to_string(&serde_yaml::to_value(obj)?, &style)?.into_bytes(),
+                None => serde_yaml::to_string(&obj)?.into_bytes(),
+            },
+            ToVariant::Cbor => serde_cbor::to_vec(&obj)?,
+            ToVariant::Ron => ron::to_string(&obj)?.into_bytes(),
             ToVariant::PrettyRon => {
                 let s = ron::ser::PrettyConfig::new();
-                let s = ron::ser::to_string_pretty(&obj, s).unwrap();
+                let s = ron::ser::to_string_pretty(&obj, s)?;
                 s.into_bytes()
             }
-            ToVariant::Toml => toml::to_string(&obj).unwrap().into_bytes(),
-            ToVariant::Bson => bson::to_vec(&obj).unwrap(),
-            ToVariant::Ini => serde_ini::to_vec(&obj).unwrap(),
+            ToVariant::Toml => formats::toml::to_string(obj, opts.toml_style)?.into_bytes(),
+            ToVariant::Bson => bson::to_vec(&obj)?,
+            ToVariant::Ini => serde_ini::to_vec(&obj)?,
+            ToVariant::Env => formats::env::to_string(&serde_json::to_value(obj)?)?.into_bytes(),
+            ToVariant::Properties => {
+                formats::properties::to_string(&serde_json::to_value(obj)?)?.into_bytes()
+            }
+            ToVariant::Plist | ToVariant::BinaryPlist => formats::plist::to_vec(&obj, self == ToVariant::BinaryPlist)?,
+            ToVariant::Kdl => formats::kdl::to_string(&serde_json::to_value(obj)?)?.into_bytes(),
+            ToVariant::Form => formats::form::to_string(&serde_json::to_value(obj)?)?.into_bytes(),
+            ToVariant::Protobuf => {
+                formats::protobuf::encode(schema.protobuf_message()?, serde_json::to_value(obj)?)?
+            }
+            ToVariant::Avro => {
+                let avro_schema = schema
+                    .avro_schema
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("Avro output requires --avro-schema <FILE>"))?;
+                formats::avro::to_vec(serde_json::to_value(obj)?, avro_schema)?
+            }
+            ToVariant::Csv | ToVariant::Tsv => {
+                let table = formats::csv::Table::new(
+                    &serde_json::to_value(obj)?,
+                    opts.columns.as_deref(),
+                    opts.flatten,
+                )?;
+                formats::csv::to_vec(&table, if self == ToVariant::Tsv { b'\t' } else { b',' })?
+            }
+            ToVariant::Markdown => {
+                let table = formats::csv::Table::new(
+                    &serde_json::to_value(obj)?,
+                    opts.columns.as_deref(),
+                    opts.flatten,
+                )?;
+                formats::markdown::to_string(&table).into_bytes()
+            }
+            ToVariant::JsonSchema => {
+                serde_json::to_vec_pretty(&formats::jsonschema::infer(&serde_json::to_value(obj)?))?
+            }
+            ToVariant::Hcl => formats::hcl::to_string(&serde_json::to_value(obj)?)?.into_bytes(),
+            ToVariant::Rust => {
+                formats::rust::to_string(&serde_json::to_value(obj)?, &opts.rust_derive, opts.rust_strings)
+                    .into_bytes()
+            }
+            ToVariant::Xml => {
+                formats::xml::to_string(serde_json::to_value(obj)?, &opts.xml_root, &opts.xml_attr_prefix)?
+                    .into_bytes()
+            }
+            ToVariant::Sql => {
+                let table = schema
+                    .table
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("SQL output requires --table <NAME>"))?;
+                formats::sql::to_string(&serde_json::to_value(obj)?, table, opts.sql_dialect)?.into_bytes()
+            }
             _ => {
                 panic!("Special variants have custom handling.")
             }
+        };
+        Ok(buf)
+    }
+}
+
+// Answers in the compose file take precedence over the config file
+fn quadlet_prompter(files: &[ComposeFile], config: Config, prompt_opts: &PromptOpts) -> Result<Box<dyn Prompter>> {
+    let mut presets = config.answers;
+    for file in files {
+        presets.extend(preset_answers(file)?);
+    }
+    // Add everything not already answered, to switch off on the review screen
+    #[cfg(feature = "tui")]
+    if prompt_opts.review {
+        for (key, ..) in review::REVIEWED {
+            presets.entry(key.to_string()).or_insert(Preset::Answer(true));
+        }
+    }
+    prompt_opts.prompter(presets)
+}
+
+/// Convert a compose file to quadlets with podlet, writing the compose file it's given to `filename`
+fn generate_quadlets(
+    mut file: ComposeFile,
+    dir: Option<&Path>,
+    filename: &Path,
+    quadlet_opts: &QuadletOpts,
+    prompter: &mut dyn Prompter,
+) -> Result<IniFiles> {
+    set_project_name(&mut file, quadlet_opts.project_name.as_deref());
+    let mut file = process_compose(file, dir, prompter)?;
+    let builds = extract_builds(&mut file, dir, prompter)?;
+    // Init containers become services of their own, so before anything is taken out of services
+    let init = extract_init_containers(&mut file)?;
+    let mut directives = extract_restart_policies(&mut file, prompter)?;
+    // Devices are read from deploy.resources, so before the limits are taken out along with it
+    let extracted = vec![
+        init,
+        extract_devices(&mut file),
+        extract_resource_limits(&mut file, quadlet_opts.limits)?,
+        extract_security_options(&mut file),
+        extract_mounts(&mut file),
+        extract_kernel_settings(&mut file),
+    ];
+    for (service, more) in extracted.into_iter().flatten() {
+        directives.entry(service).or_default().extend(more);
+    }
+    let network_settings = extract_network_settings(&mut file);
+    let overrides = extract_overrides(&mut file)?;
+    let replicas = extract_replicas(&mut file);
+    relabel_bind_mounts(&mut file, quadlet_opts.relabel, prompter)?;
+
+    let s = serde_yaml::to_string(&file)?;
+
+    // todo: use pere
+    if !filename.exists() || prompter.confirm(
+        "overwrite-compose",
+        &format!("File '{}' already exists. Overwrite?", filename.display()),
+        true,
+    )? {
+        std::fs::write(filename, &s)?;
+    }
+
+    let quadlets = get_raw_quadlets(&filename.to_path_buf(), !quadlet_opts.no_pod)?;
+    let mut processed_quadlets = process_quadlets(quadlets, dir, prompter)?;
+    add_directives(&mut processed_quadlets, directives);
+    add_network_settings(&mut processed_quadlets, network_settings);
+    apply_overrides(&mut processed_quadlets, overrides);
+    add_replicas(&mut processed_quadlets, replicas);
+    processed_quadlets.0.extend(builds.0);
+    if quadlet_opts.image_units {
+        add_image_units(&mut processed_quadlets, quadlet_opts.all_tags, quadlet_opts.arch.as_deref());
+    }
+    if let Some(prefix) = &quadlet_opts.name_prefix {
+        prefix_units(&mut processed_quadlets, prefix);
+    }
+    if quadlet_opts.target {
+        // Without a pod, it's named after the project
+        let name = match processed_quadlets.0.keys().find_map(|name| name.strip_suffix(".pod")) {
+            Some(pod) => pod.to_string(),
+            None => format!(
+                "{}{}",
+                quadlet_opts.name_prefix.as_deref().unwrap_or_default(),
+                file.other.get("name").and_then(|n| n.as_str()).unwrap_or("compose")
+            ),
+        };
+        add_target(&mut processed_quadlets, &name);
+    }
+    Ok(processed_quadlets)
+}
+
+#[allow(unused_variables)]
+fn review_quadlets(units: &mut IniFiles, prompt_opts: &PromptOpts) -> Result<()> {
+    #[cfg(feature = "tui")]
+    if prompt_opts.review {
+        if !is_interactive() {
+            log::warn!("Skipping --review without a terminal");
+        } else if !review::review(units)? {
+            bail!("Review cancelled, nothing was written");
+        }
+    }
+    Ok(())
+}
+
+fn run_update(cmd: UpdateCmd) -> Result<()> {
+    let config = Config::load(cmd.config.as_deref())?;
+    let file: ComposeFile = serde_yaml::from_slice(&std::fs::read(&cmd.compose)?)
+        .with_context(|| format!("Failed to parse {}", cmd.compose.display()))?;
+    let mut prompter = quadlet_prompter(std::slice::from_ref(&file), config, &cmd.prompt_opts)?;
+
+    // podlet reads the processed compose file from disk
+    let tmp_dir = TempFileBuilder::new().tempdir()?;
+    let mut units = generate_quadlets(
+        file,
+        cmd.compose.parent(),
+        &tmp_dir.path().join("compose.yaml"),
+        &cmd.quadlet_opts,
+        &mut prompter,
+    )?;
+    if let Some(command) = &cmd.notify_opts.notify_failure {
+        add_on_failure(&mut units, command)?;
+    }
+    review_quadlets(&mut units, &cmd.prompt_opts)?;
+    gate_version(&mut units, cmd.quadlet_opts.target_podman_version);
+    check_keys(&units, &mut prompter)?;
+    check_port_conflicts(&units, cmd.quadlet_opts.check_listening)?;
+    check_resources(&units, &mut prompter)?;
+    if cmd.quadlet_opts.verify_signatures {
+        verify_signatures(&units, cmd.quadlet_opts.cosign_key.as_deref(), cmd.quadlet_opts.signature_policy.as_deref())?;
+    }
+
+    let changes = update_quadlets(&units, cmd.dry_run, &mut prompter)?;
+    let color = stdout().is_terminal() && env::var_os("NO_COLOR").is_none();
+    let mut out = String::new();
+    for (name, change) in &changes {
+        match change {
+            UnitChange::Added => out.push_str(&format!("added      {name}\n")),
+            UnitChange::Unchanged => out.push_str(&format!("unchanged  {name}\n")),
+            UnitChange::Changed(differences) => {
+                out.push_str(&format!("changed    {name}\n"));
+                for line in diff::to_tree(differences, color).lines() {
+                    out.push_str(&format!("    {line}\n"));
+                }
+            }
+        }
+    }
+    stdout().lock().write_all(out.as_bytes())?;
+
+    if !cmd.dry_run {
+        restart_changed(&changes, &mut prompter)?;
+    }
+    Ok(())
+}
+
+fn run_portable(cmd: PortableCmd) -> Result<()> {
+    let config = Config::load(cmd.config.as_deref())?;
+    let mut bytes = std::fs::read(&cmd.input).with_context(|| format!("Failed to read {}", cmd.input.display()))?;
+    let mut path = cmd.input.clone();
+    if path.extension().and_then(|e| e.to_str()) == Some("tera") {
+        bytes = render_template(str::from_utf8(&bytes)?, &tera::Context::new(), cmd.template_opts.rendering())?.into_bytes();
+        path.set_extension("");
+    }
+    let mut units: IniFiles = FromVariant::from(&path).deserialize_into(&bytes, &InputOpts::default(), &SchemaOpts::default())?;
+    let name = match cmd.name {
+        Some(name) => name,
+        None => path.file_stem().and_then(|s| s.to_str()).map(str::to_string).ok_or_else(|| anyhow!("Name the image with --name"))?,
+    };
+
+    let environments = extract_environment(&mut units);
+    let mut units = process_systemd(units)?;
+    // The image carries no env files of its own
+    inline_environment(&environments, &mut units);
+    let image = build_portable(&units, &name, cmd.root.as_deref(), &cmd.output)?;
+    log::info!("Wrote the portable image {}", image.display());
+    if is_interactive() {
+        let mut prompter = cmd.prompt_opts.prompter(config.answers)?;
+        attach_portable(&image, &mut prompter)?;
+    }
+    Ok(())
+}
+
+// The input files, with directories replaced by the files in them, in name order
+fn input_files(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path);
+            continue;
+        }
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(&path).with_context(|| format!("Failed to read {}", path.display()))? {
+            let entry = entry?.path();
+            let hidden = entry.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with('.'));
+            if entry.is_file() && !hidden {
+                entries.push(entry);
+            }
+        }
+        if entries.is_empty() {
+            bail!("{} has no files to read", path.display());
+        }
+        entries.sort();
+        files.extend(entries);
+    }
+    Ok(files)
+}
+
+// Read an input file in the format given by --from or its extension, rendering it with Tera if
+// asked to or if it ends in .tera
+fn read_input(path: &Path, from: Option<FromVariant>, tera: bool, renderer: &Renderer) -> Result<(FromVariant, Vec<u8>, Option<Rendered>)> {
+    let mut bytes = archive::read(path)?;
+    let name = archive::name_of(path);
+    let is_tera = name.extension().and_then(|e| e.to_str()) == Some("tera");
+    let from_variant = if is_tera {
+        let mut stripped = name.clone();
+        stripped.set_extension("");
+        from.unwrap_or_else(|| FromVariant::from(&stripped))
+    } else {
+        from.unwrap_or_else(|| FromVariant::from(&name))
+    };
+    if from_variant.is_text() {
+        bytes = formats::encoding::to_utf8(bytes)?;
+    }
+    if tera || is_tera {
+        let (rendered, source_map) = renderer.render(&bytes)?;
+        return Ok((from_variant, rendered, Some(source_map)));
+    }
+    Ok((from_variant, bytes, None))
+}
+
+fn input_name(path: Option<&PathBuf>) -> String {
+    path.map_or_else(|| "stdin".to_string(), |p| p.display().to_string())
+}
+
+// Add the units read from `source` to those of earlier inputs, which `origins` records the
+// source of, refusing a unit name two inputs define rather than letting one replace the other
+fn merge_units(merged: &mut IniFiles, origins: &mut HashMap<String, String>, units: IniFiles, source: &str) -> Result<()> {
+    for (name, unit) in units.0 {
+        if let Some(earlier) = origins.get(&name) {
+            bail!("Both {earlier} and {source} define '{name}'");
         }
+        origins.insert(name.clone(), source.to_string());
+        merged.insert(name, unit);
+    }
+    Ok(())
+}
+
+fn run_render(cmd: RenderCmd) -> Result<()> {
+    let context = match &cmd.var_file {
+        Some(path) => tera::Context::from_value(read_document(path)?)
+            .with_context(|| format!("{} should map variable names to values", path.display()))?,
+        None => tera::Context::new(),
+    };
+    // What's rendered is any kind of text rather than HTML, so values are substituted as they are
+    let rendering = Rendering { autoescape: false, secrets: cmd.secrets, strict: cmd.strict_templates };
+    let mut base = glob_base(&cmd.pattern);
+    // A pattern without wildcards names one file, written straight into the output directory
+    if base.is_file() {
+        base.pop();
     }
+    let mut rendered = 0;
+    for entry in glob::glob(&cmd.pattern)? {
+        let path = entry?;
+        if !path.is_file() {
+            continue;
+        }
+        let mut target = cmd.output.join(path.strip_prefix(&base).unwrap_or(&path));
+        if target.extension().and_then(|e| e.to_str()) == Some("tera") {
+            target.set_extension("");
+        }
+        let template = std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let text = render_template(&template, &context, rendering).with_context(|| format!("Failed to render {}", path.display()))?;
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&target, text)?;
+        log::info!("Rendered {} to {}", path.display(), target.display());
+        rendered += 1;
+    }
+    if rendered == 0 {
+        bail!("No files match {}", cmd.pattern);
+    }
+    Ok(())
+}
+
+// The directory a glob starts from, before its first wildcard, which the output mirrors below
+fn glob_base(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|component| !component.as_os_str().to_string_lossy().contains(['*', '?', '[', '{']))
+        .collect()
 }
 
 pub fn run(opts: Opts) -> Result<()> {
+    if !opts.env_allow.is_empty() {
+        utils::allow_env(opts.env_allow);
+    }
+    http::set_headers(&opts.header)?;
+    if let Some(command) = opts.command {
+        return match command {
+            Command::Diff(cmd) => run_diff(cmd),
+            Command::Formats => list_formats(),
+            Command::Doctor => run_doctor(),
+            Command::Quadlet { command: QuadletCommand::Update(cmd) } => run_update(*cmd),
+            Command::Quadlet { command: QuadletCommand::Down { pod } } => stop_pod(&pod),
+            Command::Systemd { command: SystemdCommand::Portable(cmd) } => run_portable(cmd),
+            Command::Render(cmd) => run_render(cmd),
+        };
+    }
     let file_cmd = opts.file_cmd;
     let input = file_cmd.input;
     let from = file_cmd.from;
     let to = file_cmd.to;
     let output = file_cmd.output;
-    let mut tera_enabled = file_cmd.tera;
+    let url_output = file_cmd.url_output_opts;
+    let input_opts = file_cmd.input_opts;
+    let output_opts = file_cmd.output_opts;
+    let schema_opts = file_cmd.schema_opts;
+    let transform_opts = file_cmd.transform_opts;
     let verbose_enabled = opts.verbose > 0;
+    let prompt_opts = file_cmd.prompt_opts;
+    let quadlet_opts = file_cmd.quadlet_opts;
+    let systemd_opts = file_cmd.systemd_opts;
+    let notify_opts = file_cmd.notify_opts;
+    let config = Config::load(file_cmd.config.as_deref())?;
+    let profile = file_cmd.inventory_opts.load()?;
+    let context = match &profile {
+        Some((host, profile)) => profile.context(host)?,
+        None => tera::Context::new(),
+    };
+    let renderer = Renderer { context, rendering: file_cmd.template_opts.rendering(), verbose: verbose_enabled };
 
-    let mut input_path: Option<PathBuf> = None;
-    let from_variant: FromVariant;
-    let mut input_bytes = Vec::new();
-
-    match input {
-        Some(inp_path) => {
-            input_bytes = std::fs::read(&inp_path)?;
-            if inp_path.extension().and_then(|e| e.to_str()) == Some("tera") {
-                tera_enabled = true;
-                let mut stripped = inp_path.clone();
-                stripped.set_extension("");
-                from_variant = from.unwrap_or_else(|| FromVariant::from(&stripped));
-            } else {
-                from_variant = from.unwrap_or_else(|| FromVariant::from(&inp_path));
+    if file_cmd.template_opts.render_only {
+        let bytes = match input.as_slice() {
+            [] => {
+                let mut bytes = Vec::new();
+                stdin().lock().read_to_end(&mut bytes)?;
+                bytes
             }
-            input_path = Some(inp_path);
+            [path] => archive::read(path)?,
+            _ => bail!("--render-only renders one input at a time"),
+        };
+        // The text isn't HTML, so values are substituted as they are
+        let rendering = Rendering { autoescape: false, ..renderer.rendering };
+        let rendered = render_template(str::from_utf8(&bytes)?, &renderer.context, rendering)?;
+        return write_output(&output, &url_output, "text/plain", rendered.as_bytes());
+    }
+
+    let inputs = input_files(input)?;
+    let mut documents = Vec::new();
+    for inp_path in &inputs {
+        documents.push(read_input(inp_path, from, file_cmd.tera, &renderer)?);
+    }
+    if inputs.is_empty() {
+        let mut input_bytes = Vec::new();
+        stdin().lock().read_to_end(&mut input_bytes)?;
+        let from_variant = match from {
+            Some(from) => from,
+            None => {
+                let guess = FromVariant::sniff(&input_bytes).ok_or_else(|| {
+                    anyhow!("Couldn't tell the format of stdin, specify it with --from")
+                })?;
+                log::info!("Reading stdin as {guess:?}, use --from to override");
+                guess
+            }
+        };
+        if from_variant.is_text() {
+            input_bytes = formats::encoding::to_utf8(input_bytes)?;
         }
-        None => {
-            stdin().lock().read_to_end(&mut input_bytes)?;
-            from_variant = from.ok_or_else(|| {
-                anyhow!("Input format must be specified with --from when reading from stdin")
-            })?;
+        let mut source_map = None;
+        if file_cmd.tera {
+            let (rendered, map) = renderer.render(&input_bytes)?;
+            input_bytes = rendered;
+            source_map = Some(map);
         }
+        documents.push((from_variant, input_bytes, source_map));
     }
+    let input_path = inputs.first().cloned();
+    let (from_variant, input_bytes, source_map) = documents[0].clone();
 
     let to_variant = to.unwrap_or_else(|| {
-        output
+        let to_variant = output
             .as_ref()
-            .and_then(ToVariant::from_path)
-            .unwrap_or_else(|| from_variant.into())
-    });
-
-    if tera_enabled {
-        let input_str = str::from_utf8(&input_bytes)?;
-        let context = tera::Context::new();
-        let rendered = Tera::one_off(input_str, &context, true)?;
-        if verbose_enabled {
-            println!("# Tera output");
-            println!("{rendered}\n");
-            println!("---\n");
+            .and_then(|path| ToVariant::from_path(archive::uncompressed(path)))
+            .unwrap_or_else(|| from_variant.into());
+        // A binary plist stays binary, as `.plist` doesn't say which form to write
+        if to_variant == ToVariant::Plist && formats::plist::is_binary(&input_bytes) {
+            ToVariant::BinaryPlist
+        } else {
+            to_variant
         }
-        input_bytes = rendered.into_bytes();
+    });
+    if documents.len() > 1 && !matches!(to_variant, ToVariant::Systemd | ToVariant::Quadlet) {
+        bail!("Only systemd and quadlet modes merge several inputs, convert other files one at a time");
     }
+    if url_output.output_url.is_some() && matches!(to_variant, ToVariant::Systemd | ToVariant::Quadlet) {
+        bail!("--output-url sends one document, but {to_variant:?} mode writes unit files");
+    }
+    let read_back = match (file_cmd.verify, to_variant.read_back()) {
+        (false, _) => None,
+        (true, Some(read_back)) => Some(read_back),
+        (true, None) => bail!("--verify can't read {to_variant:?} output back"),
+    };
+    // Keys sorted on purpose aren't reported as reordered
+    let keep_order = !output_opts.sort_keys && !output_opts.canonical;
 
     if to_variant == ToVariant::Systemd {
-        let units: IniFiles = from_variant.deserialize_into(&input_bytes)?;
+        let mut units = IniFiles::new();
+        let mut origins = HashMap::new();
+        for (i, (from_variant, bytes, source_map)) in documents.iter().enumerate() {
+            let more: IniFiles = traced(transform_opts.deserialize_into(*from_variant, bytes, &input_opts, &schema_opts), source_map)?;
+            merge_units(&mut units, &mut origins, more, &input_name(inputs.get(i)))?;
+        }
+        if let Some((host, profile)) = &profile {
+            profile.check_units(host, units.0.keys())?;
+            units.0.retain(|name, _| profile.includes(name));
+        }
 
         if units.0.is_empty() {
             return Err(anyhow!(
@@ -278,65 +1979,205 @@ pub fn run(opts: Opts) -> Result<()> {
             ));
         }
 
-        let processed_units = process_systemd(units)?;
+        let machines = machines(&units);
+        let environments = extract_environment(&mut units);
+        let mut processed_units = process_systemd(units)?;
+        if let Some(command) = &notify_opts.notify_failure {
+            add_on_failure(&mut processed_units, command)?;
+        }
+        let mut prompter = prompt_opts.prompter(config.answers)?;
+        harden_timers(&mut processed_units, &mut prompter)?;
+        // Caught here with suggestions, rather than as systemd-analyze's bare warnings
+        for unknown in systemd::keys::validate(&processed_units) {
+            log::warn!("{unknown}");
+        }
 
-        if let Some(output_dir) = output {
-            let files = write_files(&processed_units.0, &output_dir, serde_ini::to_string)?;
+        if systemd_opts.transient {
+            inline_environment(&environments, &mut processed_units);
+            run_transient(&processed_units)?;
+        } else if let Some(output_dir) = output {
+            let env_files = environment_files(&environments, &mut processed_units, &output_dir)?;
+            let unchanged = unchanged_units(&processed_units, &env_files, &output_dir)?;
+            let mut files = write_environment_files(&env_files, &output_dir)?;
+            files.extend(write_files(&processed_units.0, &output_dir, serde_ini::to_string)?);
             if is_interactive() {
-                activate_units(files)?;
+                activate_units(files, &machines, &systemd_opts.activate, &unchanged, systemd_opts.offline_verify, &mut prompter)?;
             }
         } else {
+            inline_environment(&environments, &mut processed_units);
             print_files(&processed_units.0, serde_ini::to_string)?;
         }
     } else if to_variant == ToVariant::Quadlet {
-        let file: ComposeFile = from_variant.deserialize_into(&input_bytes)?;
-        let dir = input_path
-            .as_ref()
-            .and_then(|p| p.parent());
+        let mut files = Vec::new();
+        for (from_variant, bytes, source_map) in &documents {
+            let file: ComposeFile = traced(transform_opts.deserialize_into(*from_variant, bytes, &input_opts, &schema_opts), source_map)?;
+            files.push(file);
+        }
+        if let Some((host, profile)) = &profile {
+            profile.check_units(host, files.iter().flat_map(|file| file.services.keys()))?;
+            for file in &mut files {
+                file.services.retain(|name, _| profile.includes(name));
+            }
+        }
+
+        let mut prompter = quadlet_prompter(&files, config, &prompt_opts)?;
 
-        let file = process_compose(file, dir)?;
+        let several = files.len() > 1;
+        let mut processed_quadlets = IniFiles::new();
+        let mut origins = HashMap::new();
+        for (i, file) in files.into_iter().enumerate() {
+            // All of its services are left out by the profile
+            if file.services.is_empty() {
+                continue;
+            }
+            let input_path = inputs.get(i);
+            // A fetched compose file has no directory of its own, with an .env or build contexts
+            let dir = input_path.filter(|p| !http::is_url(p)).and_then(|p| p.parent());
+            let filename = match &output {
+                // Each input keeps a compose file of its own beside the units
+                Some(output_dir) if several => {
+                    let stem = input_path.and_then(|p| p.file_stem()).and_then(|s| s.to_str()).unwrap_or("compose");
+                    output_dir.join(format!("{}.compose.yaml", stem.trim_end_matches(".yaml").trim_end_matches(".yml")))
+                }
+                Some(output_dir) => output_dir.join("compose.yaml"),
+                None => {
+                    let tmp_file = TempFileBuilder::new().suffix(".yaml").tempfile()?;
+                    tmp_file.into_temp_path().to_path_buf()
+                }
+            };
+            let units = generate_quadlets(file, dir, &filename, &quadlet_opts, &mut prompter)?;
+            merge_units(&mut processed_quadlets, &mut origins, units, &input_name(input_path))?;
+        }
+        if let Some(command) = &notify_opts.notify_failure {
+            add_on_failure(&mut processed_quadlets, command)?;
+        }
+        review_quadlets(&mut processed_quadlets, &prompt_opts)?;
+        gate_version(&mut processed_quadlets, quadlet_opts.target_podman_version);
+        check_keys(&processed_quadlets, &mut prompter)?;
+        check_port_conflicts(&processed_quadlets, quadlet_opts.check_listening)?;
+        check_resources(&processed_quadlets, &mut prompter)?;
+        if quadlet_opts.verify_signatures {
+            verify_signatures(&processed_quadlets, quadlet_opts.cosign_key.as_deref(), quadlet_opts.signature_policy.as_deref())?;
+        }
 
-        let filename = if let Some(output_dir) = &output {
-            output_dir.join("compose.yaml")
+        if let Some(output_dir) = output {
+            let files = write_merged(&processed_quadlets, &output_dir, &mut prompter)?;
+            if is_interactive() {
+                std::env::set_current_dir(output_dir)?;
+                activate_quadlets(files, &mut prompter)?;
+            }
         } else {
-            let tmp_file = TempFileBuilder::new().suffix(".yaml").tempfile()?;
-            tmp_file.into_temp_path().to_path_buf()
-        };
+            print_files(&processed_quadlets.0, serde_ini::to_string)?;
+        }
+    } else if to_variant == ToVariant::Kube {
+        let mut file: ComposeFile = traced(transform_opts.deserialize_into(from_variant, &input_bytes, &input_opts, &schema_opts), &source_map)?;
+        set_project_name(&mut file, quadlet_opts.project_name.as_deref());
+        let dir = input_path.as_ref().filter(|p| !http::is_url(p)).and_then(|p| p.parent());
 
-        let s = serde_yaml::to_string(&file)?;
+        let mut presets = config.answers;
+        presets.extend(preset_answers(&file)?);
+        let mut prompter = prompt_opts.prompter(presets)?;
 
-        // todo: use pere
-        if !filename.exists() || ask_confirm(
-            &format!("File '{}' already exists. Overwrite?", filename.display()),
-            true,
-        )? {
-            std::fs::write(&filename, &s)?;
-        }
-        
-        let quadlets = get_raw_quadlets(&filename)?;
-        let processed_quadlets = process_quadlets(quadlets, input_path.as_ref().and_then(|p| p.parent()))?;
+        let file = process_compose(file, dir, &mut prompter)?;
+        let pod = kube::to_pod(&file)?;
 
         if let Some(output_dir) = output {
-            let files = write_files(&processed_quadlets.0, &output_dir, serde_ini::to_string)?;
+            let name = kube::pod_name(&file);
+            let yaml_path = output_dir.join(format!("{name}.yaml"));
+            std::fs::write(&yaml_path, &pod)?;
+
+            let wanted_by = prompter.confirm(
+                "add-wanted-by",
+                &format!("Add WantedBy=default.target to '{name}.kube'?"),
+                true,
+            )?;
+            let mut units = IniFiles::new();
+            units.insert(format!("{name}.kube"), kube::kube_unit(&format!("{name}.yaml"), wanted_by));
+            let mut files = write_files(&units.0, &output_dir, serde_ini::to_string)?;
+            // The unit refers to the YAML next to it, so it's linked along with it
+            files.push(yaml_path);
             if is_interactive() {
                 std::env::set_current_dir(output_dir)?;
-                activate_quadlets(files)?;
+                activate_quadlets(files, &mut prompter)?;
             }
         } else {
-            print_files(&processed_quadlets.0, serde_ini::to_string)?;
+            write_output(&None, &url_output, to_variant.content_type(), pod.as_bytes())?;
         }
-    } else if let Some(output_file) = output {
-        from_variant.serialize(input_bytes, |obj| {
-            let buf = to_variant.to_buf(obj);
-            std::fs::write(&output_file, buf).unwrap();
-        });
+    } else if input_opts.yaml_aliases == AliasPolicy::Preserve
+        && matches!(from_variant, FromVariant::Yaml)
+        && to_variant == ToVariant::Yaml
+    {
+        // Serializing always expands aliases, so the input is passed through as written
+        if !transform_opts.is_empty() || output_opts.yaml_style().is_some() || input_opts.framing != Framing::None {
+            bail!("YAML aliases can only be preserved when the document is passed through unchanged");
+        }
+        write_output(&output, &url_output, to_variant.content_type(), &input_bytes)?;
+    } else if let Some(buf) = edit_in_place(from_variant, to_variant, &input_bytes, &transform_opts, &input_opts, &output_opts)? {
+        if let Some(read_back) = read_back {
+            let original = transform_opts.deserialize_into(from_variant, &input_bytes, &input_opts, &schema_opts)?;
+            verify_output(&original, read_back, &buf, &input_opts, &schema_opts, keep_order)?;
+        }
+        write_output(&output, &url_output, to_variant.content_type(), &buf)?;
     } else {
-        from_variant.serialize(input_bytes, |obj| {
-            let buf = to_variant.to_buf(obj);
-            stdout().lock().write_all(&buf).unwrap();
-        })
+        let converted = from_variant.serialize(input_bytes, &input_opts, &schema_opts, |obj| {
+            let buf = if transform_opts.is_empty() {
+                to_variant.to_buf(obj, &output_opts, &schema_opts)?
+            } else {
+                let mut value = serde_json::to_value(obj)?;
+                transform_opts.apply(&mut value)?;
+                to_variant.to_buf(&value, &output_opts, &schema_opts)?
+            };
+            if let Some(read_back) = read_back {
+                let mut original = serde_json::to_value(obj)?;
+                transform_opts.apply(&mut original)?;
+                verify_output(&original, read_back, &buf, &input_opts, &schema_opts, keep_order)?;
+            }
+            write_output(&output, &url_output, to_variant.content_type(), &buf)
+        });
+        traced(converted, &source_map)?;
+    }
+
+    Ok(())
+}
+
+// Read the output back as `read_back` and fail on how it differs from `original`, before it's
+// written anywhere
+fn verify_output(
+    original: &serde_json::Value,
+    read_back: FromVariant,
+    output: &[u8],
+    input_opts: &InputOpts,
+    schema_opts: &SchemaOpts,
+    keep_order: bool,
+) -> Result<()> {
+    let read: serde_json::Value = read_back
+        .deserialize_into(output, input_opts, schema_opts)
+        .context("--verify couldn't read the output back")?;
+    let changes = diff::diff(original, &read);
+    let reordered = if keep_order { diff::reordered(original, &read) } else { Vec::new() };
+    if changes.is_empty() && reordered.is_empty() {
+        log::info!("The output reads back the same as the input");
+        return Ok(());
+    }
+    let color = std::io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
+    let mut report = diff::to_tree(&changes, color);
+    for path in reordered {
+        let at = if path.is_empty() { "(root)".to_string() } else { path.join(".") };
+        report.push_str(&format!("keys reordered in {at}\n"));
     }
+    bail!("The output doesn't read back the same as the input:\n{}", report.trim_end())
+}
 
+// Write the converted bytes to the output file, compressed as its extension says, or stdout, or send them to --output-url
+fn write_output(output: &Option<PathBuf>, url_output: &UrlOutputOpts, content_type: &str, buf: &[u8]) -> Result<()> {
+    if let Some(url) = &url_output.output_url {
+        let content_type = url_output.content_type.as_deref().unwrap_or(content_type);
+        return http::send(url, &url_output.method, content_type, buf);
+    }
+    match output {
+        Some(output_file) => archive::write(output_file, buf)?,
+        None => stdout().lock().write_all(buf)?,
+    }
     Ok(())
 }
 
@@ -0,0 +1,273 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::utils::is_interactive;
+
+/// Answers the questions asked while processing compose files, quadlets and units.
+///
+/// The CLI asks on the terminal with [`TtyPrompter`]; embedders can supply their own, or use
+/// [`DefaultPrompter`] and [`ScriptedPrompter`] to run without anyone to ask.
+pub trait Prompter {
+    /// Ask a yes/no question. `key` names the kind of question, such as `rename-to-app`, and
+    /// stays the same whatever the service or file it is asked about.
+    fn confirm(&mut self, key: &str, prompt: &str, default: bool) -> io::Result<bool>;
+
+    /// Ask for a line of text. Prompters that can't ask take the default.
+    fn input(&mut self, _key: &str, _prompt: &str, default: &str) -> io::Result<String> {
+        Ok(default.to_string())
+    }
+
+    /// A value given ahead of time for the question `key`, such as `registry` for `autoupdate`
+    fn preset(&self, _key: &str) -> Option<&str> {
+        None
+    }
+}
+
+impl<P: Prompter + ?Sized> Prompter for Box<P> {
+    fn confirm(&mut self, key: &str, prompt: &str, default: bool) -> io::Result<bool> {
+        (**self).confirm(key, prompt, default)
+    }
+
+    fn input(&mut self, key: &str, prompt: &str, default: &str) -> io::Result<String> {
+        (**self).input(key, prompt, default)
+    }
+
+    fn preset(&self, key: &str) -> Option<&str> {
+        (**self).preset(key)
+    }
+}
+
+/// An answer given ahead of time: yes or no, or a value to use, which also means yes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Preset {
+    Answer(bool),
+    Value(String),
+}
+
+/// Answers the questions it has a [`Preset`] for by their key, and asks another prompter the rest
+pub struct PresetPrompter<P> {
+    inner: P,
+    presets: HashMap<String, Preset>,
+}
+
+impl<P: Prompter> PresetPrompter<P> {
+    pub fn new(inner: P, presets: HashMap<String, Preset>) -> Self {
+        PresetPrompter { inner, presets }
+    }
+}
+
+impl<P: Prompter> Prompter for PresetPrompter<P> {
+    fn confirm(&mut self, key: &str, prompt: &str, default: bool) -> io::Result<bool> {
+        match self.presets.get(key) {
+            Some(Preset::Answer(answer)) => Ok(*answer),
+            Some(Preset::Value(_)) => Ok(true),
+            None => self.inner.confirm(key, prompt, default),
+        }
+    }
+
+    // Presets are shared by every question with the same key, so they can't name things
+    fn input(&mut self, key: &str, prompt: &str, default: &str) -> io::Result<String> {
+        self.inner.input(key, prompt, default)
+    }
+
+    fn preset(&self, key: &str) -> Option<&str> {
+        match self.presets.get(key) {
+            Some(Preset::Value(value)) => Some(value),
+            _ => self.inner.preset(key),
+        }
+    }
+}
+
+/// Asks on the terminal, taking the default when there is none or `SLATER_AUTO=true` is set
+pub struct TtyPrompter;
+
+impl Prompter for TtyPrompter {
+    fn confirm(&mut self, _key: &str, prompt: &str, default: bool) -> io::Result<bool> {
+        if std::env::var("SLATER_AUTO").is_ok_and(|v| v.eq_ignore_ascii_case("true")) || !is_interactive() {
+            return Ok(default);
+        }
+
+        if default {
+            demand::Confirm::new(prompt)
+                .affirmative("Yes")
+                .negative("No")
+                .run()
+        } else {
+            demand::Confirm::new(prompt)
+                .affirmative("No")
+                .negative("Yes")
+                .run()
+                .map(|v| !v)
+        }
+    }
+
+    fn input(&mut self, _key: &str, prompt: &str, default: &str) -> io::Result<String> {
+        if std::env::var("SLATER_AUTO").is_ok_and(|v| v.eq_ignore_ascii_case("true")) || !is_interactive() {
+            return Ok(default.to_string());
+        }
+
+        let answer = demand::Input::new(prompt).placeholder(default).run()?;
+        Ok(if answer.trim().is_empty() { default.to_string() } else { answer })
+    }
+}
+
+/// Always takes the default answer
+pub struct DefaultPrompter;
+
+impl Prompter for DefaultPrompter {
+    fn confirm(&mut self, _key: &str, _prompt: &str, default: bool) -> io::Result<bool> {
+        Ok(default)
+    }
+}
+
+/// Gives a fixed sequence of answers in the order the questions are asked, failing once they run out
+pub struct ScriptedPrompter {
+    answers: VecDeque<bool>,
+}
+
+impl ScriptedPrompter {
+    pub fn new(answers: impl IntoIterator<Item = bool>) -> Self {
+        ScriptedPrompter { answers: answers.into_iter().collect() }
+    }
+}
+
+impl Prompter for ScriptedPrompter {
+    fn confirm(&mut self, _key: &str, prompt: &str, _default: bool) -> io::Result<bool> {
+        self.answers
+            .pop_front()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, format!("No scripted answer for '{prompt}'")))
+    }
+}
+
+/// A question and the answer it was given, yes or no or the text entered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Answer {
+    pub key: String,
+    pub prompt: String,
+    pub answer: Preset,
+}
+
+/// Asks another prompter, writing every question and answer to `path` as they happen, in the
+/// YAML format [`ReplayPrompter`] reads
+pub struct RecordingPrompter<P> {
+    inner: P,
+    path: PathBuf,
+    answers: Vec<Answer>,
+}
+
+impl<P: Prompter> RecordingPrompter<P> {
+    pub fn new(inner: P, path: PathBuf) -> Self {
+        RecordingPrompter { inner, path, answers: Vec::new() }
+    }
+}
+
+impl<P: Prompter> RecordingPrompter<P> {
+    fn record(&mut self, key: &str, prompt: &str, answer: Preset) -> io::Result<()> {
+        self.answers.push(Answer { key: key.to_string(), prompt: prompt.to_string(), answer });
+        // Written each time so that the answers so far survive a failed run
+        let yaml = serde_yaml::to_string(&self.answers).map_err(io::Error::other)?;
+        std::fs::write(&self.path, yaml)
+    }
+}
+
+impl<P: Prompter> Prompter for RecordingPrompter<P> {
+    fn confirm(&mut self, key: &str, prompt: &str, default: bool) -> io::Result<bool> {
+        let answer = self.inner.confirm(key, prompt, default)?;
+        self.record(key, prompt, Preset::Answer(answer))?;
+        Ok(answer)
+    }
+
+    fn input(&mut self, key: &str, prompt: &str, default: &str) -> io::Result<String> {
+        let answer = self.inner.input(key, prompt, default)?;
+        self.record(key, prompt, Preset::Value(answer.clone()))?;
+        Ok(answer)
+    }
+
+    fn preset(&self, key: &str) -> Option<&str> {
+        self.inner.preset(key)
+    }
+}
+
+/// Answers from a recording. Each question takes the first unused answer recorded for the same
+/// prompt, so a replay still works if questions are asked in a different order.
+pub struct ReplayPrompter {
+    answers: Vec<Option<Answer>>,
+}
+
+impl ReplayPrompter {
+    pub fn new(answers: Vec<Answer>) -> Self {
+        ReplayPrompter { answers: answers.into_iter().map(Some).collect() }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let answers = serde_yaml::from_slice(&std::fs::read(path)?).map_err(io::Error::other)?;
+        Ok(Self::new(answers))
+    }
+}
+
+impl ReplayPrompter {
+    fn take(&mut self, key: &str, prompt: &str) -> io::Result<Preset> {
+        let slot = self
+            .answers
+            .iter_mut()
+            .find(|a| a.as_ref().is_some_and(|a| a.key == key && a.prompt == prompt))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No recorded answer for '{prompt}'")))?;
+        Ok(slot.take().expect("only unused answers are found").answer)
+    }
+}
+
+fn mismatch(prompt: &str, expected: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Recorded answer for '{prompt}' isn't {expected}"))
+}
+
+impl Prompter for ReplayPrompter {
+    fn confirm(&mut self, key: &str, prompt: &str, _default: bool) -> io::Result<bool> {
+        match self.take(key, prompt)? {
+            Preset::Answer(answer) => Ok(answer),
+            Preset::Value(_) => Err(mismatch(prompt, "yes or no")),
+        }
+    }
+
+    fn input(&mut self, key: &str, prompt: &str, _default: &str) -> io::Result<String> {
+        match self.take(key, prompt)? {
+            Preset::Value(answer) => Ok(answer),
+            Preset::Answer(_) => Err(mismatch(prompt, "text")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        let presets = HashMap::from([
+            ("rename-to-app".to_string(), Preset::Answer(false)),
+            ("autoupdate".to_string(), Preset::Value("registry".to_string())),
+        ]);
+        let mut prompter = PresetPrompter::new(ScriptedPrompter::new([true]), presets);
+        assert!(!prompter.confirm("rename-to-app", "Rename 'web'?", true).unwrap());
+        assert!(prompter.confirm("autoupdate", "Add AutoUpdate?", false).unwrap());
+        assert_eq!(prompter.preset("autoupdate"), Some("registry"));
+        assert!(prompter.confirm("add-wanted-by", "Add WantedBy?", false).unwrap());
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+        let mut recording = RecordingPrompter::new(ScriptedPrompter::new([true, false]), path.to_path_buf());
+        assert!(recording.confirm("add-wanted-by", "Add WantedBy to 'a.pod'?", false).unwrap());
+        assert!(!recording.confirm("autoupdate", "Add AutoUpdate to 'b'?", true).unwrap());
+        assert_eq!(recording.input("build-image-name", "Image name?", "localhost/b").unwrap(), "localhost/b");
+
+        let mut replay = ReplayPrompter::load(&path).unwrap();
+        assert!(!replay.confirm("autoupdate", "Add AutoUpdate to 'b'?", true).unwrap());
+        assert!(replay.confirm("add-wanted-by", "Add WantedBy to 'a.pod'?", false).unwrap());
+        assert_eq!(replay.input("build-image-name", "Image name?", "").unwrap(), "localhost/b");
+        assert!(replay.confirm("autoupdate", "Add AutoUpdate to 'b'?", true).is_err());
+    }
+}
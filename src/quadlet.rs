@@ -1,13 +1,16 @@
 use anyhow::{anyhow, Result, Context};
 use log::{self, error, info};
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use serde_yaml::Value;
 use std::{collections::HashMap, fs::File, io::{BufRead, BufReader}, path::{Path, PathBuf}, process::Command};
 
-use crate::{utils::{ask_confirm, is_root, normalize_path, systemctl_cmd, which}, formats::{Ini, IniFiles, Section}};
+use crate::{diff::{diff, Change}, utils::{env_var, is_root, normalize_path, selinux_enforcing, systemctl_cmd, which}, formats::{Ini, IniFiles, Section}, prompt::{Preset, Prompter}};
 use regex::Regex;
 
+pub mod keys;
+
 
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -83,8 +86,89 @@ fn get_qualified_name(name: &str) -> Result<String> {
     Err(anyhow!("Could not qualify image name: {}", name))
 }
 
+/// The registry an image is pulled from, which is docker.io unless its first component looks
+/// like a host
+pub fn registry_of(image: &str) -> &str {
+    match image.split_once('/') {
+        Some((host, _)) if host.contains('.') || host.contains(':') || host == "localhost" => host,
+        _ => "docker.io",
+    }
+}
+
+// Where podman, skopeo and docker keep registry credentials, in the order podman reads them
+fn auth_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    files.extend(std::env::var_os("REGISTRY_AUTH_FILE").map(PathBuf::from));
+    if let Some(runtime_dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        files.push(PathBuf::from(runtime_dir).join("containers/auth.json"));
+    }
+    if let Ok(home) = std::env::var("HOME") {
+        files.push(PathBuf::from(&home).join(".config/containers/auth.json"));
+        files.push(PathBuf::from(&home).join(".docker/config.json"));
+    }
+    files
+}
+
+// Credentials are kept under `auths` by registry, or for docker.io under docker's legacy URL,
+// and may be held by a credential helper instead
+fn has_credentials(auth: &JsonValue, registry: &str) -> bool {
+    let keys = if registry == "docker.io" {
+        vec!["docker.io", "https://index.docker.io/v1/", "index.docker.io"]
+    } else {
+        vec![registry]
+    };
+    keys.iter().any(|key| {
+        auth.get("auths").and_then(|a| a.get(*key)).is_some()
+            || auth.get("credHelpers").and_then(|h| h.get(*key)).is_some()
+    }) || auth.get("credsStore").is_some()
+}
+
+// What registries answer, through skopeo, when an image can't be read without logging in
+fn requires_auth(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    ["unauthorized", "authentication required", "denied", "401"].iter().any(|e| stderr.contains(e))
+}
+
+// Once for each registry, check whether `image` can be read without credentials, and if it can't
+// and none are stored, offer to run `podman login` (`registry-login`) rather than failing later
+// with an unclear error about its manifest
+fn check_registry_auth(image: &str, checked: &mut Vec<String>, prompter: &mut dyn Prompter) -> Result<()> {
+    let registry = registry_of(image);
+    if checked.iter().any(|r| r == registry) || which("skopeo").is_none() {
+        return Ok(());
+    }
+    checked.push(registry.to_string());
+
+    let output = Command::new("skopeo").args(["inspect", "--no-creds", "--raw"]).arg(format!("docker://{image}")).output()?;
+    if output.status.success() || !requires_auth(&String::from_utf8_lossy(&output.stderr)) {
+        return Ok(());
+    }
+    let stored = auth_files().iter().any(|path| {
+        std::fs::read(path)
+            .ok()
+            .and_then(|contents| serde_json::from_slice::<JsonValue>(&contents).ok())
+            .is_some_and(|auth| has_credentials(&auth, registry))
+    });
+    if stored {
+        return Ok(());
+    }
+
+    if prompter.confirm(
+        "registry-login",
+        &format!("'{registry}' needs you to log in to pull '{image}', and no credentials are stored. Run podman login {registry}?"),
+        true,
+    )? {
+        if !Command::new("podman").arg("login").arg(registry).status()?.success() {
+            anyhow::bail!("Logging in to '{registry}' failed");
+        }
+    } else {
+        log::warn!("Pulling '{image}' will fail until you run podman login {registry}");
+    }
+    Ok(())
+}
+
 // podlet convert doesn't support ${} in places such as volumes so we offer to make replacements
-fn replace_env_vars(value: &mut Value) -> Result<()> {
+fn replace_env_vars(value: &mut Value, prompter: &mut dyn Prompter) -> Result<()> {
     match value {
         Value::String(s) => {
             let re = Regex::new(r"\$\{[a-zA-Z_][a-zA-Z_0-9]*\}")?;
@@ -94,14 +178,15 @@ fn replace_env_vars(value: &mut Value) -> Result<()> {
             for cap in re.captures_iter(s) {
                 let var = &cap[0];
                 let var_name = &var[2..var.len() - 1];
-                if let Ok(env_var) = std::env::var(var_name) {
-                    if cfg!(feature = "integration-tests") || ask_confirm(
+                if let Some(value) = env_var(var_name) {
+                    if cfg!(feature = "integration-tests") || prompter.confirm(
+                        "replace-env-var",
                         &format!(
-                            "Replace '{var}' with '{env_var}'?"
+                            "Replace '{var}' with '{value}'?"
                         ),
                         false,
                     )? {
-                        new_s = new_s.replace(var, &env_var);
+                        new_s = new_s.replace(var, &value);
                         replacements_made = true;
                     }
                 }
@@ -113,12 +198,12 @@ fn replace_env_vars(value: &mut Value) -> Result<()> {
         }
         Value::Mapping(map) => {
             for (_key, val) in map.iter_mut() {
-                replace_env_vars(val)?;
+                replace_env_vars(val, prompter)?;
             }
         }
         Value::Sequence(seq) => {
             for item in seq.iter_mut() {
-                replace_env_vars(item)?;
+                replace_env_vars(item, prompter)?;
             }
         }
         _ => {}
@@ -126,7 +211,15 @@ fn replace_env_vars(value: &mut Value) -> Result<()> {
     Ok(())
 }
 
-pub fn process_compose(mut file: ComposeFile, initial_dir: Option<&Path>) -> Result<ComposeFile> {
+/// Answers given ahead of time under the top-level `x-slate: answers:` key
+pub fn preset_answers(file: &ComposeFile) -> Result<HashMap<String, Preset>> {
+    match file.other.get("x-slate").and_then(|x| x.get("answers")) {
+        Some(answers) => serde_yaml::from_value(answers.clone()).context("Invalid x-slate answers"),
+        None => Ok(HashMap::new()),
+    }
+}
+
+pub fn process_compose(mut file: ComposeFile, initial_dir: Option<&Path>, prompter: &mut dyn Prompter) -> Result<ComposeFile> {
     if file.services.is_empty() {
         anyhow::bail!("No services found!");
     }
@@ -151,7 +244,8 @@ pub fn process_compose(mut file: ComposeFile, initial_dir: Option<&Path>) -> Res
 
     // Offer to rename the primary container to "app"
     if service_name != "app"
-        && ask_confirm(
+        && prompter.confirm(
+            "rename-to-app",
             &format!("Do you want to rename service '{service_name}' to 'app'?"),
             false,
         )? {
@@ -170,7 +264,8 @@ pub fn process_compose(mut file: ComposeFile, initial_dir: Option<&Path>) -> Res
                 let line = line?;
                 if let Some((key, value)) = line.split_once('=') {
                     if let Ok(existing_value) = std::env::var(key) {
-                        if ! ask_confirm(
+                        if ! prompter.confirm(
+                            "overwrite-env-var",
                             &format!(
                                 "Environment variable '{key}' is already set to '{existing_value}'. Overwrite with '{value}' for variable substitution?"
                             ),
@@ -185,14 +280,16 @@ pub fn process_compose(mut file: ComposeFile, initial_dir: Option<&Path>) -> Res
         }
     }
 
+    let mut checked_registries = Vec::new();
     for (_service_name, service) in file.services.iter_mut() {
-        replace_env_vars(service)?;
+        replace_env_vars(service, prompter)?;
 
         if let Some(service_map) = service.as_mapping_mut() {
 
             // Qualify image names
             if let Some(image_val) = service_map.get_mut(Value::String("image".to_string())) {
                 if let Some(image) = image_val.as_str() {
+                    check_registry_auth(image, &mut checked_registries, prompter)?;
                     if image.matches('/').count() < 2 {
                         if let Ok(image) = get_qualified_name(image) {
                             *image_val = image.into()
@@ -254,211 +351,2190 @@ pub fn process_compose(mut file: ComposeFile, initial_dir: Option<&Path>) -> Res
     Ok(file)
 }
 
-fn parse_raw_quadlets(output: &str) -> Result<IniFiles> {
+/// Set the project name that podlet names the pod and containers after: `name`, or else
+/// `COMPOSE_PROJECT_NAME`, or else the compose file's own `name:`, as docker compose does.
+pub fn set_project_name(file: &mut ComposeFile, name: Option<&str>) {
+    let name = name.map(str::to_string).or_else(|| std::env::var("COMPOSE_PROJECT_NAME").ok().filter(|n| !n.is_empty()));
+    if let Some(name) = name {
+        file.other.insert("name".to_string(), Value::String(name));
+    }
+}
+
+/// Replace the `build:` section of each service with a `.build` quadlet unit, and point the
+/// service's image at that unit so the container is started from the image it builds.
+///
+/// The built image is tagged with the service's `image:` if it has one, or else a name asked for.
+pub fn extract_builds(file: &mut ComposeFile, initial_dir: Option<&Path>, prompter: &mut dyn Prompter) -> Result<IniFiles> {
     let mut units = IniFiles::new();
-    for block in output.split("\n---\n\n") {
-        if let Some((first_line, rest)) = block.split_once('\n') {
-            if let Some(stripped) = first_line.strip_prefix("# ") {
-                let key = stripped.trim().to_string();
-                let item: Ini = serde_ini::from_str(rest)?;
-                units.insert(key, item);
-            }
-        } else {
-            error!("Unexpected section of podlet output encountered, skipping");
+    let mut names: Vec<String> = file.services.keys().cloned().collect();
+    names.sort();
+    for name in names {
+        let Some(service_map) = file.services.get_mut(&name).and_then(|s| s.as_mapping_mut()) else {
+            continue;
+        };
+        let Some(build) = service_map.remove("build") else {
+            continue;
+        };
+
+        let (context, dockerfile, args) = match &build {
+            Value::String(context) => (context.as_str(), None, None),
+            Value::Mapping(map) => (
+                map.get("context").and_then(|c| c.as_str()).unwrap_or("."),
+                map.get("dockerfile").and_then(|d| d.as_str()),
+                map.get("args"),
+            ),
+            _ => anyhow::bail!("Invalid build section in service '{name}'"),
+        };
+        // Relative paths in a compose file are relative to the file
+        let context = normalize_path(initial_dir.map_or_else(|| PathBuf::from(context), |dir| dir.join(context)));
+
+        let tag = match service_map.get("image").and_then(|i| i.as_str()) {
+            Some(image) => image.to_string(),
+            None => prompter.input(
+                "build-image-name",
+                &format!("Name for the image built for '{name}'?"),
+                &format!("localhost/{name}"),
+            )?,
+        };
+
+        let mut section = Section::new();
+        section.insert("ImageTag".to_string(), tag);
+        if let Some(dockerfile) = dockerfile {
+            section.insert("File".to_string(), normalize_path(Path::new(&context).join(dockerfile)));
+        }
+        let args = build_args(args);
+        if !args.is_empty() {
+            section.insert("BuildArg".to_string(), args.join(" "));
         }
+        section.insert("SetWorkingDirectory".to_string(), context);
+
+        let mut unit = Ini::new();
+        unit.insert("Build".to_string(), section);
+        units.insert(format!("{name}.build"), unit);
+        service_map.insert("image".into(), format!("{name}.build").into());
     }
     Ok(units)
 }
 
-pub fn get_raw_quadlets(filepath: &PathBuf) -> Result<IniFiles> {
-    if which("podlet").is_none() {
-        anyhow::bail!("podman command not found. Please install podman.");
-    }
+// Compose build args as quoted `KEY=value` words. A bare `KEY` in list form takes its value from
+// the environment, and is left out when unset, as compose does.
+fn build_args(args: Option<&Value>) -> Vec<String> {
+    let pairs: Vec<(String, String)> = match args {
+        Some(Value::Mapping(map)) => map
+            .iter()
+            .filter_map(|(k, v)| {
+                let value = match v {
+                    Value::String(s) => s.clone(),
+                    Value::Null => env_var(k.as_str()?)?,
+                    other => serde_yaml::to_string(other).ok()?.trim_end().to_string(),
+                };
+                Some((k.as_str()?.to_string(), value))
+            })
+            .collect(),
+        Some(Value::Sequence(items)) => items
+            .iter()
+            .filter_map(|item| {
+                let item = item.as_str()?;
+                match item.split_once('=') {
+                    Some((k, v)) => Some((k.to_string(), v.to_string())),
+                    None => Some((item.to_string(), env_var(item)?)),
+                }
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    pairs
+        .into_iter()
+        .map(|(k, v)| {
+            let arg = format!("{k}={v}");
+            if arg.contains(char::is_whitespace) || arg.contains('"') {
+                format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+            } else {
+                arg
+            }
+        })
+        .collect()
+}
 
-    let output = Command::new("podlet")
-        .arg("compose")
-        .arg("--pod")
-        .arg(filepath)
-        .output()?;
+/// A directive to add to a generated unit, as section, key and value
+pub type Directive = (&'static str, &'static str, String);
 
-    if !output.status.success() {
-        anyhow::bail!(
-            "podlet conversion failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
-    }
+// What services are best run with under systemd, so they come back after crashes without
+// restarting fast enough to hit the start limit
+const RECOMMENDED_RESTART: &[(&str, &str)] = &[("Restart", "always"), ("RestartSec", "10")];
 
-    let output_str = String::from_utf8_lossy(&output.stdout);
-    parse_raw_quadlets(&output_str)
+// Init containers run once, and stay active once they succeed so their dependents can start
+fn init_directives() -> Vec<Directive> {
+    vec![
+        ("Service", "Type", "oneshot".to_string()),
+        ("Service", "RemainAfterExit", "yes".to_string()),
+        ("Service", "Restart", "no".to_string()),
+    ]
 }
 
-pub fn process_quadlets(mut units: IniFiles, initial_dir: Option<&Path>) -> Result<IniFiles> {
-    for (unit_name, unit_data) in units.0.iter_mut() {
-        if unit_name.ends_with(".pod") {
-            if ask_confirm(
-                &format!("Add WantedBy=default.target to '{unit_name}'?"),
-                true,
-            )? {
-                let install_section = unit_data.0.entry("Install".to_string()).or_insert_with(Section::new);
-                install_section.insert("WantedBy".to_string(), "default.target".to_string());
-            }
-        } else if unit_name.ends_with(".container") {
-            let unit_section = unit_data.0.entry("Unit".to_string()).or_insert_with(Section::new);
-            if ask_confirm(
-                &format!("Add After=local-fs.target network-online.target systemd-networkd-wait-online.service to '{unit_name}'?"),
-                true,
-            )? {
-                unit_section.insert("After".to_string(), "local-fs.target network-online.target systemd-networkd-wait-online.service".to_string());
+/// Turn each service's `x-init:` list into services of their own, run once before it: an entry
+/// is a command to run with the service's image, volumes and environment, or a mapping of
+/// service keys overriding them. These, and services depended on with
+/// `condition: service_completed_successfully`, get directives making them oneshot units that
+/// stay active once done, so the services depending on them start only after they succeed.
+pub fn extract_init_containers(file: &mut ComposeFile) -> Result<HashMap<String, Vec<Directive>>> {
+    let mut names: Vec<String> = file.services.keys().cloned().collect();
+    names.sort();
+
+    for name in &names {
+        let service = file.services.get_mut(name).expect("names are keys");
+        let Some(inits) = service.as_mapping_mut().and_then(|m| m.remove("x-init")) else {
+            continue;
+        };
+        let Value::Sequence(inits) = inits else {
+            anyhow::bail!("x-init of service '{name}' must be a list");
+        };
+
+        let mut template = serde_yaml::Mapping::new();
+        for key in ["image", "volumes", "environment", "env_file", "user", "working_dir"] {
+            if let Some(value) = service.get(key) {
+                template.insert(key.into(), value.clone());
             }
-
-            let service_section = unit_data.0.entry("Service".to_string()).or_insert_with(Section::new);
-            // if .env exists in the same directory as .compose, include it into the systemd service
-            if let Some(dir) = initial_dir {
-                let env_file = dir.join(".env");
-                let env_file_str=normalize_path(&env_file);
-                if env_file.exists()
-                    && ask_confirm(
-                        &format!("Add EnvironmentFile={env_file_str} to '{unit_name}'?"),
-                        true,
-                    )? {
-                        service_section.insert("EnvironmentFile".to_string(), env_file_str);
-                    }
+        }
+        let mut init_names = Vec::new();
+        for (i, init) in inits.iter().enumerate() {
+            let mut init_service = template.clone();
+            match init {
+                Value::Mapping(keys) => init_service.extend(keys.clone()),
+                command => {
+                    init_service.insert("command".into(), command.clone());
+                }
             }
+            let init_name = if inits.len() == 1 { format!("{name}-init") } else { format!("{name}-init-{}", i + 1) };
+            file.services.insert(init_name.clone(), Value::Mapping(init_service));
+            init_names.push(init_name);
+        }
 
-            let container_section = unit_data.0.entry("Container".to_string()).or_insert_with(Section::new);
-
-            let image_name = container_section.get("Image").map(|s| s.as_str()).unwrap_or("");
-            let autoupdate_value = if image_name.contains('.') { "registry" } else { "local" };
+        let service = file.services.get_mut(name).expect("names are keys");
+        let mut depends_on = service_dependencies(service);
+        let Some(service) = service.as_mapping_mut() else {
+            continue;
+        };
+        for init_name in init_names {
+            let mut condition = serde_yaml::Mapping::new();
+            condition.insert("condition".into(), "service_completed_successfully".into());
+            depends_on.insert(init_name.into(), Value::Mapping(condition));
+        }
+        service.insert("depends_on".into(), Value::Mapping(depends_on));
+    }
 
-            if ask_confirm(
-                &format!("Add AutoUpdate={autoupdate_value} to '{unit_name}'?"),
-                true,
-            )? {
-                container_section.insert("AutoUpdate".to_string(), autoupdate_value.to_string());
+    // podlet orders services by their dependencies, but only understands them as a list
+    let mut inits = Vec::new();
+    for service in file.services.values_mut() {
+        let depends_on = service_dependencies(service);
+        if depends_on.is_empty() {
+            continue;
+        }
+        for (dependency, options) in &depends_on {
+            if options.get("condition").and_then(|c| c.as_str()) == Some("service_completed_successfully") {
+                inits.push(scalar_string(dependency));
             }
         }
+        if let Some(service) = service.as_mapping_mut() {
+            service.insert("depends_on".into(), Value::Sequence(depends_on.keys().cloned().collect()));
+        }
     }
-    Ok(units)
-}       
 
-pub fn activate_quadlets(files: Vec<PathBuf>) -> Result<()> {
-    let is_root = is_root();
-    let target_dir = if cfg!(feature = "integration-tests") {
-        PathBuf::from("/tmp/slater/containers/systemd")
-    } else if is_root {
-        PathBuf::from("/etc/containers/systemd")
-    } else {
-        let home = std::env::var("HOME").context("HOME environment variable not set")?;
-        PathBuf::from(format!("{home}/.config/containers/systemd"))
-    };
+    let mut directives = HashMap::new();
+    for init in inits {
+        if let Some(service) = file.services.get_mut(&init).and_then(|s| s.as_mapping_mut()) {
+            service.remove("restart");
+        }
+        directives.insert(init, init_directives());
+    }
+    Ok(directives)
+}
 
-    let cwd = std::env::current_dir()?;
+// A service's depends_on in the long form, mapping each dependency to its options
+fn service_dependencies(service: &Value) -> serde_yaml::Mapping {
+    match service.get("depends_on") {
+        Some(Value::Mapping(depends_on)) => depends_on.clone(),
+        Some(Value::Sequence(names)) => names.iter().map(|name| (name.clone(), Value::Mapping(Default::default()))).collect(),
+        _ => serde_yaml::Mapping::new(),
+    }
+}
 
-    let mut cmd = Command::new("/usr/lib/systemd/system-generators/podman-system-generator");
-    cmd.arg("--dryrun");
-    if !is_root {
-        cmd.arg("--user");
+/// Map each service's `restart:` and `deploy.restart_policy` to `Restart=`, `RestartSec=` and
+/// `StartLimit*=` directives, asking whether to use the recommended value where they differ.
+///
+/// `deploy.restart_policy` is taken out of the file, as podlet doesn't translate it.
+pub fn extract_restart_policies(file: &mut ComposeFile, prompter: &mut dyn Prompter) -> Result<HashMap<String, Vec<Directive>>> {
+    let mut policies = HashMap::new();
+    let mut names: Vec<String> = file.services.keys().cloned().collect();
+    names.sort();
+    for name in names {
+        let service = file.services.get_mut(&name).expect("names are keys");
+        let mut directives = restart_directives(service);
+        remove_deploy_key(service, "restart_policy");
+
+        for (_, key, value) in directives.iter_mut() {
+            let key: &str = key;
+            let Some((_, recommended)) = RECOMMENDED_RESTART.iter().find(|(k, _)| *k == key) else {
+                continue;
+            };
+            if *value != **recommended
+                && prompter.confirm(
+                    "restart-recommended",
+                    &format!("Compose sets {key}={value} for '{name}', but {key}={recommended} is recommended. Use {recommended}?"),
+                    false,
+                )?
+            {
+                *value = recommended.to_string();
+            }
+        }
+        if !directives.is_empty() {
+            policies.insert(name, directives);
+        }
     }
-    cmd.env("QUADLET_UNIT_DIRS", &cwd);
+    Ok(policies)
+}
 
-    let output = cmd.output()?;
-    if !output.status.success() {
-        anyhow::bail!(
-            "Validation command failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
+fn restart_directives(service: &Value) -> Vec<Directive> {
+    let mut directives = Vec::new();
+    match service.get("restart").and_then(|r| r.as_str()) {
+        Some("no") => set(&mut directives, "Service", "Restart", "no".to_string()),
+        Some("always") | Some("unless-stopped") => set(&mut directives, "Service", "Restart", "always".to_string()),
+        Some(restart) if restart.starts_with("on-failure") => {
+            set(&mut directives, "Service", "Restart", "on-failure".to_string());
+            if let Some(retries) = restart.strip_prefix("on-failure:") {
+                set(&mut directives, "Unit", "StartLimitBurst", retries.to_string());
+            }
+        }
+        _ => {}
     }
 
-    println!("Generated systemd unit files (dry run):");
-    println!("{}", String::from_utf8_lossy(&output.stdout));
+    // Takes precedence over `restart:`, as in compose
+    if let Some(policy) = service.get("deploy").and_then(|d| d.get("restart_policy")) {
+        match policy.get("condition").and_then(|c| c.as_str()) {
+            Some("none") => set(&mut directives, "Service", "Restart", "no".to_string()),
+            Some("on-failure") => set(&mut directives, "Service", "Restart", "on-failure".to_string()),
+            Some("any") => set(&mut directives, "Service", "Restart", "always".to_string()),
+            _ => {}
+        }
+        let fields = [
+            ("delay", "Service", "RestartSec"),
+            ("max_attempts", "Unit", "StartLimitBurst"),
+            ("window", "Unit", "StartLimitIntervalSec"),
+        ];
+        for (field, section, key) in fields {
+            match policy.get(field) {
+                Some(Value::String(s)) => set(&mut directives, section, key, s.clone()),
+                Some(Value::Number(n)) => set(&mut directives, section, key, n.to_string()),
+                _ => {}
+            }
+        }
+    }
+    directives
+}
 
-    if cwd != target_dir
-        && ask_confirm(
-            &format!("Create symlinks in '{}'?", target_dir.display()),
-            true,
-        )? {
-            std::fs::create_dir_all(&target_dir)?;
+// Remove `deploy.<key>` from a service, and `deploy` itself once it's empty
+fn remove_deploy_key(service: &mut Value, key: &str) {
+    let Some(service) = service.as_mapping_mut() else {
+        return;
+    };
+    if let Some(deploy) = service.get_mut("deploy").and_then(|d| d.as_mapping_mut()) {
+        deploy.remove(key);
+        if deploy.is_empty() {
+            service.remove("deploy");
+        }
+    }
+}
 
-            for file_path in &files {
-                let file_name = file_path.file_name()
-                    .context("Failed to get filename from path")?;
-                let src = cwd.join(file_name);
-                let dst = target_dir.join(file_name);
+/// Where resource limits are enforced
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum LimitsIn {
+    /// By podman, with `Memory=`, `PidsLimit=` and `PodmanArgs=`
+    #[default]
+    Container,
+    /// By systemd for the whole service, with `MemoryMax=`, `CPUQuota=` and `TasksMax=`
+    Service,
+}
 
-                if dst.exists() {
-                    if let Err(e) = std::fs::remove_file(&dst) {
-                        error!("Failed to remove file {}: {}", dst.display(), e);
-                        continue;
-                    }
+/// Map each service's `mem_limit`, `cpus`, `pids_limit` and `deploy.resources` to directives
+/// limiting the container or its service, taking them out of the file for podlet.
+pub fn extract_resource_limits(file: &mut ComposeFile, limits_in: LimitsIn) -> Result<HashMap<String, Vec<Directive>>> {
+    let mut limits = HashMap::new();
+    for (name, service) in file.services.iter_mut() {
+        let resources = service.get("deploy").and_then(|d| d.get("resources"));
+        // `deploy.resources` takes precedence, as in compose
+        let limit = |field: &str, key: &str| {
+            resources
+                .and_then(|r| r.get("limits"))
+                .and_then(|l| l.get(field))
+                .or_else(|| service.get(key))
+                .map(scalar_string)
+        };
+        let memory = limit("memory", "mem_limit");
+        let cpus = limit("cpus", "cpus");
+        let pids = limit("pids", "pids_limit");
+        let reservation = resources
+            .and_then(|r| r.get("reservations"))
+            .and_then(|r| r.get("memory"))
+            .or_else(|| service.get("mem_reservation"))
+            .map(scalar_string);
+
+        let mut directives = Vec::new();
+        let size = |value: String| memory_size(&value).with_context(|| format!("Invalid memory size '{value}' in service '{name}'"));
+        let quota = |value: String| {
+            let cpus: f64 = value.parse().with_context(|| format!("Invalid cpus '{value}' in service '{name}'"))?;
+            anyhow::Ok(format!("{}%", (cpus * 100.0).round()))
+        };
+        match limits_in {
+            LimitsIn::Container => {
+                let mut args = Vec::new();
+                if let Some(memory) = memory {
+                    set(&mut directives, "Container", "Memory", size(memory)?);
                 }
-
-                if let Err(e) = std::os::unix::fs::symlink(&src, &dst) {
-                    error!("Failed to create symlink {} -> {}: {}", src.display(), dst.display(), e);
-                    continue;
+                if let Some(pids) = pids {
+                    set(&mut directives, "Container", "PidsLimit", pids);
+                }
+                if let Some(cpus) = cpus {
+                    args.push(format!("--cpus={cpus}"));
                 }
+                if let Some(reservation) = reservation {
+                    args.push(format!("--memory-reservation={}", size(reservation)?));
+                }
+                if !args.is_empty() {
+                    set(&mut directives, "Container", "PodmanArgs", args.join(" "));
+                }
+            }
+            LimitsIn::Service => {
+                if let Some(memory) = memory {
+                    set(&mut directives, "Service", "MemoryMax", size(memory)?);
+                }
+                if let Some(cpus) = cpus {
+                    set(&mut directives, "Service", "CPUQuota", quota(cpus)?);
+                }
+                if let Some(pids) = pids {
+                    set(&mut directives, "Service", "TasksMax", pids);
+                }
+                if let Some(reservation) = reservation {
+                    set(&mut directives, "Service", "MemoryLow", size(reservation)?);
+                }
+            }
+        }
 
-                info!("Created symlink: {} -> {}", dst.display(), src.display());
+        if let Some(map) = service.as_mapping_mut() {
+            for key in ["mem_limit", "mem_reservation", "cpus", "pids_limit"] {
+                map.remove(key);
             }
         }
+        remove_deploy_key(service, "resources");
+        if !directives.is_empty() {
+            limits.insert(name.clone(), directives);
+        }
+    }
+    Ok(limits)
+}
 
-    if ask_confirm("Reload systemd and restart the services?", true)? {
-        systemctl_cmd(is_root).arg("daemon-reload").status()?;
-        info!("systemctl-daemon reloaded!");
+/// Map each service's `cap_add`, `cap_drop`, `security_opt`, `read_only` and `userns_mode` to
+/// their quadlet keys, taking them out of the file for podlet.
+pub fn extract_security_options(file: &mut ComposeFile) -> HashMap<String, Vec<Directive>> {
+    let mut options = HashMap::new();
+    for (name, service) in file.services.iter_mut() {
+        let Some(map) = service.as_mapping_mut() else {
+            continue;
+        };
+        let mut directives = Vec::new();
+        let list = |value: Option<Value>| -> Vec<String> {
+            match value {
+                Some(Value::Sequence(items)) => items.iter().map(scalar_string).collect(),
+                Some(other) => vec![scalar_string(&other)],
+                None => Vec::new(),
+            }
+        };
 
-        for pod_path in files.iter().filter(|p| {
-            p.extension().map(|ext| ext == "pod").unwrap_or(false)
-        }) {
-            let pod_name_stem = pod_path.file_stem()
-                .and_then(|s| s.to_str())
-                .context("Failed to get pod file stem")?;
+        for (field, key) in [("cap_add", "AddCapability"), ("cap_drop", "DropCapability")] {
+            let caps = list(map.remove(field));
+            if !caps.is_empty() {
+                set(&mut directives, "Container", key, caps.join(" "));
+            }
+        }
+
+        let mut args = Vec::new();
+        for opt in list(map.remove("security_opt")) {
+            // Written as either `name=value` or `name:value`
+            let (option, value) = opt.split_once(['=', ':']).unwrap_or((opt.as_str(), ""));
+            let label = value.split_once(':');
+            match (option, value, label) {
+                ("label", "disable", _) => set(&mut directives, "Container", "SecurityLabelDisable", "true".to_string()),
+                ("label", _, Some(("type", t))) => set(&mut directives, "Container", "SecurityLabelType", t.to_string()),
+                ("label", _, Some(("level", l))) => set(&mut directives, "Container", "SecurityLabelLevel", l.to_string()),
+                ("label", _, Some(("filetype", t))) => set(&mut directives, "Container", "SecurityLabelFileType", t.to_string()),
+                ("seccomp", profile, _) if !profile.is_empty() => {
+                    set(&mut directives, "Container", "SeccompProfile", profile.to_string())
+                }
+                ("no-new-privileges", "" | "true", _) => set(&mut directives, "Container", "NoNewPrivileges", "true".to_string()),
+                ("no-new-privileges", "false", _) => {}
+                _ => {
+                    log::warn!("Passing security_opt '{opt}' of '{name}' through PodmanArgs");
+                    args.push(format!("--security-opt={opt}"));
+                }
+            }
+        }
+        if !args.is_empty() {
+            set(&mut directives, "Container", "PodmanArgs", args.join(" "));
+        }
 
-            let pod_unit_name = format!("{pod_name_stem}-pod.service");
+        if map.remove("read_only").and_then(|r| r.as_bool()) == Some(true) {
+            set(&mut directives, "Container", "ReadOnly", "true".to_string());
+        }
+        if let Some(userns) = map.remove("userns_mode") {
+            set(&mut directives, "Container", "UserNS", scalar_string(&userns));
+        }
 
-            systemctl_cmd(is_root)
-                .arg("restart")
-                .arg(&pod_unit_name)
-                .status()?;
+        if !directives.is_empty() {
+            options.insert(name.clone(), directives);
         }
     }
-
-    Ok(())
+    options
 }
 
+/// Map each service's `devices:` to `AddDevice=`, and GPUs reserved under
+/// `deploy.resources.reservations.devices` to CDI devices, taking both out of the file for podlet.
+pub fn extract_devices(file: &mut ComposeFile) -> HashMap<String, Vec<Directive>> {
+    let mut devices = HashMap::new();
+    for (name, service) in file.services.iter_mut() {
+        let mut found: Vec<String> = match service.as_mapping_mut().and_then(|m| m.remove("devices")) {
+            Some(Value::Sequence(items)) => items.iter().map(scalar_string).collect(),
+            _ => Vec::new(),
+        };
+
+        let reserved = service
+            .get_mut("deploy")
+            .and_then(|d| d.get_mut("resources"))
+            .and_then(|r| r.get_mut("reservations"))
+            .and_then(|r| r.as_mapping_mut())
+            .and_then(|r| r.remove("devices"));
+        for device in reserved.as_ref().and_then(|d| d.as_sequence()).into_iter().flatten() {
+            match cdi_devices(device) {
+                Some(cdi) => found.extend(cdi),
+                None => log::warn!("Skipping device reservation of '{name}' that isn't an NVIDIA GPU or CDI device"),
+            }
+        }
 
-#[cfg(test)]
-mod tests {
-    use crate::utils::enter_test_dir;
-
-    use super::*;
-    use std::{io::Write};
+        if !found.is_empty() {
+            let mut directives = Vec::new();
+            set_many(&mut directives, "AddDevice", "device", &found);
+            devices.insert(name.clone(), directives);
+        }
+    }
+    devices
+}
 
-    fn setup_quadlets() -> IniFiles {
-        let input = r#"
-# bookstack-app.container
-[Unit]
-Requires=bookstack-db.service
-After=bookstack-db.service
+// A compose device reservation as CDI device names, such as `nvidia.com/gpu=all`
+fn cdi_devices(device: &Value) -> Option<Vec<String>> {
+    let ids: Vec<String> = match device.get("device_ids") {
+        Some(Value::Sequence(ids)) => ids.iter().map(scalar_string).collect(),
+        _ => Vec::new(),
+    };
+    match device.get("driver").and_then(|d| d.as_str()) {
+        // Device ids are already fully qualified CDI names
+        Some("cdi") => Some(ids),
+        Some("nvidia") | None => {
+            let is_gpu = device
+                .get("capabilities")
+                .and_then(|c| c.as_sequence())
+                .is_some_and(|caps| caps.iter().any(|c| c.as_str() == Some("gpu")));
+            if device.get("driver").is_none() && !is_gpu {
+                return None;
+            }
+            if !ids.is_empty() {
+                return Some(ids.iter().map(|id| format!("nvidia.com/gpu={id}")).collect());
+            }
+            Some(match device.get("count") {
+                Some(Value::Number(n)) => (0..n.as_u64()?).map(|i| format!("nvidia.com/gpu={i}")).collect(),
+                _ => vec!["nvidia.com/gpu=all".to_string()],
+            })
+        }
+        Some(_) => None,
+    }
+}
 
-[Container]
-Image=lscr.io/linuxserver/bookstack
-Pod=bookstack.pod
+fn scalar_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => serde_yaml::to_string(other).unwrap_or_default().trim_end().to_string(),
+    }
+}
 
-[Service]
-Restart=always
+// A compose byte value such as `512m`, `1.5g` or `1048576` as a whole number of bytes, KiB, MiB
+// or GiB, in the suffixes both podman and systemd accept
+fn memory_size(value: &str) -> Option<String> {
+    let value = value.trim().to_ascii_lowercase();
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let power = match unit.trim_end_matches('b') {
+        "" => 0,
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        _ => return None,
+    };
+    let bytes = (number * 1024f64.powi(power)).round() as u64;
+    let (amount, suffix) = [(3, "G"), (2, "M"), (1, "K")]
+        .iter()
+        .map(|&(p, suffix)| (1u64 << (10 * p), suffix))
+        .find(|(size, _)| bytes >= *size && bytes.is_multiple_of(*size))
+        .map_or((bytes, ""), |(size, suffix)| (bytes / size, suffix));
+    Some(format!("{amount}{suffix}"))
+}
 
----
+/// Map each service's `tmpfs:` and `shm_size:` to `Tmpfs=` and `ShmSize=`, and rewrite long-form
+/// `volumes:` entries: binds and volumes into the short form podlet translates, tmpfs mounts into
+/// `Tmpfs=`, and any other type into `Mount=`.
+pub fn extract_mounts(file: &mut ComposeFile) -> HashMap<String, Vec<Directive>> {
+    let mut mounts = HashMap::new();
+    for (name, service) in file.services.iter_mut() {
+        let Some(map) = service.as_mapping_mut() else {
+            continue;
+        };
+        let mut tmpfs: Vec<String> = match map.remove("tmpfs") {
+            Some(Value::Sequence(items)) => items.iter().map(scalar_string).collect(),
+            Some(other) => vec![scalar_string(&other)],
+            None => Vec::new(),
+        };
+        let mut other = Vec::new();
+
+        if let Some(volumes) = map.get_mut("volumes").and_then(|v| v.as_sequence_mut()) {
+            volumes.retain_mut(|volume| {
+                let Value::Mapping(long) = volume else {
+                    return true;
+                };
+                let field = |path: &[&str]| {
+                    let mut value = long.get(path[0]);
+                    for key in &path[1..] {
+                        value = value.and_then(|v| v.get(key));
+                    }
+                    value.map(scalar_string)
+                };
+                let target = field(&["target"]).unwrap_or_default();
+                let source = field(&["source"]);
+                let read_only = long.get("read_only").and_then(|r| r.as_bool()) == Some(true);
+                match field(&["type"]).as_deref() {
+                    Some("bind") | Some("volume") => {
+                        let mut options = Vec::new();
+                        if read_only {
+                            options.push("ro".to_string());
+                        }
+                        options.extend(field(&["bind", "propagation"]));
+                        options.extend(field(&["bind", "selinux"]));
+                        if long.get("volume").and_then(|v| v.get("nocopy")).and_then(|n| n.as_bool()) == Some(true) {
+                            options.push("nocopy".to_string());
+                        }
+                        let source = match (source, field(&["type"]).as_deref()) {
+                            (Some(source), Some("bind")) => Some(normalize_path(source)),
+                            (source, _) => source,
+                        };
+                        let mut short: Vec<String> = source.into_iter().chain([target]).collect();
+                        if !options.is_empty() {
+                            short.push(options.join(","));
+                        }
+                        *volume = Value::String(short.join(":"));
+                        true
+                    }
+                    Some("tmpfs") => {
+                        let options: Vec<String> = ["size", "mode"]
+                            .iter()
+                            .filter_map(|&option| Some(format!("{option}={}", field(&["tmpfs", option])?)))
+                            .collect();
+                        tmpfs.push(if options.is_empty() { target } else { format!("{target}:{}", options.join(",")) });
+                        false
+                    }
+                    Some(kind) => {
+                        let mut mount = format!("type={kind}");
+                        if let Some(source) = source {
+                            mount.push_str(&format!(",source={source}"));
+                        }
+                        mount.push_str(&format!(",destination={target}"));
+                        if read_only {
+                            mount.push_str(",ro=true");
+                        }
+                        other.push(mount);
+                        false
+                    }
+                    None => {
+                        log::warn!("Keeping volume of '{name}' without a type as is");
+                        true
+                    }
+                }
+            });
+        }
 
-# bookstack-db.container
-[Container]
-Image=lscr.io/linuxserver/mariadb
-Pod=bookstack.pod
+        let mut directives = Vec::new();
+        set_many(&mut directives, "Tmpfs", "tmpfs", &tmpfs);
+        set_many(&mut directives, "Mount", "mount", &other);
+        if let Some(size) = map.remove("shm_size") {
+            set(&mut directives, "Container", "ShmSize", scalar_string(&size));
+        }
+        if !directives.is_empty() {
+            mounts.insert(name.clone(), directives);
+        }
+    }
+    mounts
+}
 
-[Service]
-Restart=always
+/// Map each service's `sysctls:` and `ulimits:` to `Sysctl=` and `Ulimit=`, taking them out of
+/// the file for podlet.
+pub fn extract_kernel_settings(file: &mut ComposeFile) -> HashMap<String, Vec<Directive>> {
+    let mut settings = HashMap::new();
+    for (name, service) in file.services.iter_mut() {
+        let Some(map) = service.as_mapping_mut() else {
+            continue;
+        };
+        let sysctls: Vec<String> = match map.remove("sysctls") {
+            Some(Value::Mapping(sysctls)) => sysctls
+                .iter()
+                .map(|(k, v)| format!("{}={}", scalar_string(k), scalar_string(v)))
+                .collect(),
+            Some(Value::Sequence(sysctls)) => sysctls.iter().map(scalar_string).collect(),
+            _ => Vec::new(),
+        };
+        // A single limit, or its soft and hard limits
+        let ulimits: Vec<String> = match map.remove("ulimits") {
+            Some(Value::Mapping(ulimits)) => ulimits
+                .iter()
+                .map(|(k, v)| match (v.get("soft"), v.get("hard")) {
+                    (Some(soft), Some(hard)) => {
+                        format!("{}={}:{}", scalar_string(k), scalar_string(soft), scalar_string(hard))
+                    }
+                    _ => format!("{}={}", scalar_string(k), scalar_string(v)),
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let mut directives = Vec::new();
+        // Sysctl= takes a list, where Ulimit= is repeated
+        if !sysctls.is_empty() {
+            set(&mut directives, "Container", "Sysctl", sysctls.join(" "));
+        }
+        set_many(&mut directives, "Ulimit", "ulimit", &ulimits);
+        if !directives.is_empty() {
+            settings.insert(name.clone(), directives);
+        }
+    }
+    settings
+}
 
----
+/// Keys set or removed in the unit generated for a service, by section
+pub type Overrides = Vec<(String, String, Option<String>)>;
+
+/// Take each service's `x-slate:` block out of the file. It maps unit sections to the keys to set
+/// in them, where `null` removes a key:
+///
+/// ```yaml
+/// x-slate:
+///   Container:
+///     AutoUpdate: null
+///   Install:
+///     WantedBy: multi-user.target
+/// ```
+pub fn extract_overrides(file: &mut ComposeFile) -> Result<HashMap<String, Overrides>> {
+    let mut overrides = HashMap::new();
+    for (name, service) in file.services.iter_mut() {
+        let Some(block) = service.as_mapping_mut().and_then(|m| m.remove("x-slate")) else {
+            continue;
+        };
+        let Some(sections) = block.as_mapping() else {
+            anyhow::bail!("x-slate of service '{name}' must map unit sections to keys");
+        };
+        let mut service_overrides = Vec::new();
+        for (section, keys) in sections {
+            let Some(keys) = keys.as_mapping() else {
+                anyhow::bail!("x-slate section '{}' of service '{name}' must map keys to values", scalar_string(section));
+            };
+            for (key, value) in keys {
+                let value = match value {
+                    Value::Null => None,
+                    Value::Sequence(items) => Some(items.iter().map(scalar_string).collect::<Vec<_>>().join(" ")),
+                    other => Some(scalar_string(other)),
+                };
+                service_overrides.push((scalar_string(section), scalar_string(key), value));
+            }
+        }
+        overrides.insert(name.clone(), service_overrides);
+    }
+    Ok(overrides)
+}
 
-# bookstack.pod
+/// Apply each service's overrides to its container unit, after everything else has been added.
+pub fn apply_overrides(units: &mut IniFiles, overrides: HashMap<String, Overrides>) {
+    for (service, overrides) in overrides {
+        let Some(unit) = container_unit(units, &service) else {
+            log::warn!("No container unit was generated for '{service}'");
+            continue;
+        };
+        for (section, key, value) in overrides {
+            match value {
+                Some(value) => {
+                    unit.0.entry(section).or_insert_with(Section::new).insert(key, value);
+                }
+                None => {
+                    if let Some(keys) = unit.0.get_mut(&section) {
+                        keys.shift_remove(&key);
+                        if keys.is_empty() {
+                            unit.0.shift_remove(&section);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Network settings taken out of a compose file, to add to the units podlet generates
+#[derive(Debug, Default, PartialEq)]
+pub struct NetworkSettings {
+    /// Options for attaching to each network, such as `ip=` and `alias=`
+    pub attach: HashMap<String, Vec<String>>,
+    /// `Subnet=`, `Gateway=` and `IPRange=` of each network
+    pub ipam: HashMap<String, Vec<Directive>>,
+}
+
+/// How many instances of a service to run, and the host ports the first one publishes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replicas {
+    pub count: u32,
+    pub ports: Vec<String>,
+}
+
+/// Take `deploy.replicas` out of each service that runs more than one instance, along with its
+/// `ports:`, as instances can't share a pod that publishes them once.
+pub fn extract_replicas(file: &mut ComposeFile) -> HashMap<String, Replicas> {
+    let mut replicas = HashMap::new();
+    for (name, service) in file.services.iter_mut() {
+        let count = service.get("deploy").and_then(|d| d.get("replicas")).and_then(|r| r.as_u64()).unwrap_or(1);
+        remove_deploy_key(service, "replicas");
+        if count <= 1 {
+            continue;
+        }
+        let ports = service
+            .as_mapping_mut()
+            .and_then(|m| m.remove("ports"))
+            .and_then(|p| p.as_sequence().cloned())
+            .unwrap_or_default()
+            .iter()
+            .map(|port| match port {
+                Value::Mapping(long) => {
+                    let mut spec = String::new();
+                    if let Some(ip) = long.get("host_ip") {
+                        spec.push_str(&format!("{}:", scalar_string(ip)));
+                    }
+                    if let Some(published) = long.get("published") {
+                        spec.push_str(&format!("{}:", scalar_string(published)));
+                    }
+                    spec.push_str(&long.get("target").map(scalar_string).unwrap_or_default());
+                    if let Some(protocol) = long.get("protocol") {
+                        spec.push_str(&format!("/{}", scalar_string(protocol)));
+                    }
+                    spec
+                }
+                short => scalar_string(short),
+            })
+            .collect();
+        replicas.insert(name.clone(), Replicas { count: count as u32, ports });
+    }
+    replicas
+}
+
+/// Replace the container unit of each replicated service with a template, `<name>@.container`,
+/// and an instance of it for each replica, `<name>@1.container` and so on, wanted by
+/// default.target. The instances run outside the pod on its network, and each publishes the
+/// service's host ports offset by its number less one. Units depending on the service depend on
+/// every instance instead.
+pub fn add_replicas(units: &mut IniFiles, replicas: HashMap<String, Replicas>) {
+    let mut renames = HashMap::new();
+    for (service, replicas) in replicas {
+        let Some(name) = container_unit_name(units, &service) else {
+            log::warn!("No container unit was generated for '{service}'");
+            continue;
+        };
+        let mut template = units.0.remove(&name).expect("found by name");
+        let stem = name.trim_end_matches(".container");
+
+        let container = template.0.entry("Container".to_string()).or_default();
+        if let Some(pod) = container.shift_remove("Pod") {
+            let network = units.get(&pod).and_then(|p| p.get("Pod")).and_then(|p| p.get("Network")).cloned();
+            container.extend(network.map(|network| ("Network".to_string(), network)));
+        }
+        // `@` isn't allowed in container names
+        container.insert("ContainerName".to_string(), "%p-%i".to_string());
+        // Only the instances are installed
+        template.0.shift_remove("Install");
+
+        let mut instances = Vec::new();
+        for i in 1..=replicas.count {
+            let mut instance = template.clone();
+            let ports: Vec<String> = replicas.ports.iter().map(|port| offset_port(port, i - 1)).collect();
+            let mut directives = Vec::new();
+            set_many(&mut directives, "PublishPort", "publish", &ports);
+            apply_directives(&mut instance, directives);
+            instance.0.entry("Install".to_string()).or_default()
+                .insert("WantedBy".to_string(), "default.target".to_string());
+            instances.push(format!("{stem}@{i}.service"));
+            units.insert(format!("{stem}@{i}.container"), instance);
+        }
+        units.insert(format!("{stem}@.container"), template);
+        renames.insert(format!("{stem}.service"), instances.join(" "));
+    }
+
+    for unit in units.0.values_mut() {
+        for value in unit.0.values_mut().flat_map(|section| section.values_mut()) {
+            *value = rename_references(value, &renames);
+        }
+    }
+}
+
+// `[ip:]host_port:container_port[/protocol]` with the host port, or range, moved up by `offset`
+fn offset_port(spec: &str, offset: u32) -> String {
+    let (spec, protocol) = match spec.split_once('/') {
+        Some((spec, protocol)) => (spec, format!("/{protocol}")),
+        None => (spec, String::new()),
+    };
+    let mut parts: Vec<String> = spec.rsplitn(3, ':').map(str::to_string).collect();
+    parts.reverse();
+    if parts.len() >= 2 {
+        let host = parts.len() - 2;
+        let shifted: Option<Vec<String>> = parts[host]
+            .split('-')
+            .map(|port| port.parse::<u32>().ok().map(|port| (port + offset).to_string()))
+            .collect();
+        if let Some(shifted) = shifted {
+            parts[host] = shifted.join("-");
+        }
+    }
+    parts.join(":") + &protocol
+}
+
+/// Take each service's `ipv4_address`, `ipv6_address`, `aliases`, `mac_address` and
+/// `interface_name` on its networks, and each network's `ipam` config, out of the file.
+/// Networks are reordered by `priority`, highest first, as podman attaches them in order.
+pub fn extract_network_settings(file: &mut ComposeFile) -> NetworkSettings {
+    let mut settings = NetworkSettings::default();
+    let mut names: Vec<String> = file.services.keys().cloned().collect();
+    names.sort();
+    for name in names {
+        let networks = file.services.get_mut(&name).and_then(|s| s.get_mut("networks"));
+        let Some(networks) = networks.and_then(|n| n.as_mapping_mut()) else {
+            continue;
+        };
+        let mut attached: Vec<(i64, Value, Value)> = std::mem::take(networks)
+            .into_iter()
+            .map(|(network, mut config)| {
+                let mut priority = 0;
+                if let Some(config) = config.as_mapping_mut() {
+                    priority = config.remove("priority").and_then(|p| p.as_i64()).unwrap_or(0);
+                    let mut options = Vec::new();
+                    let fields = [
+                        ("ipv4_address", "ip"),
+                        ("ipv6_address", "ip6"),
+                        ("mac_address", "mac"),
+                        ("interface_name", "interface_name"),
+                    ];
+                    for (field, option) in fields {
+                        if let Some(value) = config.remove(field) {
+                            options.push(format!("{option}={}", scalar_string(&value)));
+                        }
+                    }
+                    for alias in config.remove("aliases").as_ref().and_then(|a| a.as_sequence()).into_iter().flatten() {
+                        options.push(format!("alias={}", scalar_string(alias)));
+                    }
+                    if !options.is_empty() {
+                        let network = scalar_string(&network);
+                        match settings.attach.get(&network) {
+                            // Services in a pod share its network namespace, and so its address
+                            Some(existing) if *existing != options => {
+                                log::warn!("Ignoring the options of '{name}' on network '{network}', as another service in the pod has some")
+                            }
+                            _ => {
+                                settings.attach.insert(network, options);
+                            }
+                        }
+                    }
+                    if config.is_empty() {
+                        return (priority, network, Value::Null);
+                    }
+                }
+                (priority, network, config)
+            })
+            .collect();
+        attached.sort_by_key(|(priority, ..)| -priority);
+        networks.extend(attached.into_iter().map(|(_, network, config)| (network, config)));
+    }
+
+    let networks = file.other.get_mut("networks").and_then(|n| n.as_mapping_mut());
+    for (network, config) in networks.into_iter().flatten() {
+        let Some(ipam) = config.as_mapping_mut().and_then(|c| c.remove("ipam")) else {
+            continue;
+        };
+        let network = scalar_string(network);
+        let pools = ipam.get("config").and_then(|c| c.as_sequence()).cloned().unwrap_or_default();
+        if pools.len() > 1 {
+            log::warn!("Only using the first ipam config of network '{network}'");
+        }
+        let Some(pool) = pools.first() else {
+            continue;
+        };
+        let mut directives = Vec::new();
+        for (field, key) in [("subnet", "Subnet"), ("gateway", "Gateway"), ("ip_range", "IPRange")] {
+            if let Some(value) = pool.get(field) {
+                set(&mut directives, "Network", key, scalar_string(value));
+            }
+        }
+        if pool.get("subnet").and_then(|s| s.as_str()).is_some_and(|s| s.contains(':')) {
+            set(&mut directives, "Network", "IPv6", "true".to_string());
+        }
+        settings.ipam.insert(network, directives);
+    }
+    settings
+}
+
+/// Add attachment options to the `Network=` of the pods and containers podlet generated, and
+/// address pools to its `.network` units.
+pub fn add_network_settings(units: &mut IniFiles, settings: NetworkSettings) {
+    for unit in units.0.values_mut() {
+        for section in ["Pod", "Container"] {
+            let Some(value) = unit.0.get_mut(section).and_then(|s| s.get_mut("Network")) else {
+                continue;
+            };
+            let network = value.trim_end_matches(".network");
+            if let Some(options) = settings.attach.get(network) {
+                *value = format!("{value}:{}", options.join(","));
+            }
+        }
+    }
+    for (network, directives) in settings.ipam {
+        let Some(unit) = units.0.get_mut(&format!("{network}.network")) else {
+            log::warn!("No network unit was generated for '{network}'");
+            continue;
+        };
+        for (section, key, value) in directives {
+            unit.0.entry(section.to_string()).or_insert_with(Section::new).insert(key.to_string(), value);
+        }
+    }
+}
+
+// Host paths that must never be relabeled, as other programs on the host rely on their labels
+const SYSTEM_PATHS: &[&str] = &["/usr", "/etc", "/dev", "/sys", "/proc", "/run", "/var/run", "/boot", "/lib", "/bin", "/sbin"];
+
+/// On hosts enforcing SELinux, add a label option to bind mounts without one, so the container
+/// can read them: `z` for paths several services mount, `Z` for the rest. Asks about each mount
+/// unless `auto` is set. Mounts of system paths are left alone, with a warning.
+pub fn relabel_bind_mounts(file: &mut ComposeFile, auto: bool, prompter: &mut dyn Prompter) -> Result<()> {
+    if !selinux_enforcing() {
+        return Ok(());
+    }
+    let mut names: Vec<String> = file.services.keys().cloned().collect();
+    names.sort();
+
+    // How many services mount each host path
+    let mut sharing: HashMap<String, usize> = HashMap::new();
+    for service in file.services.values() {
+        let volumes = service.get("volumes").and_then(|v| v.as_sequence()).into_iter().flatten();
+        for (source, ..) in volumes.filter_map(bind_mount) {
+            *sharing.entry(source).or_default() += 1;
+        }
+    }
+
+    for name in names {
+        let volumes = file.services.get_mut(&name).and_then(|s| s.get_mut("volumes"));
+        let Some(volumes) = volumes.and_then(|v| v.as_sequence_mut()) else {
+            continue;
+        };
+        for volume in volumes.iter_mut() {
+            let Some((source, target, options)) = bind_mount(volume) else {
+                continue;
+            };
+            if options.iter().any(|o| o == "z" || o == "Z") {
+                continue;
+            }
+            if source == "/" || source == "/home" || source == "/var" || SYSTEM_PATHS.iter().any(|p| Path::new(&source).starts_with(p)) {
+                log::warn!("Not relabeling system path '{source}' mounted by '{name}', the container may not be able to read it");
+                continue;
+            }
+
+            let label = if sharing.get(&source).copied().unwrap_or(0) > 1 { "z" } else { "Z" };
+            if auto
+                || prompter.confirm(
+                    "selinux-relabel",
+                    &format!("SELinux is enforcing. Add :{label} to the bind mount '{source}' of '{name}' so it can be read?"),
+                    true,
+                )?
+            {
+                let mut options = options;
+                options.push(label.to_string());
+                *volume = Value::String(format!("{source}:{target}:{}", options.join(",")));
+            }
+        }
+    }
+    Ok(())
+}
+
+// A short-form bind mount as its host path, container path and options
+fn bind_mount(volume: &Value) -> Option<(String, String, Vec<String>)> {
+    let mut parts = volume.as_str()?.splitn(3, ':');
+    let source = parts.next()?;
+    let target = parts.next()?;
+    if !source.starts_with('/') && !source.starts_with('.') {
+        return None;
+    }
+    let options = parts.next().map(|o| o.split(',').map(str::to_string).collect()).unwrap_or_default();
+    Some((source.to_string(), target.to_string(), options))
+}
+
+// A key holds one value in the generated units, so values after the first are passed to podman
+// with `--<flag>=<value>` in `PodmanArgs=`
+fn set_many(directives: &mut Vec<Directive>, key: &'static str, flag: &str, values: &[String]) {
+    let Some((first, rest)) = values.split_first() else {
+        return;
+    };
+    set(directives, "Container", key, first.clone());
+    let args: Vec<String> = rest.iter().map(|value| format!("--{flag}={value}")).collect();
+    if args.is_empty() {
+        return;
+    }
+    match directives.iter_mut().find(|(_, k, _)| *k == "PodmanArgs") {
+        Some((_, _, existing)) => *existing = format!("{existing} {}", args.join(" ")),
+        None => directives.push(("Container", "PodmanArgs", args.join(" "))),
+    }
+}
+
+fn set(directives: &mut Vec<Directive>, section: &'static str, key: &'static str, value: String) {
+    directives.retain(|(s, k, _)| (*s, *k) != (section, key));
+    directives.push((section, key, value));
+}
+
+/// The container unit of a service: `<service>.container`, or `<pod>-<service>.container` as
+/// podlet names those in a pod
+pub fn container_unit<'a>(units: &'a mut IniFiles, service: &str) -> Option<&'a mut Ini> {
+    let name = container_unit_name(units, service)?;
+    units.0.get_mut(&name)
+}
+
+fn container_unit_name(units: &IniFiles, service: &str) -> Option<String> {
+    let suffix = format!("-{service}.container");
+    units.0.keys().find(|name| {
+        if name.strip_suffix(".container") == Some(service) {
+            return true;
+        }
+        let Some(pod) = name.strip_suffix(&suffix) else {
+            return false;
+        };
+        let pod_unit = format!("{pod}.pod");
+        units.0[*name].get("Container").and_then(|c| c.get("Pod")) == Some(&pod_unit)
+    }).cloned()
+}
+
+/// Add directives to the container units of the services they are for, replacing podlet's.
+pub fn add_directives(units: &mut IniFiles, directives: HashMap<String, Vec<Directive>>) {
+    for (service, directives) in directives {
+        let Some(unit) = container_unit(units, &service) else {
+            log::warn!("No container unit was generated for '{service}'");
+            continue;
+        };
+        apply_directives(unit, directives);
+    }
+}
+
+fn apply_directives(unit: &mut Ini, directives: Vec<Directive>) {
+    for (section, key, value) in directives {
+        let section = unit.0.entry(section.to_string()).or_default();
+        // Arguments are added to any podlet passes on
+        match section.get_mut(key) {
+            Some(args) if key == "PodmanArgs" => *args = format!("{args} {value}"),
+            _ => {
+                section.insert(key.to_string(), value);
+            }
+        }
+    }
+}
+
+fn parse_raw_quadlets(output: &str) -> Result<IniFiles> {
+    let mut units = IniFiles::new();
+    for block in output.split("\n---\n\n") {
+        if let Some((first_line, rest)) = block.split_once('\n') {
+            if let Some(stripped) = first_line.strip_prefix("# ") {
+                let key = stripped.trim().to_string();
+                let item: Ini = serde_ini::from_str(rest)?;
+                units.insert(key, item);
+            }
+        } else {
+            error!("Unexpected section of podlet output encountered, skipping");
+        }
+    }
+    Ok(units)
+}
+
+/// Convert a compose file with podlet, into a pod with a container for each service, or with
+/// `pod` false into standalone containers that publish their own ports.
+pub fn get_raw_quadlets(filepath: &PathBuf, pod: bool) -> Result<IniFiles> {
+    if which("podlet").is_none() {
+        anyhow::bail!("podman command not found. Please install podman.");
+    }
+
+    let mut cmd = Command::new("podlet");
+    cmd.arg("compose");
+    if pod {
+        cmd.arg("--pod");
+    }
+    let output = cmd.arg(filepath).output()?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "podlet conversion failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+    parse_raw_quadlets(&output_str)
+}
+
+pub fn process_quadlets(mut units: IniFiles, initial_dir: Option<&Path>, prompter: &mut dyn Prompter) -> Result<IniFiles> {
+    for unit_name in units.0.keys() {
+        crate::systemd::check_unit_name(unit_name)?;
+    }
+    for (unit_name, unit_data) in units.0.iter_mut() {
+        // Pods start their containers, so only containers outside a pod are wanted themselves
+        let in_pod = unit_data.get("Container").is_some_and(|c| c.contains_key("Pod"));
+        if (unit_name.ends_with(".pod") || (unit_name.ends_with(".container") && !in_pod))
+            && prompter.confirm(
+                "add-wanted-by",
+                &format!("Add WantedBy=default.target to '{unit_name}'?"),
+                true,
+            )?
+        {
+            let install_section = unit_data.0.entry("Install".to_string()).or_insert_with(Section::new);
+            install_section.insert("WantedBy".to_string(), "default.target".to_string());
+        }
+        if unit_name.ends_with(".container") {
+            let unit_section = unit_data.0.entry("Unit".to_string()).or_insert_with(Section::new);
+            if prompter.confirm(
+                "add-after-targets",
+                &format!("Add After=local-fs.target network-online.target systemd-networkd-wait-online.service to '{unit_name}'?"),
+                true,
+            )? {
+                unit_section.insert("After".to_string(), "local-fs.target network-online.target systemd-networkd-wait-online.service".to_string());
+            }
+
+            let service_section = unit_data.0.entry("Service".to_string()).or_insert_with(Section::new);
+            // if .env exists in the same directory as .compose, include it into the systemd service
+            if let Some(dir) = initial_dir {
+                let env_file = dir.join(".env");
+                let env_file_str=normalize_path(&env_file);
+                if env_file.exists()
+                    && prompter.confirm(
+                        "add-environment-file",
+                        &format!("Add EnvironmentFile={env_file_str} to '{unit_name}'?"),
+                        true,
+                    )? {
+                        service_section.insert("EnvironmentFile".to_string(), env_file_str);
+                    }
+            }
+
+            let container_section = unit_data.0.entry("Container".to_string()).or_insert_with(Section::new);
+
+            let image_name = container_section.get("Image").map(|s| s.as_str()).unwrap_or("");
+            let detected = if image_name.contains('.') { "registry" } else { "local" };
+            let autoupdate_value = prompter.preset("autoupdate").unwrap_or(detected).to_string();
+
+            if prompter.confirm(
+                "autoupdate",
+                &format!("Add AutoUpdate={autoupdate_value} to '{unit_name}'?"),
+                true,
+            )? {
+                container_section.insert("AutoUpdate".to_string(), autoupdate_value);
+            }
+        }
+    }
+    Ok(units)
+}       
+
+/// Prefix the file name of every unit and each reference to it or its service in the others,
+/// and name containers after their prefixed unit unless they already have a `ContainerName=`,
+/// which is prefixed too. Stacks with services of the same name can then share a host.
+pub fn prefix_units(units: &mut IniFiles, prefix: &str) {
+    let mut renames = HashMap::new();
+    for name in units.0.keys() {
+        renames.insert(name.clone(), format!("{prefix}{name}"));
+        if let Some(service) = service_name(name) {
+            renames.insert(service.clone(), format!("{prefix}{service}"));
+        }
+    }
+
+    for (name, mut unit) in std::mem::take(&mut units.0) {
+        for section in unit.0.values_mut() {
+            for value in section.values_mut() {
+                *value = rename_references(value, &renames);
+            }
+        }
+        if let Some(stem) = name.strip_suffix(".container") {
+            let container = unit.0.entry("Container".to_string()).or_insert_with(Section::new);
+            let container_name = match container.get("ContainerName") {
+                Some(existing) => format!("{prefix}{existing}"),
+                None => format!("{prefix}{stem}"),
+            };
+            container.insert("ContainerName".to_string(), container_name);
+        }
+        units.0.insert(format!("{prefix}{name}"), unit);
+    }
+}
+
+// Units are referred to by whole words, such as `data.volume` in `Volume=data.volume:/data`
+fn rename_references(value: &str, renames: &HashMap<String, String>) -> String {
+    let mut renamed = String::new();
+    let mut word = String::new();
+    for c in value.chars().chain(std::iter::once(' ')) {
+        if c.is_whitespace() || matches!(c, ':' | ',' | '=') {
+            renamed.push_str(renames.get(&word).unwrap_or(&word));
+            word.clear();
+            renamed.push(c);
+        } else {
+            word.push(c);
+        }
+    }
+    renamed.pop();
+    renamed
+}
+
+/// Add `<name>.target`, which wants the pod and every container, and install the units that
+/// were wanted by default.target as part of it instead, so the stack starts and stops as one.
+pub fn add_target(units: &mut IniFiles, name: &str) {
+    let target = format!("{name}.target");
+    let mut names: Vec<&String> = units.0.keys().collect();
+    names.sort();
+    let wants: Vec<String> = names
+        .into_iter()
+        .filter(|n| n.ends_with(".pod") || n.ends_with(".container"))
+        .filter_map(|n| service_name(n))
+        .collect();
+
+    for unit in units.0.values_mut() {
+        let Some(install) = unit.0.get_mut("Install") else {
+            continue;
+        };
+        if install.get("WantedBy").map(String::as_str) == Some("default.target") {
+            install.insert("WantedBy".to_string(), target.clone());
+            unit.0.entry("Unit".to_string()).or_insert_with(Section::new).insert("PartOf".to_string(), target.clone());
+        }
+    }
+
+    let mut unit = Section::new();
+    unit.insert("Description".to_string(), format!("{name} pod and containers"));
+    unit.insert("Wants".to_string(), wants.join(" "));
+    unit.insert("After".to_string(), wants.join(" "));
+    let mut install = Section::new();
+    install.insert("WantedBy".to_string(), "default.target".to_string());
+    let mut ini = Ini::new();
+    ini.insert("Unit".to_string(), unit);
+    ini.insert("Install".to_string(), install);
+    units.insert(target, ini);
+}
+
+/// The directory podman reads quadlets from, for root or the current user
+pub fn quadlet_dir(is_root: bool) -> Result<PathBuf> {
+    Ok(if cfg!(feature = "integration-tests") {
+        PathBuf::from("/tmp/slater/containers/systemd")
+    } else if is_root {
+        PathBuf::from("/etc/containers/systemd")
+    } else {
+        let home = std::env::var("HOME").context("HOME environment variable not set")?;
+        PathBuf::from(format!("{home}/.config/containers/systemd"))
+    })
+}
+
+/// The directory systemd reads units and their drop-ins from, for root or the current user
+pub fn systemd_unit_dir(is_root: bool) -> Result<PathBuf> {
+    Ok(if cfg!(feature = "integration-tests") {
+        PathBuf::from("/tmp/slater/systemd")
+    } else if is_root {
+        PathBuf::from("/etc/systemd/system")
+    } else {
+        let home = std::env::var("HOME").context("HOME environment variable not set")?;
+        PathBuf::from(format!("{home}/.config/systemd/user"))
+    })
+}
+
+/// Where distributions install quadlet's generator, tried in order
+pub const QUADLET_GENERATORS: &[&str] = &[
+    "/usr/lib/systemd/system-generators/podman-system-generator",
+    "/usr/libexec/podman/quadlet",
+    "/lib/systemd/system-generators/podman-system-generator",
+    "/usr/local/lib/systemd/system-generators/podman-system-generator",
+];
+
+/// The quadlet generator: `SLATE_QUADLET_GENERATOR` if set, or else the first of
+/// [`QUADLET_GENERATORS`] that exists
+pub fn quadlet_generator() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("SLATE_QUADLET_GENERATOR") {
+        return Some(PathBuf::from(path));
+    }
+    QUADLET_GENERATORS.iter().map(PathBuf::from).find(|path| path.exists())
+}
+
+/// Verify the signature of every image the units run, other than those they build, with
+/// `cosign verify` against `cosign_key`, or else by pulling it with skopeo under the containers
+/// signature policy, `policy` or the system's own. Fails on the first image that can't be verified.
+pub fn verify_signatures(units: &IniFiles, cosign_key: Option<&Path>, policy: Option<&Path>) -> Result<()> {
+    let tool = if cosign_key.is_some() { "cosign" } else { "skopeo" };
+    if which(tool).is_none() {
+        anyhow::bail!("{tool} is needed to verify image signatures, but isn't installed");
+    }
+
+    let mut images: Vec<String> =
+        resource_references(units).into_iter().filter(|(kind, ..)| *kind == "image").map(|(_, image, _)| image).collect();
+    images.sort();
+    images.dedup();
+    for image in images {
+        let scratch = tempfile::tempdir()?;
+        let output = verify_command(&image, cosign_key, policy, scratch.path()).output()?;
+        if !output.status.success() {
+            anyhow::bail!("The signature of '{image}' couldn't be verified: {}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        info!("Verified the signature of '{image}'");
+    }
+    Ok(())
+}
+
+// skopeo only checks signatures when copying, so the image is copied into a scratch directory
+fn verify_command(image: &str, cosign_key: Option<&Path>, policy: Option<&Path>, scratch: &Path) -> Command {
+    match cosign_key {
+        Some(key) => {
+            let mut cmd = Command::new("cosign");
+            cmd.arg("verify").arg("--key").arg(key).arg(image);
+            cmd
+        }
+        None => {
+            let mut cmd = Command::new("skopeo");
+            if let Some(policy) = policy {
+                cmd.arg("--policy").arg(policy);
+            }
+            cmd.arg("copy").arg(format!("docker://{image}")).arg(format!("dir:{}", scratch.display()));
+            cmd
+        }
+    }
+}
+
+// Networks podman provides itself, rather than a unit or `podman network create`
+const BUILTIN_NETWORKS: &[&str] = &["host", "none", "bridge", "private", "pasta", "slirp4netns", "ns", "container", "pod"];
+
+/// Check with podman that the images, networks and secrets the units use exist. Missing images
+/// are pulled when their container starts, so they're only mentioned, but containers fail to
+/// start without their networks and secrets, so you're asked whether to carry on regardless
+/// (`ignore-missing`).
+pub fn check_resources(units: &IniFiles, prompter: &mut dyn Prompter) -> Result<()> {
+    if which("podman").is_none() {
+        info!("Skipping the check for images, networks and secrets, as podman isn't installed");
+        return Ok(());
+    }
+
+    let mut missing = Vec::new();
+    for (kind, name, unit) in resource_references(units) {
+        let exists = Command::new("podman").args([kind, "exists", &name]).output()?.status.success();
+        if exists {
+            continue;
+        }
+        if kind == "image" {
+            info!("Image '{name}' isn't available locally, and will be pulled when '{unit}' starts");
+        } else {
+            error!("The {kind} '{name}' used by '{unit}' doesn't exist");
+            missing.push(name);
+        }
+    }
+    if !missing.is_empty()
+        && !prompter.confirm(
+            "ignore-missing",
+            &format!("{} doesn't exist, so units using it will fail to start. Continue anyway?", missing.join(", ")),
+            false,
+        )?
+    {
+        anyhow::bail!("Create {} first, or remove it from the compose file", missing.join(", "));
+    }
+    Ok(())
+}
+
+/// Check the generated units against the keys quadlet knows, as podman's generator only says a
+/// unit is invalid, and not which key. You're asked whether to carry on regardless
+/// (`ignore-invalid-keys`), as newer podman releases may know keys slate doesn't.
+pub fn check_keys(units: &IniFiles, prompter: &mut dyn Prompter) -> Result<()> {
+    let invalid = keys::validate(units);
+    for problem in &invalid {
+        error!("{problem}");
+    }
+    if !invalid.is_empty()
+        && !prompter.confirm(
+            "ignore-invalid-keys",
+            "Quadlet will likely refuse to generate services for these units. Continue anyway?",
+            false,
+        )?
+    {
+        anyhow::bail!("Fix the invalid keys, or the x-slate overrides that set them");
+    }
+    Ok(())
+}
+
+/// Rewrite keys newer than the podman the units are for, `target` or else the installed podman,
+/// as PodmanArgs, and warn about those that can't be, which quadlet will reject.
+pub fn gate_version(units: &mut IniFiles, target: Option<keys::Version>) {
+    let Some(version) = target.or_else(keys::podman_version) else {
+        info!("Skipping the podman version check, as podman isn't installed");
+        return;
+    };
+    for problem in keys::downgrade(units, version) {
+        log::warn!("{problem}, but the units are for podman {}.{}", version.0, version.1);
+    }
+}
+
+// The images, networks and secrets each unit uses that other units don't provide, as the kind
+// of resource, its name and the unit
+fn resource_references(units: &IniFiles) -> Vec<(&'static str, String, String)> {
+    let mut names: Vec<&String> = units.0.keys().collect();
+    names.sort();
+    let mut references = Vec::new();
+    for name in names {
+        for section in ["Container", "Pod", "Image"].iter().filter_map(|s| units.0[name].get(s)) {
+            let mut values: Vec<(&str, &str)> = Vec::new();
+            for key in ["Image", "Network", "Secret"] {
+                values.extend(section.get(key).map(|value| (key, value.as_str())));
+            }
+            for arg in section.get("PodmanArgs").into_iter().flat_map(|a| a.split_whitespace()) {
+                for (flag, key) in [("--network=", "Network"), ("--secret=", "Secret")] {
+                    values.extend(arg.strip_prefix(flag).map(|value| (key, value)));
+                }
+            }
+
+            for (key, value) in values {
+                match key {
+                    "Image" if !value.ends_with(".image") && !value.ends_with(".build") => {
+                        references.push(("image", value.to_string(), name.clone()));
+                    }
+                    "Network" => {
+                        let network = value.split(':').next().unwrap_or_default();
+                        if !network.ends_with(".network") && !BUILTIN_NETWORKS.contains(&network) {
+                            references.push(("network", network.to_string(), name.clone()));
+                        }
+                    }
+                    "Secret" => {
+                        let secret = value.split(',').next().unwrap_or_default();
+                        references.push(("secret", secret.to_string(), name.clone()));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    references
+}
+
+/// Move the image of each container into an `.image` unit, which quadlet makes the container
+/// depend on, so the image is pulled before the container starts rather than as it starts.
+/// Containers sharing an image share its unit.
+pub fn add_image_units(units: &mut IniFiles, all_tags: bool, arch: Option<&str>) {
+    let mut images: Vec<(String, String)> = Vec::new();
+    let mut names: Vec<String> = units.0.keys().filter(|n| n.ends_with(".container")).cloned().collect();
+    names.sort();
+    for name in names {
+        let Some(image) = units.0[&name].get("Container").and_then(|c| c.get("Image")).cloned() else {
+            continue;
+        };
+        // Images built or pulled by another unit already are
+        if image.ends_with(".build") || image.ends_with(".image") {
+            continue;
+        }
+
+        let unit_name = match images.iter().find(|(i, _)| *i == image) {
+            Some((_, unit_name)) => unit_name.clone(),
+            None => {
+                let unit_name = image_unit_name(&image, |n| images.iter().any(|(_, u)| u == n) || units.0.contains_key(n));
+                images.push((image.clone(), unit_name.clone()));
+                unit_name
+            }
+        };
+        if let Some(container) = units.0.get_mut(&name).and_then(|u| u.0.get_mut("Container")) {
+            container.insert("Image".to_string(), unit_name);
+        }
+    }
+
+    for (image, unit_name) in images {
+        let mut section = Section::new();
+        section.insert("Image".to_string(), image);
+        if all_tags {
+            section.insert("AllTags".to_string(), "true".to_string());
+        }
+        if let Some(arch) = arch {
+            section.insert("Arch".to_string(), arch.to_string());
+        }
+        let mut unit = Ini::new();
+        unit.insert("Image".to_string(), section);
+        units.insert(unit_name, unit);
+    }
+}
+
+// `docker.io/library/nginx:1.25` becomes `nginx-1.25.image`, numbered if that is taken
+fn image_unit_name(image: &str, taken: impl Fn(&str) -> bool) -> String {
+    let image = image.split('@').next().unwrap_or(image);
+    let base = image.rsplit('/').next().unwrap_or(image);
+    let base: String = base.chars().map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '_' { c } else { '-' }).collect();
+    let mut name = format!("{base}.image");
+    let mut i = 2;
+    while taken(&name) {
+        name = format!("{base}-{i}.image");
+        i += 1;
+    }
+    name
+}
+
+// A host port as an address, port and protocol, where an empty address means every address
+type HostPort = (String, u16, String);
+
+/// Warn about host ports published by more than one unit, among the generated units and those
+/// already installed (which the generated units of the same name replace), and with
+/// `check_listening` also ports something on the host is listening on already.
+pub fn check_port_conflicts(units: &IniFiles, check_listening: bool) -> Result<()> {
+    let mut installed = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(quadlet_dir(is_root())?) {
+        for entry in entries {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()).map(str::to_string) else {
+                continue;
+            };
+            if (name.ends_with(".container") || name.ends_with(".pod")) && !units.0.contains_key(&name) {
+                if let Ok(unit) = read_unit(&path) {
+                    installed.push((name, unit));
+                }
+            }
+        }
+    }
+
+    let mut names: Vec<&String> = units.0.keys().collect();
+    names.sort();
+    let mut publishers: Vec<Publisher> =
+        names.into_iter().map(|name| (format!("'{name}'"), true, published_ports(&units.0[name]))).collect();
+    publishers.extend(installed.iter().map(|(name, unit)| (format!("installed '{name}'"), false, published_ports(unit))));
+    if check_listening {
+        publishers.push(("a process on the host".to_string(), false, listening_ports()));
+    }
+
+    for conflict in port_conflicts(&publishers) {
+        log::warn!("{conflict}");
+    }
+    Ok(())
+}
+
+// What publishes some ports, and whether it's one of the generated units
+type Publisher = (String, bool, Vec<HostPort>);
+
+// Only pairs that include a generated unit are reported, as the rest already coexist. Generated
+// units come first.
+fn port_conflicts(publishers: &[Publisher]) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    for (i, (first, generated, first_ports)) in publishers.iter().enumerate() {
+        if !generated {
+            continue;
+        }
+        for (second, _, second_ports) in &publishers[i + 1..] {
+            for (ip, port, protocol) in first_ports {
+                let overlaps = second_ports.iter().any(|(other_ip, other_port, other_protocol)| {
+                    port == other_port && protocol == other_protocol && (ip.is_empty() || other_ip.is_empty() || ip == other_ip)
+                });
+                if overlaps {
+                    conflicts.push(format!("Host port {port}/{protocol} is published by {first} and by {second}"));
+                }
+            }
+        }
+    }
+    conflicts
+}
+
+fn published_ports(unit: &Ini) -> Vec<HostPort> {
+    let mut specs = Vec::new();
+    for section in ["Container", "Pod"].iter().filter_map(|s| unit.get(s)) {
+        specs.extend(section.get("PublishPort").cloned());
+        for arg in section.get("PodmanArgs").into_iter().flat_map(|a| a.split_whitespace()) {
+            specs.extend(arg.strip_prefix("--publish=").map(str::to_string));
+        }
+    }
+    specs.iter().flat_map(|spec| host_ports(spec)).collect()
+}
+
+// `[ip:][host_port:]container_port[/protocol]`, where the host port may be a range and the ip
+// an IPv6 address in brackets. Without a host port podman picks a free one, so there's none.
+fn host_ports(spec: &str) -> Vec<HostPort> {
+    let (spec, protocol) = spec.split_once('/').unwrap_or((spec, "tcp"));
+    let (ip, rest) = match spec.strip_prefix('[').and_then(|s| s.split_once("]:")) {
+        Some((ip, rest)) => (ip, rest),
+        None => match spec.matches(':').count() {
+            2 => spec.split_once(':').unwrap_or_default(),
+            _ => ("", spec),
+        },
+    };
+    let Some((host, _container)) = rest.split_once(':') else {
+        return Vec::new();
+    };
+    let ip = if matches!(ip, "0.0.0.0" | "::") { "" } else { ip };
+    let (start, end) = host.split_once('-').unwrap_or((host, host));
+    match (start.parse::<u16>(), end.parse::<u16>()) {
+        (Ok(start), Ok(end)) => (start..=end).map(|port| (ip.to_string(), port, protocol.to_string())).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// What `ss` reports listening, such as `0.0.0.0:80`, `[::]:443` or `*:22`
+fn listening_ports() -> Vec<HostPort> {
+    let mut ports = Vec::new();
+    for (flag, protocol) in [("-Hltn", "tcp"), ("-Hlun", "udp")] {
+        let Ok(output) = Command::new("ss").arg(flag).output() else {
+            log::warn!("Couldn't run ss to check for ports in use");
+            return ports;
+        };
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let Some(local) = line.split_whitespace().nth(3) else {
+                continue;
+            };
+            let Some((ip, port)) = local.rsplit_once(':') else {
+                continue;
+            };
+            let ip = ip.trim_start_matches('[').trim_end_matches(']').split('%').next().unwrap_or_default();
+            let ip = if matches!(ip, "0.0.0.0" | "::" | "*") { "" } else { ip };
+            if let Ok(port) = port.parse() {
+                ports.push((ip.to_string(), port, protocol.to_string()));
+            }
+        }
+    }
+    ports
+}
+
+// Where the units as last generated are kept next to the written ones, to tell keys added by
+// hand from keys slate has since changed. They're suffixed so the generator, which also reads
+// subdirectories since podman 5, doesn't take them for units.
+const BASE_DIR: &str = ".slate";
+
+/// Write each unit to `output_dir`, merging it with any file already there. Keys that were
+/// changed or added by hand since the last run are kept, keys slate manages are updated, and
+/// where both changed the same key you're asked whether to keep your value (`keep-manual-change`).
+pub fn write_merged(units: &IniFiles, output_dir: &Path, prompter: &mut dyn Prompter) -> Result<Vec<PathBuf>> {
+    let mut names: Vec<&String> = units.0.keys().collect();
+    names.sort();
+    let mut written = Vec::new();
+    for name in names {
+        let path = output_dir.join(name);
+        let merged = merged_unit(name, &units.0[name], &path, prompter)?;
+        back_up(name, &path)?;
+        std::fs::write(&path, serde_ini::to_string(&merged)?).with_context(|| format!("Failed to write to file: {path:?}"))?;
+        save_base(name, &units.0[name], &path)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+// The generated unit merged with the file at `path`, if there is one
+fn merged_unit(name: &str, generated: &Ini, path: &Path, prompter: &mut dyn Prompter) -> Result<Ini> {
+    if !path.exists() {
+        return Ok(generated.clone());
+    }
+    let deployed = read_unit(path)?;
+    let base = match std::fs::read_to_string(base_path(name, path)) {
+        Ok(base) => serde_ini::from_str(&base)?,
+        Err(_) => Ini::new(),
+    };
+    merge_unit(name, &base, &deployed, generated, prompter)
+}
+
+fn read_unit(path: &Path) -> Result<Ini> {
+    serde_ini::from_str(&std::fs::read_to_string(path)?).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+fn base_path(name: &str, path: &Path) -> PathBuf {
+    path.parent().unwrap_or(Path::new(".")).join(BASE_DIR).join(format!("{name}.base"))
+}
+
+fn save_base(name: &str, generated: &Ini, path: &Path) -> Result<()> {
+    let base_path = base_path(name, path);
+    if let Some(dir) = base_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(base_path, serde_ini::to_string(generated)?)?;
+    Ok(())
+}
+
+// Before a unit is written, keep a copy of the file it replaces, or note that it's new, so a
+// failed activation can be rolled back
+fn back_up(name: &str, path: &Path) -> Result<()> {
+    let (previous, added) = backup_paths(name, path);
+    if let Some(dir) = previous.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    for stale in [&previous, &added] {
+        if stale.exists() {
+            std::fs::remove_file(stale)?;
+        }
+    }
+    if path.exists() {
+        std::fs::copy(path, &previous)?;
+    } else {
+        std::fs::write(&added, "")?;
+    }
+    Ok(())
+}
+
+fn backup_paths(name: &str, path: &Path) -> (PathBuf, PathBuf) {
+    let dir = path.parent().unwrap_or(Path::new(".")).join(BASE_DIR);
+    (dir.join(format!("{name}.previous")), dir.join(format!("{name}.added")))
+}
+
+/// Put back the units at `paths` as they were before they were last written, removing those
+/// that were new along with their symlinks in `target_dir`. Units without a backup are left alone.
+/// Returns the names of the removed units.
+pub fn roll_back(paths: &[PathBuf], target_dir: &Path) -> Result<Vec<String>> {
+    let mut removed = Vec::new();
+    for path in paths {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let (previous, added) = backup_paths(name, path);
+        if previous.exists() {
+            std::fs::rename(&previous, path)?;
+            info!("Restored {}", path.display());
+        } else if added.exists() {
+            std::fs::remove_file(path)?;
+            std::fs::remove_file(&added)?;
+            let link = target_dir.join(name);
+            if link.is_symlink() && std::fs::read_link(&link).is_ok_and(|dst| dst == *path) {
+                std::fs::remove_file(&link)?;
+            }
+            info!("Removed {}", path.display());
+            removed.push(name.to_string());
+        }
+    }
+    Ok(removed)
+}
+
+// Containers reload with ReloadCmd= or ReloadSignal=, which quadlet turns into ExecReload=
+fn supports_reload(unit: &Ini) -> bool {
+    unit.get("Container").is_some_and(|c| c.contains_key("ReloadCmd") || c.contains_key("ReloadSignal"))
+        || unit.get("Service").is_some_and(|s| s.contains_key("ExecReload"))
+}
+
+// Restart each service, or reload those that can be if you'd rather (`reload-instead`), and
+// offer to go back to the previous units at `paths` if any of them doesn't come back up
+fn restart_or_roll_back(services: &[String], paths: &[PathBuf], prompter: &mut dyn Prompter) -> Result<()> {
+    let is_root = is_root();
+    let reloadable: Vec<String> = paths
+        .iter()
+        .filter(|path| read_unit(path).is_ok_and(|unit| supports_reload(&unit)))
+        .filter_map(|path| service_name(path.file_name()?.to_str()?))
+        .collect();
+    for service in services {
+        let verb = if reloadable.contains(service)
+            && prompter.confirm(
+                "reload-instead",
+                &format!("Reload '{service}' instead of restarting it? Changes to the unit itself only apply on restart"),
+                false,
+            )? {
+            "reload-or-restart"
+        } else {
+            "restart"
+        };
+        systemctl_cmd(is_root).arg(verb).arg(service).status()?;
+    }
+    let failed: Vec<&str> = services
+        .iter()
+        .filter(|service| {
+            !systemctl_cmd(is_root).args(["is-active", "--quiet"]).arg(service).status().is_ok_and(|s| s.success())
+        })
+        .map(String::as_str)
+        .collect();
+    if failed.is_empty() {
+        return Ok(());
+    }
+
+    error!("{} failed to start", failed.join(", "));
+    if !prompter.confirm("roll-back", "Restore the previous units and restart them?", true)? {
+        return Ok(());
+    }
+    // Services of units that are about to be removed are stopped while systemd still knows them
+    let added: Vec<String> = paths
+        .iter()
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?;
+            backup_paths(name, path).1.exists().then(|| service_name(name)).flatten()
+        })
+        .collect();
+    if !added.is_empty() {
+        systemctl_cmd(is_root).arg("stop").args(&added).status()?;
+    }
+
+    let removed = roll_back(paths, &quadlet_dir(is_root)?)?;
+    systemctl_cmd(is_root).arg("daemon-reload").status()?;
+    for service in services {
+        if !removed.iter().any(|name| service_name(name).as_ref() == Some(service)) {
+            systemctl_cmd(is_root).arg("restart").arg(service).status()?;
+        }
+    }
+    Ok(())
+}
+
+/// How an installed unit differs from the one regenerated for it
+#[derive(Debug, PartialEq)]
+pub enum UnitChange {
+    Added,
+    Changed(Vec<Change>),
+    Unchanged,
+}
+
+// Where a unit is installed: the quadlet directory, or systemd's own for targets and services
+fn install_dir(name: &str, is_root: bool) -> Result<PathBuf> {
+    if name.ends_with(".target") || name.ends_with(".service") {
+        systemd_unit_dir(is_root)
+    } else {
+        quadlet_dir(is_root)
+    }
+}
+
+/// Merge each regenerated unit with the one installed in the quadlet directory, following
+/// symlinks to where it's kept, and write those that differ unless `dry_run`.
+pub fn update_quadlets(units: &IniFiles, dry_run: bool, prompter: &mut dyn Prompter) -> Result<Vec<(String, UnitChange)>> {
+    let is_root = is_root();
+    let mut names: Vec<&String> = units.0.keys().collect();
+    names.sort();
+
+    let mut changes = Vec::new();
+    for name in names {
+        let generated = &units.0[name];
+        let target_dir = install_dir(name, is_root)?;
+        let installed = target_dir.join(name);
+        let path = if installed.exists() { std::fs::canonicalize(&installed)? } else { installed };
+
+        let merged = merged_unit(name, generated, &path, prompter)?;
+        let change = if path.exists() {
+            let differences = diff(&serde_json::to_value(read_unit(&path)?)?, &serde_json::to_value(&merged)?);
+            if differences.is_empty() {
+                UnitChange::Unchanged
+            } else {
+                UnitChange::Changed(differences)
+            }
+        } else {
+            UnitChange::Added
+        };
+
+        if !dry_run {
+            if change != UnitChange::Unchanged {
+                std::fs::create_dir_all(&target_dir)?;
+                back_up(name, &path)?;
+                std::fs::write(&path, serde_ini::to_string(&merged)?)
+                    .with_context(|| format!("Failed to write to file: {path:?}"))?;
+            }
+            save_base(name, generated, &path)?;
+        }
+        changes.push((name.clone(), change));
+    }
+    Ok(changes)
+}
+
+/// Reload systemd and restart the service of each unit that was added or changed, leaving the
+/// rest running.
+pub fn restart_changed(changes: &[(String, UnitChange)], prompter: &mut dyn Prompter) -> Result<()> {
+    let services: Vec<String> = changes
+        .iter()
+        .filter(|(_, change)| *change != UnitChange::Unchanged)
+        .filter_map(|(name, _)| service_name(name))
+        .collect();
+    if services.is_empty()
+        || !prompter.confirm("reload-restart", "Reload systemd and restart the changed services?", true)?
+    {
+        return Ok(());
+    }
+
+    let is_root = is_root();
+    systemctl_cmd(is_root).arg("daemon-reload").status()?;
+    info!("systemctl-daemon reloaded!");
+
+    let mut paths = Vec::new();
+    for (name, change) in changes {
+        if *change != UnitChange::Unchanged {
+            paths.push(std::fs::canonicalize(install_dir(name, is_root)?.join(name))?);
+        }
+    }
+    restart_or_roll_back(&services, &paths, prompter)
+}
+
+/// The container units in `dir` that run in `pod`, by their `Pod=` key
+pub fn pod_containers(dir: &Path, pod: &str) -> Result<Vec<String>> {
+    let pod_unit = format!("{pod}.pod");
+    let mut containers = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("container") {
+            continue;
+        }
+        let unit = read_unit(&path)?;
+        if unit.get("Container").and_then(|c| c.get("Pod")) == Some(&pod_unit) {
+            containers.extend(path.file_name().and_then(|n| n.to_str()).map(str::to_string));
+        }
+    }
+    containers.sort();
+    Ok(containers)
+}
+
+/// Stop a pod's service and the services of its containers, leaving their units installed so
+/// they start again on the next restart or boot.
+pub fn stop_pod(pod: &str) -> Result<()> {
+    let is_root = is_root();
+    let target_dir = quadlet_dir(is_root)?;
+    if !target_dir.join(format!("{pod}.pod")).exists() {
+        anyhow::bail!("No pod '{pod}' is installed in '{}'", target_dir.display());
+    }
+
+    let mut services: Vec<String> = pod_containers(&target_dir, pod)?.iter().filter_map(|c| service_name(c)).collect();
+    services.push(format!("{pod}-pod.service"));
+    let status = systemctl_cmd(is_root).arg("stop").args(&services).status()?;
+    if !status.success() {
+        anyhow::bail!("Failed to stop {}", services.join(" "));
+    }
+    info!("Stopped {}", services.join(" "));
+    Ok(())
+}
+
+/// The name of the service quadlet generates from a unit file, if it isn't a template that
+/// only runs as instances
+pub fn service_name(unit: &str) -> Option<String> {
+    let (stem, extension) = unit.rsplit_once('.')?;
+    if stem.ends_with('@') {
+        return None;
+    }
+    match extension {
+        "container" | "kube" => Some(format!("{stem}.service")),
+        "pod" | "network" | "volume" | "image" | "build" => Some(format!("{stem}-{extension}.service")),
+        _ => None,
+    }
+}
+
+// Three-way merge of each key, keeping the generated order with keys added by hand after it
+fn merge_unit(name: &str, base: &Ini, deployed: &Ini, generated: &Ini, prompter: &mut dyn Prompter) -> Result<Ini> {
+    let mut sections: Vec<&String> = generated.0.keys().collect();
+    sections.extend(deployed.0.keys().filter(|s| !generated.0.contains_key(*s)));
+
+    let mut merged = Ini::new();
+    for section in sections {
+        let mut keys: Vec<&String> = generated.get(section).into_iter().flat_map(|s| s.keys()).collect();
+        for key in deployed.get(section).into_iter().flat_map(|s| s.keys()) {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+
+        for key in keys {
+            let value = |ini: &Ini| ini.get(section).and_then(|s| s.get(key)).cloned();
+            let (base, deployed, generated) = (value(base), value(deployed), value(generated));
+            let kept = if deployed == base {
+                generated
+            } else if generated == base || generated == deployed {
+                deployed
+            } else {
+                let show = |value: &Option<String>| value.as_ref().map_or("no value".to_string(), |v| format!("'{v}'"));
+                log::warn!(
+                    "Conflict in '{name}' [{section}] {key}: yours has {}, slate generates {}",
+                    show(&deployed),
+                    show(&generated)
+                );
+                if prompter.confirm("keep-manual-change", &format!("Keep your {key} in '{name}'?"), true)? {
+                    deployed
+                } else {
+                    generated
+                }
+            };
+            if let Some(value) = kept {
+                merged.0.entry(section.clone()).or_default().insert(key.clone(), value);
+            }
+        }
+    }
+    Ok(merged)
+}
+
+pub fn activate_quadlets(files: Vec<PathBuf>, prompter: &mut dyn Prompter) -> Result<()> {
+    let is_root = is_root();
+    let target_dir = quadlet_dir(is_root)?;
+
+    let cwd = std::env::current_dir()?;
+
+    let generator = quadlet_generator()
+        .context("Quadlet's generator wasn't found, set SLATE_QUADLET_GENERATOR to its path")?;
+    let mut cmd = Command::new(generator);
+    cmd.arg("--dryrun");
+    if !is_root {
+        cmd.arg("--user");
+    }
+    cmd.env("QUADLET_UNIT_DIRS", &cwd);
+
+    let output = cmd.output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Validation command failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    println!("Generated systemd unit files (dry run):");
+    println!("{}", String::from_utf8_lossy(&output.stdout));
+
+    if cwd != target_dir
+        && prompter.confirm(
+            "create-symlinks",
+            &format!("Create symlinks in '{}'?", target_dir.display()),
+            true,
+        )? {
+            std::fs::create_dir_all(&target_dir)?;
+
+            for file_path in &files {
+                let file_name = file_path.file_name()
+                    .context("Failed to get filename from path")?;
+                // Targets and services aren't quadlets, so systemd links them itself below
+                if file_path.extension().is_some_and(|ext| ext == "target" || ext == "service") {
+                    continue;
+                }
+                let src = cwd.join(file_name);
+                let dst = target_dir.join(file_name);
+
+                if dst.exists() {
+                    if let Err(e) = std::fs::remove_file(&dst) {
+                        error!("Failed to remove file {}: {}", dst.display(), e);
+                        continue;
+                    }
+                }
+
+                if let Err(e) = std::os::unix::fs::symlink(&src, &dst) {
+                    error!("Failed to create symlink {} -> {}: {}", src.display(), dst.display(), e);
+                    continue;
+                }
+
+                info!("Created symlink: {} -> {}", dst.display(), src.display());
+            }
+        }
+
+    if prompter.confirm("reload-restart", "Reload systemd and restart the services?", true)? {
+        systemctl_cmd(is_root).arg("daemon-reload").status()?;
+        info!("systemctl-daemon reloaded!");
+
+        for path in files.iter().filter(|f| f.extension().is_some_and(|ext| ext == "target")) {
+            let target = cwd.join(path.file_name().context("Failed to get filename from path")?);
+            systemctl_cmd(is_root).arg("enable").arg(&target).status()?;
+        }
+        // Templates like notify-failure@.service have nothing to enable, only to link
+        for path in files.iter().filter(|f| f.extension().is_some_and(|ext| ext == "service")) {
+            let service = cwd.join(path.file_name().context("Failed to get filename from path")?);
+            systemctl_cmd(is_root).arg("link").arg(&service).status()?;
+        }
+
+        let mut services = Vec::new();
+        for path in &files {
+            let stem = path.file_stem()
+                .and_then(|s| s.to_str())
+                .context("Failed to get unit file stem")?;
+
+            // Pods and kube units start everything else in them
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some(extension @ ("pod" | "kube")) => services.extend(service_name(&format!("{stem}.{extension}"))),
+                Some("container") => {
+                    let in_pod = read_unit(&cwd.join(format!("{stem}.container")))
+                        .is_ok_and(|unit| unit.get("Container").is_some_and(|c| c.contains_key("Pod")));
+                    if !in_pod {
+                        services.push(format!("{stem}.service"));
+                    }
+                }
+                _ => {}
+            }
+        }
+        let paths: Vec<PathBuf> = files.iter().filter_map(|f| Some(cwd.join(f.file_name()?))).collect();
+        restart_or_roll_back(&services, &paths, prompter)?;
+    }
+
+    enable_auto_update(&files, prompter)?;
+    Ok(())
+}
+
+const AUTO_UPDATE_TIMER: &str = "podman-auto-update.timer";
+
+/// Offer to enable podman's auto-update timer if any of `files` sets AutoUpdate=, which does
+/// nothing without it. A value preset for `auto-update-timer`, such as `weekly`, replaces the
+/// timer's daily schedule with a drop-in.
+fn enable_auto_update(files: &[PathBuf], prompter: &mut dyn Prompter) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let auto_updated = files
+        .iter()
+        .filter(|f| f.extension().and_then(|ext| ext.to_str()) == Some("container"))
+        .filter_map(|f| read_unit(&cwd.join(f.file_name()?)).ok())
+        .any(|unit| unit.get("Container").is_some_and(|c| c.contains_key("AutoUpdate")));
+    if !auto_updated
+        || !prompter.confirm(
+            "auto-update-timer",
+            &format!("Enable {AUTO_UPDATE_TIMER} so containers with AutoUpdate= are updated?"),
+            true,
+        )?
+    {
+        return Ok(());
+    }
+
+    let is_root = is_root();
+    if let Some(schedule) = prompter.preset("auto-update-timer") {
+        let drop_in_dir = systemd_unit_dir(is_root)?.join(format!("{AUTO_UPDATE_TIMER}.d"));
+        std::fs::create_dir_all(&drop_in_dir)?;
+        std::fs::write(drop_in_dir.join("slate.conf"), timer_schedule(schedule))?;
+        systemctl_cmd(is_root).arg("daemon-reload").status()?;
+        info!("Scheduled {AUTO_UPDATE_TIMER} for {schedule}");
+    }
+    systemctl_cmd(is_root).args(["enable", "--now", AUTO_UPDATE_TIMER]).status()?;
+    Ok(())
+}
+
+// An empty OnCalendar= clears the timer's own schedule, which would otherwise still apply
+fn timer_schedule(schedule: &str) -> String {
+    format!("[Timer]\nOnCalendar=\nOnCalendar={schedule}\n")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::prompt::{DefaultPrompter, PresetPrompter, ScriptedPrompter};
+    use crate::utils::enter_test_dir;
+
+    use super::*;
+    use std::{io::Write};
+
+    fn setup_quadlets() -> IniFiles {
+        let input = r#"
+# bookstack-app.container
+[Unit]
+Requires=bookstack-db.service
+After=bookstack-db.service
+
+[Container]
+Image=lscr.io/linuxserver/bookstack
+Pod=bookstack.pod
+
+[Service]
+Restart=always
+
+---
+
+# bookstack-db.container
+[Container]
+Image=lscr.io/linuxserver/mariadb
+Pod=bookstack.pod
+
+[Service]
+Restart=always
+
+---
+
+# bookstack.pod
 [Pod]
 PublishPort=127.0.0.1:11004:80
 "#;
@@ -501,7 +2577,7 @@ PublishPort=127.0.0.1:11004:80
         let mut env_file = std::fs::File::create(&env_path).unwrap();
         writeln!(env_file, "TEST_VAR=123").unwrap();
 
-        let processed_quadlets = process_quadlets(quadlets, Some(&dir)).unwrap();
+        let processed_quadlets = process_quadlets(quadlets, Some(&dir), &mut DefaultPrompter).unwrap();
         for (name, i) in processed_quadlets.0 {
             insta::assert_snapshot!(
                 format!("process_quadlets_{}", name),
@@ -510,6 +2586,664 @@ PublishPort=127.0.0.1:11004:80
         }
     }
 
+    #[test]
+    fn test_standalone_container_wanted() {
+        let units = parse_raw_quadlets("# web.container\n[Container]\nImage=nginx\nPublishPort=80:80\n").unwrap();
+        let units = process_quadlets(units, None, &mut DefaultPrompter).unwrap();
+        assert_eq!(units.0["web.container"].0["Install"]["WantedBy"], "default.target");
+
+        let units = process_quadlets(setup_quadlets(), None, &mut DefaultPrompter).unwrap();
+        assert!(!units.0["bookstack-app.container"].0.contains_key("Install"));
+    }
+
+    #[test]
+    fn test_process_compose_prompts() {
+        let file: ComposeFile = serde_yaml::from_str("services:\n  web:\n    ports: ['80:80']\n").unwrap();
+        let mut prompter = ScriptedPrompter::new([true]);
+        let file = process_compose(file, None, &mut prompter).unwrap();
+        assert!(file.services.contains_key("app"));
+
+        let file: ComposeFile = serde_yaml::from_str("services:\n  web:\n    ports: ['80:80']\n").unwrap();
+        assert!(process_compose(file, None, &mut prompter).is_err());
+    }
+
+    #[test]
+    fn test_extract_builds() {
+        let input = r#"
+services:
+  web:
+    build:
+      context: ./web
+      dockerfile: Containerfile
+      args:
+        VERSION: "1.2"
+        GREETING: hello world
+  worker:
+    build: ./worker
+    image: localhost/worker:dev
+"#;
+        let mut file: ComposeFile = serde_yaml::from_str(input).unwrap();
+        let units = extract_builds(&mut file, Some(Path::new("/srv/app")), &mut DefaultPrompter).unwrap();
+
+        let web = &units.0["web.build"].0["Build"];
+        assert_eq!(web["ImageTag"], "localhost/web");
+        assert_eq!(web["File"], "/srv/app/web/Containerfile");
+        assert_eq!(web["BuildArg"], r#"VERSION=1.2 "GREETING=hello world""#);
+        assert_eq!(web["SetWorkingDirectory"], "/srv/app/web");
+        assert_eq!(units.0["worker.build"].0["Build"]["ImageTag"], "localhost/worker:dev");
+
+        assert_eq!(file.services["web"]["image"], "web.build");
+        assert!(file.services["web"].get("build").is_none());
+    }
+
+    #[test]
+    fn test_add_image_units() {
+        let mut units = IniFiles::new();
+        let images = [
+            ("a.container", "docker.io/library/nginx:1.25"),
+            ("b.container", "docker.io/library/nginx:1.25"),
+            ("c.container", "ghcr.io/x/nginx:1.25"),
+            ("d.container", "d.build"),
+        ];
+        for (name, image) in images {
+            let mut unit = Ini::new();
+            unit.0.entry("Container".to_string()).or_default().insert("Image".to_string(), image.to_string());
+            units.insert(name.to_string(), unit);
+        }
+        add_image_units(&mut units, true, Some("arm64"));
+
+        let image = |name: &str| units.0[name].0["Container"]["Image"].clone();
+        assert_eq!(image("a.container"), "nginx-1.25.image");
+        assert_eq!(image("b.container"), "nginx-1.25.image");
+        assert_eq!(image("c.container"), "nginx-1.25-2.image");
+        assert_eq!(image("d.container"), "d.build");
+
+        let pull = &units.0["nginx-1.25-2.image"].0["Image"];
+        assert_eq!(pull["Image"], "ghcr.io/x/nginx:1.25");
+        assert_eq!(pull["AllTags"], "true");
+        assert_eq!(pull["Arch"], "arm64");
+    }
+
+    #[test]
+    fn test_restart_policies() {
+        let input = r#"
+services:
+  web:
+    image: nginx
+    restart: on-failure:3
+    deploy:
+      restart_policy:
+        delay: 5s
+        window: 2m
+  db:
+    image: postgres
+    restart: always
+    deploy:
+      replicas: 1
+      restart_policy:
+        condition: none
+"#;
+        let mut file: ComposeFile = serde_yaml::from_str(input).unwrap();
+        let mut prompter = ScriptedPrompter::new([true, false, false]);
+        let policies = extract_restart_policies(&mut file, &mut prompter).unwrap();
+
+        assert_eq!(policies["db"], vec![("Service", "Restart", "always".to_string())]);
+        assert_eq!(
+            policies["web"],
+            vec![
+                ("Service", "Restart", "on-failure".to_string()),
+                ("Unit", "StartLimitBurst", "3".to_string()),
+                ("Service", "RestartSec", "5s".to_string()),
+                ("Unit", "StartLimitIntervalSec", "2m".to_string()),
+            ]
+        );
+        assert!(file.services["web"].get("deploy").is_none());
+        assert_eq!(file.services["db"]["deploy"]["replicas"], 1);
+
+        let mut units = IniFiles::new();
+        units.insert("web.container".to_string(), Ini::new());
+        add_directives(&mut units, policies);
+        assert_eq!(units.0["web.container"].0["Unit"]["StartLimitBurst"], "3");
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        let input = r#"
+services:
+  web:
+    image: nginx
+    mem_limit: 1.5g
+    cpus: 0.5
+    deploy:
+      resources:
+        limits:
+          pids: 100
+        reservations:
+          memory: 256m
+"#;
+        let mut file: ComposeFile = serde_yaml::from_str(input).unwrap();
+        let limits = extract_resource_limits(&mut file, LimitsIn::Service).unwrap();
+        assert_eq!(
+            limits["web"],
+            vec![
+                ("Service", "MemoryMax", "1536M".to_string()),
+                ("Service", "CPUQuota", "50%".to_string()),
+                ("Service", "TasksMax", "100".to_string()),
+                ("Service", "MemoryLow", "256M".to_string()),
+            ]
+        );
+        assert_eq!(serde_yaml::to_string(&file.services["web"]).unwrap(), "image: nginx\n");
+
+        let mut file: ComposeFile = serde_yaml::from_str(input).unwrap();
+        let limits = extract_resource_limits(&mut file, LimitsIn::Container).unwrap();
+        let mut unit = Ini::new();
+        unit.0.entry("Container".to_string()).or_default().insert("PodmanArgs".to_string(), "--init".to_string());
+        let mut units = IniFiles::new();
+        units.insert("web.container".to_string(), unit);
+        add_directives(&mut units, limits);
+        let container = &units.0["web.container"].0["Container"];
+        assert_eq!(container["Memory"], "1536M");
+        assert_eq!(container["PidsLimit"], "100");
+        assert_eq!(container["PodmanArgs"], "--init --cpus=0.5 --memory-reservation=256M");
+
+        assert_eq!(memory_size("1048576").as_deref(), Some("1M"));
+        assert_eq!(memory_size("100kb").as_deref(), Some("100K"));
+        assert_eq!(memory_size("lots"), None);
+    }
+
+    #[test]
+    fn test_security_options() {
+        let input = r#"
+services:
+  web:
+    image: nginx
+    cap_add: [NET_ADMIN, SYS_TIME]
+    cap_drop: ALL
+    security_opt:
+      - label=type:container_runtime_t
+      - no-new-privileges:true
+      - seccomp=unconfined
+      - apparmor=unconfined
+    read_only: true
+    userns_mode: keep-id
+"#;
+        let mut file: ComposeFile = serde_yaml::from_str(input).unwrap();
+        let options = extract_security_options(&mut file);
+        let get = |key: &str| options["web"].iter().find(|(_, k, _)| *k == key).map(|(_, _, v)| v.as_str());
+        assert_eq!(get("AddCapability"), Some("NET_ADMIN SYS_TIME"));
+        assert_eq!(get("DropCapability"), Some("ALL"));
+        assert_eq!(get("SecurityLabelType"), Some("container_runtime_t"));
+        assert_eq!(get("NoNewPrivileges"), Some("true"));
+        assert_eq!(get("SeccompProfile"), Some("unconfined"));
+        assert_eq!(get("PodmanArgs"), Some("--security-opt=apparmor=unconfined"));
+        assert_eq!(get("ReadOnly"), Some("true"));
+        assert_eq!(get("UserNS"), Some("keep-id"));
+        assert_eq!(serde_yaml::to_string(&file.services["web"]).unwrap(), "image: nginx\n");
+    }
+
+    #[test]
+    fn test_extract_devices() {
+        let input = r#"
+services:
+  ml:
+    image: pytorch
+    devices:
+      - /dev/dri:/dev/dri
+    deploy:
+      resources:
+        limits:
+          memory: 8g
+        reservations:
+          devices:
+            - driver: nvidia
+              count: 2
+              capabilities: [gpu]
+"#;
+        let mut file: ComposeFile = serde_yaml::from_str(input).unwrap();
+        let devices = extract_devices(&mut file);
+        assert_eq!(
+            devices["ml"],
+            vec![
+                ("Container", "AddDevice", "/dev/dri:/dev/dri".to_string()),
+                ("Container", "PodmanArgs", "--device=nvidia.com/gpu=0 --device=nvidia.com/gpu=1".to_string()),
+            ]
+        );
+        assert_eq!(file.services["ml"]["deploy"]["resources"]["limits"]["memory"], "8g");
+
+        let all: Value = serde_yaml::from_str("capabilities: [gpu]").unwrap();
+        assert_eq!(cdi_devices(&all), Some(vec!["nvidia.com/gpu=all".to_string()]));
+        let tpu: Value = serde_yaml::from_str("driver: tpu").unwrap();
+        assert_eq!(cdi_devices(&tpu), None);
+    }
+
+    #[test]
+    fn test_extract_mounts() {
+        let input = r#"
+services:
+  web:
+    image: nginx
+    tmpfs: /run
+    shm_size: 256m
+    volumes:
+      - /srv/html:/usr/share/nginx/html
+      - type: bind
+        source: /srv/conf
+        target: /etc/nginx
+        read_only: true
+        bind:
+          propagation: rslave
+          selinux: z
+      - type: volume
+        source: cache
+        target: /var/cache/nginx
+        volume:
+          nocopy: true
+      - type: tmpfs
+        target: /tmp
+        tmpfs:
+          size: 1000000
+      - type: image
+        source: docker.io/library/alpine
+        target: /alpine
+"#;
+        let mut file: ComposeFile = serde_yaml::from_str(input).unwrap();
+        let mounts = extract_mounts(&mut file);
+        assert_eq!(
+            mounts["web"],
+            vec![
+                ("Container", "Tmpfs", "/run".to_string()),
+                ("Container", "PodmanArgs", "--tmpfs=/tmp:size=1000000".to_string()),
+                ("Container", "Mount", "type=image,source=docker.io/library/alpine,destination=/alpine".to_string()),
+                ("Container", "ShmSize", "256m".to_string()),
+            ]
+        );
+        let volumes: Vec<&str> =
+            file.services["web"]["volumes"].as_sequence().unwrap().iter().map(|v| v.as_str().unwrap()).collect();
+        assert_eq!(
+            volumes,
+            ["/srv/html:/usr/share/nginx/html", "/srv/conf:/etc/nginx:ro,rslave,z", "cache:/var/cache/nginx:nocopy"]
+        );
+    }
+
+    #[test]
+    fn test_bind_mount() {
+        let mount = |s: &str| bind_mount(&Value::String(s.to_string()));
+        assert_eq!(mount("./data:/data:ro,z"), Some(("./data".into(), "/data".into(), vec!["ro".into(), "z".into()])));
+        assert_eq!(mount("/srv:/srv"), Some(("/srv".into(), "/srv".into(), vec![])));
+        assert_eq!(mount("cache:/var/cache"), None);
+    }
+
+    #[test]
+    fn test_kernel_settings() {
+        let input = r#"
+services:
+  search:
+    image: elasticsearch
+    sysctls:
+      vm.max_map_count: 262144
+      net.core.somaxconn: 1024
+    ulimits:
+      nproc: 65535
+      memlock:
+        soft: -1
+        hard: -1
+"#;
+        let mut file: ComposeFile = serde_yaml::from_str(input).unwrap();
+        let settings = extract_kernel_settings(&mut file);
+        assert_eq!(
+            settings["search"],
+            vec![
+                ("Container", "Sysctl", "vm.max_map_count=262144 net.core.somaxconn=1024".to_string()),
+                ("Container", "Ulimit", "nproc=65535".to_string()),
+                ("Container", "PodmanArgs", "--ulimit=memlock=-1:-1".to_string()),
+            ]
+        );
+        assert!(file.services["search"].get("ulimits").is_none());
+    }
+
+    #[test]
+    fn test_network_settings() {
+        let input = r#"
+services:
+  web:
+    image: nginx
+    networks:
+      back:
+        priority: 1
+      front:
+        priority: 10
+        ipv4_address: 172.20.0.5
+        aliases: [www, site]
+networks:
+  front:
+    ipam:
+      config:
+        - subnet: 172.20.0.0/16
+          gateway: 172.20.0.1
+  back: {}
+"#;
+        let mut file: ComposeFile = serde_yaml::from_str(input).unwrap();
+        let settings = extract_network_settings(&mut file);
+        assert_eq!(settings.attach["front"], ["ip=172.20.0.5", "alias=www", "alias=site"]);
+        assert_eq!(
+            serde_yaml::to_string(&file.services["web"]["networks"]).unwrap(),
+            "front: null\nback: null\n"
+        );
+
+        let raw = "# web.pod\n[Pod]\nNetwork=front.network\n\n---\n\n# front.network\n[Network]\nDriver=bridge\n";
+        let mut units = parse_raw_quadlets(raw).unwrap();
+        add_network_settings(&mut units, settings);
+        assert_eq!(units.0["web.pod"].0["Pod"]["Network"], "front.network:ip=172.20.0.5,alias=www,alias=site");
+        assert_eq!(units.0["front.network"].0["Network"]["Subnet"], "172.20.0.0/16");
+        assert_eq!(units.0["front.network"].0["Network"]["Gateway"], "172.20.0.1");
+    }
+
+    #[test]
+    fn test_pod_containers() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("app-web.container"), "[Container]\nImage=nginx\nPod=app.pod\n").unwrap();
+        std::fs::write(dir.path().join("app-db.container"), "[Container]\nImage=postgres\nPod=app.pod\n").unwrap();
+        std::fs::write(dir.path().join("other.container"), "[Container]\nImage=redis\nPod=other.pod\n").unwrap();
+        std::fs::write(dir.path().join("app.pod"), "[Pod]\n").unwrap();
+
+        assert_eq!(pod_containers(dir.path(), "app").unwrap(), ["app-db.container", "app-web.container"]);
+    }
+
+    #[test]
+    fn test_roll_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let web = dir.path().join("web.container");
+        let db = dir.path().join("db.container");
+        std::fs::write(&web, "[Container]\nImage=nginx:1.24\n").unwrap();
+
+        for (path, contents) in [(&web, "[Container]\nImage=nginx:1.25\n"), (&db, "[Container]\nImage=postgres\n")] {
+            back_up(path.file_name().unwrap().to_str().unwrap(), path).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        std::os::unix::fs::symlink(&db, target.path().join("db.container")).unwrap();
+
+        let removed = roll_back(&[web.clone(), db.clone()], target.path()).unwrap();
+        assert_eq!(removed, ["db.container"]);
+        assert_eq!(std::fs::read_to_string(&web).unwrap(), "[Container]\nImage=nginx:1.24\n");
+        assert!(!db.exists());
+        assert!(!target.path().join("db.container").is_symlink());
+
+        // Nothing is left to roll back to a second time
+        assert!(roll_back(std::slice::from_ref(&web), target.path()).unwrap().is_empty());
+        assert!(web.exists());
+    }
+
+    #[test]
+    fn test_timer_schedule() {
+        assert_eq!(timer_schedule("Sun 03:00"), "[Timer]\nOnCalendar=\nOnCalendar=Sun 03:00\n");
+    }
+
+    #[test]
+    fn test_add_target() {
+        let mut units = setup_quadlets();
+        units.0.get_mut("bookstack.pod").unwrap().0.entry("Install".to_string()).or_default()
+            .insert("WantedBy".to_string(), "default.target".to_string());
+        add_target(&mut units, "bookstack");
+
+        let target = &units.0["bookstack.target"].0;
+        assert_eq!(target["Unit"]["Wants"], "bookstack-app.service bookstack-db.service bookstack-pod.service");
+        assert_eq!(target["Install"]["WantedBy"], "default.target");
+        let pod = &units.0["bookstack.pod"].0;
+        assert_eq!(pod["Install"]["WantedBy"], "bookstack.target");
+        assert_eq!(pod["Unit"]["PartOf"], "bookstack.target");
+    }
+
+    #[test]
+    fn test_prefix_units() {
+        let mut units = setup_quadlets();
+        units.0.get_mut("bookstack-db.container").unwrap().0.get_mut("Container").unwrap()
+            .insert("ContainerName".to_string(), "mariadb".to_string());
+        prefix_units(&mut units, "prod-");
+
+        let mut names: Vec<&String> = units.0.keys().collect();
+        names.sort();
+        assert_eq!(names, ["prod-bookstack-app.container", "prod-bookstack-db.container", "prod-bookstack.pod"]);
+        let app = &units.0["prod-bookstack-app.container"].0;
+        assert_eq!(app["Unit"]["Requires"], "prod-bookstack-db.service");
+        assert_eq!(app["Container"]["Pod"], "prod-bookstack.pod");
+        assert_eq!(app["Container"]["ContainerName"], "prod-bookstack-app");
+        assert_eq!(app["Container"]["Image"], "lscr.io/linuxserver/bookstack");
+        assert_eq!(units.0["prod-bookstack-db.container"].0["Container"]["ContainerName"], "prod-mariadb");
+
+        let mut file: ComposeFile = serde_yaml::from_str("name: wiki\nservices:\n  app:\n    image: nginx\n").unwrap();
+        set_project_name(&mut file, Some("docs"));
+        assert_eq!(file.other["name"], "docs");
+    }
+
+    #[test]
+    fn test_port_conflicts() {
+        assert_eq!(host_ports("127.0.0.1:11004:80"), [("127.0.0.1".to_string(), 11004, "tcp".to_string())]);
+        assert_eq!(host_ports("0.0.0.0:53:53/udp"), [(String::new(), 53, "udp".to_string())]);
+        assert_eq!(host_ports("[::1]:8080:80").len(), 1);
+        assert_eq!(host_ports("8000-8002:8000-8002").len(), 3);
+        assert!(host_ports("80").is_empty());
+
+        let mut units = setup_quadlets();
+        let web: Ini = serde_ini::from_str("[Container]\nImage=nginx\nPublishPort=11004:80\nPodmanArgs=--publish=443:443\n").unwrap();
+        units.insert("web.container".to_string(), web);
+        let mut publishers: Vec<Publisher> =
+            units.0.iter().map(|(name, unit)| (format!("'{name}'"), true, published_ports(unit))).collect();
+        publishers.sort_by(|a, b| a.0.cmp(&b.0));
+        publishers.push(("a process on the host".to_string(), false, vec![(String::new(), 443, "tcp".to_string())]));
+
+        assert_eq!(
+            port_conflicts(&publishers),
+            [
+                "Host port 11004/tcp is published by 'bookstack.pod' and by 'web.container'",
+                "Host port 443/tcp is published by 'web.container' and by a process on the host",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resource_references() {
+        let mut units = setup_quadlets();
+        let web: Ini = serde_ini::from_str(
+            "[Container]\nImage=web.build\nNetwork=proxy:alias=web\nSecret=db-password,type=env,target=DB_PASSWORD\nPodmanArgs=--secret=api-key --network=host\n",
+        )
+        .unwrap();
+        units.insert("web.container".to_string(), web);
+        let mut pod = Ini::new();
+        pod.insert("Pod".to_string(), Section::from([("Network".to_string(), "front.network".to_string())]));
+        units.insert("bookstack.pod".to_string(), pod);
+
+        let references = resource_references(&units);
+        let kinds: Vec<(&str, &str)> = references.iter().map(|(kind, name, _)| (*kind, name.as_str())).collect();
+        assert_eq!(
+            kinds,
+            [
+                ("image", "lscr.io/linuxserver/bookstack"),
+                ("image", "lscr.io/linuxserver/mariadb"),
+                ("network", "proxy"),
+                ("secret", "db-password"),
+                ("secret", "api-key"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verify_command() {
+        let args = |cmd: Command| -> Vec<String> {
+            std::iter::once(cmd.get_program()).chain(cmd.get_args()).map(|a| a.to_string_lossy().into_owned()).collect()
+        };
+        let cosign = verify_command("quay.io/app:1", Some(Path::new("cosign.pub")), None, Path::new("/tmp/x"));
+        assert_eq!(args(cosign), ["cosign", "verify", "--key", "cosign.pub", "quay.io/app:1"]);
+        let skopeo = verify_command("quay.io/app:1", None, Some(Path::new("policy.json")), Path::new("/tmp/x"));
+        assert_eq!(args(skopeo), ["skopeo", "--policy", "policy.json", "copy", "docker://quay.io/app:1", "dir:/tmp/x"]);
+    }
+
+    #[test]
+    fn test_registry_auth() {
+        assert_eq!(registry_of("nginx"), "docker.io");
+        assert_eq!(registry_of("library/nginx:1.25"), "docker.io");
+        assert_eq!(registry_of("ghcr.io/owner/app"), "ghcr.io");
+        assert_eq!(registry_of("localhost:5000/app"), "localhost:5000");
+
+        let auth = serde_json::json!({"auths": {"https://index.docker.io/v1/": {}, "ghcr.io": {"auth": "x"}}});
+        assert!(has_credentials(&auth, "docker.io"));
+        assert!(has_credentials(&auth, "ghcr.io"));
+        assert!(!has_credentials(&auth, "quay.io"));
+        assert!(has_credentials(&serde_json::json!({"credHelpers": {"quay.io": "pass"}}), "quay.io"));
+
+        assert!(requires_auth("Error: reading manifest: unauthorized: authentication required"));
+        assert!(!requires_auth("Error: manifest unknown"));
+    }
+
+    #[test]
+    fn test_service_name() {
+        assert_eq!(service_name("web.container").as_deref(), Some("web.service"));
+        assert_eq!(service_name("app.pod").as_deref(), Some("app-pod.service"));
+        assert_eq!(service_name("front.network").as_deref(), Some("front-network.service"));
+        assert_eq!(service_name("app.yaml"), None);
+    }
+
+    #[test]
+    fn test_merge_unit() {
+        let base: Ini = serde_ini::from_str("[Container]\nImage=nginx:1.24\nAutoUpdate=registry\nPublishPort=80:80\n").unwrap();
+        let deployed: Ini = serde_ini::from_str(
+            "[Container]\nImage=nginx:1.24\nAutoUpdate=local\nPublishPort=8080:80\n\n[Service]\nTimeoutStartSec=900\n",
+        )
+        .unwrap();
+        let generated: Ini = serde_ini::from_str("[Container]\nImage=nginx:1.25\nAutoUpdate=registry\nPublishPort=9090:80\n").unwrap();
+
+        let mut prompter = ScriptedPrompter::new([false]);
+        let merged = merge_unit("web.container", &base, &deployed, &generated, &mut prompter).unwrap();
+        // Updated by slate, changed by hand, then a conflict resolved in favour of slate
+        assert_eq!(merged.0["Container"]["Image"], "nginx:1.25");
+        assert_eq!(merged.0["Container"]["AutoUpdate"], "local");
+        assert_eq!(merged.0["Container"]["PublishPort"], "9090:80");
+        assert_eq!(merged.0["Service"]["TimeoutStartSec"], "900");
+
+        // Keys slate no longer generates are removed unless changed by hand
+        let generated: Ini = serde_ini::from_str("[Container]\nImage=nginx:1.24\n").unwrap();
+        let merged = merge_unit("web.container", &base, &base, &generated, &mut DefaultPrompter).unwrap();
+        assert_eq!(merged.0["Container"].len(), 1);
+    }
+
+    #[test]
+    fn test_init_containers() {
+        let input = r#"
+services:
+  web:
+    image: nginx
+    volumes: ["./html:/usr/share/nginx/html"]
+    x-init:
+      - chown -R 101 /usr/share/nginx/html
+    depends_on:
+      db: {condition: service_started}
+      migrate: {condition: service_completed_successfully}
+  migrate:
+    image: app
+    restart: always
+  db:
+    image: postgres
+"#;
+        let mut file: ComposeFile = serde_yaml::from_str(input).unwrap();
+        let directives = extract_init_containers(&mut file).unwrap();
+
+        let init = &file.services["web-init"];
+        assert_eq!(init["command"], "chown -R 101 /usr/share/nginx/html");
+        assert_eq!(init["volumes"][0], "./html:/usr/share/nginx/html");
+        assert!(init.get("x-init").is_none());
+        assert_eq!(file.services["web"]["depends_on"], serde_yaml::from_str::<Value>("[db, migrate, web-init]").unwrap());
+        assert!(file.services["migrate"].get("restart").is_none());
+
+        let mut inits: Vec<&String> = directives.keys().collect();
+        inits.sort();
+        assert_eq!(inits, ["migrate", "web-init"]);
+        assert_eq!(directives["migrate"][0], ("Service", "Type", "oneshot".to_string()));
+
+        let mut units = setup_quadlets();
+        add_directives(&mut units, HashMap::from([("db".to_string(), init_directives())]));
+        assert_eq!(units.0["bookstack-db.container"].0["Service"]["Type"], "oneshot");
+    }
+
+    #[test]
+    fn test_replicas() {
+        let input = r#"
+services:
+  app:
+    image: nginx
+    ports: ["127.0.0.1:8080:80", {target: 443, published: 8443, protocol: tcp}]
+    deploy:
+      replicas: 2
+  db:
+    image: mariadb
+    deploy:
+      replicas: 1
+"#;
+        let mut file: ComposeFile = serde_yaml::from_str(input).unwrap();
+        let replicas = extract_replicas(&mut file);
+        assert_eq!(replicas["app"], Replicas { count: 2, ports: vec!["127.0.0.1:8080:80".to_string(), "8443:443/tcp".to_string()] });
+        assert!(!replicas.contains_key("db"));
+        assert!(file.services["app"].get("ports").is_none());
+        assert!(file.services["db"].get("deploy").is_none());
+
+        let mut units = setup_quadlets();
+        units.0.get_mut("bookstack.pod").unwrap().0.get_mut("Pod").unwrap()
+            .insert("Network".to_string(), "front.network".to_string());
+        add_replicas(&mut units, replicas);
+
+        assert!(!units.0.contains_key("bookstack-app.container"));
+        let template = &units.0["bookstack-app@.container"].0["Container"];
+        assert_eq!(template.get("Pod"), None);
+        assert_eq!(template["Network"], "front.network");
+        assert_eq!(template["ContainerName"], "%p-%i");
+        let second = &units.0["bookstack-app@2.container"].0;
+        assert_eq!(second["Container"]["PublishPort"], "127.0.0.1:8081:80");
+        assert_eq!(second["Container"]["PodmanArgs"], "--publish=8444:443/tcp");
+        assert_eq!(second["Install"]["WantedBy"], "default.target");
+        assert_eq!(service_name("bookstack-app@.container"), None);
+
+        assert_eq!(offset_port("9000-9002:9000-9002", 3), "9003-9005:9000-9002");
+        assert_eq!(offset_port("80", 1), "80");
+    }
+
+    #[test]
+    fn test_overrides() {
+        let input = r#"
+services:
+  web:
+    image: nginx
+    x-slate:
+      Container:
+        AutoUpdate: null
+        Label: [a=1, b=2]
+      Install:
+        WantedBy: multi-user.target
+"#;
+        let mut file: ComposeFile = serde_yaml::from_str(input).unwrap();
+        let overrides = extract_overrides(&mut file).unwrap();
+        assert!(file.services["web"].get("x-slate").is_none());
+
+        let mut units = setup_quadlets();
+        units.0.insert("web.container".to_string(), Ini::new());
+        units.0.get_mut("web.container").unwrap().0.entry("Container".to_string()).or_default().insert("AutoUpdate".to_string(), "registry".to_string());
+        apply_overrides(&mut units, overrides);
+        let web = &units.0["web.container"].0;
+        assert_eq!(web["Container"].get("AutoUpdate"), None);
+        assert_eq!(web["Container"]["Label"], "a=1 b=2");
+        assert_eq!(web["Install"]["WantedBy"], "multi-user.target");
+
+        let mut file: ComposeFile = serde_yaml::from_str("services:\n  web:\n    x-slate: [1]\n").unwrap();
+        assert!(extract_overrides(&mut file).is_err());
+    }
+
+    #[test]
+    fn test_preset_answers() {
+        let input = "services:\n  web:\n    ports: ['80:80']\nx-slate:\n  answers:\n    rename-to-app: false\n";
+        let file: ComposeFile = serde_yaml::from_str(input).unwrap();
+        let mut prompter = PresetPrompter::new(ScriptedPrompter::new([true]), preset_answers(&file).unwrap());
+        let file = process_compose(file, None, &mut prompter).unwrap();
+        assert!(file.services.contains_key("web"));
+    }
+
     #[test]
     fn test_parse_qualified_name() {
         let input = r#"[
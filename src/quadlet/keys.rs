@@ -0,0 +1,309 @@
+use std::{fmt, process::Command};
+
+use crate::{doctor::parse_version, formats::IniFiles, utils::closest_match};
+
+/// A podman version, as major and minor
+pub type Version = (u32, u32);
+
+// The keys quadlet accepts in each section, grouped by the podman release that added them
+const KEYS: &[(&str, Version, &[&str])] = &[
+    ("Container", (4, 4), &[
+        "AddCapability", "AddDevice", "Annotation", "AutoUpdate", "ContainerName", "DropCapability",
+        "Environment", "EnvironmentFile", "EnvironmentHost", "Exec", "ExposeHostPort", "Group",
+        "HealthCmd", "HealthInterval", "HealthOnFailure", "HealthRetries", "HealthStartPeriod",
+        "HealthStartupCmd", "HealthStartupInterval", "HealthStartupRetries", "HealthStartupSuccess",
+        "HealthStartupTimeout", "HealthTimeout", "IP", "IP6", "Image", "Label", "LogDriver", "Mount",
+        "Network", "NoNewPrivileges", "Notify", "PodmanArgs", "PublishPort", "ReadOnly", "RunInit",
+        "SeccompProfile", "Secret", "SecurityLabelDisable", "SecurityLabelFileType",
+        "SecurityLabelLevel", "SecurityLabelType", "Timezone", "Tmpfs", "User", "UserNS",
+        "VolatileTmp", "Volume",
+    ]),
+    ("Container", (4, 5), &["HostName", "Rootfs", "Sysctl", "Ulimit", "WorkingDir"]),
+    ("Container", (4, 6), &["DNS", "DNSOption", "DNSSearch", "SecurityLabelNested"]),
+    ("Container", (4, 7), &[
+        "ContainersConfModule", "GlobalArgs", "Mask", "PidsLimit", "ReadOnlyTmpfs", "ShmSize", "Unmask",
+    ]),
+    ("Container", (4, 8), &["Entrypoint", "GIDMap", "Pull", "StopTimeout", "SubGIDMap", "SubUIDMap", "UIDMap"]),
+    ("Container", (5, 0), &["AddHost", "GroupAdd", "Pod", "Memory"]),
+    ("Container", (5, 1), &["CgroupsMode", "LogOpt", "ReloadCmd", "ReloadSignal", "ServiceName", "StartWithPod"]),
+    ("Container", (5, 2), &["NetworkAlias", "Retry", "RetryDelay", "StopSignal"]),
+    ("Pod", (5, 0), &[
+        "ContainersConfModule", "GIDMap", "GlobalArgs", "IP", "IP6", "Network", "PodName", "PodmanArgs",
+        "PublishPort", "SubGIDMap", "SubUIDMap", "UIDMap", "UserNS", "Volume",
+    ]),
+    ("Pod", (5, 1), &["AddHost", "NetworkAlias", "ServiceName"]),
+    ("Pod", (5, 2), &["DNS", "DNSOption", "DNSSearch", "HostName", "ShmSize"]),
+    ("Network", (4, 4), &[
+        "DisableDNS", "Driver", "Gateway", "IPAMDriver", "IPRange", "IPv6", "Internal", "Label",
+        "Options", "PodmanArgs", "Subnet",
+    ]),
+    ("Network", (4, 7), &["ContainersConfModule", "DNS", "GlobalArgs", "NetworkName"]),
+    ("Network", (5, 3), &["NetworkDeleteOnStop"]),
+    ("Volume", (4, 4), &["Copy", "Device", "Group", "Label", "Options", "Type", "User"]),
+    ("Volume", (4, 7), &["ContainersConfModule", "Driver", "GlobalArgs", "Image", "PodmanArgs", "VolumeName"]),
+    ("Kube", (4, 4), &["ConfigMap", "LogDriver", "Network", "PublishPort", "UserNS", "Yaml"]),
+    ("Kube", (4, 5), &["AutoUpdate"]),
+    ("Kube", (4, 7), &["ContainersConfModule", "ExitCodePropagation", "GlobalArgs", "PodmanArgs", "SetWorkingDirectory"]),
+    ("Kube", (5, 0), &["KubeDownForce"]),
+    ("Image", (4, 8), &[
+        "AllTags", "Arch", "AuthFile", "CertDir", "ContainersConfModule", "Creds", "DecryptionKey",
+        "GlobalArgs", "Image", "OS", "PodmanArgs", "TLSVerify", "Variant",
+    ]),
+    ("Image", (5, 0), &["ImageTag"]),
+    ("Build", (5, 0), &[
+        "Annotation", "Arch", "AuthFile", "ContainersConfModule", "DNS", "DNSOption", "DNSSearch",
+        "Environment", "File", "ForceRM", "GlobalArgs", "GroupAdd", "ImageTag", "Label", "Network",
+        "PodmanArgs", "Pull", "Secret", "SetWorkingDirectory", "Target", "TLSVerify", "Variant", "Volume",
+    ]),
+    ("Build", (5, 4), &["BuildArg", "IgnoreFile"]),
+];
+
+// Sections systemd itself handles, which quadlet passes through to the service
+const SYSTEMD_SECTIONS: &[&str] = &["Unit", "Service", "Install", "Quadlet"];
+
+/// Something quadlet would reject in a generated unit
+#[derive(Debug, PartialEq)]
+pub enum Invalid {
+    Section { unit: String, section: String, expected: &'static str },
+    Key { unit: String, section: String, key: String, suggestion: Option<&'static str> },
+}
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Invalid::Section { unit, section, expected } => {
+                write!(f, "{unit}: [{section}] isn't a quadlet section, expected [{expected}]")
+            }
+            Invalid::Key { unit, section, key, suggestion } => {
+                write!(f, "{unit}: [{section}] has no key '{key}'")?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, ", did you mean '{suggestion}'?")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The quadlet section for a unit file, from its extension
+pub fn unit_section(name: &str) -> Option<&'static str> {
+    let extension = name.rsplit('.').next()?;
+    KEYS.iter().map(|(section, _, _)| *section).find(|section| section.eq_ignore_ascii_case(extension))
+}
+
+/// The podman version that added a key to a section, if quadlet knows the key at all
+pub fn key_since(section: &str, key: &str) -> Option<Version> {
+    KEYS.iter()
+        .find(|(s, _, keys)| *s == section && keys.contains(&key))
+        .map(|(_, since, _)| *since)
+}
+
+/// Parse a podman version like `4.9`, for `--target-podman-version`
+pub fn parse_target_version(version: &str) -> Result<Version, String> {
+    parse_version(version).ok_or_else(|| format!("'{version}' isn't a podman version, like 4.9"))
+}
+
+/// The version of the installed podman, if there is one
+pub fn podman_version() -> Option<Version> {
+    let output = Command::new("podman").arg("--version").output().ok()?;
+    parse_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+// The podman flags equivalent to keys added after PodmanArgs, as the key, flag and whether the
+// key holds a space-separated list
+const FLAGS: &[(&str, &str, bool)] = &[
+    ("AddHost", "add-host", true),
+    ("BuildArg", "build-arg", true),
+    ("DNS", "dns", true),
+    ("DNSOption", "dns-option", true),
+    ("DNSSearch", "dns-search", true),
+    ("Entrypoint", "entrypoint", false),
+    ("GroupAdd", "group-add", true),
+    ("HostName", "hostname", false),
+    ("IgnoreFile", "ignorefile", false),
+    ("LogOpt", "log-opt", false),
+    ("Memory", "memory", false),
+    ("NetworkAlias", "network-alias", true),
+    ("PidsLimit", "pids-limit", false),
+    ("Pull", "pull", false),
+    ("ShmSize", "shm-size", false),
+    ("StopSignal", "stop-signal", false),
+    ("StopTimeout", "stop-timeout", false),
+    ("Sysctl", "sysctl", true),
+    ("Ulimit", "ulimit", false),
+];
+
+/// Rewrite the keys of the units that podman `version` doesn't support as the equivalent
+/// PodmanArgs where there is one, returning what's left unsupported: whole unit types, like
+/// `.pod` before podman 5.0, and keys without an equivalent flag.
+pub fn downgrade(units: &mut IniFiles, version: Version) -> Vec<String> {
+    let mut names: Vec<String> = units.0.keys().cloned().collect();
+    names.sort();
+    let mut unsupported = Vec::new();
+    for name in names {
+        let Some(expected) = unit_section(&name) else { continue };
+        let since = section_since(expected);
+        if since > version {
+            unsupported.push(format!("{name}: .{} units need podman {}.{}", expected.to_lowercase(), since.0, since.1));
+            continue;
+        }
+        let Some(section) = units.0.get_mut(&name).and_then(|unit| unit.0.get_mut(expected)) else {
+            continue;
+        };
+        let args_supported = key_since(expected, "PodmanArgs").is_some_and(|since| since <= version);
+        let too_new: Vec<(String, Version)> = section
+            .keys()
+            .filter_map(|key| key_since(expected, key).filter(|since| *since > version).map(|since| (key.clone(), since)))
+            .collect();
+        for (key, since) in too_new {
+            match FLAGS.iter().find(|(k, _, _)| *k == key) {
+                Some((_, flag, list)) if args_supported => {
+                    let value = section.shift_remove(&key).unwrap_or_default();
+                    let values = if *list { split_words(&value) } else { vec![value] };
+                    let args: Vec<String> = values.iter().map(|value| format!("--{flag}={value}")).collect();
+                    let args = args.join(" ");
+                    match section.get_mut("PodmanArgs") {
+                        Some(existing) => *existing = format!("{existing} {args}"),
+                        None => {
+                            section.insert("PodmanArgs".to_string(), args);
+                        }
+                    }
+                }
+                _ => unsupported.push(format!("{name}: [{expected}] {key} needs podman {}.{}", since.0, since.1)),
+            }
+        }
+    }
+    unsupported
+}
+
+// The podman version that added a unit type
+fn section_since(section: &str) -> Version {
+    KEYS.iter().filter(|(s, _, _)| *s == section).map(|(_, since, _)| *since).min().unwrap_or_default()
+}
+
+// Split a list value on whitespace outside double quotes, keeping the quotes for PodmanArgs
+fn split_words(value: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in value.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Check every section and key of the quadlet units against the keys quadlet knows, suggesting
+/// the closest key for misspellings. Units without a quadlet extension, like `.target`, and the
+/// sections systemd handles are skipped.
+pub fn validate(units: &IniFiles) -> Vec<Invalid> {
+    let mut names: Vec<&String> = units.0.keys().collect();
+    names.sort();
+    let mut invalid = Vec::new();
+    for name in names {
+        let Some(expected) = unit_section(name) else { continue };
+        for (section, keys) in units.0[name].0.iter() {
+            if SYSTEMD_SECTIONS.contains(&section.as_str()) || section.starts_with("X-") {
+                continue;
+            }
+            if section != expected {
+                invalid.push(Invalid::Section { unit: name.clone(), section: section.clone(), expected });
+                continue;
+            }
+            for key in keys.keys().filter(|key| key_since(section, key).is_none()) {
+                invalid.push(Invalid::Key {
+                    unit: name.clone(),
+                    section: section.clone(),
+                    key: key.clone(),
+                    suggestion: closest_key(section, key),
+                });
+            }
+        }
+    }
+    invalid
+}
+
+// The known key nearest to a misspelt one
+fn closest_key(section: &str, key: &str) -> Option<&'static str> {
+    let known = KEYS.iter().filter(|(s, _, _)| *s == section).flat_map(|(_, _, keys)| keys.iter().copied());
+    closest_match(key, known)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::{Ini, Section};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_validate() {
+        let mut container = Section::new();
+        container.insert("Image".to_string(), "docker.io/library/nginx".to_string());
+        container.insert("PublishPorts".to_string(), "8080:80".to_string());
+        container.insert("Frobnicate".to_string(), "yes".to_string());
+        let mut web = Ini::new();
+        web.insert("Unit".to_string(), Section::new());
+        web.insert("Container".to_string(), container);
+        web.insert("Pod".to_string(), Section::new());
+
+        let mut target = Ini::new();
+        target.insert("Target".to_string(), Section::new());
+
+        let units = IniFiles(HashMap::from([
+            ("web.container".to_string(), web),
+            ("app.target".to_string(), target),
+        ]));
+        let invalid = validate(&units);
+
+        assert_eq!(invalid.len(), 3);
+        assert_eq!(invalid[0].to_string(), "web.container: [Container] has no key 'PublishPorts', did you mean 'PublishPort'?");
+        assert_eq!(invalid[1].to_string(), "web.container: [Container] has no key 'Frobnicate'");
+        assert_eq!(invalid[2].to_string(), "web.container: [Pod] isn't a quadlet section, expected [Container]");
+        assert_eq!(key_since("Build", "BuildArg"), Some((5, 4)));
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let mut container = Section::new();
+        container.insert("Image".to_string(), "docker.io/library/nginx".to_string());
+        container.insert("Pod".to_string(), "app.pod".to_string());
+        container.insert("Sysctl".to_string(), "net.core.somaxconn=1024".to_string());
+        container.insert("DNS".to_string(), "1.1.1.1 9.9.9.9".to_string());
+        container.insert("PodmanArgs".to_string(), "--init".to_string());
+        let mut web = Ini::new();
+        web.insert("Container".to_string(), container);
+        let mut pod = Ini::new();
+        pod.insert("Pod".to_string(), Section::new());
+
+        let mut units = IniFiles(HashMap::from([
+            ("web.container".to_string(), web.clone()),
+            ("app.pod".to_string(), pod),
+        ]));
+        let unsupported = downgrade(&mut units, (4, 4));
+
+        assert_eq!(unsupported, vec![
+            "app.pod: .pod units need podman 5.0".to_string(),
+            "web.container: [Container] Pod needs podman 5.0".to_string(),
+        ]);
+        let container = units.0["web.container"].get("Container").unwrap();
+        assert_eq!(container.get("Sysctl"), None);
+        assert_eq!(container["PodmanArgs"], "--init --sysctl=net.core.somaxconn=1024 --dns=1.1.1.1 --dns=9.9.9.9");
+
+        let mut units = IniFiles(HashMap::from([("web.container".to_string(), web)]));
+        assert!(downgrade(&mut units, (5, 2)).is_empty());
+        assert_eq!(parse_target_version("5.2.0"), Ok((5, 2)));
+    }
+}
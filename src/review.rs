@@ -0,0 +1,149 @@
+use anyhow::Result;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+    DefaultTerminal, Frame,
+};
+
+use crate::formats::IniFiles;
+
+/// The questions the review screen answers instead, with the directive each one adds
+pub const REVIEWED: &[(&str, &str, &str)] = &[
+    ("add-wanted-by", "Install", "WantedBy"),
+    ("add-after-targets", "Unit", "After"),
+    ("add-environment-file", "Service", "EnvironmentFile"),
+    ("autoupdate", "Container", "AutoUpdate"),
+];
+
+/// A reviewed directive in a generated unit, kept if enabled
+#[derive(Debug, Clone, PartialEq)]
+pub struct Toggle {
+    pub unit: String,
+    pub section: &'static str,
+    pub key: &'static str,
+    pub value: String,
+    pub enabled: bool,
+}
+
+/// The reviewed directives present in `units`, ordered by unit name
+pub fn toggles(units: &IniFiles) -> Vec<Toggle> {
+    let mut names: Vec<&String> = units.0.keys().collect();
+    names.sort();
+    let mut toggles = Vec::new();
+    for name in names {
+        for &(_, section, key) in REVIEWED {
+            if let Some(value) = units.0[name].0.get(section).and_then(|s| s.get(key)) {
+                toggles.push(Toggle { unit: name.clone(), section, key, value: value.clone(), enabled: true });
+            }
+        }
+    }
+    toggles
+}
+
+/// Remove the directives that were switched off, along with any section left empty.
+pub fn apply(units: &mut IniFiles, toggles: &[Toggle]) {
+    for toggle in toggles.iter().filter(|t| !t.enabled) {
+        let Some(unit) = units.0.get_mut(&toggle.unit) else {
+            continue;
+        };
+        if let Some(section) = unit.0.get_mut(toggle.section) {
+            section.shift_remove(toggle.key);
+            if section.is_empty() {
+                unit.0.shift_remove(toggle.section);
+            }
+        }
+    }
+}
+
+/// Show every reviewed directive in `units` on one screen to switch on and off, applying the
+/// choices once confirmed. Returns false if the review was cancelled.
+pub fn review(units: &mut IniFiles) -> Result<bool> {
+    let mut toggles = toggles(units);
+    if toggles.is_empty() {
+        return Ok(true);
+    }
+
+    let mut terminal = ratatui::init();
+    let confirmed = run(&mut terminal, &mut toggles);
+    ratatui::restore();
+
+    if confirmed? {
+        apply(units, &toggles);
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+fn run(terminal: &mut DefaultTerminal, toggles: &mut [Toggle]) -> Result<bool> {
+    let mut state = ListState::default().with_selected(Some(0));
+    loop {
+        terminal.draw(|frame| draw(frame, toggles, &mut state))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => state.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') => state.select_next(),
+            KeyCode::Char(' ') => {
+                if let Some(toggle) = state.selected().and_then(|i| toggles.get_mut(i)) {
+                    toggle.enabled = !toggle.enabled;
+                }
+            }
+            KeyCode::Enter => return Ok(true),
+            KeyCode::Esc | KeyCode::Char('q') => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, toggles: &[Toggle], state: &mut ListState) {
+    let [list_area, help_area] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+    let width = toggles.iter().map(|t| t.unit.len()).max().unwrap_or(0);
+    let items: Vec<ListItem> = toggles
+        .iter()
+        .map(|t| {
+            let mark = if t.enabled { 'x' } else { ' ' };
+            ListItem::new(format!("[{mark}] {:width$}  {}={}", t.unit, t.key, t.value))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::bordered().title("Review generated units"))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, list_area, state);
+    frame.render_widget(Paragraph::new("↑/↓ move  space toggle  enter confirm  esc cancel"), help_area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::Ini;
+
+    #[test]
+    fn test_apply_toggles() {
+        let mut units = IniFiles::new();
+        let mut web = Ini::new();
+        web.0.entry("Container".to_string()).or_default().insert("Image".to_string(), "nginx".to_string());
+        web.0.entry("Container".to_string()).or_default().insert("AutoUpdate".to_string(), "local".to_string());
+        web.0.entry("Service".to_string()).or_default().insert("EnvironmentFile".to_string(), "/srv/.env".to_string());
+        units.insert("web.container".to_string(), web);
+
+        let mut toggles = toggles(&units);
+        assert_eq!(toggles.len(), 2);
+        assert_eq!(toggles[0].key, "EnvironmentFile");
+        for toggle in &mut toggles {
+            toggle.enabled = false;
+        }
+        apply(&mut units, &toggles);
+
+        let web = &units.0["web.container"].0;
+        assert!(!web.contains_key("Service"));
+        assert_eq!(web["Container"].get("AutoUpdate"), None);
+        assert_eq!(web["Container"]["Image"], "nginx");
+    }
+}
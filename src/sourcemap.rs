@@ -0,0 +1,120 @@
+use regex::Regex;
+
+// Above this many template lines times rendered lines, lines aren't matched up
+const MAX_CELLS: usize = 4_000_000;
+
+/// A template and the text it rendered to, to trace errors reading the text back to the template
+#[derive(Debug, Clone)]
+pub struct Rendered {
+    template: String,
+    text: String,
+}
+
+impl Rendered {
+    pub fn new(template: &str, text: &str) -> Self {
+        Rendered { template: template.to_string(), text: text.to_string() }
+    }
+
+    /// The template line, counted from 1, that most likely rendered line `line` of the text.
+    /// Lines the template has word for word are matched up, and the others are taken to come
+    /// from the template lines between the nearest matched ones, in order.
+    pub fn source_line(&self, line: usize) -> Option<usize> {
+        let template: Vec<&str> = self.template.lines().collect();
+        let text: Vec<&str> = self.text.lines().collect();
+        let rendered = line.checked_sub(1).filter(|r| *r < text.len())?;
+        let pairs = matching_lines(&template, &text)?;
+        if let Some((_, t)) = pairs.iter().find(|(r, _)| *r == rendered) {
+            return Some(t + 1);
+        }
+        let previous = pairs.iter().rev().find(|(r, _)| *r < rendered);
+        let (first_r, first_t) = previous.map_or((0, 0), |(r, t)| (r + 1, t + 1));
+        let next_t = pairs.iter().find(|(r, _)| *r > rendered).map_or(template.len(), |(_, t)| *t);
+        if first_t >= next_t {
+            // Nothing in the template between them, as when a loop repeats a line
+            return previous.map(|(_, t)| t + 1);
+        }
+        Some(first_t + (rendered - first_r).min(next_t - first_t - 1) + 1)
+    }
+
+    /// `error`, followed by the rendered line it names and the template lines it likely came from
+    pub fn explain(&self, error: anyhow::Error) -> anyhow::Error {
+        let message = format!("{error:#}");
+        let position = Regex::new(r"line (\d+)").unwrap();
+        let Some(line) = position.captures(&message).and_then(|c| c[1].parse::<usize>().ok()) else {
+            return error;
+        };
+        let Some(rendered) = self.text.lines().nth(line.wrapping_sub(1)) else {
+            return error;
+        };
+        let mut explained = format!("{message}\n\nRendered line {line}:\n  {rendered}");
+        if let Some(source) = self.source_line(line) {
+            explained.push_str(&format!("\nrendered from template line {source}:\n{}", excerpt(&self.template, source)));
+        }
+        anyhow::anyhow!(explained)
+    }
+}
+
+// The line, counted from 1, with a line either side, numbered and marked
+fn excerpt(text: &str, line: usize) -> String {
+    text.lines()
+        .enumerate()
+        .skip(line.saturating_sub(2))
+        .take(if line > 1 { 3 } else { 2 })
+        .map(|(i, content)| format!("{} {:>4} | {content}", if i + 1 == line { ">" } else { " " }, i + 1))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// The (rendered, template) indices of the longest common sequence of identical lines
+fn matching_lines(template: &[&str], text: &[&str]) -> Option<Vec<(usize, usize)>> {
+    if template.len().saturating_mul(text.len()) > MAX_CELLS {
+        return None;
+    }
+    let width = template.len() + 1;
+    let mut lengths = vec![0u32; (text.len() + 1) * width];
+    for r in (0..text.len()).rev() {
+        for t in (0..template.len()).rev() {
+            lengths[r * width + t] = if text[r] == template[t] {
+                lengths[(r + 1) * width + t + 1] + 1
+            } else {
+                lengths[(r + 1) * width + t].max(lengths[r * width + t + 1])
+            };
+        }
+    }
+    let (mut r, mut t) = (0, 0);
+    let mut pairs = Vec::new();
+    while r < text.len() && t < template.len() {
+        if text[r] == template[t] {
+            pairs.push((r, t));
+            r += 1;
+            t += 1;
+        } else if lengths[(r + 1) * width + t] >= lengths[r * width + t + 1] {
+            r += 1;
+        } else {
+            t += 1;
+        }
+    }
+    Some(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_line() {
+        let template = "app:\n{% if tls %}\n  port: 443\n{% endif %}\n  image: {{ image }}\n  name: app";
+        let text = "app:\n\n  port: 443\n\n  image: nginx: 1\n  name: app";
+        let rendered = Rendered::new(template, text);
+        assert_eq!(rendered.source_line(3), Some(3));
+        assert_eq!(rendered.source_line(5), Some(5));
+        assert_eq!(rendered.source_line(9), None);
+
+        let error = rendered.explain(anyhow::anyhow!("mapping values are not allowed at line 5 column 15"));
+        assert_eq!(
+            error.to_string(),
+            "mapping values are not allowed at line 5 column 15\n\nRendered line 5:\n    image: nginx: 1\n\
+             rendered from template line 5:\n     4 | {% endif %}\n>    5 |   image: {{ image }}\n     6 |   name: app"
+        );
+    }
+}
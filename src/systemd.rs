@@ -1,22 +1,102 @@
-use anyhow::{Ok, Result};
+use anyhow::Result;
 use std::{
-    collections::HashMap, fs, path::PathBuf, process::Command
+    collections::HashMap, fs, path::{Path, PathBuf}, process::Command
 };
+use clap::ValueEnum;
 use log::{error,info};
 
-use crate::{formats::{Ini, IniFiles, Section}, utils::{self, systemctl_cmd}};
+use crate::{formats::{Ini, IniFiles, Section}, prompt::Prompter, utils::{self, systemctl_cmd}};
 
-pub fn activate_units(written_files: Vec<PathBuf>) -> anyhow::Result<()> {
+pub mod keys;
+pub mod lint;
+
+/// How activation brings up a unit
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum Activation {
+    /// Enable the unit and start it now
+    #[default]
+    EnableNow,
+    /// Enable the unit to start at boot, without starting it now
+    Enable,
+    /// Start the unit this once, without enabling it
+    Start,
+}
+
+impl Activation {
+    fn args(self) -> &'static [&'static str] {
+        match self {
+            Activation::EnableNow => &["enable", "--now"],
+            Activation::Enable => &["enable"],
+            Activation::Start => &["start"],
+        }
+    }
+}
+
+/// Parse `--activate`, as a mode for every unit, or `<type>=<mode>` for units of one type like `timer`
+pub fn parse_activation(rule: &str) -> Result<(Option<String>, Activation), String> {
+    let (unit_type, mode) = match rule.split_once('=') {
+        Some((unit_type, mode)) => (Some(unit_type.trim_start_matches('.').to_string()), mode),
+        None => (None, rule),
+    };
+    let mode = Activation::from_str(mode, true).map_err(|_| {
+        format!("'{mode}' isn't an activation, expected enable-now, enable or start")
+    })?;
+    Ok((unit_type, mode))
+}
+
+// The activation of a unit: the last rule for its type, or else the last for every unit
+fn activation_for(file_name: &str, rules: &[(Option<String>, Activation)]) -> Activation {
+    let extension = file_name.rsplit('.').next().unwrap_or_default();
+    let typed = rules.iter().rev().find(|(unit_type, _)| unit_type.as_deref() == Some(extension));
+    let any = rules.iter().rev().find(|(unit_type, _)| unit_type.is_none());
+    typed.or(any).map(|(_, mode)| *mode).unwrap_or_default()
+}
+
+/// The units whose file in `output_dir` already holds what they'd be written as, which
+/// activation leaves running as they are. A service whose env file from `env_files` changed
+/// counts as changed too.
+pub fn unchanged_units(units: &IniFiles, env_files: &HashMap<String, String>, output_dir: &Path) -> Result<Vec<String>> {
+    let holds = |filename: &str, content: &str| fs::read_to_string(output_dir.join(filename)).is_ok_and(|existing| existing == content);
+    let mut unchanged = Vec::new();
+    for (name, unit) in &units.0 {
+        let env_changed = name
+            .strip_suffix(".service")
+            .map(|base| format!("{base}.env"))
+            .and_then(|env_file| env_files.get(&env_file).map(|content| !holds(&env_file, content)))
+            .unwrap_or(false);
+        if !env_changed && holds(name, &serde_ini::to_string(unit).unwrap_or_default()) {
+            unchanged.push(name.clone());
+        }
+    }
+    Ok(unchanged)
+}
+
+pub fn activate_units(
+    written_files: Vec<PathBuf>,
+    machines: &[Machine],
+    activation: &[(Option<String>, Activation)],
+    unchanged: &[String],
+    offline_verify: bool,
+    prompter: &mut dyn Prompter,
+) -> anyhow::Result<()> {
 
     info!("Verifying systemd units");
+    // Minimal containers and CI often lack systemd-analyze, so fall back to slate's own checks
+    let offline_verify = offline_verify || utils::which("systemd-analyze").is_none();
     let mut failed_files = Vec::new();
-    for file in &written_files {
-        let status = Command::new("systemd-analyze")
-            .arg("verify")
-            .arg(file)
-            .status()?;
+    // systemd-analyze only knows unit files, networkd and nspawn check their own when loaded
+    for file in written_files.iter().filter(|file| is_unit_file(file)) {
+        let passed = if offline_verify {
+            let problems = lint::lint(file);
+            for problem in &problems {
+                error!("{problem}");
+            }
+            problems.is_empty()
+        } else {
+            Command::new("systemd-analyze").arg("verify").arg(file).status()?.success()
+        };
 
-        if !status.success() {
+        if !passed {
             error!("Verification failed for {}", file.display());
             failed_files.push(file);
         }
@@ -26,7 +106,7 @@ pub fn activate_units(written_files: Vec<PathBuf>) -> anyhow::Result<()> {
         info!("One or more unit files failed verification.");
 
         // Prompt to delete failed files
-        if utils::ask_confirm("Delete the failed files?", false)? {
+        if prompter.confirm("delete-failed-units", "Delete the failed files?", false)? {
             for file in &failed_files {
                 if let Err(e) = fs::remove_file(file) {
                     error!("Failed to delete {}: {}", file.display(), e);
@@ -43,7 +123,7 @@ pub fn activate_units(written_files: Vec<PathBuf>) -> anyhow::Result<()> {
     }
     info!("All units passed!");
         
-    if utils::ask_confirm("Activate the new service files? (Ensure your files have been created in the correct directories!)", true)? {
+    if prompter.confirm("activate-units", "Activate the new service files? (Ensure your files have been created in the correct directories!)", true)? {
         
         let is_root = utils::is_root();
 
@@ -53,11 +133,14 @@ pub fn activate_units(written_files: Vec<PathBuf>) -> anyhow::Result<()> {
 
             let file_name = file.file_name().unwrap().to_str().unwrap();
 
-            if file_name.ends_with(".timer") {
+            let args = activation_for(file_name, activation).args();
+            if file_name.ends_with(".timer") || file_name.ends_with(".target") {
                 systemctl_cmd(is_root)
-                    .args(["enable", "--now", file_name])
+                    .args(args)
+                    .arg(file_name)
                     .status()?;
-            } else if file_name.ends_with(".service") {
+            } else if file_name.ends_with(".service") && !file_name.ends_with("@.service") {
+                // Templates are only started as instances, like notify-failure@.service
                 let service_base = file_name.strip_suffix(".service").unwrap();
                 let timer_exists = written_files.iter().any(|f| {
                     f.file_name()
@@ -69,20 +152,720 @@ pub fn activate_units(written_files: Vec<PathBuf>) -> anyhow::Result<()> {
 
                 if !timer_exists {
                     systemctl_cmd(is_root)
-                        .args(["enable", "--now", file_name])
+                        .args(args)
+                        .arg(file_name)
                         .status()?;
                 }
             }
         }
+        restart_changed(&written_files, activation, unchanged, prompter)?;
+        reload_networkd(&written_files, prompter)?;
+        start_machines(&written_files, machines, prompter)?;
+    }
+
+    Ok(())
+}
+
+/// `enable --now` leaves services that are running already as they were, so restart those whose
+/// file changed, or reload them with `reload-or-restart` where they have an `ExecReload=` and
+/// you'd rather (`reload-instead`). Unchanged services, and those only enabled, are left alone.
+fn restart_changed(
+    written_files: &[PathBuf],
+    activation: &[(Option<String>, Activation)],
+    unchanged: &[String],
+    prompter: &mut dyn Prompter,
+) -> Result<()> {
+    let is_root = utils::is_root();
+    for file in written_files {
+        let Some(name) = file.file_name().and_then(|n| n.to_str()) else { continue };
+        if !name.ends_with(".service")
+            || name.ends_with("@.service")
+            || unchanged.iter().any(|u| u == name)
+            || activation_for(name, activation) == Activation::Enable
+        {
+            continue;
+        }
+        let active = systemctl_cmd(is_root).args(["is-active", "--quiet", name]).status().is_ok_and(|s| s.success());
+        if !active {
+            continue;
+        }
+        let reloadable = fs::read_to_string(file)
+            .ok()
+            .and_then(|content| serde_ini::from_str::<Ini>(&content).ok())
+            .is_some_and(|unit| unit.get("Service").is_some_and(|s| s.contains_key("ExecReload")));
+        let verb = if reloadable
+            && prompter.confirm(
+                "reload-instead",
+                &format!("Reload '{name}' instead of restarting it? Changes to the unit itself only apply on restart"),
+                false,
+            )? {
+            "reload-or-restart"
+        } else {
+            "restart"
+        };
+        systemctl_cmd(is_root).arg(verb).arg(name).status()?;
+    }
+    Ok(())
+}
+
+/// Start the services and timers as transient units with systemd-run rather than installing
+/// them, passing their settings as properties. Transient units are gone once they stop or the
+/// system reboots, and other kinds of unit are skipped.
+pub fn run_transient(units: &IniFiles) -> Result<()> {
+    let is_root = utils::is_root();
+    let mut names: Vec<&String> = units.0.keys().collect();
+    names.sort();
+    for name in names {
+        let Some(stem) = name.strip_suffix(".service") else {
+            if !name.ends_with(".timer") {
+                log::warn!("Skipping {name}, which systemd-run can't start as a transient unit");
+            }
+            continue;
+        };
+        let timer = units.get(&format!("{stem}.timer"));
+        let mut cmd = transient_command(stem, &units.0[name], timer, is_root)?;
+        info!("Starting {name} with systemd-run");
+        if !cmd.status()?.success() {
+            error!("systemd-run failed to start {name}");
+        }
+    }
+    Ok(())
+}
+
+fn transient_command(name: &str, service: &Ini, timer: Option<&Ini>, is_root: bool) -> Result<Command> {
+    let mut cmd = Command::new("systemd-run");
+    if !is_root {
+        cmd.arg("--user");
+    }
+    cmd.arg(format!("--unit={name}"));
+    let mut exec_start = None;
+    for (section, keys) in service.0.iter() {
+        for (key, value) in keys {
+            match (section.as_str(), key.as_str()) {
+                // Transient units can't be enabled
+                ("Install", _) => {}
+                ("Service", "ExecStart") => exec_start = Some(value),
+                _ => {
+                    cmd.arg(format!("--property={key}={value}"));
+                }
+            }
+        }
+    }
+    for (section, keys) in timer.into_iter().flat_map(|timer| timer.0.iter()) {
+        if section == "Timer" {
+            for (key, value) in keys.iter().filter(|(key, _)| *key != "Unit") {
+                cmd.arg(format!("--timer-property={key}={value}"));
+            }
+        }
+    }
+
+    let exec_start = exec_start.ok_or_else(|| anyhow::anyhow!("{name}.service has no ExecStart to run"))?;
+    // systemd-run takes the command itself, without ExecStart's prefixes
+    let command = shell_words::split(exec_start.trim_start_matches(['-', '@', ':', '+', '!']))?;
+    cmd.arg("--").args(command);
+    Ok(cmd)
+}
+
+// The file extensions of systemd-networkd's configuration, by the section that marks an entry as one
+const NETWORKD: &[(&str, &str)] = &[("Network", "network"), ("NetDev", "netdev"), ("Link", "link")];
+
+fn is_unit_file(file: &Path) -> bool {
+    let extension = file.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    ["service", "timer", "target", "slice"].contains(&extension)
+}
+
+fn is_networkd_file(file: &Path) -> bool {
+    let extension = file.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    NETWORKD.iter().any(|(_, ext)| *ext == extension)
+}
+
+/// Have networkd load new .network and .netdev files, which also reports those it can't parse.
+/// .link files are applied by udev when their devices next appear.
+fn reload_networkd(written_files: &[PathBuf], prompter: &mut dyn Prompter) -> Result<()> {
+    if !written_files.iter().any(|file| is_networkd_file(file)) {
+        return Ok(());
+    }
+    if utils::which("networkctl").is_none() {
+        info!("Skipping the networkd reload, as networkctl isn't installed");
+        return Ok(());
+    }
+    if prompter.confirm("reload-networkd", "Reload systemd-networkd to apply the network configuration?", true)? {
+        let status = Command::new("networkctl").arg("reload").status()?;
+        if !status.success() {
+            error!("networkctl failed to reload the network configuration, see `journalctl -u systemd-networkd`");
+        }
+    }
+    Ok(())
+}
+
+// The resource-control keys of a slice, by the prefix of the accounting key they need
+const ACCOUNTING: &[(&str, &str)] = &[
+    ("CPU", "CPUAccounting"),
+    ("Memory", "MemoryAccounting"),
+    ("IO", "IOAccounting"),
+    ("Tasks", "TasksAccounting"),
+];
+
+// The services listed under `Units` of a slice or target, without their .service suffix
+fn take_members(section: &mut Section) -> Vec<String> {
+    section
+        .shift_remove("Units")
+        .map(|units| {
+            units.split_whitespace().map(|unit| unit.strip_suffix(".service").unwrap_or(unit).to_string()).collect()
+        })
+        .unwrap_or_default()
+}
+
+// Move the Description of a slice or target to its [Unit] section
+fn take_description(unit: &mut Ini, section: &mut Section) {
+    if let Some(description) = section.shift_remove("Description") {
+        unit.0.entry("Unit".to_string()).or_default().insert("Description".to_string(), description);
+    }
+}
+
+/// Turn an entry with a `Slice` section into a .slice unit, turning on the accounting its limits
+/// need. `Units` lists the services to run in the slice, which are returned.
+fn process_slice(unit_name: &str, mut unit: Ini) -> Result<(Ini, Vec<String>)> {
+    if unit.0.contains_key("Service") || unit.0.contains_key("Timer") {
+        anyhow::bail!("{unit_name} has a Slice section, so it can't also have a Service or Timer section");
+    }
+    let mut slice = unit.0.shift_remove("Slice").unwrap_or_default();
+    let members = take_members(&mut slice);
+    take_description(&mut unit, &mut slice);
+    for (prefix, accounting) in ACCOUNTING {
+        if slice.keys().any(|key| key.starts_with(prefix) && !key.ends_with("Accounting")) {
+            slice.entry(accounting.to_string()).or_insert_with(|| "yes".to_string());
+        }
+    }
+    unit.insert("Slice".to_string(), slice);
+    Ok((unit, members))
+}
+
+/// Turn an entry with a `Target` section into a .target unit, wanted by its `WantedBy` (by
+/// default `default.target`). `Units` lists the services, or their timers, the target wants,
+/// which are returned.
+fn process_target(unit_name: &str, mut unit: Ini) -> Result<(Ini, Vec<String>)> {
+    if unit.0.contains_key("Service") || unit.0.contains_key("Timer") {
+        anyhow::bail!("{unit_name} has a Target section, so it can't also have a Service or Timer section");
+    }
+    // Target units have no section of their own
+    let mut target = unit.0.shift_remove("Target").unwrap_or_default();
+    let members = take_members(&mut target);
+    take_description(&mut unit, &mut target);
+    let install = unit.0.entry("Install".to_string()).or_default();
+    if let Some(wanted_by) = target.shift_remove("WantedBy") {
+        install.insert("WantedBy".to_string(), wanted_by);
+    }
+    install.entry("WantedBy".to_string()).or_insert_with(|| "default.target".to_string());
+    if let Some(key) = target.keys().next() {
+        anyhow::bail!("{unit_name}: Target sections only take Description, Units and WantedBy, not {key}");
+    }
+    Ok((unit, members))
+}
+
+// Add a unit to a space-separated WantedBy
+fn add_wanted_by(install: &mut Section, target: &str) {
+    match install.get_mut("WantedBy") {
+        Some(wanted_by) if !wanted_by.split_whitespace().any(|t| t == target) => {
+            *wanted_by = format!("{wanted_by} {target}");
+        }
+        Some(_) => {}
+        None => {
+            install.insert("WantedBy".to_string(), target.to_string());
+        }
+    }
+}
+
+/// A machine to run with systemd-nspawn, and the image to import for it, if any
+#[derive(Debug, Clone, PartialEq)]
+pub struct Machine {
+    pub name: String,
+    pub image: Option<String>,
+}
+
+/// The machines described by entries with a `Machine` section, whose images are imported on activation
+pub fn machines(configs: &IniFiles) -> Vec<Machine> {
+    let mut machines: Vec<Machine> = configs
+        .0
+        .iter()
+        .filter_map(|(name, unit)| {
+            let machine = unit.get("Machine")?;
+            Some(Machine { name: name.clone(), image: machine.get("Image").cloned() })
+        })
+        .collect();
+    machines.sort_by(|a, b| a.name.cmp(&b.name));
+    machines
+}
+
+// serde_ini writes values as they are, so a key given several times is joined into extra lines
+fn repeat_key(key: &str, values: &[&str]) -> String {
+    values.join(&format!("\n{key}="))
+}
+
+/// Turn an entry with a `Machine` section into a `.nspawn` file, and its `Unit` and `Service`
+/// sections into a drop-in for its `systemd-nspawn@.service` instance. `Binds` and
+/// `BindsReadOnly` list host paths to bind mount, `Network` is `veth`, `host`, `none`,
+/// `bridge:<bridge>`, `zone:<zone>`, `macvlan:<interface>` or `ipvlan:<interface>`, and
+/// `Capabilities` and `DropCapabilities` list capabilities. Other sections, like `Exec`, are
+/// passed on to the `.nspawn` file.
+fn process_machine(unit_name: &str, mut unit: Ini) -> Result<Vec<(String, Ini)>> {
+    if unit.0.contains_key("Timer") {
+        anyhow::bail!("{unit_name} has a Machine section, so it can't also have a Timer section");
+    }
+    let mut machine = unit.0.shift_remove("Machine").unwrap_or_default();
+    // The image is imported on activation, and the service is the template's
+    machine.shift_remove("Image");
+    let mut drop_in = Ini::new();
+    for section in ["Unit", "Service"] {
+        if let Some(content) = unit.0.shift_remove(section) {
+            drop_in.insert(section.to_string(), content);
+        }
+    }
+
+    let mut nspawn = unit;
+    for (key, section, nspawn_key) in [
+        ("Capabilities", "Exec", "Capability"),
+        ("DropCapabilities", "Exec", "DropCapability"),
+        ("Binds", "Files", "Bind"),
+        ("BindsReadOnly", "Files", "BindReadOnly"),
+    ] {
+        if let Some(value) = machine.shift_remove(key) {
+            let value = match section {
+                // Capability lists are space-separated, bind mounts take a key each
+                "Files" => repeat_key(nspawn_key, &value.split_whitespace().collect::<Vec<_>>()),
+                _ => value,
+            };
+            nspawn.0.entry(section.to_string()).or_default().insert(nspawn_key.to_string(), value);
+        }
+    }
+    if let Some(network) = machine.shift_remove("Network") {
+        let (key, value) = match network.split_once(':') {
+            Some(("bridge", bridge)) => ("Bridge", bridge.to_string()),
+            Some(("zone", zone)) => ("Zone", zone.to_string()),
+            Some(("macvlan", interface)) => ("MACVLAN", interface.to_string()),
+            Some(("ipvlan", interface)) => ("IPVLAN", interface.to_string()),
+            _ => match network.as_str() {
+                "veth" => ("VirtualEthernet", "yes".to_string()),
+                "host" => ("VirtualEthernet", "no".to_string()),
+                "none" => ("Private", "yes".to_string()),
+                _ => anyhow::bail!("{unit_name}: unknown Network '{network}'"),
+            },
+        };
+        nspawn.0.entry("Network".to_string()).or_default().insert(key.to_string(), value);
+    }
+    if let Some(key) = machine.keys().next() {
+        anyhow::bail!("{unit_name}: Machine sections only take Image, Binds, BindsReadOnly, Network, Capabilities and DropCapabilities, not {key}");
+    }
+
+    let mut files = vec![(format!("{unit_name}.nspawn"), nspawn)];
+    if !drop_in.0.is_empty() {
+        files.push((format!("systemd-nspawn@{unit_name}.service.d/slate.conf"), drop_in));
+    }
+    Ok(files)
+}
+
+/// Install the `.nspawn` files where systemd-nspawn looks for them, import the machines' images,
+/// and enable and start them with machinectl
+fn start_machines(written_files: &[PathBuf], machines: &[Machine], prompter: &mut dyn Prompter) -> Result<()> {
+    let nspawn_dir = Path::new("/etc/systemd/nspawn");
+    for file in written_files.iter().filter(|file| file.extension().is_some_and(|e| e == "nspawn")) {
+        let target = nspawn_dir.join(file.file_name().unwrap());
+        if file.parent() != Some(nspawn_dir) {
+            fs::create_dir_all(nspawn_dir)?;
+            fs::copy(file, &target)?;
+            info!("Installed {}", target.display());
+        }
+    }
+
+    for machine in machines {
+        if let Some(image) = &machine.image {
+            let imported = Path::new("/var/lib/machines").join(&machine.name);
+            let exists = imported.exists() || imported.with_extension("raw").exists();
+            if !exists
+                && prompter.confirm(
+                    "import-machine-image",
+                    &format!("Copy {image} to /var/lib/machines for the machine {}?", machine.name),
+                    true,
+                )?
+            {
+                let import = if Path::new(image).is_dir() { "import-fs" } else { "import-raw" };
+                let status = Command::new("machinectl").args([import, image, &machine.name]).status()?;
+                if !status.success() {
+                    error!("Failed to import {image} for the machine {}", machine.name);
+                    continue;
+                }
+            }
+        }
+        for action in ["enable", "start"] {
+            Command::new("machinectl").args([action, &machine.name]).status()?;
+        }
     }
+    Ok(())
+}
 
+/// Offer each timer with an `OnCalendar=` the settings that keep many timers from firing at
+/// once, scaled to how often it fires: `RandomizedDelaySec=` spreads them over a tenth of the
+/// period, up to an hour, `AccuracySec=` lets systemd coalesce wakeups within a twentieth, and
+/// `Persistent=true` catches up on runs missed while the machine was off, for daily or rarer
+/// timers. Keys the timer sets already are left alone (`timer-hardening`).
+pub fn harden_timers(units: &mut IniFiles, prompter: &mut dyn Prompter) -> Result<()> {
+    let mut names: Vec<String> = units.0.keys().filter(|name| name.ends_with(".timer")).cloned().collect();
+    names.sort();
+    for name in names {
+        let Some(timer) = units.0.get_mut(&name).and_then(|unit| unit.0.get_mut("Timer")) else {
+            continue;
+        };
+        let Some(period) = timer.get("OnCalendar").map(|spec| calendar_period(spec)) else {
+            continue;
+        };
+        let defaults: Vec<(&str, String)> =
+            timer_defaults(period).into_iter().filter(|(key, _)| !timer.contains_key(*key)).collect();
+        if defaults.is_empty() {
+            continue;
+        }
+        let listed: Vec<String> = defaults.iter().map(|(key, value)| format!("{key}={value}")).collect();
+        if prompter.confirm("timer-hardening", &format!("Add {} to '{name}'?", listed.join(", ")), false)? {
+            for (key, value) in defaults {
+                timer.insert(key.to_string(), value);
+            }
+        }
+    }
+    Ok(())
+}
+
+// The settings suggested for a timer firing every `period` seconds
+fn timer_defaults(period: u64) -> Vec<(&'static str, String)> {
+    let mut defaults = vec![
+        ("RandomizedDelaySec", format_span((period / 10).clamp(1, 3600))),
+        ("AccuracySec", format_span((period / 20).clamp(1, 3600))),
+    ];
+    if period >= 86400 {
+        defaults.push(("Persistent", "true".to_string()));
+    }
+    defaults
+}
+
+/// Roughly how many seconds apart an `OnCalendar=` expression fires, from its shorthand or the
+/// finest field that repeats
+pub fn calendar_period(spec: &str) -> u64 {
+    let shorthand = match spec.trim() {
+        "minutely" => Some(60),
+        "hourly" => Some(3600),
+        "daily" => Some(86400),
+        "weekly" => Some(604800),
+        "monthly" => Some(2592000),
+        "quarterly" => Some(7776000),
+        "semiannually" => Some(15552000),
+        "yearly" | "annually" => Some(31536000),
+        _ => None,
+    };
+    if let Some(period) = shorthand {
+        return period;
+    }
+
+    let words: Vec<&str> = spec.split_whitespace().collect();
+    let time = words.iter().find(|word| word.contains(':')).copied().unwrap_or("00:00");
+    let mut fields = time.split(':');
+    let hour = fields.next().unwrap_or("0");
+    let minute = fields.next().unwrap_or("0");
+    // A field repeats with `*`, a `/step` or a list of values
+    let repeat = |field: &str, unit: u64, whole: u64| -> Option<u64> {
+        if let Some((_, step)) = field.split_once('/') {
+            return Some(step.parse::<u64>().unwrap_or(1) * unit);
+        }
+        if field.contains('*') {
+            return Some(unit);
+        }
+        let count = field.split(',').count() as u64;
+        (count > 1).then_some(whole / count)
+    };
+    if let Some(period) = repeat(minute, 60, 3600) {
+        return period;
+    }
+    if let Some(period) = repeat(hour, 3600, 86400) {
+        return period;
+    }
+
+    let date = words.iter().find(|word| word.contains('-')).copied().unwrap_or("*-*-*");
+    let weekday = words.first().is_some_and(|word| word.starts_with(|c: char| c.is_ascii_alphabetic()));
+    match date.rsplit('-').next() {
+        Some(day) if !day.contains('*') => 2592000,
+        _ if weekday => 604800,
+        _ => 86400,
+    }
+}
+
+// Seconds in the largest whole unit systemd accepts
+fn format_span(seconds: u64) -> String {
+    match seconds {
+        s if s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s % 60 == 0 => format!("{}min", s / 60),
+        s => format!("{s}s"),
+    }
+}
+
+/// Take the `Environment` section out of each service's entry, so its variables can be kept in
+/// an env file of their own rather than inline in the unit
+pub fn extract_environment(configs: &mut IniFiles) -> HashMap<String, Section> {
+    configs
+        .0
+        .iter_mut()
+        .filter(|(_, unit)| unit.0.contains_key("Service"))
+        .filter_map(|(name, unit)| Some((name.clone(), unit.0.shift_remove("Environment")?)))
+        .collect()
+}
+
+/// Each service's variables as the contents of `<name>.env`, by file name, with the service's
+/// `EnvironmentFile=` pointed at where it's written in `output_dir`
+pub fn environment_files(
+    environments: &HashMap<String, Section>,
+    units: &mut IniFiles,
+    output_dir: &Path,
+) -> Result<HashMap<String, String>> {
+    let output_dir = fs::canonicalize(output_dir)?;
+    let mut files = HashMap::new();
+    for (name, variables) in environments {
+        let filename = format!("{name}.env");
+        if let Some(service) = units.0.get_mut(&format!("{name}.service")).and_then(|unit| unit.0.get_mut("Service")) {
+            let path = output_dir.join(&filename);
+            let path = path.to_string_lossy();
+            let files = match service.get("EnvironmentFile") {
+                Some(existing) => repeat_key("EnvironmentFile", &[existing.as_str(), &path]),
+                None => path.to_string(),
+            };
+            service.insert("EnvironmentFile".to_string(), files);
+        }
+        files.insert(filename, env_file(variables));
+    }
+    Ok(files)
+}
+
+/// Write the env files from [`environment_files`] like the units, but readable only by their
+/// owner, as they often hold secrets
+pub fn write_environment_files(files: &HashMap<String, String>, output_dir: &Path) -> Result<Vec<PathBuf>> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    for filename in files.keys() {
+        let path = output_dir.join(filename);
+        // Created with no permissions for others before anything is written to it
+        fs::OpenOptions::new().write(true).create(true).truncate(false).mode(0o600).open(&path)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+    utils::write_files(files, output_dir, |content| Ok::<_, std::convert::Infallible>(content.clone()))
+}
+
+/// Add each service's variables to its `Environment=` instead, where no file is written
+pub fn inline_environment(environments: &HashMap<String, Section>, units: &mut IniFiles) {
+    for (name, variables) in environments {
+        if let Some(service) = units.0.get_mut(&format!("{name}.service")).and_then(|unit| unit.0.get_mut("Service")) {
+            let assignments: Vec<String> = variables.iter().map(|(key, value)| quote(&format!("{key}={value}"))).collect();
+            let assignments = assignments.join(" ");
+            match service.get_mut("Environment") {
+                Some(existing) => *existing = format!("{existing} {assignments}"),
+                None => {
+                    service.insert("Environment".to_string(), assignments);
+                }
+            }
+        }
+    }
+}
+
+// An env file as EnvironmentFile= reads it, with every value quoted
+fn env_file(variables: &Section) -> String {
+    variables.iter().map(|(key, value)| format!("{key}={}\n", quote(value))).collect()
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The template service started with the name of a unit that failed
+pub const NOTIFY_FAILURE: &str = "notify-failure@.service";
+
+/// Add `notify-failure@.service`, which runs `command` when a unit fails, and point `OnFailure=`
+/// of every service and container unit at it. `command` is a Tera template given `unit`, the
+/// name of the failed unit, and `host`, as systemd specifiers, so a literal `%` is written `%%`.
+pub fn add_on_failure(units: &mut IniFiles, command: &str) -> Result<()> {
+    let mut context = tera::Context::new();
+    context.insert("unit", "%i");
+    context.insert("host", "%H");
+    let command = utils::render_template(command, &context, utils::Rendering::default())?;
+
+    for (name, unit) in units.0.iter_mut() {
+        let extension = name.rsplit('.').next().unwrap_or_default();
+        if name != NOTIFY_FAILURE && (extension == "service" || extension == "container") {
+            unit.0
+                .entry("Unit".to_string())
+                .or_default()
+                .insert("OnFailure".to_string(), "notify-failure@%n.service".to_string());
+        }
+    }
+
+    let mut notify = Ini::new();
+    notify.insert("Unit".to_string(), Section::from([("Description".to_string(), "Notify that %i failed".to_string())]));
+    notify.insert(
+        "Service".to_string(),
+        Section::from([("Type".to_string(), "oneshot".to_string()), ("ExecStart".to_string(), command)]),
+    );
+    units.0.insert(NOTIFY_FAILURE.to_string(), notify);
+    Ok(())
+}
+
+// The unit types portablectl attaches from an image
+const PORTABLE_UNITS: &[&str] = &["service", "socket", "target", "timer", "path"];
+
+/// Lay out a portable service image in `<output_dir>/<name>`: the contents of `root`, if given,
+/// with the units in `usr/lib/systemd/system`. portablectl only attaches units named after the
+/// image, as `<name>.service` or `<name>-*.service`, so others are refused. An os-release is added
+/// where the root has none, along with the files portable services mount over.
+pub fn build_portable(units: &IniFiles, name: &str, root: Option<&Path>, output_dir: &Path) -> Result<PathBuf> {
+    let mut names: Vec<&String> = units.0.keys().collect();
+    names.sort();
+    for unit in &names {
+        let (stem, extension) = unit.rsplit_once('.').unwrap_or((unit.as_str(), ""));
+        if !PORTABLE_UNITS.contains(&extension) {
+            anyhow::bail!("{unit} can't be part of a portable service, which only attaches {}", PORTABLE_UNITS.join(", "));
+        }
+        let prefixed = stem == name || [format!("{name}-"), format!("{name}@")].iter().any(|prefix| stem.starts_with(prefix));
+        if !prefixed {
+            anyhow::bail!("{unit} must be named {name}.{extension} or {name}-*.{extension} to be attached from the image {name}");
+        }
+    }
+
+    let image = output_dir.join(name);
+    fs::create_dir_all(&image)?;
+    if let Some(root) = root {
+        let status = Command::new("cp").arg("-a").arg(root.join(".")).arg(&image).status()?;
+        if !status.success() {
+            anyhow::bail!("Failed to copy {} into {}", root.display(), image.display());
+        }
+    }
+
+    let unit_dir = image.join("usr/lib/systemd/system");
+    fs::create_dir_all(&unit_dir)?;
+    utils::write_files(&units.0, &unit_dir, serde_ini::to_string)?;
+
+    if !image.join("etc/os-release").exists() && !image.join("usr/lib/os-release").exists() {
+        fs::write(image.join("usr/lib/os-release"), format!("ID={name}\nPORTABLE_PREFIXES={name}\n"))?;
+    }
+    fs::create_dir_all(image.join("etc"))?;
+    for file in ["etc/resolv.conf", "etc/machine-id"] {
+        let path = image.join(file);
+        if !path.exists() {
+            fs::write(path, "")?;
+        }
+    }
+    Ok(image)
+}
+
+/// Attach a portable service image with portablectl, enabling and starting its units
+pub fn attach_portable(image: &Path, prompter: &mut dyn Prompter) -> Result<()> {
+    if utils::which("portablectl").is_none() {
+        info!("Skipping attaching {}, as portablectl isn't installed", image.display());
+        return Ok(());
+    }
+    if prompter.confirm("attach-portable", &format!("Attach {} and start its units?", image.display()), true)? {
+        let image = fs::canonicalize(image)?;
+        let status = Command::new("portablectl").args(["attach", "--enable", "--now"]).arg(&image).status()?;
+        if !status.success() {
+            error!("portablectl failed to attach {}", image.display());
+        }
+    }
+    Ok(())
+}
+
+/// Check the configuration of an entry with a `Network`, `NetDev` or `Link` section, which is
+/// written as systemd-networkd's `.network`, `.netdev` or `.link` file as it is.
+fn process_networkd(unit_name: &str, unit: Ini, kind: &str) -> Result<Ini> {
+    if unit.0.contains_key("Service") || unit.0.contains_key("Timer") {
+        anyhow::bail!("{unit_name} has a {kind} section, so it can't also have a Service or Timer section");
+    }
+    if kind == "NetDev" {
+        let netdev = unit.get("NetDev").unwrap();
+        for key in ["Name", "Kind"] {
+            if !netdev.contains_key(key) {
+                anyhow::bail!("{unit_name}: the NetDev section needs a {key}");
+            }
+        }
+    } else if !unit.0.contains_key("Match") {
+        log::warn!("{unit_name} has no Match section, so it applies to every interface");
+    }
+    Ok(unit)
+}
+
+/// Escape `name` for use in a unit name as `systemd-escape` does: `/` becomes `-`, and `-`, a
+/// leading `.` and bytes other than ASCII letters, digits, `:`, `_` and `.` become `\xNN`
+pub fn escape_unit_name(name: &str) -> String {
+    let mut escaped = String::new();
+    for (i, byte) in name.bytes().enumerate() {
+        match byte {
+            b'/' => escaped.push('-'),
+            b'.' if i == 0 => escaped.push_str("\\x2e"),
+            b if b.is_ascii_alphanumeric() || b == b':' || b == b'_' || b == b'.' => escaped.push(b as char),
+            b => escaped.push_str(&format!("\\x{b:02x}")),
+        }
+    }
+    escaped
+}
+
+/// Fail on unit file names systemd would refuse at daemon-reload, suggesting the escaped name
+pub fn check_unit_name(file_name: &str) -> Result<()> {
+    let stem = file_name.rsplit_once('.').map_or(file_name, |(stem, _)| stem);
+    let valid = |c: char| c.is_ascii_alphanumeric() || ":-_.\\@".contains(c);
+    if stem.is_empty() || !stem.chars().all(valid) {
+        anyhow::bail!(
+            "'{stem}' can't be a unit name, as systemd only allows ASCII letters, digits and ':-_.\\@'. \
+             Name it '{}' instead, which the systemd_escape Tera filter gives",
+            escape_unit_name(stem)
+        );
+    }
+    if file_name.len() > 255 {
+        anyhow::bail!("'{file_name}' is longer than the 255 characters systemd allows in a unit name");
+    }
     Ok(())
 }
 
 pub fn process_systemd(configs: IniFiles) -> Result<IniFiles> {
+    for unit_name in configs.0.keys() {
+        check_unit_name(&format!("{unit_name}.service"))?;
+    }
     let mut output_units: HashMap<String, Ini> = HashMap::new();
+    // The slice and targets of each service
+    let mut slices: HashMap<String, String> = HashMap::new();
+    let mut targets: HashMap<String, Vec<String>> = HashMap::new();
 
-    for (unit_name, unit_file_struct) in configs.0 {
+    let mut services = Vec::new();
+    for (unit_name, unit) in configs.0 {
+        if unit.0.contains_key("Slice") {
+            let slice_name = format!("{unit_name}.slice");
+            let (slice, members) = process_slice(&unit_name, unit)?;
+            slices.extend(members.into_iter().map(|service| (service, slice_name.clone())));
+            output_units.insert(slice_name, slice);
+        } else if unit.0.contains_key("Machine") {
+            output_units.extend(process_machine(&unit_name, unit)?);
+        } else if let Some((kind, extension)) = NETWORKD.iter().find(|(kind, _)| unit.0.contains_key(*kind)) {
+            let network = process_networkd(&unit_name, unit, kind)?;
+            output_units.insert(format!("{unit_name}.{extension}"), network);
+        } else if unit.0.contains_key("Target") {
+            let target_name = format!("{unit_name}.target");
+            let (target, members) = process_target(&unit_name, unit)?;
+            for service in members {
+                targets.entry(service).or_default().push(target_name.clone());
+            }
+            output_units.insert(target_name, target);
+        } else {
+            services.push((unit_name, unit));
+        }
+    }
+    let mut listed: Vec<(&String, &String)> = slices.iter().collect();
+    listed.extend(targets.iter().flat_map(|(service, targets)| targets.iter().map(move |target| (service, target))));
+    for (service, unit) in listed {
+        if !services.iter().any(|(unit_name, _)| unit_name == service) {
+            anyhow::bail!("{unit} lists {service}, which isn't defined");
+        }
+    }
+
+    for (unit_name, unit_file_struct) in services {
         let mut unit = unit_file_struct.0;
 
         let mut processed_unit = Ini::new();
@@ -112,6 +895,15 @@ pub fn process_systemd(configs: IniFiles) -> Result<IniFiles> {
         service_section.insert("StandardOutput".to_string(), "journal".to_string());
         service_section.insert("StandardError".to_string(), "journal".to_string());
 
+        if let Some(slice) = slices.get(&unit_name) {
+            service_section.insert("Slice".to_string(), slice.clone());
+        }
+        if timer_section_content.is_none() {
+            for target in targets.get(&unit_name).into_iter().flatten() {
+                add_wanted_by(processed_unit.0.entry("Install".to_string()).or_default(), target);
+            }
+        }
+
         let service_filename = format!("{unit_name}.service");
         output_units.insert(service_filename, processed_unit);
 
@@ -124,11 +916,15 @@ pub fn process_systemd(configs: IniFiles) -> Result<IniFiles> {
             let mut timer_unit_install = Section::new();
 
             for (key, value) in timer_content.iter() {
-                // Handle Description seperately
+                // Handle Description and WantedBy seperately
                 if key == "Description" {
                     timer_unit_unit.insert(key.clone(), value.clone());
                     continue;
                 }
+                if key == "WantedBy" {
+                    timer_unit_install.insert(key.clone(), value.clone());
+                    continue;
+                }
                 timer_unit_timer.insert(key.clone(), value.clone());
             }
 
@@ -139,7 +935,11 @@ pub fn process_systemd(configs: IniFiles) -> Result<IniFiles> {
 
             // Autodefine the other sections
             timer_unit_timer.insert("Unit".to_string(), format!("{unit_name}.service"));
-            timer_unit_install.insert("WantedBy".to_string(), "timers.target".to_string());
+            timer_unit_install.entry("WantedBy".to_string()).or_insert_with(|| "timers.target".to_string());
+            // A timed service's targets start its timer rather than the service
+            for target in targets.get(&unit_name).into_iter().flatten() {
+                add_wanted_by(&mut timer_unit_install, target);
+            }
 
             // Assemble the final timer file from its sections.
             timer_unit.insert("Unit".to_string(), timer_unit_unit);
@@ -159,6 +959,7 @@ pub fn process_systemd(configs: IniFiles) -> Result<IniFiles> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::prompt::ScriptedPrompter;
     use crate::formats::{Ini, IniFiles, Section};
     use std::collections::HashMap;
 
@@ -189,4 +990,290 @@ mod tests {
         insta::assert_yaml_snapshot!("service_with_timer_service", service);
         insta::assert_yaml_snapshot!("service_with_timer_timer", timer);
     }
+
+    #[test]
+    fn service_in_slice() {
+        let mut slice_section = Section::new();
+        slice_section.insert("Description".to_string(), "Batch jobs".to_string());
+        slice_section.insert("MemoryMax".to_string(), "2G".to_string());
+        slice_section.insert("CPUQuota".to_string(), "50%".to_string());
+        slice_section.insert("Units".to_string(), "backup sync.service".to_string());
+        let mut slice = Ini::new();
+        slice.insert("Slice".to_string(), slice_section);
+
+        let mut units = HashMap::new();
+        units.insert("batch".to_string(), slice);
+        for name in ["backup", "sync", "web"] {
+            let mut service_section = Section::new();
+            service_section.insert("ExecStart".to_string(), format!("/usr/local/bin/{name}"));
+            let mut service = Ini::new();
+            service.insert("Service".to_string(), service_section);
+            units.insert(name.to_string(), service);
+        }
+
+        let result = process_systemd(IniFiles(units)).unwrap();
+
+        let slice = result.get("batch.slice").unwrap();
+        assert_eq!(slice.get("Unit").unwrap()["Description"], "Batch jobs");
+        let slice = slice.get("Slice").unwrap();
+        assert_eq!(slice.get("Units"), None);
+        assert_eq!(slice["MemoryAccounting"], "yes");
+        assert_eq!(slice["CPUAccounting"], "yes");
+        assert_eq!(slice.get("IOAccounting"), None);
+        assert_eq!(result.get("backup.service").unwrap().get("Service").unwrap()["Slice"], "batch.slice");
+        assert_eq!(result.get("sync.service").unwrap().get("Service").unwrap()["Slice"], "batch.slice");
+        assert_eq!(result.get("web.service").unwrap().get("Service").unwrap().get("Slice"), None);
+    }
+
+    #[test]
+    fn services_wanted_by_target() {
+        let mut target_section = Section::new();
+        target_section.insert("Description".to_string(), "Media stack".to_string());
+        target_section.insert("Units".to_string(), "jellyfin scan".to_string());
+        let mut target = Ini::new();
+        target.insert("Target".to_string(), target_section);
+
+        let mut units = HashMap::new();
+        units.insert("media".to_string(), target);
+        for name in ["jellyfin", "scan"] {
+            let mut service_section = Section::new();
+            service_section.insert("ExecStart".to_string(), format!("/usr/bin/{name}"));
+            let mut service = Ini::new();
+            service.insert("Service".to_string(), service_section);
+            if name == "scan" {
+                let mut timer_section = Section::new();
+                timer_section.insert("OnCalendar".to_string(), "daily".to_string());
+                timer_section.insert("WantedBy".to_string(), "multi-user.target".to_string());
+                service.insert("Timer".to_string(), timer_section);
+            }
+            units.insert(name.to_string(), service);
+        }
+
+        let result = process_systemd(IniFiles(units)).unwrap();
+
+        let target = result.get("media.target").unwrap();
+        assert_eq!(target.get("Unit").unwrap()["Description"], "Media stack");
+        assert_eq!(target.get("Install").unwrap()["WantedBy"], "default.target");
+        assert_eq!(target.get("Target"), None);
+        assert_eq!(result.get("jellyfin.service").unwrap().get("Install").unwrap()["WantedBy"], "media.target");
+        assert_eq!(result.get("scan.service").unwrap().get("Install"), None);
+        let timer = result.get("scan.timer").unwrap();
+        assert_eq!(timer.get("Install").unwrap()["WantedBy"], "multi-user.target media.target");
+        assert_eq!(timer.get("Timer").unwrap().get("WantedBy"), None);
+    }
+
+    #[test]
+    fn networkd_files() {
+        let mut netdev_section = Section::new();
+        netdev_section.insert("Name".to_string(), "br0".to_string());
+        netdev_section.insert("Kind".to_string(), "bridge".to_string());
+        let mut netdev = Ini::new();
+        netdev.insert("NetDev".to_string(), netdev_section);
+
+        let mut match_section = Section::new();
+        match_section.insert("Name".to_string(), "br0".to_string());
+        let mut network_section = Section::new();
+        network_section.insert("DHCP".to_string(), "yes".to_string());
+        let mut network = Ini::new();
+        network.insert("Match".to_string(), match_section);
+        network.insert("Network".to_string(), network_section);
+        // .network files may have a Link section too
+        network.insert("Link".to_string(), Section::from([("MTUBytes".to_string(), "9000".to_string())]));
+
+        let units = HashMap::from([("br0".to_string(), netdev.clone()), ("lan".to_string(), network.clone())]);
+        let result = process_systemd(IniFiles(units)).unwrap();
+
+        assert_eq!(result.get("br0.netdev").map(|unit| &unit.0), Some(&netdev.0));
+        assert_eq!(result.get("lan.network").map(|unit| &unit.0), Some(&network.0));
+        assert_eq!(result.0.len(), 2);
+
+        netdev.0["NetDev"].shift_remove("Kind");
+        let units = HashMap::from([("br0".to_string(), netdev)]);
+        assert!(process_systemd(IniFiles(units)).is_err());
+    }
+
+    #[test]
+    fn unchanged_unit_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut service = Ini::new();
+        service.insert("Service".to_string(), Section::from([("ExecStart".to_string(), "/usr/bin/app".to_string())]));
+        let units = process_systemd(IniFiles(HashMap::from([
+            ("app".to_string(), service.clone()),
+            ("worker".to_string(), service),
+        ])))
+        .unwrap();
+        utils::write_files(&units.0, dir.path(), serde_ini::to_string).unwrap();
+        fs::write(dir.path().join("worker.service"), "[Service]\nExecStart=/usr/bin/old\n").unwrap();
+
+        assert_eq!(unchanged_units(&units, &HashMap::new(), dir.path()).unwrap(), vec!["app.service".to_string()]);
+
+        let env_files = HashMap::from([("app.env".to_string(), "KEY=\"new\"\n".to_string())]);
+        fs::write(dir.path().join("app.env"), "KEY=\"old\"\n").unwrap();
+        assert!(unchanged_units(&units, &env_files, dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unit_name_escaping() {
+        assert_eq!(escape_unit_name("my app/α"), "my\\x20app-\\xce\\xb1");
+        assert_eq!(escape_unit_name(".hidden-dir"), "\\x2ehidden\\x2ddir");
+        assert!(check_unit_name("my app.service").unwrap_err().to_string().contains("'my\\x20app'"));
+        check_unit_name("web@1.container").unwrap();
+    }
+
+    #[test]
+    fn activation_rules() {
+        let rules: Vec<_> = ["enable", "timer=enable-now", ".target=start"]
+            .iter()
+            .map(|rule| parse_activation(rule).unwrap())
+            .collect();
+        assert_eq!(activation_for("app.service", &rules), Activation::Enable);
+        assert_eq!(activation_for("app.timer", &rules), Activation::EnableNow);
+        assert_eq!(activation_for("app.target", &rules), Activation::Start);
+        assert_eq!(activation_for("app.service", &[]), Activation::EnableNow);
+        assert!(parse_activation("timer=later").is_err());
+    }
+
+    #[test]
+    fn environment_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut service = Ini::new();
+        service.insert("Service".to_string(), Section::from([("ExecStart".to_string(), "/usr/bin/app".to_string())]));
+        service.insert("Environment".to_string(), Section::from([
+            ("DATABASE_URL".to_string(), "postgres://db/app".to_string()),
+            ("GREETING".to_string(), r#"say "hi""#.to_string()),
+        ]));
+        let mut configs = IniFiles(HashMap::from([("app".to_string(), service)]));
+
+        let environments = extract_environment(&mut configs);
+        let mut units = process_systemd(configs).unwrap();
+        assert_eq!(units.get("app.service").unwrap().get("Environment"), None);
+
+        let mut inlined = IniFiles(units.0.clone());
+        inline_environment(&environments, &mut inlined);
+        assert_eq!(
+            inlined.get("app.service").unwrap().get("Service").unwrap()["Environment"],
+            r#""DATABASE_URL=postgres://db/app" "GREETING=say \"hi\"""#
+        );
+
+        let files = environment_files(&environments, &mut units, dir.path()).unwrap();
+        let written = write_environment_files(&files, dir.path()).unwrap();
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&written[0]).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read_to_string(&written[0]).unwrap(), "DATABASE_URL=\"postgres://db/app\"\nGREETING=\"say \\\"hi\\\"\"\n");
+        assert_eq!(units.get("app.service").unwrap().get("Service").unwrap()["EnvironmentFile"], written[0].to_string_lossy());
+    }
+
+    #[test]
+    fn timer_hardening() {
+        assert_eq!(calendar_period("*:7/15"), 900);
+        assert_eq!(calendar_period("*-*-* 23:00,23:20"), 1800);
+        assert_eq!(calendar_period("*-*-* 00,01,02,03,04:00:00"), 17280);
+        assert_eq!(calendar_period("*-*-* 04:00:00"), 86400);
+        assert_eq!(calendar_period("Mon 09:00"), 604800);
+        assert_eq!(calendar_period("*-*-01 02:00"), 2592000);
+        assert_eq!(calendar_period("hourly"), 3600);
+
+        let mut service = Ini::new();
+        service.insert("Service".to_string(), Section::from([("ExecStart".to_string(), "/usr/bin/backup".to_string())]));
+        let timer = Section::from([
+            ("OnCalendar".to_string(), "daily".to_string()),
+            ("AccuracySec".to_string(), "1s".to_string()),
+        ]);
+        service.insert("Timer".to_string(), timer);
+        let mut units = process_systemd(IniFiles(HashMap::from([("backup".to_string(), service)]))).unwrap();
+
+        harden_timers(&mut units, &mut ScriptedPrompter::new([true])).unwrap();
+
+        let timer = units.get("backup.timer").unwrap().get("Timer").unwrap();
+        assert_eq!(timer["RandomizedDelaySec"], "1h");
+        assert_eq!(timer["AccuracySec"], "1s");
+        assert_eq!(timer["Persistent"], "true");
+    }
+
+    #[test]
+    fn notify_on_failure() {
+        let mut service = Ini::new();
+        service.insert("Service".to_string(), Section::from([("ExecStart".to_string(), "/usr/bin/app".to_string())]));
+        let mut units = process_systemd(IniFiles(HashMap::from([("app".to_string(), service)]))).unwrap();
+
+        add_on_failure(&mut units, r#"curl -d "{{ unit }} failed on {{ host }}" ntfy.sh/alerts"#).unwrap();
+
+        assert_eq!(units.get("app.service").unwrap().get("Unit").unwrap()["OnFailure"], "notify-failure@%n.service");
+        let notify = units.get(NOTIFY_FAILURE).unwrap();
+        assert_eq!(notify.get("Service").unwrap()["ExecStart"], r#"curl -d "%i failed on %H" ntfy.sh/alerts"#);
+        assert_eq!(notify.get("Unit").unwrap().get("OnFailure"), None);
+    }
+
+    #[test]
+    fn transient_timer() {
+        let mut service_section = Section::new();
+        service_section.insert("ExecStart".to_string(), "-/usr/bin/backup --to 'my disk'".to_string());
+        let mut service = Ini::new();
+        service.insert("Service".to_string(), service_section);
+        let mut timer_section = Section::new();
+        timer_section.insert("OnCalendar".to_string(), "daily".to_string());
+        service.insert("Timer".to_string(), timer_section);
+
+        let units = process_systemd(IniFiles(HashMap::from([("backup".to_string(), service)]))).unwrap();
+        let cmd = transient_command("backup", units.get("backup.service").unwrap(), units.get("backup.timer"), false).unwrap();
+        let args: Vec<&str> = cmd.get_args().map(|arg| arg.to_str().unwrap()).collect();
+
+        assert_eq!(args, vec![
+            "--user",
+            "--unit=backup",
+            "--property=Type=oneshot",
+            "--property=StandardOutput=journal",
+            "--property=StandardError=journal",
+            "--timer-property=OnCalendar=daily",
+            "--",
+            "/usr/bin/backup",
+            "--to",
+            "my disk",
+        ]);
+    }
+
+    #[test]
+    fn portable_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut service_section = Section::new();
+        service_section.insert("ExecStart".to_string(), "/usr/bin/app".to_string());
+        let mut service = Ini::new();
+        service.insert("Service".to_string(), service_section);
+
+        let units = process_systemd(IniFiles(HashMap::from([("app".to_string(), service.clone())]))).unwrap();
+        let image = build_portable(&units, "app", None, dir.path()).unwrap();
+        assert!(image.join("usr/lib/systemd/system/app.service").exists());
+        assert_eq!(fs::read_to_string(image.join("usr/lib/os-release")).unwrap(), "ID=app\nPORTABLE_PREFIXES=app\n");
+        assert!(image.join("etc/machine-id").exists());
+
+        let units = process_systemd(IniFiles(HashMap::from([("worker".to_string(), service)]))).unwrap();
+        assert!(build_portable(&units, "app", None, dir.path()).is_err());
+    }
+
+    #[test]
+    fn nspawn_machine() {
+        let mut machine_section = Section::new();
+        machine_section.insert("Image".to_string(), "/srv/images/debian".to_string());
+        machine_section.insert("Binds".to_string(), "/srv/data:/data /srv/logs:/var/log/app".to_string());
+        machine_section.insert("Network".to_string(), "bridge:br0".to_string());
+        machine_section.insert("Capabilities".to_string(), "CAP_NET_ADMIN CAP_SYS_TIME".to_string());
+        let mut service_section = Section::new();
+        service_section.insert("MemoryMax".to_string(), "4G".to_string());
+        let mut machine = Ini::new();
+        machine.insert("Machine".to_string(), machine_section);
+        machine.insert("Service".to_string(), service_section);
+
+        let units = IniFiles(HashMap::from([("debian".to_string(), machine)]));
+        assert_eq!(machines(&units), vec![Machine { name: "debian".to_string(), image: Some("/srv/images/debian".to_string()) }]);
+        let result = process_systemd(units).unwrap();
+
+        let nspawn = serde_ini::to_string(result.get("debian.nspawn").unwrap()).unwrap();
+        assert!(nspawn.contains("Bind=/srv/data:/data\nBind=/srv/logs:/var/log/app"));
+        assert!(nspawn.contains("Capability=CAP_NET_ADMIN CAP_SYS_TIME"));
+        assert!(nspawn.contains("Bridge=br0"));
+        assert!(!nspawn.contains("Image"));
+        let drop_in = result.get("systemd-nspawn@debian.service.d/slate.conf").unwrap();
+        assert_eq!(drop_in.get("Service").unwrap()["MemoryMax"], "4G");
+        assert_eq!(result.0.len(), 2);
+    }
 }
\ No newline at end of file
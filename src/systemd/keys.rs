@@ -0,0 +1,215 @@
+use std::fmt;
+
+use crate::{formats::IniFiles, utils::closest_match};
+
+const UNIT: &[&str] = &[
+    "Description", "Documentation", "Wants", "Requires", "Requisite", "BindsTo", "PartOf", "Upholds",
+    "Conflicts", "Before", "After", "OnFailure", "OnSuccess", "PropagatesReloadTo", "ReloadPropagatedFrom",
+    "PropagatesStopTo", "StopPropagatedFrom", "JoinsNamespaceOf", "RequiresMountsFor", "WantsMountsFor",
+    "OnFailureJobMode", "OnSuccessJobMode", "IgnoreOnIsolate", "StopWhenUnneeded", "RefuseManualStart",
+    "RefuseManualStop", "AllowIsolate", "DefaultDependencies", "SurviveFinalKillSignal", "CollectMode",
+    "FailureAction", "SuccessAction", "FailureActionExitStatus", "SuccessActionExitStatus", "JobTimeoutSec",
+    "JobRunningTimeoutSec", "JobTimeoutAction", "JobTimeoutRebootArgument", "StartLimitIntervalSec",
+    "StartLimitBurst", "StartLimitAction", "RebootArgument", "SourcePath",
+];
+
+// Each is a key after Condition or Assert
+const CONDITIONS: &[&str] = &[
+    "Architecture", "Firmware", "Virtualization", "Host", "KernelCommandLine", "KernelVersion", "Credential",
+    "Environment", "Security", "Capability", "ACPower", "NeedsUpdate", "FirstBoot", "PathExists",
+    "PathExistsGlob", "PathIsDirectory", "PathIsSymbolicLink", "PathIsMountPoint", "PathIsReadWrite",
+    "PathIsEncrypted", "DirectoryNotEmpty", "FileNotEmpty", "FileIsExecutable", "User", "Group",
+    "ControlGroupController", "Memory", "CPUs", "CPUFeature", "OSRelease", "MemoryPressure", "CPUPressure",
+    "IOPressure",
+];
+
+const INSTALL: &[&str] = &["Alias", "WantedBy", "RequiredBy", "UpheldBy", "Also", "DefaultInstance"];
+
+const TIMER: &[&str] = &[
+    "OnActiveSec", "OnBootSec", "OnStartupSec", "OnUnitActiveSec", "OnUnitInactiveSec", "OnCalendar",
+    "AccuracySec", "RandomizedDelaySec", "RandomizedOffsetSec", "FixedRandomDelay", "DeferReactivation",
+    "OnClockChange", "OnTimezoneChange", "Unit", "Persistent", "WakeSystem", "RemainAfterElapse",
+];
+
+const SERVICE: &[&str] = &[
+    "Type", "ExitType", "RemainAfterExit", "GuessMainPID", "PIDFile", "BusName", "ExecStart", "ExecStartPre",
+    "ExecStartPost", "ExecCondition", "ExecReload", "ExecStop", "ExecStopPost", "RestartSec", "RestartSteps",
+    "RestartMaxDelaySec", "TimeoutStartSec", "TimeoutStopSec", "TimeoutAbortSec", "TimeoutSec",
+    "TimeoutStartFailureMode", "TimeoutStopFailureMode", "RuntimeMaxSec", "RuntimeRandomizedExtraSec",
+    "WatchdogSec", "Restart", "RestartMode", "SuccessExitStatus", "RestartPreventExitStatus",
+    "RestartForceExitStatus", "RootDirectoryStartOnly", "NonBlocking", "NotifyAccess", "Sockets",
+    "FileDescriptorStoreMax", "FileDescriptorStorePreserve", "USBFunctionDescriptors", "USBFunctionStrings",
+    "OOMPolicy", "OpenFile", "ReloadSignal",
+];
+
+// Options of the processes a service runs
+const EXEC: &[&str] = &[
+    "WorkingDirectory", "RootDirectory", "RootImage", "RootImageOptions", "RootEphemeral", "RootHash",
+    "RootHashSignature", "RootVerity", "RootImagePolicy", "MountImagePolicy", "ExtensionImagePolicy",
+    "MountAPIVFS", "ProtectProc", "ProcSubset", "BindPaths", "BindReadOnlyPaths", "MountImages",
+    "ExtensionImages", "ExtensionDirectories", "User", "Group", "DynamicUser", "SupplementaryGroups",
+    "SetLoginEnvironment", "PAMName", "CapabilityBoundingSet", "AmbientCapabilities", "NoNewPrivileges",
+    "SecureBits", "SELinuxContext", "AppArmorProfile", "SmackProcessLabel", "LimitCPU", "LimitFSIZE",
+    "LimitDATA", "LimitSTACK", "LimitCORE", "LimitRSS", "LimitNOFILE", "LimitAS", "LimitNPROC",
+    "LimitMEMLOCK", "LimitLOCKS", "LimitSIGPENDING", "LimitMSGQUEUE", "LimitNICE", "LimitRTPRIO",
+    "LimitRTTIME", "UMask", "CoredumpFilter", "KeyringMode", "OOMScoreAdjust", "TimerSlackNSec",
+    "Personality", "IgnoreSIGPIPE", "Nice", "CPUSchedulingPolicy", "CPUSchedulingPriority",
+    "CPUSchedulingResetOnFork", "CPUAffinity", "NUMAPolicy", "NUMAMask", "IOSchedulingClass",
+    "IOSchedulingPriority", "ProtectSystem", "ProtectHome", "RuntimeDirectory", "StateDirectory",
+    "CacheDirectory", "LogsDirectory", "ConfigurationDirectory", "RuntimeDirectoryMode",
+    "StateDirectoryMode", "CacheDirectoryMode", "LogsDirectoryMode", "ConfigurationDirectoryMode",
+    "RuntimeDirectoryPreserve", "TimeoutCleanSec", "ReadWritePaths", "ReadOnlyPaths", "InaccessiblePaths",
+    "ExecPaths", "NoExecPaths", "TemporaryFileSystem", "PrivateTmp", "PrivateDevices", "PrivateNetwork",
+    "NetworkNamespacePath", "PrivateIPC", "IPCNamespacePath", "MemoryKSM", "PrivateUsers",
+    "ProtectHostname", "ProtectClock", "ProtectKernelTunables", "ProtectKernelModules", "ProtectKernelLogs",
+    "ProtectControlGroups", "RestrictAddressFamilies", "RestrictFileSystems", "RestrictNamespaces",
+    "LockPersonality", "MemoryDenyWriteExecute", "RestrictRealtime", "RestrictSUIDSGID", "RemoveIPC",
+    "PrivateMounts", "MountFlags", "SystemCallFilter", "SystemCallErrorNumber", "SystemCallArchitectures",
+    "SystemCallLog", "Environment", "EnvironmentFile", "PassEnvironment", "UnsetEnvironment",
+    "StandardInput", "StandardOutput", "StandardError", "StandardInputText", "StandardInputData",
+    "LogLevelMax", "LogExtraFields", "LogRateLimitIntervalSec", "LogRateLimitBurst", "LogFilterPatterns",
+    "LogNamespace", "SyslogIdentifier", "SyslogFacility", "SyslogLevel", "SyslogLevelPrefix", "TTYPath",
+    "TTYReset", "TTYVHangup", "TTYRows", "TTYColumns", "TTYVTDisallocate", "LoadCredential",
+    "LoadCredentialEncrypted", "ImportCredential", "SetCredential", "SetCredentialEncrypted",
+    "UtmpIdentifier", "UtmpMode",
+];
+
+const KILL: &[&str] = &[
+    "KillMode", "KillSignal", "RestartKillSignal", "SendSIGHUP", "SendSIGKILL", "FinalKillSignal",
+    "WatchdogSignal",
+];
+
+// Options of services and slices alike
+const RESOURCE_CONTROL: &[&str] = &[
+    "CPUAccounting", "CPUWeight", "StartupCPUWeight", "CPUQuota", "CPUQuotaPeriodSec", "AllowedCPUs",
+    "StartupAllowedCPUs", "AllowedMemoryNodes", "StartupAllowedMemoryNodes", "MemoryAccounting", "MemoryMin",
+    "MemoryLow", "StartupMemoryLow", "DefaultStartupMemoryLow", "MemoryHigh", "StartupMemoryHigh",
+    "MemoryMax", "StartupMemoryMax", "MemorySwapMax", "StartupMemorySwapMax", "MemoryZSwapMax",
+    "StartupMemoryZSwapMax", "MemoryZSwapWriteback", "TasksAccounting", "TasksMax", "IOAccounting",
+    "IOWeight", "StartupIOWeight", "IODeviceWeight", "IOReadBandwidthMax", "IOWriteBandwidthMax",
+    "IOReadIOPSMax", "IOWriteIOPSMax", "IODeviceLatencyTargetSec", "IPAccounting", "IPAddressAllow",
+    "IPAddressDeny", "SocketBindAllow", "SocketBindDeny", "RestrictNetworkInterfaces", "NFTSet",
+    "IPIngressFilterPath", "IPEgressFilterPath", "BPFProgram", "DeviceAllow", "DevicePolicy", "Slice",
+    "Delegate", "DelegateSubgroup", "DisableControllers", "ManagedOOMSwap", "ManagedOOMMemoryPressure",
+    "ManagedOOMMemoryPressureLimit", "ManagedOOMMemoryPressureDurationSec", "ManagedOOMPreference",
+    "MemoryPressureWatch", "MemoryPressureThresholdSec", "CoredumpReceive", "BlockIOAccounting",
+    "BlockIOWeight", "CPUShares", "MemoryLimit",
+];
+
+// The sections each type of unit slate generates may have
+const SECTIONS: &[(&str, &[&str])] = &[
+    ("service", &["Unit", "Service", "Install"]),
+    ("timer", &["Unit", "Timer", "Install"]),
+    ("target", &["Unit", "Install"]),
+    ("slice", &["Unit", "Slice", "Install"]),
+];
+
+fn section_keys(section: &str) -> Vec<&'static str> {
+    let groups: &[&[&str]] = match section {
+        "Unit" => &[UNIT],
+        "Install" => &[INSTALL],
+        "Timer" => &[TIMER],
+        "Service" => &[SERVICE, EXEC, KILL, RESOURCE_CONTROL],
+        "Slice" => &[RESOURCE_CONTROL],
+        _ => &[],
+    };
+    groups.iter().flat_map(|keys| keys.iter().copied()).collect()
+}
+
+fn is_condition(key: &str) -> bool {
+    ["Condition", "Assert"]
+        .iter()
+        .filter_map(|prefix| key.strip_prefix(prefix))
+        .any(|condition| CONDITIONS.contains(&condition))
+}
+
+/// A section or key systemd doesn't know in a generated unit
+#[derive(Debug, PartialEq)]
+pub enum Unknown {
+    Section { unit: String, section: String },
+    Key { unit: String, section: String, key: String, suggestion: Option<String> },
+}
+
+impl fmt::Display for Unknown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Unknown::Section { unit, section } => write!(f, "{unit}: systemd ignores the unknown section [{section}]"),
+            Unknown::Key { unit, section, key, suggestion } => {
+                write!(f, "{unit}: [{section}] has no key '{key}'")?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, ", did you mean '{suggestion}'?")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Check the sections and keys of the services, timers, targets and slices against those systemd
+/// knows, suggesting the closest key for misspellings like `ExecStrat=`. Sections and keys
+/// starting with `X-`, which systemd leaves to other tools, and other files are skipped.
+pub fn validate(units: &IniFiles) -> Vec<Unknown> {
+    let mut names: Vec<&String> = units.0.keys().collect();
+    names.sort();
+    let mut unknown = Vec::new();
+    for name in names {
+        let extension = name.rsplit('.').next().unwrap_or_default();
+        let Some((_, sections)) = SECTIONS.iter().find(|(ext, _)| *ext == extension) else { continue };
+        for (section, keys) in units.0[name].0.iter().filter(|(section, _)| !section.starts_with("X-")) {
+            if !sections.contains(&section.as_str()) {
+                unknown.push(Unknown::Section { unit: name.clone(), section: section.clone() });
+                continue;
+            }
+            let known = section_keys(section);
+            for key in keys.keys().filter(|key| !key.starts_with("X-")) {
+                if known.contains(&key.as_str()) || (section == "Unit" && is_condition(key)) {
+                    continue;
+                }
+                let mut candidates: Vec<String> = known.iter().map(|key| key.to_string()).collect();
+                if section == "Unit" {
+                    candidates.extend(CONDITIONS.iter().flat_map(|c| [format!("Condition{c}"), format!("Assert{c}")]));
+                }
+                let suggestion = closest_match(key, candidates.iter().map(String::as_str)).map(str::to_string);
+                unknown.push(Unknown::Key { unit: name.clone(), section: section.clone(), key: key.clone(), suggestion });
+            }
+        }
+    }
+    unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::{Ini, Section};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_validate() {
+        let mut service = Ini::new();
+        service.insert("Unit".to_string(), Section::from([
+            ("Description".to_string(), "App".to_string()),
+            ("ConditionPathExist".to_string(), "/srv/app".to_string()),
+            ("AssertUser".to_string(), "app".to_string()),
+        ]));
+        service.insert("Service".to_string(), Section::from([
+            ("ExecStrat".to_string(), "/usr/bin/app".to_string()),
+            ("MemoryMax".to_string(), "1G".to_string()),
+            ("X-Owner".to_string(), "ops".to_string()),
+        ]));
+        service.insert("Timer".to_string(), Section::new());
+        let mut network = Ini::new();
+        network.insert("Network".to_string(), Section::from([("DHCP".to_string(), "yes".to_string())]));
+
+        let units = IniFiles(HashMap::from([
+            ("app.service".to_string(), service),
+            ("lan.network".to_string(), network),
+        ]));
+        let unknown: Vec<String> = validate(&units).iter().map(ToString::to_string).collect();
+
+        assert_eq!(unknown, vec![
+            "app.service: [Unit] has no key 'ConditionPathExist', did you mean 'ConditionPathExists'?",
+            "app.service: [Service] has no key 'ExecStrat', did you mean 'ExecStart'?",
+            "app.service: systemd ignores the unknown section [Timer]",
+        ]);
+    }
+}
@@ -0,0 +1,116 @@
+use std::{fs, path::Path};
+
+// The suffixes a unit named in a dependency can have
+const UNIT_TYPES: &[&str] = &[
+    "service", "socket", "device", "mount", "automount", "swap", "target", "path", "timer", "slice", "scope",
+];
+
+// Keys listing other units
+const DEPENDENCIES: &[&str] = &[
+    "Wants", "Requires", "Requisite", "BindsTo", "PartOf", "Upholds", "Conflicts", "Before", "After",
+    "OnFailure", "OnSuccess", "WantedBy", "RequiredBy", "UpheldBy", "Also", "Slice",
+];
+
+const TRIGGERS: &[&str] = &["OnActiveSec", "OnBootSec", "OnStartupSec", "OnUnitActiveSec", "OnUnitInactiveSec", "OnCalendar"];
+
+/// Check a written unit file without systemd-analyze, for minimal containers and CI: each line
+/// is a section header or an assignment, services have the commands their type needs, timers have
+/// a trigger and a unit beside them to start, and dependencies name units. Each problem found is
+/// returned as a message.
+pub fn lint(file: &Path) -> Vec<String> {
+    let name = file.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let content = match fs::read_to_string(file) {
+        Ok(content) => content,
+        Err(e) => return vec![format!("{name}: can't be read: {e}")],
+    };
+    let mut problems = Vec::new();
+    let mut entries: Vec<(String, String, String)> = Vec::new();
+    let mut section: Option<String> = None;
+    let mut continued = false;
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        let continues = line.ends_with('\\');
+        if std::mem::replace(&mut continued, continues) || line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            match header.strip_suffix(']') {
+                Some(header) if !header.is_empty() => section = Some(header.to_string()),
+                _ => problems.push(format!("{name}:{}: malformed section header '{line}'", number + 1)),
+            }
+            continue;
+        }
+        let Some(section) = &section else {
+            problems.push(format!("{name}:{}: '{line}' comes before any section", number + 1));
+            continue;
+        };
+        match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                entries.push((section.clone(), key.trim().to_string(), value.trim().to_string()))
+            }
+            _ => problems.push(format!("{name}:{}: '{line}' isn't a key=value assignment", number + 1)),
+        }
+    }
+
+    let values = |section: &str, key: &str| -> Vec<&str> {
+        entries.iter().filter(|(s, k, _)| s == section && k == key).map(|(_, _, v)| v.as_str()).collect()
+    };
+    let extension = name.rsplit('.').next().unwrap_or_default();
+    if extension == "service" {
+        let service_type = values("Service", "Type").last().copied().unwrap_or("simple");
+        let exec_start = values("Service", "ExecStart").into_iter().filter(|v| !v.is_empty()).count();
+        if exec_start == 0 && service_type != "oneshot" {
+            problems.push(format!("{name}: needs an ExecStart=, which only Type=oneshot services can go without"));
+        } else if exec_start == 0 && values("Service", "ExecStop").is_empty() && values("Unit", "SuccessAction").is_empty() {
+            problems.push(format!("{name}: has no ExecStart=, ExecStop= or SuccessAction= to run"));
+        } else if exec_start > 1 && service_type != "oneshot" {
+            problems.push(format!("{name}: only Type=oneshot services can have more than one ExecStart="));
+        }
+    }
+    if extension == "timer" {
+        if !TRIGGERS.iter().any(|trigger| !values("Timer", trigger).is_empty()) {
+            problems.push(format!("{name}: has none of {} to fire it", TRIGGERS.join("=, ") + "="));
+        }
+        let unit = values("Timer", "Unit").last().map_or_else(|| name.replace(".timer", ".service"), |u| u.to_string());
+        if !file.with_file_name(&unit).exists() {
+            problems.push(format!("{name}: starts {unit}, which isn't beside it"));
+        }
+    }
+    for (_, key, value) in entries.iter().filter(|(_, key, _)| DEPENDENCIES.contains(&key.as_str())) {
+        for unit in value.split_whitespace() {
+            let suffix = unit.rsplit_once('.').map(|(_, suffix)| suffix);
+            if !suffix.is_some_and(|suffix| UNIT_TYPES.contains(&suffix)) {
+                problems.push(format!("{name}: {key}= lists '{unit}', which isn't a unit name with a type like .service"));
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            path
+        };
+        let good = write("app.service", "[Unit]\nAfter=network-online.target\n[Service]\nExecStart=/usr/bin/app \\\n  --verbose\n[Install]\nWantedBy=default.target\n");
+        let timer = write("app.timer", "[Timer]\nOnCalendar=daily\n");
+        assert!(lint(&good).is_empty());
+        assert!(lint(&timer).is_empty());
+
+        let bad = write("job.service", "Description=Job\n[Unit]\nWants=network-online\n[Service]\nType=simple\nExecStart=/bin/a\nExecStart=/bin/b\n");
+        assert_eq!(lint(&bad), vec![
+            "job.service:1: 'Description=Job' comes before any section",
+            "job.service: only Type=oneshot services can have more than one ExecStart=",
+            "job.service: Wants= lists 'network-online', which isn't a unit name with a type like .service",
+        ]);
+        let orphan = write("other.timer", "[Timer]\nPersistent=true\n");
+        assert_eq!(lint(&orphan).len(), 2);
+    }
+}
@@ -0,0 +1,210 @@
+//! Reshaping applied to a document between reading and writing it.
+
+use serde_json::{Map, Value};
+
+/// A dotted path such as `services.*.image`, where `*` within a segment matches any run of
+/// characters and list items are matched by index. `\.` escapes a literal dot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyPath(Vec<String>);
+
+impl std::str::FromStr for KeyPath {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = vec![String::new()];
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => segments.last_mut().unwrap().extend(chars.next()),
+                '.' => segments.push(String::new()),
+                c => segments.last_mut().unwrap().push(c),
+            }
+        }
+        Ok(KeyPath(segments))
+    }
+}
+
+fn glob(pattern: &str, s: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == s,
+        Some((prefix, rest)) => {
+            let Some(s) = s.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=s.len()).filter(|i| s.is_char_boundary(*i)).any(|i| glob(rest, &s[i..]))
+        }
+    }
+}
+
+// The remainders of the paths whose first segment matches `key`
+fn descend<'a>(paths: &[&'a [String]], key: &str) -> Vec<&'a [String]> {
+    paths
+        .iter()
+        .filter_map(|p| p.split_first())
+        .filter(|(first, _)| glob(first, key))
+        .map(|(_, rest)| rest)
+        .collect()
+}
+
+/// Keep only what lies under one of `paths`, along with the maps and lists leading to it.
+pub fn only(value: &mut Value, paths: &[KeyPath]) {
+    let paths: Vec<&[String]> = paths.iter().map(|p| p.0.as_slice()).collect();
+    let empty = match value {
+        Value::Object(_) => Value::Object(Map::new()),
+        Value::Array(_) => Value::Array(Vec::new()),
+        _ => Value::Null,
+    };
+    *value = keep(value.take(), &paths).unwrap_or(empty);
+}
+
+fn keep(value: Value, paths: &[&[String]]) -> Option<Value> {
+    if paths.iter().any(|p| p.is_empty()) {
+        return Some(value);
+    }
+    match value {
+        Value::Object(map) => {
+            let map: Map<String, Value> = map
+                .into_iter()
+                .filter_map(|(key, val)| {
+                    let rest = descend(paths, &key);
+                    if rest.is_empty() {
+                        return None;
+                    }
+                    keep(val, &rest).map(|val| (key, val))
+                })
+                .collect();
+            (!map.is_empty()).then_some(Value::Object(map))
+        }
+        Value::Array(items) => {
+            let items: Vec<Value> = items
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, item)| {
+                    let rest = descend(paths, &i.to_string());
+                    if rest.is_empty() {
+                        return None;
+                    }
+                    keep(item, &rest)
+                })
+                .collect();
+            (!items.is_empty()).then_some(Value::Array(items))
+        }
+        _ => None,
+    }
+}
+
+/// Remove everything under one of `paths`.
+pub fn exclude(value: &mut Value, paths: &[KeyPath]) {
+    let paths: Vec<&[String]> = paths.iter().map(|p| p.0.as_slice()).collect();
+    remove(value, &paths);
+}
+
+fn remove(value: &mut Value, paths: &[&[String]]) {
+    let matched = |key: &str| {
+        let rest = descend(paths, key);
+        let gone = rest.iter().any(|p| p.is_empty());
+        (gone, rest)
+    };
+    match value {
+        Value::Object(map) => {
+            map.retain(|key, val| {
+                let (gone, rest) = matched(key);
+                if !gone && !rest.is_empty() {
+                    remove(val, &rest);
+                }
+                !gone
+            });
+        }
+        Value::Array(items) => {
+            let mut i = 0;
+            items.retain_mut(|item| {
+                let (gone, rest) = matched(&i.to_string());
+                i += 1;
+                if !gone && !rest.is_empty() {
+                    remove(item, &rest);
+                }
+                !gone
+            });
+        }
+        _ => {}
+    }
+}
+
+/// Drop `null`s and, with `empty`, empty strings, lists and maps, from maps and lists alike.
+///
+/// Pruning works bottom-up, so a map left empty by pruning its contents is removed as well.
+pub fn prune(value: &mut Value, nulls: bool, empty: bool) {
+    let pruned = |v: &Value| match v {
+        Value::Null => nulls,
+        Value::String(s) => empty && s.is_empty(),
+        Value::Array(items) => empty && items.is_empty(),
+        Value::Object(map) => empty && map.is_empty(),
+        _ => false,
+    };
+    match value {
+        Value::Object(map) => {
+            for val in map.values_mut() {
+                prune(val, nulls, empty);
+            }
+            map.retain(|_, val| !pruned(val));
+        }
+        Value::Array(items) => {
+            for item in items.iter_mut() {
+                prune(item, nulls, empty);
+            }
+            items.retain(|item| !pruned(item));
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn paths(p: &[&str]) -> Vec<KeyPath> {
+        p.iter().map(|p| p.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_only() {
+        let mut value = json!({
+            "services": {"web": {"image": "nginx", "ports": [80]}, "db": {"image": "postgres"}, "x": {}},
+            "volumes": {"data": {}}
+        });
+        only(&mut value, &paths(&["services.*.image"]));
+        assert_eq!(value, json!({"services": {"web": {"image": "nginx"}, "db": {"image": "postgres"}}}));
+
+        let mut value = json!({"a.b": 1, "list": [{"n": 1, "m": 2}, {"m": 3}]});
+        only(&mut value, &paths(&["a\\.b", "list.*.n"]));
+        assert_eq!(value, json!({"a.b": 1, "list": [{"n": 1}]}));
+    }
+
+    #[test]
+    fn test_exclude() {
+        let mut value = json!({
+            "x-common": {"a": 1},
+            "services": {"web": {"image": "nginx", "x-slate": true}},
+            "list": [1, 2, 3]
+        });
+        exclude(&mut value, &paths(&["x-*", "services.*.x-*", "list.1"]));
+        assert_eq!(value, json!({"services": {"web": {"image": "nginx"}}, "list": [1, 3]}));
+    }
+
+    #[test]
+    fn test_prune() {
+        let input = json!({"a": null, "b": "", "c": [null, 1, {}], "d": {"e": {"f": null}}, "g": 0});
+        let mut value = input.clone();
+        prune(&mut value, true, false);
+        assert_eq!(value, json!({"b": "", "c": [1, {}], "d": {"e": {}}, "g": 0}));
+
+        let mut value = input.clone();
+        prune(&mut value, true, true);
+        assert_eq!(value, json!({"c": [1], "g": 0}));
+
+        let mut value = input;
+        prune(&mut value, false, true);
+        assert_eq!(value, json!({"a": null, "c": [null, 1], "d": {"e": {"f": null}}, "g": 0}));
+    }
+}
@@ -1,9 +1,10 @@
 use anyhow::{Context};
 use serde::Serialize;
 use std::process::Command;
-use std::{env, fs, io};
+use std::{env, fs};
 use std::{collections::HashMap};
 use std::path::{PathBuf, Component, Path};
+use std::sync::OnceLock;
 
 pub fn write_files<P, T, E, S>(
     units: &HashMap<String, T>,
@@ -24,6 +25,10 @@ where
 
 
         let file_path = output_dir.join(filename);
+        // Drop-ins are written to a directory of their own
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create directory: {parent:?}"))?;
+        }
 
         fs::write(&file_path, string_content)
             .with_context(|| format!("Failed to write to file: {file_path:?}"))?;
@@ -70,6 +75,10 @@ pub fn is_interactive() -> bool {
     has_permission(&PathBuf::from("/dev/tty"), 0o222)
 }
 
+pub fn selinux_enforcing() -> bool {
+    std::fs::read_to_string("/sys/fs/selinux/enforce").is_ok_and(|s| s.trim() == "1")
+}
+
 extern "C" {
     fn geteuid() -> u32;
 }
@@ -86,34 +95,6 @@ pub fn systemctl_cmd(is_root: bool) -> Command {
     cmd
 }
 
-#[cfg(test)]
-pub fn ask_confirm(_prompt: &str, yes_default: bool) -> io::Result<bool> {
-    Ok(yes_default)
-}
-
-#[cfg(not(test))]
-use demand::Confirm;
-
-#[cfg(not(test))]
-pub fn ask_confirm(prompt: &str, yes_default: bool) -> io::Result<bool> {
-    if std::env::var("SLATER_AUTO").is_ok_and(|v| v.eq_ignore_ascii_case("true")) || ! is_interactive() {
-        return Ok(yes_default);
-    }
-
-    if yes_default {
-        Confirm::new(prompt)
-            .affirmative("Yes")
-            .negative("No")
-            .run()
-    } else {
-        Confirm::new(prompt)
-            .affirmative("No")
-            .negative("Yes")
-            .run()
-            .map(|v| !v)
-    }
-}
-
 pub fn normalize_path<P: AsRef<Path>>(path_input: P) -> String {
     let path = path_input.as_ref();
     let path = if path.is_absolute() {
@@ -163,6 +144,93 @@ pub fn which(cmd: &str) -> Option<PathBuf> {
     None
 }
 
+// The patterns of environment variables slate may read, all of them if unset
+static ENV_ALLOW: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Only let templates and compose interpolation see environment variables matching one of
+/// `patterns`, like `APP_*`, so generated files can't pick up unrelated secrets from CI
+pub fn allow_env(patterns: Vec<String>) {
+    let _ = ENV_ALLOW.set(patterns);
+}
+
+/// The environment variable `name`, unless it's left out by [`allow_env`]
+pub fn env_var(name: &str) -> Option<String> {
+    if let Some(patterns) = ENV_ALLOW.get() {
+        if !patterns.iter().any(|pattern| wildcard_match(pattern, name)) {
+            return None;
+        }
+    }
+    env::var(name).ok()
+}
+
+// Whether `name` matches `pattern`, where `*` stands for any run of characters
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else { return false };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else { return rest.is_empty() };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// How templates are rendered
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Rendering {
+    /// Escape substituted values as HTML, as `Tera::one_off` does when asked to
+    pub autoescape: bool,
+    /// Offer the functions reading secrets, `pass`, `keyring` and `file`
+    pub secrets: bool,
+    /// Fail on undefined variables in `if` conditions too, which are otherwise just false, and
+    /// report the line of each undefined name
+    pub strict: bool,
+}
+
+/// Render a Tera template as `Tera::one_off` does, with slate's own filters and functions
+pub fn render_template(input: &str, context: &tera::Context, rendering: Rendering) -> tera::Result<String> {
+    let mut tera = tera::Tera::default();
+    crate::filters::register(&mut tera);
+    if rendering.secrets {
+        crate::filters::register_secrets(&mut tera);
+    }
+    tera.add_raw_template("slate", input)?;
+    tera.autoescape_on(if rendering.autoescape { vec!["slate"] } else { vec![] });
+    if !rendering.strict {
+        return tera.render("slate", context);
+    }
+    crate::filters::check_conditions(input, context)?;
+    tera.render("slate", context).map_err(|e| crate::filters::locate_error(input, &e))
+}
+
+/// The candidate nearest to a misspelt name, ignoring case, if it's within a couple of edits
+pub fn closest_match<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|candidate| (edit_distance(&candidate.to_lowercase(), &name.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
+
 // #[cfg(test)]
 pub fn enter_test_dir() -> std::path::PathBuf {
     let dir = std::path::Path::new("/tmp/slater");
@@ -189,4 +257,13 @@ pub fn enter_test_dir() -> std::path::PathBuf {
           assert_eq!(normalize_path("a/../b/c"), format!("{}/b/c", current_dir.to_str().unwrap()));
           assert_eq!(normalize_path("../a/b/c"), format!("{parent_dir}/a/b/c"));
       }
+
+      #[test]
+      fn test_wildcard_match() {
+          assert!(wildcard_match("APP_*", "APP_DOMAIN"));
+          assert!(!wildcard_match("APP_*", "AWS_SECRET_ACCESS_KEY"));
+          assert!(wildcard_match("HOME", "HOME") && !wildcard_match("HOME", "HOMEBREW"));
+          assert!(wildcard_match("*_PORT", "DB_PORT") && wildcard_match("A*B*C", "AxxBxC"));
+          assert!(wildcard_match("A*BA", "ABA") && !wildcard_match("A*BA", "AB"));
+      }
   }
\ No newline at end of file
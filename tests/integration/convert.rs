@@ -0,0 +1,28 @@
+use anyhow::Result;
+use assert_cmd::Command;
+
+#[test]
+fn test_stdin_format_is_guessed() -> Result<()> {
+    Command::cargo_bin("slate")?
+        .args(["--to", "yaml"])
+        .write_stdin(r#"{"name": "app", "port": 80}"#)
+        .assert()
+        .success()
+        .stdout("name: app\nport: 80\n");
+
+    Command::cargo_bin("slate")?
+        .args(["--to", "json"])
+        .write_stdin("[server]\nport = 80\n")
+        .assert()
+        .success()
+        .stdout(r#"{"server":{"port":80}}"#);
+
+    // --from still wins over the guess
+    Command::cargo_bin("slate")?
+        .args(["--from", "yaml", "--to", "json"])
+        .write_stdin("[1, 2]")
+        .assert()
+        .success()
+        .stdout("[1,2]");
+    Ok(())
+}
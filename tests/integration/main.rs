@@ -1,3 +1,4 @@
 // This file enables the integration test suite
+mod convert;
 mod quadlet;
 mod systemd;
\ No newline at end of file
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use slaters::prompt::DefaultPrompter;
 use slaters::quadlet::{process_compose, ComposeFile};
 use slaters::utils::enter_test_dir;
 use std::{fs::File};
@@ -51,7 +52,7 @@ fn test_process_compose() -> Result<()> {
     let file: ComposeFile = serde_yaml::from_reader(file)?;
 
     enter_test_dir();
-    let file = process_compose(file, None)?;
+    let file = process_compose(file, None, &mut DefaultPrompter)?;
 
     insta::assert_yaml_snapshot!(file);
     Ok(())